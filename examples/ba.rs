@@ -0,0 +1,97 @@
+/*
+A small two-view bundle adjustment example
+
+ - Two pinhole cameras look down their z-axis at a grid of landmarks
+ - Each camera observes every landmark, giving a reprojection factor
+ - The first camera is fixed with a tight prior, and a prior on a single
+   landmark fixes the scale
+ - Both the second camera and all landmarks start from perturbed guesses
+*/
+
+use factrs::{
+    assign_symbols,
+    core::{Graph, LevenMarquardt, PriorResidual, Values},
+    dtype, fac,
+    linalg::{vectorx, Vector3},
+    residuals::ProjectionResidual,
+    traits::*,
+    variables::{MatrixLieGroup, VectorVar2, VectorVar3, SE3, SO3},
+};
+
+// C for cameras, L for landmarks
+assign_symbols!(C: SE3; L: VectorVar3);
+
+const FX: dtype = 500.0;
+const FY: dtype = 500.0;
+const CX: dtype = 320.0;
+const CY: dtype = 240.0;
+
+fn main() {
+    // Ground truth cameras, the second shifted to the right and looking back
+    // slightly towards the landmarks
+    let cameras = [
+        SE3::identity(),
+        SE3::from_rot_trans(
+            SO3::exp(vectorx![0.0, -0.1, 0.0].as_view()),
+            Vector3::new(1.0, 0.0, 0.0),
+        ),
+    ];
+
+    // Ground truth landmarks, a slightly curved grid in front of the cameras
+    let landmarks: Vec<VectorVar3> = (0..9)
+        .map(|i| {
+            let x = (i % 3) as dtype - 1.0;
+            let y = (i / 3) as dtype - 1.0;
+            VectorVar3::new(x, y, 5.0 + 0.2 * x * y)
+        })
+        .collect();
+
+    let mut graph = Graph::new();
+
+    // Only used to generate the measurements
+    let camera_model = ProjectionResidual::new(VectorVar2::identity(), FX, FY, CX, CY);
+
+    // Reprojection factors, modeled with 1 pixel of noise
+    for (i, cam) in cameras.iter().enumerate() {
+        for (j, l) in landmarks.iter().enumerate() {
+            let p_c = VectorVar3::from(cam.inverse().apply(l.0.as_view()));
+            let pixel = camera_model.project(p_c).expect("Landmark behind camera");
+
+            let residual = ProjectionResidual::new(pixel, FX, FY, CX, CY);
+            graph.add_factor(fac![residual, (C(i as u32), L(j as u32)), 1.0 as std]);
+        }
+    }
+
+    // Fix the gauge freedom, the first camera and the scale
+    graph.add_factor(fac![
+        PriorResidual::new(cameras[0].clone()),
+        C(0),
+        1e-3 as std
+    ]);
+    graph.add_factor(fac![
+        PriorResidual::new(landmarks[0].clone()),
+        L(0),
+        1e-3 as std
+    ]);
+
+    // Perturbed initial guesses
+    let mut values = Values::new();
+    values.insert(C(0), cameras[0].clone());
+    let noise = vectorx![0.02, -0.03, 0.01, 0.1, -0.05, 0.08];
+    values.insert(C(1), cameras[1].oplus(noise.as_view()));
+    for (j, l) in landmarks.iter().enumerate() {
+        let offset = 0.1 * (j as dtype - 4.0);
+        let noise = vectorx![offset, -offset, 0.5 * offset];
+        values.insert(L(j as u32), l.oplus(noise.as_view()));
+    }
+
+    // Optimize!
+    let mut opt: LevenMarquardt = LevenMarquardt::new(graph);
+    println!("Initial error: {:.4e}", opt.error(&values));
+    let result = opt.optimize(values).expect("Optimization failed");
+    println!("Final error: {:.4e}", opt.error(&result));
+
+    let c1: &SE3 = result.get(C(1)).expect("Missing camera");
+    println!("Estimated second camera: {}", c1);
+    println!("True second camera:      {}", cameras[1]);
+}
@@ -0,0 +1,44 @@
+use diol::prelude::{black_box, list, Bench, BenchConfig, Bencher};
+use factrs::{containers::LinearizationCache, utils::load_g20};
+
+const DATA_DIR: &str = "../examples/data/";
+
+// Emulates a multi-start scenario, where a handful of starting points are each
+// relinearized a number of times (ie during a line search) before moving on
+const STARTS: usize = 4;
+
+fn uncached(bencher: Bencher, repeats: usize) {
+    let (graph, init) = load_g20(&format!("{}{}", DATA_DIR, "M3500.g2o"));
+    bencher.bench(|| {
+        for _ in 0..STARTS {
+            for _ in 0..repeats {
+                let mut linear = graph.linearize(&init);
+                black_box(&mut linear);
+            }
+        }
+    });
+}
+
+fn cached(bencher: Bencher, repeats: usize) {
+    let (graph, init) = load_g20(&format!("{}{}", DATA_DIR, "M3500.g2o"));
+    bencher.bench(|| {
+        let mut cache = LinearizationCache::new();
+        for _ in 0..STARTS {
+            cache.clear();
+            for _ in 0..repeats {
+                let mut linear = graph.linearize_cached(&init, &mut cache);
+                black_box(&mut linear);
+            }
+        }
+    });
+}
+
+fn main() -> std::io::Result<()> {
+    let to_run = list![uncached, cached];
+
+    let mut bench = Bench::new(BenchConfig::from_args()?);
+    bench.register_many(to_run, [1, 5, 20]);
+    bench.run()?;
+
+    Ok(())
+}
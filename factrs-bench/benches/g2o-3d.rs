@@ -1,3 +1,5 @@
+use std::sync::Arc;
+
 use diol::prelude::{black_box, list, Bench, BenchConfig, Bencher};
 
 const DATA_DIR: &str = "../examples/data/";
@@ -6,6 +8,7 @@ const DATA_DIR: &str = "../examples/data/";
 use factrs::{core::GaussNewton, traits::Optimizer, utils::load_g20};
 fn factrs(bencher: Bencher, file: &str) {
     let (graph, init) = load_g20(&format!("{}{}", DATA_DIR, file));
+    let graph = Arc::new(graph);
     bencher.bench(|| {
         let mut opt: GaussNewton = GaussNewton::new(graph.clone());
         let mut results = opt.optimize(init.clone());
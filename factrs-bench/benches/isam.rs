@@ -0,0 +1,79 @@
+use diol::prelude::{black_box, list, Bench, BenchConfig, Bencher};
+use factrs::{
+    assign_symbols,
+    containers::{FactorBuilder, Graph, Values},
+    dtype,
+    optimizers::{GaussNewton, Isam},
+    residuals::{BetweenResidual, PriorResidual},
+    traits::*,
+    variables::SE2,
+};
+
+assign_symbols!(X: SE2);
+
+// Emulates online SLAM, where a single odometry factor and pose arrive at each
+// time step and an estimate is needed after each one
+fn first() -> (Graph, Values) {
+    let mut graph = Graph::new();
+    let res = PriorResidual::new(SE2::identity());
+    graph.add_factor(FactorBuilder::new1(res, X(0)).build());
+    let mut values = Values::new();
+    values.insert(X(0), SE2::identity());
+    (graph, values)
+}
+
+fn odometry(i: u32) -> (Graph, Values) {
+    let mut graph = Graph::new();
+    let res = BetweenResidual::new(SE2::new(0.1, 1.0, 0.0));
+    graph.add_factor(FactorBuilder::new2(res, X(i), X(i + 1)).build());
+    let mut values = Values::new();
+    values.insert(X(i + 1), SE2::new(0.1 * i as dtype, i as dtype, 0.0));
+    (graph, values)
+}
+
+fn batch(bencher: Bencher, steps: u32) {
+    bencher.bench(|| {
+        let (mut graph, mut values) = first();
+        for i in 0..steps {
+            let (g, v) = odometry(i);
+            for f in g {
+                graph.add_factor(f);
+            }
+            for (k, v) in v {
+                values.entry(k).or_insert(v);
+            }
+            let mut opt: GaussNewton = GaussNewton::new(graph.clone());
+            values = opt
+                .optimize(values)
+                .expect("Optimization failed")
+                .into_values();
+        }
+        black_box(&mut values);
+    });
+}
+
+fn isam(bencher: Bencher, steps: u32) {
+    bencher.bench(|| {
+        let mut isam: Isam = Isam::new();
+        let (graph, values) = first();
+        isam.add_factors(graph);
+        isam.add_values(values);
+        isam.update().expect("iSAM update failed");
+        for i in 0..steps {
+            let (graph, values) = odometry(i);
+            isam.add_factors(graph);
+            isam.add_values(values);
+            black_box(isam.update());
+        }
+    });
+}
+
+fn main() -> std::io::Result<()> {
+    let to_run = list![batch, isam];
+
+    let mut bench = Bench::new(BenchConfig::from_args()?);
+    bench.register_many(to_run, [50, 200]);
+    bench.run()?;
+
+    Ok(())
+}
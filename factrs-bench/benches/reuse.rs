@@ -0,0 +1,43 @@
+use std::sync::Arc;
+
+use diol::prelude::{black_box, list, Bench, BenchConfig, Bencher};
+use factrs::{core::GaussNewton, traits::Optimizer, utils::load_g20};
+
+const DATA_DIR: &str = "../examples/data/";
+
+// Emulates a parameter sweep, where the same shaped graph is re-solved a number
+// of times with only the measurements changing
+fn fresh(bencher: Bencher, solves: usize) {
+    let (graph, init) = load_g20(&format!("{}{}", DATA_DIR, "M3500.g2o"));
+    let graph = Arc::new(graph);
+    bencher.bench(|| {
+        for _ in 0..solves {
+            let mut opt: GaussNewton = GaussNewton::new(graph.clone());
+            let mut results = opt.optimize(init.clone());
+            black_box(&mut results);
+        }
+    });
+}
+
+fn reused(bencher: Bencher, solves: usize) {
+    let (graph, init) = load_g20(&format!("{}{}", DATA_DIR, "M3500.g2o"));
+    let graph = Arc::new(graph);
+    bencher.bench(|| {
+        let mut opt: GaussNewton = GaussNewton::new(graph.clone());
+        for _ in 0..solves {
+            opt.set_graph(graph.clone());
+            let mut results = opt.optimize(init.clone());
+            black_box(&mut results);
+        }
+    });
+}
+
+fn main() -> std::io::Result<()> {
+    let to_run = list![fresh, reused];
+
+    let mut bench = Bench::new(BenchConfig::from_args()?);
+    bench.register_many(to_run, [1, 5]);
+    bench.run()?;
+
+    Ok(())
+}
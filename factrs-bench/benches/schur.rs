@@ -0,0 +1,98 @@
+use std::sync::Arc;
+
+use diol::prelude::{black_box, list, Bench, BenchConfig, Bencher};
+use factrs::{
+    assign_symbols,
+    core::{GaussNewton, Graph, PriorResidual, Values},
+    dtype, fac,
+    linalg::{vectorx, Vector3},
+    linear::{CholeskySolver, SchurSolver},
+    residuals::ProjectionResidual,
+    traits::*,
+    variables::{MatrixLieGroup, VectorVar2, VectorVar3, SE3, SO3},
+};
+
+assign_symbols!(C: SE3; L: VectorVar3);
+
+// Synthetic BAL style problem, a ring of cameras looking in at a cloud of
+// landmarks, each camera observing every landmark
+fn problem(num_cams: usize) -> (Graph, Values) {
+    let num_points = 20 * num_cams;
+    let cameras = (0..num_cams)
+        .map(|i| {
+            let theta = 0.5 * i as dtype / num_cams as dtype;
+            let rot = SO3::exp(vectorx![0.0, theta, 0.0].as_view());
+            let xyz = Vector3::new(-5.0 * theta.sin(), 0.0, 5.0 - 5.0 * theta.cos());
+            SE3::from_rot_trans(rot, xyz)
+        })
+        .collect::<Vec<_>>();
+    let landmarks = (0..num_points)
+        .map(|j| {
+            let j = j as dtype;
+            VectorVar3::new((0.7 * j).sin(), (1.3 * j).cos(), 5.0 + (0.3 * j).sin())
+        })
+        .collect::<Vec<_>>();
+
+    let mut graph = Graph::new();
+    let model = ProjectionResidual::new(VectorVar2::identity(), 500.0, 500.0, 0.0, 0.0);
+    for (i, cam) in cameras.iter().enumerate() {
+        for (j, l) in landmarks.iter().enumerate() {
+            let p_c = VectorVar3::from(cam.inverse().apply(l.0.as_view()));
+            let pixel = model.project(p_c).expect("Landmark behind camera");
+            let res = ProjectionResidual::new(pixel, 500.0, 500.0, 0.0, 0.0);
+            graph.add_factor(fac![res, (C(i as u32), L(j as u32))]);
+        }
+    }
+    graph.add_factor(fac![
+        PriorResidual::new(cameras[0].clone()),
+        C(0),
+        1e-3 as std
+    ]);
+    graph.add_factor(fac![
+        PriorResidual::new(landmarks[0].clone()),
+        L(0),
+        1e-3 as std
+    ]);
+
+    let mut values = Values::new();
+    for (i, cam) in cameras.iter().enumerate() {
+        let noise = vectorx![0.01, -0.01, 0.01, 0.05, -0.05, 0.05];
+        values.insert(C(i as u32), cam.oplus(noise.as_view()));
+    }
+    for (j, l) in landmarks.iter().enumerate() {
+        let noise = vectorx![0.1, -0.1, 0.1];
+        values.insert(L(j as u32), l.oplus(noise.as_view()));
+    }
+    (graph, values)
+}
+
+fn cholesky(bencher: Bencher, num_cams: usize) {
+    let (graph, init) = problem(num_cams);
+    let graph = Arc::new(graph);
+    bencher.bench(|| {
+        let mut opt: GaussNewton<CholeskySolver> = GaussNewton::new(graph.clone());
+        let mut results = opt.optimize(init.clone());
+        black_box(&mut results);
+    });
+}
+
+fn schur(bencher: Bencher, num_cams: usize) {
+    let (graph, init) = problem(num_cams);
+    let graph = Arc::new(graph);
+    bencher.bench(|| {
+        let solver = SchurSolver::new(['L']);
+        let mut opt: GaussNewton<SchurSolver> = GaussNewton::with_solver(graph.clone(), solver);
+        let mut results = opt.optimize(init.clone());
+        black_box(&mut results);
+    });
+}
+
+fn main() -> std::io::Result<()> {
+    let to_run = list![cholesky, schur];
+
+    let mut bench = Bench::new(BenchConfig::from_args()?);
+    bench.register_many(to_run, [5, 20]);
+    bench.run()?;
+
+    Ok(())
+}
@@ -0,0 +1,44 @@
+use std::sync::Arc;
+
+use diol::prelude::{black_box, list, Bench, BenchConfig, Bencher};
+use factrs::{
+    core::GaussNewton,
+    linear::{CholeskySolver, LDLTSolver},
+    traits::Optimizer,
+    utils::load_g20,
+};
+
+const DATA_DIR: &str = "../examples/data/";
+
+fn cholesky(bencher: Bencher, file: &str) {
+    let (graph, init) = load_g20(&format!("{}{}", DATA_DIR, file));
+    let graph = Arc::new(graph);
+    bencher.bench(|| {
+        let mut opt: GaussNewton<CholeskySolver> = GaussNewton::new(graph.clone());
+        let mut results = opt.optimize(init.clone());
+        black_box(&mut results);
+    });
+}
+
+fn ldlt(bencher: Bencher, file: &str) {
+    let (graph, init) = load_g20(&format!("{}{}", DATA_DIR, file));
+    let graph = Arc::new(graph);
+    bencher.bench(|| {
+        let mut opt: GaussNewton<LDLTSolver> = GaussNewton::new(graph.clone());
+        let mut results = opt.optimize(init.clone());
+        black_box(&mut results);
+    });
+}
+
+fn main() -> std::io::Result<()> {
+    let to_run = list![cholesky, ldlt];
+
+    let mut bench = Bench::new(BenchConfig::from_args()?);
+    bench.register_many(
+        to_run,
+        ["M3500.g2o", "parking-garage.g2o", "sphere2500.g2o"],
+    );
+    bench.run()?;
+
+    Ok(())
+}
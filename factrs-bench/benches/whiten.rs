@@ -0,0 +1,96 @@
+use diol::prelude::{black_box, list, Bench, BenchConfig, Bencher};
+use factrs::{
+    linalg::{MatrixX, VectorX},
+    noise::GaussianNoise,
+    traits::{NoiseModel, Residual},
+    utils::load_g20,
+};
+
+const DATA_DIR: &str = "../examples/data/";
+
+// Number of factor evaluations to emulate per benchmark iteration
+const EVALS: usize = 10_000;
+
+// The previous approach, a dynamically sized product into a fresh allocation
+fn dynamic(bencher: Bencher, dim: usize) {
+    let sqrt_inf = MatrixX::identity(dim, dim) * 10.0;
+    let m = MatrixX::from_element(dim, dim, 1.0);
+    let v = VectorX::from_element(dim, 1.0);
+    bencher.bench(|| {
+        for _ in 0..EVALS {
+            // Clones stand in for the freshly computed residual & jacobian
+            let m = m.clone();
+            let mut out = MatrixX::zeros(dim, dim);
+            sqrt_inf.mul_to(&m, &mut out);
+            black_box(&mut out);
+
+            let v = v.clone();
+            let mut out = VectorX::zeros(dim);
+            sqrt_inf.mul_to(&v, &mut out);
+            black_box(&mut out);
+        }
+    });
+}
+
+// Current in place, fixed size whitening
+fn fixed(bencher: Bencher, dim: usize) {
+    let noise: Box<dyn NoiseModel> = match dim {
+        3 => Box::new(GaussianNoise::<3>::from_scalar_sigma(0.1)),
+        6 => Box::new(GaussianNoise::<6>::from_scalar_sigma(0.1)),
+        _ => unreachable!(),
+    };
+    let m = MatrixX::from_element(dim, dim, 1.0);
+    let v = VectorX::from_element(dim, 1.0);
+    bencher.bench(|| {
+        for _ in 0..EVALS {
+            // Clones stand in for the freshly computed residual & jacobian
+            let mut out = noise.whiten_mat(m.clone());
+            black_box(&mut out);
+            let mut out = noise.whiten_vec(v.clone());
+            black_box(&mut out);
+        }
+    });
+}
+
+// Whiten every residual of a graph, each into a fresh allocation
+fn graph_alloc(bencher: Bencher, file: &str) {
+    let (graph, init) = load_g20(&format!("{}{}", DATA_DIR, file));
+    let residuals = graph
+        .iter()
+        .map(|f| f.residual().residual(&init, f.keys()))
+        .collect::<Vec<_>>();
+    bencher.bench(|| {
+        for (f, r) in graph.iter().zip(residuals.iter()) {
+            let mut out = f.noise().whiten_vec(r.clone());
+            black_box(&mut out);
+        }
+    });
+}
+
+// Whiten every residual of a graph into reused scratch buffers
+fn graph_scratch(bencher: Bencher, file: &str) {
+    let (graph, init) = load_g20(&format!("{}{}", DATA_DIR, file));
+    let residuals = graph
+        .iter()
+        .map(|f| f.residual().residual(&init, f.keys()))
+        .collect::<Vec<_>>();
+    let mut scratch = residuals.clone();
+    bencher.bench(|| {
+        for ((f, r), out) in graph.iter().zip(residuals.iter()).zip(scratch.iter_mut()) {
+            out.copy_from(r);
+            f.noise().whiten_vec_mut(out);
+            black_box(&mut *out);
+        }
+    });
+}
+
+fn main() -> std::io::Result<()> {
+    let to_run = list![dynamic, fixed];
+
+    let mut bench = Bench::new(BenchConfig::from_args()?);
+    bench.register_many(to_run, [3, 6]);
+    bench.register_many(list![graph_alloc, graph_scratch], ["M3500.g2o"]);
+    bench.run()?;
+
+    Ok(())
+}
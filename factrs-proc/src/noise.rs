@@ -1,6 +1,6 @@
 use proc_macro2::{Ident, TokenStream as TokenStream2};
 use quote::quote;
-use syn::{GenericParam, ItemImpl, Type, TypePath};
+use syn::{parse_quote, GenericParam, ImplItem, ItemImpl, Type, TypePath};
 
 fn type_name(mut ty: &Type) -> Option<Ident> {
     loop {
@@ -16,7 +16,20 @@ fn type_name(mut ty: &Type) -> Option<Ident> {
     }
 }
 
-pub fn mark(item: ItemImpl) -> TokenStream2 {
+pub fn mark(mut item: ItemImpl) -> TokenStream2 {
+    // Implement dim from Dim, so it's available on trait objects
+    let has_dim = item
+        .items
+        .iter()
+        .any(|i| matches!(i, ImplItem::Fn(f) if f.sig.ident == "dim"));
+    if !has_dim {
+        item.items.push(parse_quote!(
+            fn dim(&self) -> usize {
+                <Self::Dim as factrs::linalg::DimName>::USIZE
+            }
+        ));
+    }
+
     if !cfg!(feature = "serde") {
         return quote! { #item };
     }
@@ -33,7 +33,7 @@ pub fn mark(item: ItemImpl) -> TokenStream2 {
     // Have to do three things here to get typetag to work
     // - Impl typetag::Tagged
     // - Register our variable in typetag
-    // - Tag PriorResidual, BetweenResidual with this type
+    // - Tag PriorResidual, BetweenResidual, CompositionResidual with this type
     match item.generics.params.len() {
         0 => {
             let msg = "variable should have dtype generic";
@@ -61,6 +61,7 @@ pub fn mark(item: ItemImpl) -> TokenStream2 {
 fn tag_all(kind: &TokenStream2, name: &str) -> TokenStream2 {
     let name_prior = format!("PriorResidual<{}>", name);
     let name_between = format!("BetweenResidual<{}>", name);
+    let name_composition = format!("CompositionResidual<{}>", name);
     quote! {
         // Self
         typetag::__private::inventory::submit! {
@@ -97,5 +98,17 @@ fn tag_all(kind: &TokenStream2, name: &str) -> TokenStream2 {
                 )) as typetag::__private::DeserializeFn<<dyn factrs::residuals::Residual as typetag::__private::Strictest>::Object>,
             )
         }
+
+        // Composition
+        typetag::__private::inventory::submit! {
+            <dyn factrs::residuals::Residual>::typetag_register(
+                #name_composition,
+                (|deserializer| typetag::__private::Result::Ok(
+                    typetag::__private::Box::new(
+                        typetag::__private::erased_serde::deserialize::<factrs::residuals::CompositionResidual<#kind>>(deserializer)?
+                    ),
+                )) as typetag::__private::DeserializeFn<<dyn factrs::residuals::Residual as typetag::__private::Strictest>::Object>,
+            )
+        }
     }
 }
@@ -0,0 +1,210 @@
+use foldhash::HashMap;
+
+use super::{Factor, FactorId, Values};
+use crate::{dtype, linalg::VectorX, linear::LinearFactor};
+
+/// Memoization of factor linearizations
+///
+/// Caches the [LinearFactor] of each factor in a graph alongside the
+/// parameters (see [Variable::to_params](crate::variables::Variable::to_params))
+/// of the variables it was linearized at. On the next call to
+/// [Graph::linearize_cached](super::Graph::linearize_cached), a factor whose
+/// variables haven't moved more than `tol` in any parameter reuses its previous
+/// linearization, while any other factor is relinearized and its entry
+/// replaced.
+///
+/// This is useful in workflows that evaluate the same graph at the same or
+/// nearby points many times, such as multi-start or line searches. Note that
+/// with a nonzero tolerance the reused linearizations are only approximate.
+///
+/// A cache is tied to a single [Graph](super::Graph). Entries are keyed by
+/// [FactorId], so adding or removing factors keeps the rest valid, while
+/// changing a factor in place, such as with
+/// [replace_factor](super::Graph::replace_factor), drops them all.
+#[derive(Default)]
+pub struct LinearizationCache {
+    tol: dtype,
+    entries: HashMap<FactorId, (VectorX, LinearFactor)>,
+    revision: usize,
+    hits: usize,
+    misses: usize,
+}
+
+impl LinearizationCache {
+    /// Create a cache that only reuses linearizations at identical values
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a cache that reuses linearizations within a tolerance
+    pub fn with_tolerance(tol: dtype) -> Self {
+        Self {
+            tol,
+            ..Default::default()
+        }
+    }
+
+    /// Drop all cached linearizations
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.hits = 0;
+        self.misses = 0;
+    }
+
+    /// Number of linearizations reused so far
+    pub fn hits(&self) -> usize {
+        self.hits
+    }
+
+    /// Number of linearizations computed so far
+    pub fn misses(&self) -> usize {
+        self.misses
+    }
+
+    // Drop entries for removed factors, or everything if a factor was changed
+    pub(crate) fn sync(&mut self, revision: usize, ids: &[FactorId]) {
+        if self.revision != revision {
+            self.entries.clear();
+            self.revision = revision;
+        }
+        self.entries.retain(|id, _| ids.binary_search(id).is_ok());
+    }
+
+    // Linearize the factor with the given id, or pull it from the cache
+    pub(crate) fn linearize(
+        &mut self,
+        id: FactorId,
+        factor: &Factor,
+        values: &Values,
+    ) -> LinearFactor {
+        let params = factor
+            .keys()
+            .iter()
+            .flat_map(|k| {
+                values
+                    .get_raw(*k)
+                    .expect("Key missing in values")
+                    .to_params()
+                    .into_iter()
+                    .copied()
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        let params = VectorX::from_vec(params);
+
+        match self.entries.get(&id) {
+            // Fixing or freeing a variable changes which blocks there are
+            Some((cached, linear))
+                if cached.len() == params.len()
+                    && (cached - &params).amax() <= self.tol
+                    && linear
+                        .keys
+                        .iter()
+                        .eq(factor.keys().iter().filter(|k| !values.is_fixed(**k))) =>
+            {
+                self.hits += 1;
+                linear.clone()
+            }
+            _ => {
+                self.misses += 1;
+                let linear = factor.linearize(values);
+                self.entries.insert(id, (params, linear.clone()));
+                linear
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use faer_ext::IntoNalgebra;
+    use matrixcompare::assert_matrix_eq;
+
+    use super::*;
+    use crate::{
+        assign_symbols,
+        containers::{FactorBuilder, Graph, ValuesOrder},
+        linear::LinearValues,
+        residuals::{BetweenResidual, PriorResidual},
+        traits::*,
+        variables::SE2,
+    };
+
+    assign_symbols!(X: SE2);
+
+    fn setup() -> (Graph, Values) {
+        let mut graph = Graph::new();
+        let res = PriorResidual::new(SE2::new(0.1, 1.0, 2.0));
+        graph.add_factor(FactorBuilder::new1(res, X(0)).build());
+        let res = BetweenResidual::new(SE2::new(0.2, 1.0, 0.0));
+        graph.add_factor(FactorBuilder::new2(res, X(0), X(1)).build());
+
+        let mut values = Values::new();
+        values.insert(X(0), SE2::identity());
+        values.insert(X(1), SE2::identity());
+        (graph, values)
+    }
+
+    #[test]
+    fn reuse() {
+        let (graph, values) = setup();
+        let mut cache = LinearizationCache::new();
+
+        let _ = graph.linearize_cached(&values, &mut cache);
+        assert_eq!(cache.misses(), 2);
+        let cached = graph.linearize_cached(&values, &mut cache);
+        assert_eq!(cache.hits(), 2);
+
+        let expected = graph.linearize(&values);
+        let dx = LinearValues::zero_from_values(&values);
+        assert_eq!(cached.error(&dx), expected.error(&dx));
+    }
+
+    #[test]
+    fn invalidate() {
+        let (graph, mut values) = setup();
+        let mut cache = LinearizationCache::new();
+        let _ = graph.linearize_cached(&values, &mut cache);
+
+        // Only X(1) moves, so only the between factor is relinearized
+        values.insert(X(1), SE2::new(0.5, 1.0, 1.0));
+        let cached = graph.linearize_cached(&values, &mut cache);
+        assert_eq!(cache.hits(), 1);
+        assert_eq!(cache.misses(), 3);
+
+        let expected = graph.linearize(&values);
+        let order = ValuesOrder::from_values(&values);
+        let graph_order = expected.sparsity_pattern(order);
+        let got = cached.residual_jacobian(&graph_order);
+        let expected = expected.residual_jacobian(&graph_order);
+        assert_matrix_eq!(
+            got.diff.to_dense().as_ref().into_nalgebra(),
+            expected.diff.to_dense().as_ref().into_nalgebra(),
+            comp = float
+        );
+    }
+
+    #[test]
+    fn mutate() {
+        let (mut graph, values) = setup();
+        let mut cache = LinearizationCache::new();
+        let ids = graph.ids().collect::<Vec<_>>();
+        let _ = graph.linearize_cached(&values, &mut cache);
+
+        // Removing the prior moves the between factor, but its entry is kept
+        graph.remove_factor(ids[0]);
+        let _ = graph.linearize_cached(&values, &mut cache);
+        assert_eq!(cache.hits(), 1);
+
+        // A replaced factor has to be relinearized
+        let res = BetweenResidual::new(SE2::new(-0.2, 0.0, 1.0));
+        graph.replace_factor(ids[1], FactorBuilder::new2(res, X(0), X(1)).build());
+        let cached = graph.linearize_cached(&values, &mut cache);
+        assert_eq!(cache.hits(), 1);
+        assert_eq!(cache.misses(), 3);
+
+        let expected = graph.linearize(&values);
+        let dx = LinearValues::zero_from_values(&values);
+        assert_eq!(cached.error(&dx), expected.error(&dx));
+    }
+}
@@ -9,7 +9,7 @@ use super::{DefaultSymbolHandler, KeyFormatter, Symbol, TypedSymbol};
 use crate::{
     containers::{Key, Values},
     dtype,
-    linalg::{Const, DiffResult, MatrixBlock},
+    linalg::{Const, DiffResult, MatrixBlock, VectorX},
     linear::LinearFactor,
     noise::{NoiseModel, UnitNoise},
     residuals::Residual,
@@ -32,6 +32,12 @@ use crate::{
 /// - <blue>Robust Kernel</blue>: The robust kernel weights the error of the
 ///   factor, given by the traits in the [robust](crate::robust) module.
 ///
+/// The robust kernel is applied to the factor as a whole, not to each
+/// element of the residual. It is evaluated at the squared norm of the
+/// whitened residual, ie the Mahalanobis distance, so a multi-dimensional
+/// measurement such as a 2D reprojection gets a single weight and is accepted
+/// or rejected as a whole.
+///
 /// The easiest way to construct a factor is using the [fac](factrs::fac) macro,
 /// or alternatively, using [FactorBuilder].
 ///
@@ -63,10 +69,66 @@ pub struct Factor {
     residual: Box<dyn Residual>,
     noise: Box<dyn NoiseModel>,
     robust: Box<dyn RobustCost>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    category: Category,
+}
+
+/// Category tag for a factor.
+///
+/// Purely metadata, it has no effect on the optimization itself. It allows for
+/// treating groups of factors differently after the graph has been built, for
+/// example robustifying only loop closures with
+/// [Graph::robustify_category](crate::containers::Graph::robustify_category).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Category {
+    #[default]
+    Unspecified,
+    Prior,
+    Odometry,
+    LoopClosure,
+    Landmark,
+    /// User defined category
+    Custom(u32),
 }
 
 impl Factor {
+    /// Create a factor directly from trait objects.
+    ///
+    /// This is useful when residuals are chosen at runtime (e.g. from a config
+    /// file), where the concrete types needed by [FactorBuilder] and
+    /// [fac](factrs::fac) aren't known at compile time. Unlike those, nothing
+    /// is checked at compile time, so it is up to the caller to ensure the
+    /// keys match the residual's variables. The dimension of the noise model
+    /// is checked against the residual here, panicking if they differ rather
+    /// than failing with a shape mismatch once optimizing.
+    pub fn from_boxed(
+        residual: Box<dyn Residual>,
+        keys: Vec<Key>,
+        noise: Box<dyn NoiseModel>,
+        robust: Box<dyn RobustCost>,
+    ) -> Self {
+        assert_eq!(
+            residual.dim_out(),
+            noise.dim(),
+            "Residual output dimension {} does not match noise model dimension {}",
+            residual.dim_out(),
+            noise.dim()
+        );
+        Self {
+            keys,
+            residual,
+            noise,
+            robust,
+            category: Category::default(),
+        }
+    }
+
     /// Compute the error of the factor given a set of values.
+    ///
+    /// This is the robust loss of the squared norm of the whitened residual,
+    /// ie $\rho(\|r\|^2_\Sigma)$, and is what the factor contributes to
+    /// [Graph::error](crate::containers::Graph::error).
     pub fn error(&self, values: &Values) -> dtype {
         let r = self.residual.residual(values, &self.keys);
         let r = self.noise.whiten_vec(r);
@@ -74,19 +136,41 @@ impl Factor {
         self.robust.loss(norm2)
     }
 
+    /// Compute the weight the robust kernel applies to the factor.
+    ///
+    /// This is the same iteratively reweighted least squares weight used when
+    /// [linearizing](Factor::linearize), evaluated at the whitened residual,
+    /// and normalized to $[0, 1]$ so it can be thresholded to find outliers. It
+    /// is divided by the kernel's weight at a zero residual, so an inlier has a
+    /// weight of 1, with the weight dropping toward 0 as a measurement is
+    /// rejected. Every built-in kernel is bounded this way except
+    /// [L1](crate::robust::L1), whose raw weight $1/|r|$ grows without bound
+    /// as the residual shrinks; it's clamped to 1 for any $|r| < 1$. Factors
+    /// without a robust kernel always have a weight of 1.
+    pub fn robust_weight(&self, values: &Values) -> dtype {
+        let r = self.residual.residual(values, &self.keys);
+        let r = self.noise.whiten_vec(r);
+        let weight = self.robust.weight(r.norm_squared());
+        let zero = self.robust.weight(0.0);
+        let weight = if zero > 0.0 { weight / zero } else { weight };
+        weight.clamp(0.0, 1.0)
+    }
+
     /// Compute the dimension of the output of the factor.
     pub fn dim_out(&self) -> usize {
         self.residual.dim_out()
     }
 
     /// Linearize the factor given a set of values into a [LinearFactor].
+    ///
+    /// [Fixed](Values::fix) variables never move, so they get no block and
+    /// are left out of the keys of the result.
     pub fn linearize(&self, values: &Values) -> LinearFactor {
         // Compute residual and jacobian
         let DiffResult { value: r, diff: a } = self.residual.residual_jacobian(values, &self.keys);
 
         // Whiten residual and jacobian
-        let r = self.noise.whiten_vec(r);
-        let a = self.noise.whiten_mat(a);
+        let (r, a) = self.noise.whiten_linearized(r, a);
 
         // Weight according to robust cost
         let norm2 = r.norm_squared();
@@ -94,25 +178,121 @@ impl Factor {
         let a = weight * a;
         let b = -weight * r;
 
-        // Turn A into a MatrixBlock
-        let idx = self
+        // Turn A into a MatrixBlock, dropping the columns of fixed variables
+        let mut keys = Vec::with_capacity(self.keys.len());
+        let mut idx = Vec::with_capacity(self.keys.len());
+        let mut cols = Vec::with_capacity(a.ncols());
+        let mut col = 0;
+        for k in self.keys.iter() {
+            let dim = values.get_raw(*k).expect("Key missing in values").dim();
+            if !values.is_fixed(*k) {
+                keys.push(*k);
+                idx.push(cols.len());
+                cols.extend(col..col + dim);
+            }
+            col += dim;
+        }
+        let a = if cols.len() == a.ncols() {
+            a
+        } else {
+            a.select_columns(cols.iter())
+        };
+        let a = MatrixBlock::new(a, idx);
+
+        LinearFactor::new(keys, a, b)
+    }
+
+    /// Linearize the factor at one point, expressed about another.
+    ///
+    /// The residual and Jacobian are evaluated at `linearization_values`, but
+    /// the resulting [LinearFactor] is a function of the update applied to
+    /// `base_values`. To first order, the residual at $x_b \oplus \Delta$ is
+    /// $r(x_l) + J (x_b \ominus x_l + \Delta)$, so $b$ is shifted by
+    /// $-J (x_b \ominus x_l)$. When both points coincide, this is identical to
+    /// [linearize](Factor::linearize).
+    pub fn linearize_at(
+        &self,
+        linearization_values: &Values,
+        base_values: &Values,
+    ) -> LinearFactor {
+        let linear = self.linearize(linearization_values);
+
+        let offset = linear
             .keys
             .iter()
-            .scan(0, |sum, k| {
-                let out = Some(*sum);
-                *sum += values.get_raw(*k).expect("Key missing in values").dim();
-                out
+            .flat_map(|k| {
+                let base = base_values.get_raw(*k).expect("Key missing in values");
+                let lin = linearization_values
+                    .get_raw(*k)
+                    .expect("Key missing in values");
+                base.ominus_dyn(lin).iter().copied().collect::<Vec<_>>()
             })
             .collect::<Vec<_>>();
-        let a = MatrixBlock::new(a, idx);
+        let offset = VectorX::from_vec(offset);
 
-        LinearFactor::new(self.keys.clone(), a, b)
+        let b = &linear.b - linear.a.mat() * offset;
+        LinearFactor::new(linear.keys, linear.a, b)
+    }
+
+    /// Compute the rank of the Jacobian block of each variable.
+    ///
+    /// Returned in the same order as [Factor::keys]. A rank below the dimension
+    /// of a variable means this measurement alone leaves some of its directions
+    /// unconstrained, such as a bearing measurement of a 3D landmark. The
+    /// whitened Jacobian is used without any robust weighting, so the result
+    /// doesn't depend on how large the residual currently is.
+    pub fn jacobian_ranks(&self, values: &Values) -> Vec<usize> {
+        let DiffResult { diff: a, .. } = self.residual.residual_jacobian(values, &self.keys);
+        let a = self.noise.whiten_mat(a);
+
+        let mut col = 0;
+        self.keys
+            .iter()
+            .map(|k| {
+                let dim = values.get_raw(*k).expect("Key missing in values").dim();
+                let sv = a.columns(col, dim).clone_owned().singular_values();
+                col += dim;
+                let tol = sv.max() * a.nrows().max(dim) as dtype * dtype::EPSILON;
+                sv.iter().filter(|s| **s > tol).count()
+            })
+            .collect()
     }
 
     /// Get the keys of the factor.
     pub fn keys(&self) -> &[Key] {
         &self.keys
     }
+
+    /// Get the residual of the factor.
+    ///
+    /// Can be downcast to recover the concrete type, ie
+    /// `factor.residual().downcast_ref::<PriorResidual<SE2>>()`.
+    pub fn residual(&self) -> &dyn Residual {
+        self.residual.as_ref()
+    }
+
+    /// Get the noise model of the factor.
+    pub fn noise(&self) -> &dyn NoiseModel {
+        self.noise.as_ref()
+    }
+
+    /// Get the category of the factor.
+    pub fn category(&self) -> Category {
+        self.category
+    }
+
+    /// Set the category of the factor.
+    pub fn set_category(&mut self, category: Category) {
+        self.category = category;
+    }
+
+    /// Replace the robust kernel of the factor.
+    pub fn set_robust<C>(&mut self, robust: C)
+    where
+        C: 'static + RobustCost,
+    {
+        self.robust = Box::new(robust);
+    }
 }
 
 impl fmt::Debug for Factor {
@@ -182,12 +362,51 @@ impl<KF: KeyFormatter> fmt::Debug for FactorFormatter<'_, KF> {
 /// Builder for a factor.
 ///
 /// If the noise model or robust kernel aren't set, they default to [UnitNoise]
-/// and [L2] respectively.
+/// and [L2] respectively. If the residual type is only known at runtime, use
+/// [Factor::from_boxed] instead.
+///
+/// The output dimension of the residual is carried in `DIM_OUT`, and
+/// [noise](FactorBuilder::noise) only accepts a noise model of that same
+/// dimension. A mismatch is thus caught when building the graph, rather than
+/// as a shape panic during optimization. For example, a 3-dimensional noise
+/// model can't be used with a 6-dimensional [SE3](crate::variables::SE3)
+/// prior,
+/// ```compile_fail
+/// # use factrs::{
+///    assign_symbols,
+///    containers::FactorBuilder,
+///    noise::GaussianNoise,
+///    residuals::PriorResidual,
+///    traits::*,
+///    variables::SE3,
+/// };
+/// # assign_symbols!(X: SE3);
+/// let residual = PriorResidual::new(SE3::identity());
+/// let noise = GaussianNoise::<3>::from_scalar_sigma(0.1);
+/// // error: expected `Const<6>`, found `Const<3>`
+/// let factor = FactorBuilder::new1(residual, X(0)).noise(noise).build();
+/// ```
+/// while the matching dimension compiles just fine,
+/// ```
+/// # use factrs::{
+///    assign_symbols,
+///    containers::FactorBuilder,
+///    noise::GaussianNoise,
+///    residuals::PriorResidual,
+///    traits::*,
+///    variables::SE3,
+/// };
+/// # assign_symbols!(X: SE3);
+/// let residual = PriorResidual::new(SE3::identity());
+/// let noise = GaussianNoise::<6>::from_scalar_sigma(0.1);
+/// let factor = FactorBuilder::new1(residual, X(0)).noise(noise).build();
+/// ```
 pub struct FactorBuilder<const DIM_OUT: usize> {
     keys: Vec<Key>,
     residual: Box<dyn Residual>,
     noise: Option<Box<dyn NoiseModel>>,
     robust: Option<Box<dyn RobustCost>>,
+    category: Category,
 }
 
 macro_rules! impl_new_builder {
@@ -206,6 +425,7 @@ macro_rules! impl_new_builder {
                     residual: Box::new(residual),
                     noise: None,
                     robust: None,
+                    category: Category::default(),
                 }
             }
 
@@ -222,6 +442,7 @@ macro_rules! impl_new_builder {
                     residual: Box::new(residual),
                     noise: None,
                     robust: None,
+                    category: Category::default(),
                 }
             }
         }
@@ -248,6 +469,9 @@ impl<const DIM_OUT: usize> FactorBuilder<DIM_OUT> {
     }
 
     /// Add a robust kernel to the factor.
+    ///
+    /// The kernel weights the whole factor by the norm of its whitened
+    /// residual, see [Factor] for details.
     pub fn robust<C>(mut self, robust: C) -> Self
     where
         C: 'static + RobustCost,
@@ -256,11 +480,26 @@ impl<const DIM_OUT: usize> FactorBuilder<DIM_OUT> {
         self
     }
 
+    /// Tag the factor with a [Category].
+    pub fn category(mut self, category: Category) -> Self {
+        self.category = category;
+        self
+    }
+
     /// Build the factor.
     pub fn build(self) -> Factor
     where
         UnitNoise<DIM_OUT>: NoiseModel,
     {
+        // Only reachable with a hand-written Residual whose dim_out disagrees with
+        // its DimOut, everything else is checked at compile time
+        assert_eq!(
+            self.residual.dim_out(),
+            DIM_OUT,
+            "Residual output dimension {} does not match noise model dimension {}",
+            self.residual.dim_out(),
+            DIM_OUT
+        );
         let noise = self.noise.unwrap_or_else(|| Box::new(UnitNoise::<DIM_OUT>));
         let robust = self.robust.unwrap_or_else(|| Box::new(L2));
         Factor {
@@ -268,6 +507,7 @@ impl<const DIM_OUT: usize> FactorBuilder<DIM_OUT> {
             residual: self.residual,
             noise,
             robust,
+            category: self.category,
         }
     }
 }
@@ -276,15 +516,15 @@ impl<const DIM_OUT: usize> FactorBuilder<DIM_OUT> {
 mod tests {
 
     use factrs_proc::fac;
-    use matrixcompare::assert_matrix_eq;
+    use matrixcompare::{assert_matrix_eq, assert_scalar_eq};
 
     use super::*;
     use crate::{
         assign_symbols,
         linalg::{Diff, NumericalDiff},
         noise::GaussianNoise,
-        residuals::{BetweenResidual, PriorResidual},
-        robust::GemanMcClure,
+        residuals::{BetweenResidual, MaskedPriorResidual, PriorResidual},
+        robust::{GemanMcClure, Huber, L1},
         variables::{Variable, VectorVar3},
     };
 
@@ -330,6 +570,78 @@ mod tests {
         assert_matrix_eq!(grad_got, grad_num, comp = abs, tol = TOL);
     }
 
+    #[test]
+    fn robust_joint_norm() {
+        let prior = VectorVar3::new(1.0, 2.0, 3.0);
+        let noise = GaussianNoise::<3>::from_diag_sigmas(1e-1, 2e-1, 3e-1);
+        let robust = Huber::default();
+        let factor: Factor = fac![PriorResidual::new(prior), X(0), noise, robust.clone()];
+
+        let mut values = Values::new();
+        values.insert_unchecked(X(0), VectorVar3::identity());
+
+        // Each whitened element is 10, so the squared Mahalanobis norm is 300
+        let weight = factor.robust_weight(&values);
+        assert_scalar_eq!(weight, robust.weight(300.0), comp = abs, tol = TOL);
+        assert!((weight - robust.weight(100.0)).abs() > TOL);
+
+        // And the same single weight scales every row of the linearization
+        let linear = factor.linearize(&values);
+        let expected = VectorX::from_element(3, 10.0 * weight.sqrt());
+        assert_matrix_eq!(linear.b.abs(), expected, comp = abs, tol = TOL);
+    }
+
+    #[test]
+    fn robust_weight_normalized() {
+        let prior = VectorVar3::new(0.0, 0.0, 0.0);
+        let noise = GaussianNoise::<3>::from_scalar_sigma(1.0);
+        let factor: Factor = fac![PriorResidual::new(prior), X(0), noise, L1];
+
+        // The raw L1 weight here is 10, but it's clamped to 1
+        let mut values = Values::new();
+        values.insert_unchecked(X(0), VectorVar3::new(0.1, 0.0, 0.0));
+        assert_scalar_eq!(factor.robust_weight(&values), 1.0, comp = abs, tol = TOL);
+
+        // And far outliers still get small weights
+        values.insert_unchecked(X(0), VectorVar3::new(100.0, 0.0, 0.0));
+        assert_scalar_eq!(factor.robust_weight(&values), 0.01, comp = abs, tol = TOL);
+    }
+
+    #[test]
+    fn from_boxed() {
+        let prior = VectorVar3::new(1.0, 2.0, 3.0);
+        let noise = GaussianNoise::<3>::from_diag_sigmas(1e-1, 2e-1, 3e-1);
+        let robust = GemanMcClure::default();
+
+        let residual: Box<dyn Residual> = Box::new(PriorResidual::new(prior.clone()));
+        let factor = Factor::from_boxed(
+            residual,
+            vec![X(0).into()],
+            Box::new(noise.clone()),
+            Box::new(robust.clone()),
+        );
+        let expected: Factor = fac![PriorResidual::new(prior), X(0), noise, robust];
+
+        let mut values = Values::new();
+        values.insert_unchecked(X(0), VectorVar3::identity());
+
+        assert_eq!(factor.keys(), expected.keys());
+        assert_eq!(factor.dim_out(), 3);
+        assert_eq!(factor.error(&values), expected.error(&values));
+    }
+
+    #[test]
+    #[should_panic(expected = "does not match noise model dimension 2")]
+    fn from_boxed_mismatch() {
+        let residual: Box<dyn Residual> = Box::new(PriorResidual::new(VectorVar3::identity()));
+        Factor::from_boxed(
+            residual,
+            vec![X(0).into()],
+            Box::new(GaussianNoise::<2>::from_scalar_sigma(0.1)),
+            Box::new(GemanMcClure::default()),
+        );
+    }
+
     #[test]
     fn linearize_block() {
         let bet = VectorVar3::new(1.0, 2.0, 3.0);
@@ -362,4 +674,25 @@ mod tests {
             comp = float
         );
     }
+
+    #[test]
+    fn jacobian_ranks() {
+        let mut values = Values::new();
+        values.insert_unchecked(X(0), VectorVar3::new(0.1, 0.2, 0.3));
+        values.insert_unchecked(X(1), VectorVar3::new(1.0, 2.0, 3.0));
+
+        let bet = BetweenResidual::new(VectorVar3::new(1.0, 2.0, 3.0));
+        let factor: Factor = fac![bet, (X(0), X(1))];
+        assert_eq!(factor.jacobian_ranks(&values), vec![3, 3]);
+
+        // Only measures two of the three dimensions
+        let masked = MaskedPriorResidual::new(VectorVar3::identity(), &[true, false, true]);
+        let factor = Factor::from_boxed(
+            Box::new(masked),
+            vec![X(0).into()],
+            Box::new(UnitNoise::<2>),
+            Box::new(L2),
+        );
+        assert_eq!(factor.jacobian_ranks(&values), vec![2]);
+    }
 }
@@ -1,17 +1,33 @@
 use std::{
+    collections::HashSet,
     fmt::{Debug, Write},
+    hash::BuildHasher,
     marker::PhantomData,
+    sync::OnceLock,
 };
 
 use faer::sparse::SymbolicSparseColMat;
+use foldhash::HashMap;
 use pad_adapter::PadAdapter;
 
-use super::{DefaultSymbolHandler, Idx, KeyFormatter, Values, ValuesOrder};
+use super::{
+    DefaultSymbolHandler, Idx, Key, KeyFormatter, LinearizationCache, Symbol, TypedSymbol, Values,
+    ValuesOrder,
+};
 // Once "debug_closure_helpers" is stabilized, we won't need this anymore
 // Need custom debug to handle pretty key printing at the moment
 // Pad adapter helps with the pretty printing
 use crate::containers::factor::FactorFormatter;
-use crate::{containers::Factor, dtype, linear::LinearGraph};
+use crate::{
+    containers::{Category, Factor},
+    dtype,
+    linalg::VectorX,
+    linear::LinearGraph,
+    noise::NoiseModel,
+    residuals::{BetweenResidual, MarginalResidual, Residual},
+    robust::{RobustCost, L2},
+    variables::VariableDtype,
+};
 
 /// Structure to represent a nonlinear factor graph
 ///
@@ -23,6 +39,14 @@ use crate::{containers::Factor, dtype, linear::LinearGraph};
 /// optimization it will be iteratively linearized about a set of variables and
 /// solved iteratively.
 ///
+/// Variables are referenced purely by their [Key](crate::containers::Key), so
+/// a single variable, such as a sensor extrinsic calibration, can be shared
+/// across any number of factors simply by reusing its key. Each factor
+/// contributes its own rows to the Jacobian, and these are summed into the
+/// same columns when forming the normal equations. The sparsity pattern is
+/// computed once per optimization, so even a variable connected to thousands
+/// of factors (a dense row/column in $A^\top A$) doesn't slow down assembly.
+///
 /// ```
 /// # use factrs::{
 ///    assign_symbols,
@@ -37,10 +61,100 @@ use crate::{containers::Factor, dtype, linear::LinearGraph};
 /// let mut graph = Graph::new();
 /// graph.add_factor(factor);
 /// ```
+///
+/// Each factor is given a [FactorId] when added, which can later be used to
+/// [remove](Graph::remove_factor) or [replace](Graph::replace_factor) it, such
+/// as when dropping old factors in a sliding window.
 #[derive(Default, Clone)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "GraphData"))]
 pub struct Graph {
     factors: Vec<Factor>,
+    // Parallel to factors, always sorted as ids are handed out in order
+    ids: Vec<FactorId>,
+    next_id: usize,
+    // Built on the first connectivity query, reset whenever factors change
+    #[cfg_attr(feature = "serde", serde(skip))]
+    adjacency: OnceLock<HashMap<Key, Vec<FactorId>>>,
+    // Bumped whenever a factor is changed in place, so a LinearizationCache
+    // knows its entries are stale
+    #[cfg_attr(feature = "serde", serde(skip))]
+    revision: usize,
+}
+
+// Deserialized form of a Graph, checked before it's converted. Graphs
+// serialized before factors had ids have neither ids nor next_id, so they're
+// handed out in order.
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct GraphData {
+    factors: Vec<Factor>,
+    #[serde(default)]
+    ids: Option<Vec<FactorId>>,
+    #[serde(default)]
+    next_id: usize,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<GraphData> for Graph {
+    type Error = String;
+
+    fn try_from(data: GraphData) -> Result<Self, Self::Error> {
+        let GraphData {
+            factors,
+            ids,
+            next_id,
+        } = data;
+        let Some(ids) = ids else {
+            return Ok(Self {
+                ids: (0..factors.len()).map(FactorId).collect(),
+                next_id: factors.len(),
+                factors,
+                adjacency: OnceLock::new(),
+                revision: 0,
+            });
+        };
+
+        if ids.len() != factors.len() {
+            return Err(format!(
+                "graph has {} factors but {} ids",
+                factors.len(),
+                ids.len()
+            ));
+        }
+        if ids.windows(2).any(|w| w[0] >= w[1]) {
+            return Err("graph factor ids must be strictly increasing".to_string());
+        }
+        // New ids must never reuse an existing one
+        let next_id = ids.last().map_or(next_id, |id| next_id.max(id.0 + 1));
+
+        Ok(Self {
+            factors,
+            ids,
+            next_id,
+            adjacency: OnceLock::new(),
+            revision: 0,
+        })
+    }
+}
+
+/// Stable handle to a factor in a [Graph]
+///
+/// Returned by [Graph::add_factor], and stays valid as other factors are
+/// removed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FactorId(usize);
+
+/// How [Graph::subgraph] handles factors straddling the boundary of the keys
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Boundary {
+    /// Drop them, keeping only the factors entirely inside
+    #[default]
+    Exclude,
+    /// Keep them, with the variables outside copied in and
+    /// [fixed](Values::fix)
+    Fix,
 }
 
 impl Graph {
@@ -51,11 +165,236 @@ impl Graph {
     pub fn with_capacity(capacity: usize) -> Self {
         Self {
             factors: Vec::with_capacity(capacity),
+            ids: Vec::with_capacity(capacity),
+            next_id: 0,
+            adjacency: OnceLock::new(),
+            revision: 0,
         }
     }
 
-    pub fn add_factor(&mut self, factor: Factor) {
+    pub fn add_factor(&mut self, factor: Factor) -> FactorId {
+        let id = FactorId(self.next_id);
+        self.next_id += 1;
+        self.adjacency.take();
         self.factors.push(factor);
+        self.ids.push(id);
+        id
+    }
+
+    /// Add a loop closure between two poses.
+    ///
+    /// `relative` is the pose of `key_b` expressed in the frame of `key_a`, ie
+    /// ${}_a T_b = T_a^{-1} T_b$, as with [BetweenResidual::from_relative].
+    /// The factor is tagged as [Category::LoopClosure], so it can later be
+    /// robustified along with any other loop closures using
+    /// [robustify_category](Graph::robustify_category). Panics if the
+    /// dimension of the noise model doesn't match the pose.
+    /// ```
+    /// # use factrs::{
+    ///    assign_symbols,
+    ///    containers::Graph,
+    ///    noise::GaussianNoise,
+    ///    variables::SE2,
+    /// };
+    /// # assign_symbols!(X: SE2);
+    /// let mut graph = Graph::new();
+    /// let noise = GaussianNoise::<3>::from_scalar_sigma(0.1);
+    /// graph.add_loop_closure(X(10), X(0), SE2::new(0.0, 0.5, 0.0), noise);
+    /// ```
+    pub fn add_loop_closure<P, K1, K2, N>(
+        &mut self,
+        key_a: K1,
+        key_b: K2,
+        relative: P,
+        noise: N,
+    ) -> FactorId
+    where
+        P: VariableDtype + 'static,
+        BetweenResidual<P>: Residual,
+        K1: TypedSymbol<P>,
+        K2: TypedSymbol<P>,
+        N: 'static + NoiseModel,
+    {
+        let mut factor = Factor::from_boxed(
+            Box::new(BetweenResidual::from_relative(relative)),
+            vec![key_a.into(), key_b.into()],
+            Box::new(noise),
+            Box::new(L2),
+        );
+        factor.set_category(Category::LoopClosure);
+        self.add_factor(factor)
+    }
+
+    /// Iterate over the [FactorIds](FactorId) of the factors in the graph, in
+    /// the same order as [iter](Graph::iter)
+    pub fn ids(&self) -> impl Iterator<Item = FactorId> + '_ {
+        self.ids.iter().copied()
+    }
+
+    /// Get a factor by its [FactorId], if it's still in the graph
+    pub fn get(&self, id: FactorId) -> Option<&Factor> {
+        self.position(id).map(|i| &self.factors[i])
+    }
+
+    /// Remove a factor, returning it if it was still in the graph
+    ///
+    /// The remaining factors keep their order and ids.
+    pub fn remove_factor(&mut self, id: FactorId) -> Option<Factor> {
+        let i = self.position(id)?;
+        self.adjacency.take();
+        self.ids.remove(i);
+        Some(self.factors.remove(i))
+    }
+
+    /// Replace a factor in place, returning the old one
+    ///
+    /// The new factor keeps the same id and position. If `id` is no longer in
+    /// the graph, nothing is changed and None is returned.
+    pub fn replace_factor(&mut self, id: FactorId, factor: Factor) -> Option<Factor> {
+        let i = self.position(id)?;
+        self.adjacency.take();
+        self.revision += 1;
+        Some(std::mem::replace(&mut self.factors[i], factor))
+    }
+
+    /// Get the factors connected to a key
+    ///
+    /// Returned in the same order as [ids](Graph::ids), and empty if the key
+    /// isn't in the graph. The first query builds an index of the graph, which
+    /// is kept until the factors are next changed, so repeated queries are
+    /// cheap.
+    pub fn factors_for_key(&self, key: impl Symbol) -> Vec<FactorId> {
+        self.adjacency()
+            .get(&key.into())
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Get the keys sharing a factor with a key
+    ///
+    /// Each neighbor is listed once, in the order it's first seen in the
+    /// factors of [factors_for_key](Graph::factors_for_key), and the key
+    /// itself is never included.
+    pub fn neighbors(&self, key: impl Symbol) -> Vec<Key> {
+        let key = key.into();
+        let mut neighbors = Vec::new();
+        for id in self.adjacency().get(&key).into_iter().flatten() {
+            let factor = &self.factors[self.position(*id).expect("Stale adjacency")];
+            for k in factor.keys() {
+                if *k != key && !neighbors.contains(k) {
+                    neighbors.push(*k);
+                }
+            }
+        }
+        neighbors
+    }
+
+    /// Extract the factors and values involving a set of keys
+    ///
+    /// Factors whose keys are all in `keys` are copied into the new graph,
+    /// along with the variables for `keys` from `values`, keeping whether they
+    /// are [fixed](Values::fix). Factors straddling the boundary, ie touching
+    /// both keys inside and outside the set, are handled according to
+    /// [Boundary]. This is useful for optimizing a local window of a larger
+    /// problem independently, such as the most recent poses of a trajectory.
+    /// To instead summarize everything outside as a prior, see
+    /// [marginalize_subgraph](crate::optimizers::marginalize_subgraph).
+    /// ```
+    /// # use factrs::{
+    ///    assign_symbols,
+    ///    containers::{Boundary, Graph, Key, Values},
+    ///    fac,
+    ///    residuals::BetweenResidual,
+    ///    variables::VectorVar1,
+    /// };
+    /// # use std::collections::HashSet;
+    /// # assign_symbols!(X: VectorVar1);
+    /// let mut graph = Graph::new();
+    /// let mut values = Values::new();
+    /// for i in 0..5 {
+    ///     values.insert(X(i), VectorVar1::new(0.0));
+    ///     let res = BetweenResidual::new(VectorVar1::new(1.0));
+    ///     graph.add_factor(fac![res, (X(i), X(i + 1))]);
+    /// }
+    /// values.insert(X(5), VectorVar1::new(0.0));
+    ///
+    /// let window: HashSet<Key> = (3..6).map(|i| X(i).into()).collect();
+    /// let (local, local_values) = graph.subgraph(&window, &values, Boundary::Exclude);
+    /// assert_eq!(local.len(), 2);
+    /// assert_eq!(local_values.len(), 3);
+    /// ```
+    pub fn subgraph<S: BuildHasher>(
+        &self,
+        keys: &HashSet<Key, S>,
+        values: &Values,
+        boundary: Boundary,
+    ) -> (Graph, Values) {
+        let mut graph = Graph::new();
+        let mut outside = Vec::new();
+        for factor in self.factors.iter() {
+            let num_inside = factor.keys().iter().filter(|k| keys.contains(*k)).count();
+            let keep = num_inside == factor.keys().len()
+                || (num_inside > 0 && boundary != Boundary::Exclude);
+            if keep {
+                for k in factor.keys().iter().filter(|k| !keys.contains(*k)) {
+                    if !outside.contains(k) {
+                        outside.push(*k);
+                    }
+                }
+                graph.add_factor(factor.clone());
+            }
+        }
+
+        let mut sub = Values::new();
+        for key in keys.iter() {
+            if let Some(value) = values.get_raw(*key) {
+                sub.entry(*key).or_insert(value.clone_box());
+                if values.is_fixed(*key) {
+                    sub.fix(*key);
+                }
+            }
+        }
+        for key in outside.iter() {
+            let value = values.get_raw(*key).expect("Key missing in values");
+            sub.entry(*key).or_insert(value.clone_box());
+            sub.fix(*key);
+        }
+
+        (graph, sub)
+    }
+
+    fn adjacency(&self) -> &HashMap<Key, Vec<FactorId>> {
+        self.adjacency.get_or_init(|| {
+            let mut adjacency: HashMap<Key, Vec<FactorId>> = HashMap::default();
+            for (id, factor) in self.ids.iter().zip(self.factors.iter()) {
+                for key in factor.keys() {
+                    let ids = adjacency.entry(*key).or_default();
+                    // Guard against a factor listing the same key twice
+                    if ids.last() != Some(id) {
+                        ids.push(*id);
+                    }
+                }
+            }
+            adjacency
+        })
+    }
+
+    /// Add every factor from `other` to the end of this graph
+    ///
+    /// Factors are never deduplicated, so any sharing the same keys, or even
+    /// being identical, are all kept. Each is given a new [FactorId] as if it
+    /// were added with [add_factor](Graph::add_factor), so the ids from
+    /// `other` are no longer valid.
+    pub fn extend(&mut self, other: Graph) {
+        self.factors.reserve(other.len());
+        self.ids.reserve(other.len());
+        for factor in other {
+            self.add_factor(factor);
+        }
+    }
+
+    fn position(&self, id: FactorId) -> Option<usize> {
+        self.ids.binary_search(&id).ok()
     }
 
     pub fn len(&self) -> usize {
@@ -66,33 +405,257 @@ impl Graph {
         self.factors.is_empty()
     }
 
+    /// Iterate over the factors in the graph
+    pub fn iter(&self) -> std::slice::Iter<'_, Factor> {
+        self.factors.iter()
+    }
+
+    /// Apply a robust kernel to all factors of the given [Category].
+    ///
+    /// Replaces any kernel those factors already had, while factors of other
+    /// categories are left untouched. Useful in pose-graph SLAM to keep
+    /// odometry as L2 while robustifying only loop closures.
+    pub fn robustify_category<C>(&mut self, category: Category, robust: C)
+    where
+        C: 'static + RobustCost + Clone,
+    {
+        self.revision += 1;
+        self.factors
+            .iter_mut()
+            .filter(|f| f.category() == category)
+            .for_each(|f| f.set_robust(robust.clone()));
+    }
+
+    /// Check if two graphs have the same structure
+    ///
+    /// That is, the same keys and residual dimension for every factor, in the
+    /// same order. Graphs with the same structure share a sparsity pattern.
+    pub fn same_structure(&self, other: &Graph) -> bool {
+        self.len() == other.len()
+            && self
+                .factors
+                .iter()
+                .zip(other.factors.iter())
+                .all(|(a, b)| a.keys() == b.keys() && a.dim_out() == b.dim_out())
+    }
+
+    /// Total dimension of all residuals in the graph.
+    pub fn dim_out(&self) -> usize {
+        self.factors.iter().map(|f| f.dim_out()).sum()
+    }
+
+    /// Total cost of the graph, the sum of every [Factor::error].
     pub fn error(&self, values: &Values) -> dtype {
         self.factors.iter().map(|f| f.error(values)).sum()
     }
 
+    /// Error of each factor, in the same order as [ids](Graph::ids).
+    ///
+    /// Without a robust kernel, each error is half the squared Mahalanobis
+    /// distance of the residual, so twice it can be checked against a
+    /// chi-squared distribution with [Factor::dim_out] degrees of freedom to
+    /// flag outliers.
+    pub fn errors(&self, values: &Values) -> Vec<dtype> {
+        self.factors.iter().map(|f| f.error(values)).collect()
+    }
+
+    /// Find the variables nothing anchors in place.
+    ///
+    /// Variables are grouped into connected components through the factors,
+    /// and those in a component with neither a prior nor a
+    /// [fixed](Values::fix) variable are returned, sorted by key. Such a
+    /// component can drift freely as a whole, ie its gauge freedom isn't
+    /// fixed, leaving the linear system singular. Both unary factors and the
+    /// [MarginalResidual] priors left by
+    /// [marginalize](crate::optimizers::marginalize) count as priors.
+    ///
+    /// This is a cheap heuristic. A prior constraining only part of a
+    /// variable, such as a position-only prior on a pose, is treated as fully
+    /// anchoring its component.
+    pub fn unanchored(&self, values: &Values) -> Vec<Key> {
+        let keys = values.iter().map(|(key, _)| *key).collect::<Vec<_>>();
+        let index = keys
+            .iter()
+            .enumerate()
+            .map(|(i, key)| (*key, i))
+            .collect::<HashMap<_, _>>();
+
+        // Union-find over the variables
+        fn find(parent: &mut [usize], mut i: usize) -> usize {
+            while parent[i] != i {
+                parent[i] = parent[parent[i]];
+                i = parent[i];
+            }
+            i
+        }
+        let mut parent = (0..keys.len()).collect::<Vec<_>>();
+        let mut anchored = vec![false; keys.len()];
+        for factor in self.factors.iter() {
+            let idx = factor
+                .keys()
+                .iter()
+                .filter_map(|key| index.get(key).copied())
+                .collect::<Vec<_>>();
+            let Some(&first) = idx.first() else {
+                continue;
+            };
+            let root = find(&mut parent, first);
+            for &i in idx.iter().skip(1) {
+                let other = find(&mut parent, i);
+                parent[other] = root;
+                anchored[root] |= anchored[other];
+            }
+            if factor.keys().len() == 1 || factor.residual().is::<MarginalResidual>() {
+                anchored[root] = true;
+            }
+        }
+        for (i, key) in keys.iter().enumerate() {
+            if values.is_fixed(*key) {
+                let root = find(&mut parent, i);
+                anchored[root] = true;
+            }
+        }
+
+        let mut out = (0..keys.len())
+            .filter(|&i| !anchored[find(&mut parent, i)])
+            .map(|i| keys[i])
+            .collect::<Vec<_>>();
+        out.sort_by_key(|key| key.0);
+        out
+    }
+
     pub fn linearize(&self, values: &Values) -> LinearGraph {
         let factors = self.factors.iter().map(|f| f.linearize(values)).collect();
         LinearGraph::from_vec(factors)
     }
 
+    /// Compute the rank of every factor's Jacobian blocks.
+    ///
+    /// See [Factor::jacobian_ranks]. Handy for tracking down measurements that
+    /// under-constrain their variables, and so observability issues.
+    pub fn jacobian_ranks(&self, values: &Values) -> Vec<(FactorId, Vec<usize>)> {
+        self.ids()
+            .zip(self.factors.iter())
+            .map(|(id, f)| (id, f.jacobian_ranks(values)))
+            .collect()
+    }
+
+    /// Compute the robust weight of every factor.
+    ///
+    /// See [Factor::robust_weight]. Evaluated at the optimized values, this
+    /// reports the final weights of the iteratively reweighted least squares,
+    /// so factors with a weight near 0 are the measurements a robust kernel
+    /// rejected, such as bad loop closures.
+    pub fn robust_weights(&self, values: &Values) -> HashMap<FactorId, dtype> {
+        self.ids()
+            .zip(self.factors.iter())
+            .map(|(id, f)| (id, f.robust_weight(values)))
+            .collect()
+    }
+
+    /// Compute the gradient of the total cost for each variable.
+    ///
+    /// This is $J^\top r$ (whitened and robustly weighted, as during
+    /// linearization), split up by variable and keyed by [Key]. Its negative
+    /// is the steepest descent direction, and at a local minimum every entry
+    /// should be near zero, making it handy to check first-order optimality.
+    pub fn gradient(&self, values: &Values) -> HashMap<Key, VectorX> {
+        let mut gradient = HashMap::<Key, VectorX>::default();
+        for f in self.factors.iter() {
+            let linear = f.linearize(values);
+            for (i, key) in linear.keys.iter().enumerate() {
+                // Linearization stores b = -r
+                let g = -(linear.a.get_block(i).transpose() * &linear.b);
+                *gradient
+                    .entry(*key)
+                    .or_insert_with(|| VectorX::zeros(g.len())) += g;
+            }
+        }
+        gradient
+    }
+
+    /// Compute the Hessian-vector product $J^\top J v$.
+    ///
+    /// `v` is a tangent space vector laid out according to `order`, as is the
+    /// result. Rather than assembling $J^\top J$, each factor is linearized in
+    /// turn and contributes $J_i^\top (J_i v)$, so memory stays proportional
+    /// to the largest factor. This is the main building block of matrix-free
+    /// iterative solvers, such as conjugate gradient.
+    pub fn hessian_vec_product(
+        &self,
+        values: &Values,
+        v: &VectorX,
+        order: &ValuesOrder,
+    ) -> VectorX {
+        let mut out = VectorX::zeros(v.len());
+        for f in self.factors.iter() {
+            let linear = f.linearize(values);
+            // Fixed variables aren't in the order and are skipped
+            let indices = linear
+                .keys
+                .iter()
+                .enumerate()
+                .filter_map(|(i, key)| order.get(*key).map(|idx| (i, idx)))
+                .collect::<Vec<_>>();
+
+            let jv: VectorX = indices
+                .iter()
+                .map(|(i, Idx { idx, dim })| linear.a.mul(*i, v.rows(*idx, *dim)))
+                .fold(VectorX::zeros(linear.b.len()), |acc, x| acc + x);
+            for (i, Idx { idx, dim }) in indices.iter() {
+                let mut block = out.rows_mut(*idx, *dim);
+                block += linear.a.get_block(*i).transpose() * &jv;
+            }
+        }
+        out
+    }
+
+    /// Linearize the graph at one point, expressed about another.
+    ///
+    /// See [Factor::linearize_at] for details. The resulting linear graph is
+    /// solved for an update to `base_values`.
+    pub fn linearize_at(&self, linearization_values: &Values, base_values: &Values) -> LinearGraph {
+        let factors = self
+            .factors
+            .iter()
+            .map(|f| f.linearize_at(linearization_values, base_values))
+            .collect();
+        LinearGraph::from_vec(factors)
+    }
+
+    /// Linearize the graph, reusing cached linearizations where possible.
+    ///
+    /// Identical to [linearize](Graph::linearize), but only relinearizes
+    /// factors whose variables changed, see [LinearizationCache].
+    pub fn linearize_cached(&self, values: &Values, cache: &mut LinearizationCache) -> LinearGraph {
+        cache.sync(self.revision, &self.ids);
+        let factors = self
+            .ids
+            .iter()
+            .zip(self.factors.iter())
+            .map(|(id, f)| cache.linearize(*id, f, values))
+            .collect();
+        LinearGraph::from_vec(factors)
+    }
+
     pub fn sparsity_pattern(&self, order: ValuesOrder) -> GraphOrder {
-        let total_rows = self.factors.iter().map(|f| f.dim_out()).sum();
+        let total_rows = self.dim_out();
         let total_columns = order.dim();
 
         let mut indices = Vec::<(usize, usize)>::new();
 
         let _ = self.factors.iter().fold(0, |row, f| {
-            f.keys().iter().for_each(|key| {
-                (0..f.dim_out()).for_each(|i| {
-                    let Idx {
-                        idx: col,
-                        dim: col_dim,
-                    } = order.get(*key).expect("Key missing in values");
-                    (0..*col_dim).for_each(|j| {
-                        indices.push((row + i, col + j));
+            // Fixed variables aren't in the order, so they get no columns
+            f.keys()
+                .iter()
+                .filter_map(|key| order.get(*key))
+                .for_each(|col| {
+                    (0..f.dim_out()).for_each(|i| {
+                        (0..col.dim).for_each(|j| {
+                            indices.push((row + i, col.idx + j));
+                        });
                     });
                 });
-            });
             row + f.dim_out()
         });
 
@@ -107,6 +670,15 @@ impl Graph {
     }
 }
 
+impl IntoIterator for Graph {
+    type Item = Factor;
+    type IntoIter = std::vec::IntoIter<Factor>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.factors.into_iter()
+    }
+}
+
 impl Debug for Graph {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         GraphFormatter::<DefaultSymbolHandler>::new(self).fmt(f)
@@ -163,3 +735,426 @@ pub struct GraphOrder {
     // Contains the order of values to put into the sparsity pattern
     pub sparsity_order: faer::sparse::ValuesOrder<usize>,
 }
+
+#[cfg(test)]
+mod tests {
+    use faer_ext::IntoNalgebra;
+    use matrixcompare::assert_matrix_eq;
+
+    use super::*;
+    use crate::{
+        assign_symbols,
+        containers::FactorBuilder,
+        fac,
+        optimizers::{Dogleg, GaussNewton, LevenMarquardt, Optimizer},
+        residuals::{BetweenResidual, PriorResidual},
+        robust::Huber,
+        variables::{Variable, VectorVar1, SE2},
+    };
+
+    assign_symbols!(X: VectorVar1; Y: SE2);
+
+    #[test]
+    fn subgraph() {
+        // Chain X0 - ... - X5 with a prior on X0
+        let mut graph = Graph::new();
+        let mut values = Values::new();
+        graph.add_factor(fac![PriorResidual::new(VectorVar1::new(0.0)), X(0)]);
+        for i in 0..5 {
+            let bet = BetweenResidual::new(VectorVar1::new(1.0));
+            graph.add_factor(fac![bet, (X(i), X(i + 1))]);
+            values.insert(X(i), VectorVar1::new(i as dtype));
+        }
+        values.insert(X(5), VectorVar1::new(5.0));
+        values.fix(X(4));
+
+        let window = (2..5).map(|i| X(i).into()).collect::<HashSet<Key>>();
+
+        // Only X2 - X3 - X4 inside
+        let (sub, sub_values) = graph.subgraph(&window, &values, Boundary::Exclude);
+        assert_eq!(sub.len(), 2);
+        assert_eq!(sub_values.len(), 3);
+        assert!(sub_values.is_fixed(X(4)));
+        assert!(!sub_values.is_fixed(X(2)));
+
+        // Plus X1 - X2 and X4 - X5, with X1 and X5 fixed
+        let (sub, sub_values) = graph.subgraph(&window, &values, Boundary::Fix);
+        assert_eq!(sub.len(), 4);
+        assert_eq!(sub_values.len(), 5);
+        assert!(sub_values.is_fixed(X(1)));
+        assert!(sub_values.is_fixed(X(5)));
+    }
+
+    #[test]
+    fn subgraph_fixed_optimize() {
+        let mut graph = Graph::new();
+        let mut values = Values::new();
+        for i in 0..5 {
+            let bet = BetweenResidual::new(VectorVar1::new(1.0));
+            graph.add_factor(fac![bet, (X(i), X(i + 1))]);
+            values.insert(X(i), VectorVar1::new(i as dtype));
+        }
+        values.insert(X(5), VectorVar1::new(5.0));
+        values.fix(X(4));
+
+        // Window X2 - X3, held in place by X1 and X4
+        let window = (2..4).map(|i| X(i).into()).collect::<HashSet<Key>>();
+        let (sub, mut sub_values) = graph.subgraph(&window, &values, Boundary::Fix);
+        sub_values.insert(X(2), VectorVar1::new(1.5));
+        sub_values.insert(X(3), VectorVar1::new(3.5));
+
+        let check = |out: Values| {
+            for i in 1..5 {
+                let x: &VectorVar1 = out.get(X(i)).expect("Missing X");
+                assert!((x[0] - i as dtype).abs() < 1e-6, "X{i} is {}", x[0]);
+            }
+        };
+        let mut opt: LevenMarquardt = LevenMarquardt::new(sub.clone());
+        check(opt.optimize(sub_values.clone()).expect("LM failed"));
+        let mut opt: Dogleg = Dogleg::new(sub);
+        check(opt.optimize(sub_values).expect("Dogleg failed"));
+    }
+
+    #[test]
+    fn connectivity() {
+        // Chain X0 - X1 - X2 - X3 with a prior on X0
+        let mut graph = Graph::new();
+        let prior = graph.add_factor(fac![PriorResidual::new(VectorVar1::new(0.0)), X(0)]);
+        let ids = (0..3)
+            .map(|i| {
+                let bet = BetweenResidual::new(VectorVar1::new(1.0));
+                graph.add_factor(fac![bet, (X(i), X(i + 1))])
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(graph.factors_for_key(X(0)), vec![prior, ids[0]]);
+        assert_eq!(graph.factors_for_key(X(1)), vec![ids[0], ids[1]]);
+        assert_eq!(graph.factors_for_key(X(3)), vec![ids[2]]);
+        assert!(graph.factors_for_key(X(4)).is_empty());
+
+        let key = |k: X| -> Key { k.into() };
+        assert_eq!(graph.neighbors(X(0)), vec![key(X(1))]);
+        assert_eq!(graph.neighbors(X(2)), vec![key(X(1)), key(X(3))]);
+
+        // Changing the graph updates the index
+        graph.remove_factor(ids[1]);
+        assert_eq!(graph.factors_for_key(X(1)), vec![ids[0]]);
+        assert_eq!(graph.neighbors(X(2)), vec![key(X(3))]);
+        let bet = BetweenResidual::new(VectorVar1::new(3.0));
+        let id = graph.add_factor(fac![bet, (X(0), X(3))]);
+        assert_eq!(graph.factors_for_key(X(3)), vec![ids[2], id]);
+        assert_eq!(graph.neighbors(X(0)), vec![key(X(1)), key(X(3))]);
+    }
+
+    #[test]
+    #[allow(clippy::unnecessary_cast)]
+    fn loop_closure() {
+        use std::f64::consts::FRAC_PI_2;
+
+        use crate::noise::GaussianNoise;
+
+        // Drive around a 4x4 square, turning left at each corner, with odometry
+        // that overestimates both distance and rotation
+        let biased = |i: u32| {
+            let theta = if i % 4 == 3 { FRAC_PI_2 as dtype } else { 0.0 };
+            SE2::new(theta + 0.02, 1.05, 0.0)
+        };
+
+        let mut graph = Graph::new();
+        graph.add_factor(FactorBuilder::new1(PriorResidual::new(SE2::identity()), Y(0)).build());
+        let mut values = Values::new();
+        values.insert(Y(0), SE2::identity());
+        let mut pose = SE2::identity();
+        for i in 0..16 {
+            let odom = BetweenResidual::from_relative(biased(i));
+            graph.add_factor(fac![odom, (Y(i), Y(i + 1)), 0.1 as std]);
+            pose = pose.compose(&biased(i));
+            values.insert(Y(i + 1), pose.clone());
+        }
+
+        // Ends up back at the start, which the drift alone misses
+        let drift = |graph: Graph| {
+            let mut opt: GaussNewton = GaussNewton::new(graph);
+            let result = opt.optimize(values.clone()).expect("Optimization failed");
+            let end: &SE2 = result.get(Y(16)).expect("Missing Y(16)");
+            end.ominus(&SE2::identity()).norm()
+        };
+        let before = drift(graph.clone());
+
+        let noise = GaussianNoise::<3>::from_scalar_sigma(0.01);
+        let id = graph.add_loop_closure(Y(16), Y(0), SE2::identity(), noise);
+        assert_eq!(graph.get(id).unwrap().category(), Category::LoopClosure);
+        let after = drift(graph);
+
+        assert!(before > 0.5);
+        assert!(after < 0.1 * before);
+    }
+
+    #[test]
+    fn robustify_category() {
+        let mut values = Values::new();
+        values.insert(X(0), VectorVar1::identity());
+        values.insert(X(1), VectorVar1::new(10.0));
+
+        // Large residuals so Huber is in its linear regime
+        let odom = BetweenResidual::new(VectorVar1::new(0.0));
+        let loop_closure = BetweenResidual::new(VectorVar1::new(0.0));
+
+        let mut graph = Graph::new();
+        graph.add_factor(
+            FactorBuilder::new2(odom, X(0), X(1))
+                .category(Category::Odometry)
+                .build(),
+        );
+        graph.add_factor(
+            FactorBuilder::new2(loop_closure, X(0), X(1))
+                .category(Category::LoopClosure)
+                .build(),
+        );
+        let before = graph.errors(&values);
+
+        graph.robustify_category(Category::LoopClosure, Huber::default());
+        let after = graph.errors(&values);
+
+        assert_eq!(before[0], after[0]);
+        assert!(after[1] < before[1]);
+    }
+
+    #[test]
+    fn robust_weights() {
+        let mut values = Values::new();
+        values.insert(X(0), VectorVar1::new(0.0));
+        values.insert(X(1), VectorVar1::new(0.1));
+
+        // A consistent odometry and a loop closure far off
+        let mut graph = Graph::new();
+        let res = BetweenResidual::new(VectorVar1::new(0.1));
+        let odom = graph.add_factor(FactorBuilder::new2(res, X(0), X(1)).build());
+        let res = BetweenResidual::new(VectorVar1::new(10.0));
+        let loop_closure = graph.add_factor(
+            FactorBuilder::new2(res, X(0), X(1))
+                .robust(Huber::default())
+                .build(),
+        );
+
+        let weights = graph.robust_weights(&values);
+        assert_eq!(weights.len(), 2);
+        assert_eq!(weights[&odom], 1.0);
+        assert!(weights[&loop_closure] < 0.2);
+    }
+
+    #[test]
+    fn unanchored() {
+        let mut values = Values::new();
+        let mut graph = Graph::new();
+        for i in 0..6 {
+            values.insert(X(i), VectorVar1::new(i as dtype));
+        }
+        // Three chains, one with a prior, one with a fixed variable, one free
+        for i in [0, 2, 4] {
+            let res = BetweenResidual::new(VectorVar1::new(1.0));
+            graph.add_factor(FactorBuilder::new2(res, X(i), X(i + 1)).build());
+        }
+        let res = PriorResidual::new(VectorVar1::new(0.0));
+        graph.add_factor(FactorBuilder::new1(res, X(1)).build());
+        values.fix(X(2));
+
+        let expected: Vec<Key> = vec![X(4).into(), X(5).into()];
+        assert_eq!(graph.unanchored(&values), expected);
+
+        values.fix(X(5));
+        assert!(graph.unanchored(&values).is_empty());
+    }
+
+    #[test]
+    fn unanchored_marginal() {
+        let mut values = Values::new();
+        let mut graph = Graph::new();
+        for i in 0..3 {
+            values.insert(X(i), VectorVar1::new(i as dtype));
+        }
+        let res = PriorResidual::new(VectorVar1::new(0.0));
+        graph.add_factor(FactorBuilder::new1(res, X(0)).build());
+        for i in 1..3 {
+            let res = BetweenResidual::new(VectorVar1::new(i as dtype));
+            graph.add_factor(FactorBuilder::new2(res, X(0), X(i)).build());
+        }
+
+        // The prior on X(0) is carried over to both remaining variables
+        crate::optimizers::marginalize(&mut graph, &mut values, &[X(0).into()])
+            .expect("Nothing to marginalize");
+        assert!(graph.unanchored(&values).is_empty());
+    }
+
+    #[test]
+    fn errors() {
+        let mut values = Values::new();
+        values.insert(X(0), VectorVar1::new(1.0));
+        values.insert(X(1), VectorVar1::new(3.0));
+
+        let mut graph = Graph::new();
+        let res = PriorResidual::new(VectorVar1::new(0.0));
+        graph.add_factor(FactorBuilder::new1(res, X(0)).build());
+        let res = BetweenResidual::new(VectorVar1::new(1.0));
+        graph.add_factor(FactorBuilder::new2(res, X(0), X(1)).build());
+
+        let errors = graph.errors(&values);
+        assert_eq!(errors, vec![0.5, 0.5]);
+        assert_eq!(errors.iter().sum::<dtype>(), graph.error(&values));
+    }
+
+    #[test]
+    fn gradient_at_solution() {
+        let mut graph = Graph::new();
+        let res = PriorResidual::new(VectorVar1::new(0.5));
+        graph.add_factor(FactorBuilder::new1(res, X(0)).build());
+        let res = BetweenResidual::new(VectorVar1::new(1.0));
+        graph.add_factor(FactorBuilder::new2(res, X(0), X(1)).build());
+        let res = PriorResidual::new(VectorVar1::new(2.0));
+        graph.add_factor(FactorBuilder::new1(res, X(1)).build());
+
+        let mut values = Values::new();
+        values.insert(X(0), VectorVar1::new(0.0));
+        values.insert(X(1), VectorVar1::new(0.0));
+
+        // Away from the solution the gradient is nonzero
+        let gradient = graph.gradient(&values);
+        assert_eq!(gradient.len(), 2);
+        assert!(gradient.values().any(|g| g.norm() > 1e-2));
+
+        let mut opt: GaussNewton = GaussNewton::new(graph.clone());
+        let values = opt.optimize(values).expect("Optimization failed");
+        for g in graph.gradient(&values).values() {
+            assert!(g.norm() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn linearize_at_same_point() {
+        let mut values = Values::new();
+        values.insert(X(0), VectorVar1::new(1.0));
+        values.insert(X(1), VectorVar1::new(3.0));
+
+        let mut graph = Graph::new();
+        let res = PriorResidual::new(VectorVar1::new(0.5));
+        graph.add_factor(FactorBuilder::new1(res, X(0)).build());
+        let res = BetweenResidual::new(VectorVar1::new(1.0));
+        graph.add_factor(FactorBuilder::new2(res, X(0), X(1)).build());
+
+        let expected = graph.linearize(&values);
+        let got = graph.linearize_at(&values, &values);
+
+        let order = ValuesOrder::from_values(&values);
+        let graph_order = expected.sparsity_pattern(order);
+        let expected = expected.residual_jacobian(&graph_order);
+        let got = got.residual_jacobian(&graph_order);
+        assert_matrix_eq!(
+            got.value.as_ref().into_nalgebra(),
+            expected.value.as_ref().into_nalgebra(),
+            comp = float
+        );
+        assert_matrix_eq!(
+            got.diff.to_dense().as_ref().into_nalgebra(),
+            expected.diff.to_dense().as_ref().into_nalgebra(),
+            comp = float
+        );
+    }
+
+    #[test]
+    fn hessian_vec_product() {
+        let mut values = Values::new();
+        values.insert(Y(0), SE2::new(0.1, 1.0, 2.0));
+        values.insert(Y(1), SE2::new(-0.3, 2.0, 1.5));
+        values.insert(X(0), VectorVar1::new(0.5));
+
+        let mut graph = Graph::new();
+        let res = PriorResidual::new(SE2::identity());
+        graph.add_factor(FactorBuilder::new1(res, Y(0)).build());
+        let res = BetweenResidual::new(SE2::new(0.2, 1.0, -0.5));
+        graph.add_factor(FactorBuilder::new2(res, Y(0), Y(1)).build());
+        let res = PriorResidual::new(VectorVar1::new(2.0));
+        graph.add_factor(FactorBuilder::new1(res, X(0)).build());
+
+        let order = ValuesOrder::from_values(&values);
+        let v = VectorX::from_fn(order.dim(), |i, _| (i as dtype) - 3.0);
+        let got = graph.hessian_vec_product(&values, &v, &order);
+
+        let graph_order = graph.sparsity_pattern(order);
+        let jac = graph
+            .linearize(&values)
+            .residual_jacobian(&graph_order)
+            .diff
+            .to_dense()
+            .as_ref()
+            .into_nalgebra()
+            .clone_owned();
+        let expected = jac.transpose() * (&jac * &v);
+
+        assert_matrix_eq!(got, expected, comp = abs, tol = 1e-6);
+    }
+
+    #[test]
+    fn remove_replace_factor() {
+        let mut graph = Graph::new();
+        let ids = (0..3)
+            .map(|i| {
+                let res = PriorResidual::new(VectorVar1::new(i as dtype));
+                graph.add_factor(FactorBuilder::new1(res, X(i)).build())
+            })
+            .collect::<Vec<_>>();
+
+        // Removing the middle one leaves the others in order
+        assert!(graph.remove_factor(ids[1]).is_some());
+        assert!(graph.remove_factor(ids[1]).is_none());
+        assert!(graph.get(ids[1]).is_none());
+        assert_eq!(graph.len(), 2);
+        let keys: Vec<Key> = graph.iter().map(|f| f.keys()[0]).collect();
+        assert_eq!(keys, vec![Key::from(X(0)), Key::from(X(2))]);
+
+        // Other handles are still valid
+        let res = PriorResidual::new(VectorVar1::new(5.0));
+        let old = graph.replace_factor(ids[2], FactorBuilder::new1(res, X(3)).build());
+        assert_eq!(old.expect("Missing factor").keys()[0], Key::from(X(2)));
+        assert_eq!(
+            graph.get(ids[2]).expect("Missing factor").keys()[0],
+            Key::from(X(3))
+        );
+        assert_eq!(
+            graph.get(ids[0]).expect("Missing factor").keys()[0],
+            Key::from(X(0))
+        );
+
+        // New ids are never reused
+        let res = PriorResidual::new(VectorVar1::new(1.0));
+        let id = graph.add_factor(FactorBuilder::new1(res, X(1)).build());
+        assert!(!ids.contains(&id));
+        assert_eq!(graph.len(), 3);
+    }
+
+    #[test]
+    fn extend() {
+        let mut graph = Graph::new();
+        let mut other = Graph::new();
+        for i in 0..2 {
+            let res = PriorResidual::new(VectorVar1::new(i as dtype));
+            graph.add_factor(FactorBuilder::new1(res, X(i)).build());
+            let res = BetweenResidual::new(VectorVar1::new(1.0));
+            other.add_factor(FactorBuilder::new2(res, X(i), X(i + 1)).build());
+        }
+        // A factor duplicating the keys of one already in the graph
+        let res = PriorResidual::new(VectorVar1::new(3.0));
+        other.add_factor(FactorBuilder::new1(res, X(0)).build());
+
+        let ids = graph.ids().collect::<Vec<_>>();
+        graph.extend(other);
+        assert_eq!(graph.len(), 5);
+        let keys: Vec<Vec<Key>> = graph.iter().map(|f| f.keys().to_vec()).collect();
+        assert_eq!(keys[2], vec![Key::from(X(0)), Key::from(X(1))]);
+        assert_eq!(keys[4], vec![Key::from(X(0))]);
+
+        // Existing ids are untouched, and new ones aren't reused
+        assert_eq!(graph.ids().take(2).collect::<Vec<_>>(), ids);
+        let all = graph.ids().collect::<foldhash::HashSet<_>>();
+        assert_eq!(all.len(), 5);
+    }
+}
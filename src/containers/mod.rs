@@ -1,16 +1,20 @@
 //! Various containers for storing variables, residuals, factors, etc.
 
 mod symbol;
-pub use symbol::{DefaultSymbolHandler, Key, KeyFormatter, Symbol, TypedSymbol};
+pub use symbol::{DefaultSymbolHandler, Key, KeyFormatter, ParseKeyError, Symbol, TypedSymbol};
 
 mod values;
-pub use values::{Values, ValuesFormatter};
+pub use values::{InvalidVariable, Values, ValuesFormatter};
 
 mod order;
-pub use order::{Idx, ValuesOrder};
+pub(crate) use order::min_degree;
+pub use order::{Idx, ValuesOrder, VariableOrdering};
 
 mod graph;
-pub use graph::{Graph, GraphFormatter, GraphOrder};
+pub use graph::{Boundary, FactorId, Graph, GraphFormatter, GraphOrder};
+
+mod cache;
+pub use cache::LinearizationCache;
 
 mod factor;
-pub use factor::{Factor, FactorBuilder, FactorFormatter};
+pub use factor::{Category, Factor, FactorBuilder, FactorFormatter};
@@ -1,8 +1,8 @@
-use std::collections::hash_map::Iter as HashMapIter;
+use std::collections::{hash_map::Iter as HashMapIter, BTreeSet};
 
 use foldhash::HashMap;
 
-use super::{Key, Symbol, Values};
+use super::{Graph, Key, Symbol, Values};
 
 /// Location of a variable in a list
 ///
@@ -18,7 +18,8 @@ pub struct Idx {
 ///
 /// Likely won't need to ever interface with this unless a custom optimizer is
 /// being implemented. Since the map isn't ordered, we need to track both idx
-/// and len of each variable
+/// and len of each variable. [Fixed](Values::fix) variables are never
+/// included.
 #[derive(Debug, Clone)]
 pub struct ValuesOrder {
     map: HashMap<Key, Idx>,
@@ -32,7 +33,7 @@ impl ValuesOrder {
     }
     pub fn from_values(values: &Values) -> Self {
         let map = values
-            .iter()
+            .iter_free()
             .scan(0, |idx, (key, val)| {
                 let order = *idx;
                 *idx += val.dim();
@@ -51,6 +52,56 @@ impl ValuesOrder {
         Self { map, dim }
     }
 
+    /// Create an order placing the variables in `keys` first, in the given
+    /// order, followed by all remaining variables in `values`
+    ///
+    /// Keys that aren't in `values` or are fixed are skipped.
+    pub fn from_keys(values: &Values, keys: impl IntoIterator<Item = Key>) -> Self {
+        let mut map = HashMap::default();
+        let mut dim = 0;
+        let rest = values.iter().map(|(key, _)| *key);
+        for key in keys.into_iter().chain(rest) {
+            if map.contains_key(&key) || values.is_fixed(key) {
+                continue;
+            }
+            let Some(var) = values.get_raw(key) else {
+                continue;
+            };
+            map.insert(
+                key,
+                Idx {
+                    idx: dim,
+                    dim: var.dim(),
+                },
+            );
+            dim += var.dim();
+        }
+
+        Self { map, dim }
+    }
+
+    /// Check if this order can be used for `values`, ie it has exactly the same
+    /// free keys with the same dimensions
+    pub fn matches(&self, values: &Values) -> bool {
+        self.len() == values.iter_free().count()
+            && values
+                .iter_free()
+                .all(|(key, val)| self.map.get(key).is_some_and(|idx| idx.dim == val.dim()))
+    }
+
+    /// Append a variable after all current ones, leaving their indices
+    /// untouched
+    ///
+    /// Returns false and does nothing if `key` is already in the order.
+    pub fn push(&mut self, key: Key, dim: usize) -> bool {
+        if self.map.contains_key(&key) {
+            return false;
+        }
+        self.map.insert(key, Idx { idx: self.dim, dim });
+        self.dim += dim;
+        true
+    }
+
     pub fn get(&self, symbol: impl Symbol) -> Option<&Idx> {
         self.map.get(&symbol.into())
     }
@@ -72,11 +123,97 @@ impl ValuesOrder {
     }
 }
 
+/// How variables are ordered into the columns of the linear system
+///
+/// This sets which column block each variable is mapped to. Most solvers,
+/// including the [faer] based ones such as the default
+/// [CholeskySolver](crate::linear::CholeskySolver) and
+/// [LDLTSolver](crate::linear::LDLTSolver), apply their own fill reducing
+/// permutation on top of this, so for them it only changes the column layout,
+/// not the fill in. To eliminate variables in exactly this order, pair it
+/// with [LDLTSolver::natural](crate::linear::LDLTSolver::natural).
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum VariableOrdering {
+    /// The order variables are stored in [Values]
+    #[default]
+    Natural,
+    /// A user specified order, with any unlisted variables placed after
+    Keys(Vec<Key>),
+    /// Greedy minimum degree on the variable connectivity of the graph
+    MinDegree,
+}
+
+impl VariableOrdering {
+    pub fn from_keys(keys: impl IntoIterator<Item = impl Symbol>) -> Self {
+        Self::Keys(keys.into_iter().map(|k| k.into()).collect())
+    }
+
+    /// Compute the order of the variables in `values`
+    pub fn order(&self, graph: &Graph, values: &Values) -> ValuesOrder {
+        match self {
+            Self::Natural => ValuesOrder::from_values(values),
+            Self::Keys(keys) => ValuesOrder::from_keys(values, keys.iter().copied()),
+            Self::MinDegree => ValuesOrder::from_keys(values, key_min_degree(graph, values)),
+        }
+    }
+}
+
+// Minimum degree on the variable connectivity of the graph
+fn key_min_degree(graph: &Graph, values: &Values) -> Vec<Key> {
+    // Sort so ties are broken the same way every time
+    let mut keys = values.iter().map(|(key, _)| *key).collect::<Vec<_>>();
+    keys.sort_by_key(|key| key.0);
+    let index = keys
+        .iter()
+        .enumerate()
+        .map(|(i, key)| (*key, i))
+        .collect::<HashMap<_, _>>();
+
+    let mut adj = vec![BTreeSet::<usize>::new(); keys.len()];
+    for factor in graph.iter() {
+        let idx = factor
+            .keys()
+            .iter()
+            .filter_map(|key| index.get(key).copied())
+            .collect::<Vec<_>>();
+        for &i in idx.iter() {
+            adj[i].extend(idx.iter().filter(|&&j| j != i));
+        }
+    }
+
+    min_degree(adj).into_iter().map(|i| keys[i]).collect()
+}
+
+// Repeatedly eliminate the node with the fewest neighbors, connecting its
+// neighbors to account for fill in. Takes the symmetric adjacency of each node
+// and returns the nodes in elimination order.
+pub(crate) fn min_degree(mut adj: Vec<BTreeSet<usize>>) -> Vec<usize> {
+    let mut queue = adj
+        .iter()
+        .enumerate()
+        .map(|(i, nbrs)| (nbrs.len(), i))
+        .collect::<BTreeSet<_>>();
+    let mut order = Vec::with_capacity(adj.len());
+    while let Some((_, i)) = queue.pop_first() {
+        order.push(i);
+        let nbrs = std::mem::take(&mut adj[i]);
+        for &u in nbrs.iter() {
+            queue.remove(&(adj[u].len(), u));
+            adj[u].remove(&i);
+            adj[u].extend(nbrs.iter().filter(|&&v| v != u));
+            queue.insert((adj[u].len(), u));
+        }
+    }
+    order
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
     use crate::{
-        containers::Values,
+        containers::{FactorBuilder, Values},
+        residuals::BetweenResidual,
         symbols::X,
         variables::{Variable, VectorVar2, VectorVar3, VectorVar6},
     };
@@ -99,4 +236,38 @@ mod test {
         assert_eq!(order.get(X(1)).expect("Missing key").dim, 6);
         assert_eq!(order.get(X(2)).expect("Missing key").dim, 3);
     }
+
+    #[test]
+    fn from_keys() {
+        let mut v = Values::new();
+        v.insert_unchecked(X(0), VectorVar2::identity());
+        v.insert_unchecked(X(1), VectorVar6::identity());
+        v.insert_unchecked(X(2), VectorVar3::identity());
+
+        // X(5) isn't in values, and X(0) is placed after the listed keys
+        let order = ValuesOrder::from_keys(&v, [X(2).into(), X(5).into(), X(1).into()]);
+        assert_eq!(order.len(), 3);
+        assert_eq!(order.dim(), 11);
+        assert_eq!(order.get(X(2)).expect("Missing key").idx, 0);
+        assert_eq!(order.get(X(1)).expect("Missing key").idx, 3);
+        assert_eq!(order.get(X(0)).expect("Missing key").idx, 9);
+    }
+
+    #[test]
+    fn min_degree_star() {
+        // Hub connected to every other variable, which should be eliminated
+        // last to avoid filling in everything
+        let mut graph = Graph::new();
+        let mut v = Values::new();
+        v.insert_unchecked(X(0), VectorVar2::identity());
+        for i in 1..5 {
+            v.insert_unchecked(X(i), VectorVar2::identity());
+            let res = BetweenResidual::new(VectorVar2::identity());
+            graph.add_factor(FactorBuilder::new2_unchecked(res, X(0), X(i)).build());
+        }
+
+        let order = VariableOrdering::MinDegree.order(&graph, &v);
+        assert_eq!(order.dim(), 10);
+        assert_eq!(order.get(X(0)).expect("Missing key").idx, 8);
+    }
 }
@@ -2,6 +2,7 @@
 use std::{
     fmt::{self, Write},
     mem::size_of,
+    str::FromStr,
 };
 
 use crate::variables::VariableDtype;
@@ -62,7 +63,8 @@ impl DefaultSymbolHandler {
     }
 
     pub fn key_to_sym(k: Key) -> (char, u32) {
-        let chr = ((k.0 & CHR_MASK) >> IDX_SIZE) as u8 as char;
+        let chr = char::from_u32(((k.0 & CHR_MASK) >> IDX_SIZE) as u32)
+            .unwrap_or(char::REPLACEMENT_CHARACTER);
         let idx = (k.0 & IDX_MASK) as u32;
         (chr, idx)
     }
@@ -70,8 +72,55 @@ impl DefaultSymbolHandler {
     pub fn format(f: &mut dyn Write, chr: char, idx: u32) -> fmt::Result {
         write!(f, "{}{}", chr, idx)
     }
+
+    /// Parse a key from its formatted form, ie `X123`
+    ///
+    /// The inverse of [format](Self::format), so any key formatted by
+    /// [DefaultSymbolHandler] parses back to itself. This is also available as
+    /// [Key::from_str].
+    /// ```
+    /// # use factrs::{assign_symbols, containers::{DefaultSymbolHandler, Key}, variables::SO2};
+    /// # assign_symbols!(X: SO2);
+    /// let key = DefaultSymbolHandler::parse("X123").unwrap();
+    /// assert_eq!(key, Key::from(X(123)));
+    /// ```
+    pub fn parse(s: &str) -> Result<Key, ParseKeyError> {
+        let err = || ParseKeyError(s.to_string());
+        let mut chars = s.chars();
+        let chr = chars.next().ok_or_else(err)?;
+        let idx = chars.as_str();
+        if chr.is_ascii_digit() || idx.is_empty() || !idx.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(err());
+        }
+        let idx = idx.parse::<u32>().map_err(|_| err())?;
+        Ok(Self::sym_to_key(chr, idx))
+    }
+}
+
+impl FromStr for Key {
+    type Err = ParseKeyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        DefaultSymbolHandler::parse(s)
+    }
 }
 
+/// A string that isn't a valid symbol, see [DefaultSymbolHandler::parse]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseKeyError(pub String);
+
+impl fmt::Display for ParseKeyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Invalid symbol {:?}, expected a char followed by an index",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for ParseKeyError {}
+
 impl KeyFormatter for DefaultSymbolHandler {
     fn fmt(f: &mut dyn Write, key: Key) -> fmt::Result {
         let (chr, idx) = Self::key_to_sym(key);
@@ -125,3 +174,43 @@ macro_rules! assign_symbols {
         )*
     };
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::variables::SO2;
+
+    assign_symbols!(X: SO2; L: SO2);
+
+    fn format(key: Key) -> String {
+        let mut s = String::new();
+        DefaultSymbolHandler::fmt(&mut s, key).unwrap();
+        s
+    }
+
+    #[test]
+    fn parse() {
+        let key: Key = "X123".parse().unwrap();
+        assert_eq!(key, X(123).into());
+        assert_eq!(format!("{:?}", X(123)), "X123");
+    }
+
+    #[test]
+    fn round_trip() {
+        let keys = [
+            X(0).into(),
+            L(u32::MAX).into(),
+            DefaultSymbolHandler::sym_to_key('λ', 7),
+        ];
+        for key in keys {
+            assert_eq!(format(key).parse::<Key>(), Ok(key));
+        }
+    }
+
+    #[test]
+    fn parse_invalid() {
+        for s in ["", "X", "123", "X-1", "X+1", "X1.5", "X99999999999"] {
+            assert!(s.parse::<Key>().is_err(), "{s} should fail");
+        }
+    }
+}
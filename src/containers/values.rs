@@ -3,14 +3,15 @@ use std::{
     marker::PhantomData,
 };
 
-use foldhash::HashMap;
+use foldhash::{HashMap, HashSet};
 use pad_adapter::PadAdapter;
 
 use super::{
     symbol::{DefaultSymbolHandler, KeyFormatter},
-    Key, Symbol, TypedSymbol,
+    Key, Symbol, TypedSymbol, ValuesOrder,
 };
 use crate::{
+    linalg::VectorX,
     linear::LinearValues,
     variables::{VariableDtype, VariableSafe},
 };
@@ -35,10 +36,15 @@ use crate::{
 /// let mut values = Values::new();
 /// values.insert(X(0), x);
 /// ```
+///
+/// Variables can also be [fixed](Values::fix), holding them constant during
+/// optimization.
 #[derive(Default, Clone)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Values {
     values: HashMap<Key, Box<dyn VariableSafe>>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    fixed: HashSet<Key>,
 }
 
 impl Values {
@@ -84,6 +90,15 @@ impl Values {
         self.values.get(&symbol.into()).map(|f| f.as_ref())
     }
 
+    pub(crate) fn remove_raw<S>(&mut self, symbol: S) -> Option<Box<dyn VariableSafe>>
+    where
+        S: Symbol,
+    {
+        let key = symbol.into();
+        self.fixed.remove(&key);
+        self.values.remove(&key)
+    }
+
     /// Returns the underlying variable.
     ///
     /// This will return the value if variable is in the graph. Requires a typed
@@ -112,6 +127,24 @@ impl Values {
     }
 
     /// Returns the underlying variable, not checking the type.
+    ///
+    /// Works with any [Symbol], including a plain [Key], with the type given
+    /// by annotation or turbofish. The downcast is done internally, returning
+    /// None if the key isn't found or holds a different type.
+    /// ```
+    /// # use factrs::{
+    /// #    assign_symbols,
+    /// #    containers::{Key, Values},
+    /// #    variables::{SO2, SE2},
+    /// # };
+    /// # assign_symbols!(X: SO2);
+    /// # let mut values = Values::new();
+    /// # values.insert(X(0), SO2::from_theta(0.1));
+    /// let key: Key = X(0).into();
+    /// let x_out = values.get_unchecked::<_, SO2>(key);
+    /// assert!(x_out.is_some());
+    /// assert!(values.get_unchecked::<_, SE2>(key).is_none());
+    /// ```
     pub fn get_unchecked<S, V>(&self, symbol: S) -> Option<&V>
     where
         S: Symbol,
@@ -149,16 +182,99 @@ impl Values {
         S: TypedSymbol<V>,
         V: VariableDtype,
     {
+        let key = symbol.into();
+        self.fixed.remove(&key);
         self.values
-            .remove(&symbol.into())
+            .remove(&key)
             .and_then(|value| value.downcast::<V>().ok())
             .map(|value| *value)
     }
 
+    /// Hold a variable constant during optimization.
+    ///
+    /// Fixed variables are left out of the [ValuesOrder], so they have no
+    /// columns in the linear system and updates are never applied to them.
+    /// Factors touching them are still evaluated at their current value. This
+    /// is the proper way to fix the gauge with an anchor, or to keep a known
+    /// calibration constant, rather than adding a prior with a tiny sigma.
+    /// ```
+    /// # use factrs::{
+    /// #    assign_symbols,
+    /// #    containers::Values,
+    /// #    variables::SO2,
+    /// # };
+    /// # assign_symbols!(X: SO2);
+    /// let mut values = Values::new();
+    /// values.insert(X(0), SO2::from_theta(0.1));
+    /// values.fix(X(0));
+    /// assert!(values.is_fixed(X(0)));
+    /// ```
+    pub fn fix(&mut self, key: impl Symbol) {
+        self.fixed.insert(key.into());
+    }
+
+    /// Allow a [fixed](Values::fix) variable to be optimized again.
+    pub fn unfix(&mut self, key: impl Symbol) {
+        self.fixed.remove(&key.into());
+    }
+
+    /// Check if a variable is [fixed](Values::fix).
+    pub fn is_fixed(&self, key: impl Symbol) -> bool {
+        self.fixed.contains(&key.into())
+    }
+
+    /// Returns an iterator over the variables that aren't
+    /// [fixed](Values::fix).
+    pub fn iter_free(&self) -> impl Iterator<Item = (&Key, &Box<dyn VariableSafe>)> {
+        self.values
+            .iter()
+            .filter(|(key, _)| !self.fixed.contains(key))
+    }
+
+    /// Insert every variable from `new` whose key isn't already present.
+    ///
+    /// Existing estimates are left untouched, making this the usual way to
+    /// initialize newly added variables while keeping an optimized solution.
+    /// Variables [fixed](Values::fix) in `new` are fixed only if they're
+    /// inserted, and existing variables stay fixed or free as they were.
+    pub fn merge_defaults(&mut self, new: Values) {
+        for (key, value) in new.values {
+            if let Entry::Vacant(e) = self.values.entry(key) {
+                e.insert(value);
+                if new.fixed.contains(&key) {
+                    self.fixed.insert(key);
+                }
+            }
+        }
+    }
+
+    /// Insert every variable from `other`, overwriting any already present.
+    ///
+    /// On duplicate keys the variable from `other` wins, the same as extending
+    /// a `HashMap`. Use [merge_defaults](Values::merge_defaults) to keep
+    /// the existing variables instead. Variables [fixed](Values::fix) in
+    /// either are fixed in the result.
+    pub fn extend(&mut self, other: Values) {
+        self.values.extend(other.values);
+        self.fixed.extend(other.fixed);
+    }
+
     pub fn iter(&self) -> impl Iterator<Item = (&Key, &Box<dyn VariableSafe>)> {
         self.values.iter()
     }
 
+    /// Returns an iterator over all variables, sorted by [Key].
+    ///
+    /// [Values::iter] follows the arbitrary order of the underlying HashMap,
+    /// which can change between runs. This sorts on demand instead, giving a
+    /// reproducible order that groups variables by symbol, then index. Used
+    /// when printing values, so dumps can be diffed between runs.
+    pub fn iter_ordered(&self) -> impl Iterator<Item = (&Key, &Box<dyn VariableSafe>)> {
+        let mut items = self.values.iter().collect::<Vec<_>>();
+        items.sort_by_key(|(key, _)| key.0);
+        items.into_iter()
+    }
+
     /// Returns a iterator of references of all variables of a specific type in
     /// the values.
     ///
@@ -180,6 +296,28 @@ impl Values {
             .filter_map(|(_, value)| value.downcast_ref::<T>())
     }
 
+    /// Check that every variable is well-formed.
+    ///
+    /// Calls [is_valid](crate::variables::Variable::is_valid) on each
+    /// variable, for example to catch non-unit quaternions from a buggy parser
+    /// before they silently degrade an optimization. Returns all offending
+    /// variables, sorted by key.
+    pub fn validate(&self) -> Result<(), Vec<InvalidVariable>> {
+        let mut invalid = self
+            .values
+            .iter()
+            .filter(|(_, v)| !v.is_valid())
+            .map(|(k, _)| InvalidVariable { key: *k })
+            .collect::<Vec<_>>();
+
+        if invalid.is_empty() {
+            Ok(())
+        } else {
+            invalid.sort_by_key(|i| i.key.0);
+            Err(invalid)
+        }
+    }
+
     /// Update variables in place via the
     /// [oplus](crate::variables::Variable::oplus) operation.
     ///
@@ -194,6 +332,70 @@ impl Values {
             }
         }
     }
+
+    /// Update variables in place from a single stacked tangent space vector.
+    ///
+    /// This is the retraction step performed by the optimizers, exposed for
+    /// writing custom solver loops. `order` gives the location of each
+    /// variable in `delta`, and is usually built with
+    /// [ValuesOrder::from_values]. Variables not in `order` are left
+    /// unchanged.
+    pub fn apply_delta(&mut self, delta: &VectorX, order: &ValuesOrder) {
+        assert!(
+            delta.len() == order.dim(),
+            "Dimension mismatch between delta and order in values apply_delta"
+        );
+        for (key, idx) in order.iter() {
+            if let Some(v) = self.values.get_mut(key) {
+                assert!(
+                    v.dim() == idx.dim,
+                    "Dimension mismatch in values apply_delta"
+                );
+                v.oplus_mut(delta.rows(idx.idx, idx.dim));
+            }
+        }
+    }
+
+    /// Non-mutating version of [Values::apply_delta].
+    pub fn oplus(&self, delta: &VectorX, order: &ValuesOrder) -> Values {
+        let mut out = self.clone();
+        out.apply_delta(delta, order);
+        out
+    }
+
+    /// Compute the tangent space difference to `other` as a single stacked
+    /// vector.
+    ///
+    /// Each variable is differenced with
+    /// [ominus](crate::variables::Variable::ominus) and placed in the output
+    /// according to `order`, the same layout [Values::apply_delta] and the
+    /// optimizers use. This makes it the inverse of [Values::oplus], ie
+    /// `y.oplus(&x.ominus(&y, &order), &order)` recovers `x`. Panics if a key
+    /// in `order` is missing from either values.
+    pub fn ominus(&self, other: &Values, order: &ValuesOrder) -> VectorX {
+        let mut delta = VectorX::zeros(order.dim());
+        for (key, idx) in order.iter() {
+            let x = self.values.get(key).expect("Missing key in values ominus");
+            let y = other.values.get(key).expect("Missing key in values ominus");
+            delta
+                .rows_mut(idx.idx, idx.dim)
+                .copy_from(&x.ominus_dyn(y.as_ref()));
+        }
+        delta
+    }
+}
+
+/// A variable that failed [Values::validate]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InvalidVariable {
+    pub key: Key,
+}
+
+impl fmt::Display for InvalidVariable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid variable ")?;
+        DefaultSymbolHandler::fmt(f, self.key)
+    }
 }
 
 impl fmt::Debug for Values {
@@ -232,13 +434,13 @@ impl<KF: KeyFormatter> fmt::Display for ValuesFormatter<'_, KF> {
         if f.alternate() {
             f.write_str("Values {\n")?;
             let mut pad = PadAdapter::new(f);
-            for (key, value) in self.values.iter() {
+            for (key, value) in self.values.iter_ordered() {
                 KF::fmt(&mut pad, *key)?;
                 writeln!(pad, ": {:#.p$},", value, p = precision)?;
             }
         } else {
             f.write_str("Values { ")?;
-            for (key, value) in self.values.iter() {
+            for (key, value) in self.values.iter_ordered() {
                 KF::fmt(f, *key)?;
                 write!(f, ": {:.p$}, ", value, p = precision)?;
             }
@@ -253,13 +455,13 @@ impl<KF: KeyFormatter> fmt::Debug for ValuesFormatter<'_, KF> {
         if f.alternate() {
             f.write_str("Values {\n")?;
             let mut pad = PadAdapter::new(f);
-            for (key, value) in self.values.iter() {
+            for (key, value) in self.values.iter_ordered() {
                 KF::fmt(&mut pad, *key)?;
                 writeln!(pad, ": {:#.p$?},", value, p = precision)?;
             }
         } else {
             f.write_str("Values { ")?;
-            for (key, value) in self.values.iter() {
+            for (key, value) in self.values.iter_ordered() {
                 KF::fmt(f, *key)?;
                 write!(f, ": {:.p$?}, ", value, p = precision)?;
             }
@@ -276,3 +478,185 @@ impl IntoIterator for Values {
         self.values.into_iter()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use matrixcompare::assert_matrix_eq;
+
+    use super::*;
+    use crate::{
+        assign_symbols, dtype,
+        linalg::vectorx,
+        variables::{Variable, SE2, SO3},
+    };
+
+    assign_symbols!(X: SE2; R: SO3);
+
+    fn values() -> Values {
+        let mut values = Values::new();
+        values.insert(X(0), SE2::new(0.1, 1.0, 2.0));
+        values.insert(X(1), SE2::new(-0.3, 0.0, 1.0));
+        values.insert(R(0), SO3::exp(vectorx![0.1, 0.2, 0.3].as_view()));
+        values
+    }
+
+    #[test]
+    fn apply_delta_zero() {
+        let mut values = values();
+        let order = ValuesOrder::from_values(&values);
+        values.apply_delta(&VectorX::zeros(order.dim()), &order);
+
+        let expected = self::values();
+        for (key, value) in expected.iter() {
+            let got = values.get_raw(*key).expect("Missing key");
+            assert_matrix_eq!(got.to_params(), value.to_params(), comp = abs, tol = 1e-6);
+        }
+    }
+
+    #[test]
+    fn apply_delta_oplus() {
+        let mut values = values();
+        let order = ValuesOrder::from_values(&values);
+        let delta = VectorX::from_fn(order.dim(), |i, _| (i as dtype + 1.0) / 10.0);
+        values.apply_delta(&delta, &order);
+
+        let expected = self::values();
+        let idx = order.get(X(1)).expect("Missing key");
+        let x1: &SE2 = expected.get(X(1)).expect("Missing key");
+        let x1 = x1.oplus(delta.rows(idx.idx, idx.dim));
+        let got: &SE2 = values.get(X(1)).expect("Missing key");
+        assert_matrix_eq!(got.to_params(), x1.to_params(), comp = float);
+
+        let idx = order.get(R(0)).expect("Missing key");
+        let r0: &SO3 = expected.get(R(0)).expect("Missing key");
+        let r0 = r0.oplus(delta.rows(idx.idx, idx.dim));
+        let got: &SO3 = values.get(R(0)).expect("Missing key");
+        assert_matrix_eq!(got.to_params(), r0.to_params(), comp = float);
+    }
+
+    #[test]
+    fn oplus_ominus_round_trip() {
+        let values = values();
+        let order = ValuesOrder::from_values(&values);
+        let delta = VectorX::from_fn(order.dim(), |i, _| (i as dtype - 3.0) / 20.0);
+
+        let moved = values.oplus(&delta, &order);
+        let got = moved.ominus(&values, &order);
+        assert_matrix_eq!(got, delta, comp = abs, tol = 1e-6);
+        let zero = VectorX::zeros(order.dim());
+        assert_matrix_eq!(values.ominus(&values, &order), zero, comp = abs, tol = 1e-6);
+    }
+
+    #[test]
+    fn iter_ordered() {
+        let values = values();
+        let keys = values.iter_ordered().map(|(k, _)| k.0).collect::<Vec<_>>();
+        assert!(keys.windows(2).all(|w| w[0] < w[1]));
+
+        // Printing doesn't depend on insertion order
+        let mut reversed = Values::new();
+        reversed.insert(R(0), SO3::exp(vectorx![0.1, 0.2, 0.3].as_view()));
+        reversed.insert(X(1), SE2::new(-0.3, 0.0, 1.0));
+        reversed.insert(X(0), SE2::new(0.1, 1.0, 2.0));
+        assert_eq!(format!("{}", values), format!("{}", reversed));
+        assert_eq!(format!("{:#?}", values), format!("{:#?}", reversed));
+    }
+
+    #[test]
+    fn get_wrong_type() {
+        let mut values = values();
+        let key: Key = X(0).into();
+        assert!(values.get_unchecked::<_, SE2>(key).is_some());
+        assert!(values.get_unchecked::<_, SO3>(key).is_none());
+        assert!(values.get_unchecked_mut::<_, SO3>(key).is_none());
+        assert!(values.get_unchecked::<_, SE2>(X(5)).is_none());
+    }
+
+    #[test]
+    fn fix() {
+        let mut values = values();
+        values.fix(X(0));
+        assert!(values.is_fixed(X(0)));
+        assert!(!values.is_fixed(X(1)));
+
+        let order = ValuesOrder::from_values(&values);
+        assert_eq!(order.len(), 2);
+        assert!(order.get(X(0)).is_none());
+        assert!(order.matches(&values));
+
+        // Applying a delta leaves the fixed variable alone
+        let before: SE2 = values.get(X(0)).expect("Missing key").clone();
+        let delta = VectorX::from_element(order.dim(), 0.1);
+        values.apply_delta(&delta, &order);
+        let after: &SE2 = values.get(X(0)).expect("Missing key");
+        assert_matrix_eq!(after.ominus(&before), VectorX::zeros(3), comp = float);
+
+        values.unfix(X(0));
+        assert!(!values.is_fixed(X(0)));
+        assert_eq!(ValuesOrder::from_values(&values).len(), 3);
+    }
+
+    #[test]
+    fn extend() {
+        let mut values = values();
+        let mut other = Values::new();
+        other.insert(X(2), SE2::new(0.5, 2.0, 3.0));
+        other.fix(X(2));
+
+        // Disjoint keys are all kept
+        values.extend(other);
+        assert_eq!(values.len(), 4);
+        assert!(values.get(X(0)).is_some());
+        assert!(values.get(R(0)).is_some());
+        assert!(values.is_fixed(X(2)));
+
+        // Overlapping keys are overwritten
+        let mut other = Values::new();
+        let x = SE2::new(1.0, -1.0, 0.0);
+        other.insert(X(0), x.clone());
+        values.extend(other);
+        assert_eq!(values.len(), 4);
+        let got: &SE2 = values.get(X(0)).expect("Missing key");
+        assert_matrix_eq!(got.ominus(&x), VectorX::zeros(3), comp = float);
+    }
+
+    #[test]
+    fn validate() {
+        let mut values = values();
+        assert!(values.validate().is_ok());
+
+        values.insert(R(1), SO3::from_xyzw(0.1, 0.2, 0.3, 2.0));
+        let invalid = values.validate().unwrap_err();
+        assert_eq!(invalid, vec![InvalidVariable { key: R(1).into() }]);
+    }
+
+    #[test]
+    fn merge_defaults() {
+        let mut values = values();
+        let mut new = Values::new();
+        new.insert(X(0), SE2::identity());
+        new.insert(X(2), SE2::new(0.5, 3.0, 4.0));
+        new.fix(X(0));
+        new.fix(X(2));
+        values.merge_defaults(new);
+
+        assert_eq!(values.len(), 4);
+        assert!(values.is_fixed(X(2)));
+        assert!(!values.is_fixed(X(0)));
+        let x0: &SE2 = values.get(X(0)).expect("Missing key");
+        let expected = SE2::new(0.1, 1.0, 2.0);
+        assert_matrix_eq!(x0.to_params(), expected.to_params(), comp = float);
+        let x2: &SE2 = values.get(X(2)).expect("Missing key");
+        let expected = SE2::new(0.5, 3.0, 4.0);
+        assert_matrix_eq!(x2.to_params(), expected.to_params(), comp = float);
+    }
+
+    #[test]
+    fn remove_raw_unfixes() {
+        let mut values = values();
+        values.fix(X(0));
+        values.remove_raw(X(0));
+        values.insert(X(0), SE2::identity());
+        assert!(!values.is_fixed(X(0)));
+    }
+}
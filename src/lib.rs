@@ -10,7 +10,8 @@
 //! - Common Lie Groups supported (SO2, SO3, SE2, SE3) with optimization in Lie
 //!   Algebras
 //! - Automatic differentiation via dual numbers
-//! - Serialization of graphs & variables via optional serde support
+//! - Serialization of graphs & variables via optional serde support, with
+//!   JSON files via the `json` feature
 //! - Easy conversion to rerun types for straightforward visualization
 //!
 //! # Background
@@ -160,7 +161,8 @@ pub use factrs_proc::fac;
 /// enabled, it will add a tag for serialization.
 ///
 /// ### [Noise](factrs::traits::NoiseModel)
-/// If serde is disabled, does nothing. Otherwise, it will tag the noise model
+/// Implements [dim](factrs::traits::NoiseModel::dim) from the `Dim` of the
+/// noise model. Additionally, if serde is enabled, it will tag the noise model
 /// for serialization, up to size 32.
 ///
 /// ### [Robust](factrs::traits::RobustCost)
@@ -243,8 +245,17 @@ pub mod core {
 /// at once.
 /// - Iterator of VectorVar2 -> Points2D
 /// - Iterator of VectorVar3 -> Points3D
-/// - Iterator of SE2 -> Arrows2D, Points2D
-/// - Iterator of SE3 -> Arrows3D, Points3D
+/// - Iterator of SE2 -> Arrows2D, Points2D, LineStrips2D
+/// - Iterator of SE3 -> Arrows3D, Points3D, LineStrips3D
+///
+/// Since [Values](crate::containers::Values) are unordered, trajectories are
+/// better made with [trajectory_2d](crate::rerun::trajectory_2d) and
+/// [trajectory_3d](crate::rerun::trajectory_3d), which connect poses in order
+/// of their index. The edges of between factors can be drawn with
+/// [edges_3d](crate::rerun::edges_3d), colored by how well each is satisfied,
+/// and recovered covariances as ellipses with
+/// [covariance_2d](crate::rerun::covariance_2d) and
+/// [covariance_3d](crate::rerun::covariance_3d).
 pub mod rerun;
 
 #[cfg(feature = "serde")]
@@ -0,0 +1,236 @@
+use faer::{sparse::SparseColMatRef, Mat, MatRef};
+
+use super::LinearSolver;
+use crate::{containers::ValuesOrder, dtype};
+
+/// Preconditioner used by [ConjugateGradient]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Preconditioner {
+    /// No preconditioning
+    Identity,
+    /// Scale by the inverse of the diagonal of the system
+    #[default]
+    Jacobi,
+}
+
+/// Iterative conjugate gradient linear solver
+///
+/// Rather than factoring the system, this iteratively refines the solution
+/// using only matrix-vector products, so it's well suited to very large
+/// problems where a direct factorization is too expensive. Least squares
+/// problems are solved matrix-free on the normal equations (CGLS), by
+/// computing $J^\top (J p)$ directly, so $J^\top J$ is never formed.
+///
+/// Iteration stops after `max_iterations`, or once the norm of the residual
+/// relative to the right hand side drops below `tolerance`.
+#[derive(Clone, Debug)]
+pub struct ConjugateGradient {
+    pub max_iterations: usize,
+    pub tolerance: dtype,
+    pub preconditioner: Preconditioner,
+}
+
+impl Default for ConjugateGradient {
+    fn default() -> Self {
+        Self {
+            max_iterations: 500,
+            tolerance: 1e-10,
+            preconditioner: Preconditioner::default(),
+        }
+    }
+}
+
+impl ConjugateGradient {
+    // Preconditioned conjugate gradient on the system applied by `apply`
+    fn solve_single<F>(&self, apply: F, diag: &[dtype], b: &[dtype]) -> Vec<dtype>
+    where
+        F: Fn(&[dtype]) -> Vec<dtype>,
+    {
+        let precondition = |r: &[dtype]| -> Vec<dtype> {
+            match self.preconditioner {
+                Preconditioner::Identity => r.to_vec(),
+                Preconditioner::Jacobi => r
+                    .iter()
+                    .zip(diag)
+                    .map(|(r, d)| if *d > 0.0 { r / d } else { *r })
+                    .collect(),
+            }
+        };
+
+        let mut x = vec![0.0; b.len()];
+        let b_norm = dot(b, b).sqrt();
+        if b_norm == 0.0 {
+            return x;
+        }
+
+        let mut r = b.to_vec();
+        let mut z = precondition(&r);
+        let mut p = z.clone();
+        let mut rz = dot(&r, &z);
+        for _ in 0..self.max_iterations {
+            let ap = apply(&p);
+            let alpha = rz / dot(&p, &ap);
+            axpy(alpha, &p, &mut x);
+            axpy(-alpha, &ap, &mut r);
+            if dot(&r, &r).sqrt() <= self.tolerance * b_norm {
+                break;
+            }
+
+            z = precondition(&r);
+            let rz_new = dot(&r, &z);
+            let beta = rz_new / rz;
+            rz = rz_new;
+            for (p, z) in p.iter_mut().zip(z.iter()) {
+                *p = z + beta * *p;
+            }
+        }
+        x
+    }
+
+    fn solve_columns<F>(&self, apply: F, diag: &[dtype], b: MatRef<dtype>) -> Mat<dtype>
+    where
+        F: Fn(&[dtype]) -> Vec<dtype>,
+    {
+        let mut x = Mat::zeros(b.nrows(), b.ncols());
+        for j in 0..b.ncols() {
+            let b_j = (0..b.nrows()).map(|i| b.read(i, j)).collect::<Vec<_>>();
+            let x_j = self.solve_single(&apply, diag, &b_j);
+            for (i, v) in x_j.into_iter().enumerate() {
+                x.write(i, j, v);
+            }
+        }
+        x
+    }
+}
+
+impl LinearSolver for ConjugateGradient {
+    fn solve_symmetric(
+        &mut self,
+        a: SparseColMatRef<usize, dtype>,
+        b: MatRef<dtype>,
+    ) -> Mat<dtype> {
+        let diag = (0..a.ncols())
+            .map(|j| {
+                a.row_indices_of_col(j)
+                    .zip(a.values_of_col(j))
+                    .find(|(i, _)| *i == j)
+                    .map_or(0.0, |(_, v)| *v)
+            })
+            .collect::<Vec<_>>();
+
+        self.solve_columns(|p| mul(a, p), &diag, b)
+    }
+
+    fn solve_lst_sq(&mut self, a: SparseColMatRef<usize, dtype>, b: MatRef<dtype>) -> Mat<dtype> {
+        // Diagonal of A^T A is the squared norm of each column
+        let diag = (0..a.ncols())
+            .map(|j| a.values_of_col(j).iter().map(|v| v * v).sum::<dtype>())
+            .collect::<Vec<_>>();
+
+        let b = (0..b.ncols())
+            .map(|j| {
+                let b_j = (0..b.nrows()).map(|i| b.read(i, j)).collect::<Vec<_>>();
+                mul_transpose(a, &b_j)
+            })
+            .collect::<Vec<_>>();
+        let atb = Mat::from_fn(a.ncols(), b.len(), |i, j| b[j][i]);
+
+        self.solve_columns(|p| mul_transpose(a, &mul(a, p)), &diag, atb.as_ref())
+    }
+
+    // Nothing is cached between solves, so keep the configured parameters
+    fn set_order(&mut self, _order: &ValuesOrder) {}
+}
+
+// ------------------------- Helpers ------------------------- //
+
+fn dot(x: &[dtype], y: &[dtype]) -> dtype {
+    x.iter().zip(y).map(|(x, y)| x * y).sum()
+}
+
+// y += alpha * x
+fn axpy(alpha: dtype, x: &[dtype], y: &mut [dtype]) {
+    for (y, x) in y.iter_mut().zip(x) {
+        *y += alpha * x;
+    }
+}
+
+// A x
+fn mul(a: SparseColMatRef<usize, dtype>, x: &[dtype]) -> Vec<dtype> {
+    let mut y = vec![0.0; a.nrows()];
+    for (j, x_j) in x.iter().enumerate() {
+        for (i, v) in a.row_indices_of_col(j).zip(a.values_of_col(j)) {
+            y[i] += v * x_j;
+        }
+    }
+    y
+}
+
+// A^T x
+fn mul_transpose(a: SparseColMatRef<usize, dtype>, x: &[dtype]) -> Vec<dtype> {
+    (0..a.ncols())
+        .map(|j| {
+            a.row_indices_of_col(j)
+                .zip(a.values_of_col(j))
+                .map(|(i, v)| v * x[i])
+                .sum::<dtype>()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use matrixcompare::assert_matrix_eq;
+
+    use super::*;
+    use crate::{
+        assign_symbols,
+        containers::{FactorBuilder, Graph, Values},
+        linalg::{vectorx, VectorX},
+        noise::GaussianNoise,
+        optimizers::{GaussNewton, Optimizer},
+        residuals::{BetweenResidual, PriorResidual},
+        test_optimizer,
+        variables::{Variable, SE2},
+    };
+
+    test_optimizer!(GaussNewton<ConjugateGradient>);
+
+    assign_symbols!(X: SE2);
+
+    #[test]
+    fn matches_direct() {
+        // Square loop of poses, with a loop closure that disagrees slightly
+        let mut graph = Graph::new();
+        let noise = GaussianNoise::<3>::from_diag_sigmas(0.01, 0.01, 0.01);
+        let res = PriorResidual::new(SE2::identity());
+        graph.add_factor(FactorBuilder::new1(res, X(0)).noise(noise).build());
+        for i in 0..4 {
+            let noise = GaussianNoise::<3>::from_diag_sigmas(0.1, 0.2, 0.2);
+            let res = BetweenResidual::new(SE2::new(1.57, 1.0, 0.05 * i as dtype));
+            let fac = FactorBuilder::new2(res, X(i), X((i + 1) % 4))
+                .noise(noise)
+                .build();
+            graph.add_factor(fac);
+        }
+
+        let mut values = Values::new();
+        for i in 0..4 {
+            let xi = vectorx![0.1 * i as dtype, i as dtype, 0.5];
+            values.insert(X(i), SE2::exp(xi.as_view()));
+        }
+
+        let mut direct: GaussNewton = GaussNewton::new(graph.clone());
+        let expected = direct
+            .optimize(values.clone())
+            .expect("Optimization failed");
+        let mut iterative: GaussNewton<ConjugateGradient> = GaussNewton::new(graph);
+        let got = iterative.optimize(values).expect("Optimization failed");
+
+        for i in 0..4 {
+            let g: &SE2 = got.get(X(i)).expect("Missing X");
+            let e: &SE2 = expected.get(X(i)).expect("Missing X");
+            assert_matrix_eq!(g.ominus(e), VectorX::zeros(3), comp = abs, tol = 1e-6);
+        }
+    }
+}
@@ -10,6 +10,7 @@ use crate::{
 /// This is the linear equivalent of [Factor](crate::containers::Factor). It
 /// consists of the relevant keys, a [MatrixBlock] A, and a [VectorX] b. Again,
 /// this *shouldn't* ever need to be used by hand.
+#[derive(Clone)]
 pub struct LinearFactor {
     pub keys: Vec<Key>,
     pub a: MatrixBlock,
@@ -32,8 +33,11 @@ impl LinearFactor {
         self.b.len()
     }
 
+    /// Error of the linear factor at `vector`, ie $\|A x - b\|^2 / 2$
+    ///
+    /// Panics if a key is missing from `vector`.
     pub fn error(&self, vector: &LinearValues) -> dtype {
-        let ax: VectorX = self
+        let ax = self
             .keys
             .iter()
             .enumerate()
@@ -45,7 +49,7 @@ impl LinearFactor {
                         .expect("Missing key in LinearValues::error"),
                 )
             })
-            .sum();
+            .fold(VectorX::zeros(self.b.len()), |acc, x| acc + x);
         (ax - &self.b).norm_squared() / 2.0
     }
 }
@@ -3,7 +3,7 @@ use faer_ext::IntoFaer;
 
 use super::LinearValues;
 use crate::{
-    containers::{GraphOrder, Idx, ValuesOrder},
+    containers::{GraphOrder, ValuesOrder},
     dtype,
     linalg::DiffResult,
     linear::LinearFactor,
@@ -31,6 +31,19 @@ impl LinearGraph {
         self.factors.push(factor);
     }
 
+    /// Replace the factor at `idx`, returning the old one
+    pub fn replace_factor(&mut self, idx: usize, factor: LinearFactor) -> LinearFactor {
+        std::mem::replace(&mut self.factors[idx], factor)
+    }
+
+    pub fn len(&self) -> usize {
+        self.factors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.factors.is_empty()
+    }
+
     pub fn error(&self, values: &LinearValues) -> dtype {
         self.factors.iter().map(|f| f.error(values)).sum()
     }
@@ -44,17 +57,17 @@ impl LinearGraph {
         let mut indices = Vec::<(usize, usize)>::new();
 
         let _ = self.factors.iter().fold(0, |row, f| {
-            f.keys.iter().for_each(|key| {
-                (0..f.dim_out()).for_each(|i| {
-                    let Idx {
-                        idx: col,
-                        dim: col_dim,
-                    } = order.get(*key).expect("Key missing in values");
-                    (0..*col_dim).for_each(|j| {
-                        indices.push((row + i, col + j));
+            // Fixed variables aren't in the order, so they get no columns
+            f.keys
+                .iter()
+                .filter_map(|key| order.get(*key))
+                .for_each(|col| {
+                    (0..f.dim_out()).for_each(|i| {
+                        (0..col.dim).for_each(|j| {
+                            indices.push((row + i, col.idx + j));
+                        });
                     });
                 });
-            });
             row + f.dim_out()
         });
 
@@ -87,15 +100,17 @@ impl LinearGraph {
         let mut values: Vec<dtype> = Vec::new();
         // Iterate over all factors
         let _ = self.factors.iter().fold(0, |row, f| {
-            // Iterate over keys
-            (0..f.keys.len()).for_each(|idx| {
-                // Iterate over rows, then column elements
-                f.a.get_block(idx).row_iter().for_each(|r| {
-                    r.iter().for_each(|val| {
-                        values.push(*val);
+            // Iterate over keys, skipping fixed variables like the sparsity pattern
+            (0..f.keys.len())
+                .filter(|idx| graph_order.order.get(f.keys[*idx]).is_some())
+                .for_each(|idx| {
+                    // Iterate over rows, then column elements
+                    f.a.get_block(idx).row_iter().for_each(|r| {
+                        r.iter().for_each(|val| {
+                            values.push(*val);
+                        });
                     });
                 });
-            });
             row + f.dim_out()
         });
 
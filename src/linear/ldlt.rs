@@ -0,0 +1,379 @@
+use std::{collections::BTreeSet, ops::Mul};
+
+use faer::{sparse::SparseColMatRef, Mat, MatRef};
+
+use super::LinearSolver;
+use crate::{
+    containers::{min_degree, ValuesOrder},
+    dtype,
+};
+
+// ------------------------- Symbolic Analysis ------------------------- //
+
+/// Fill reducing ordering and structure of the LDLᵀ factor
+struct SymbolicLdlt {
+    // New to old index, and its inverse
+    perm: Vec<usize>,
+    perm_inv: Vec<usize>,
+    // Elimination tree
+    parent: Vec<Option<usize>>,
+    // Column pointers of L
+    l_ptrs: Vec<usize>,
+}
+
+impl SymbolicLdlt {
+    fn new(a: SparseColMatRef<usize, dtype>, natural: bool) -> Self {
+        let n = a.ncols();
+        let perm = if natural {
+            (0..n).collect()
+        } else {
+            block_min_degree(a)
+        };
+        let mut perm_inv = vec![0; n];
+        for (new, &old) in perm.iter().enumerate() {
+            perm_inv[old] = new;
+        }
+
+        // Walk up the elimination tree from each entry of the upper triangle,
+        // counting the nonzeros in each column of L
+        let mut parent = vec![None; n];
+        let mut flag = vec![0; n];
+        let mut l_nnz = vec![0; n];
+        for (k, &col) in perm.iter().enumerate() {
+            flag[k] = k;
+            for &row in a.row_indices_of_col_raw(col) {
+                let mut i = perm_inv[row];
+                if i >= k {
+                    continue;
+                }
+                while flag[i] != k {
+                    if parent[i].is_none() {
+                        parent[i] = Some(k);
+                    }
+                    l_nnz[i] += 1;
+                    flag[i] = k;
+                    i = parent[i].expect("Missing parent in elimination tree");
+                }
+            }
+        }
+
+        let mut l_ptrs = vec![0; n + 1];
+        for (k, nnz) in l_nnz.iter().enumerate() {
+            l_ptrs[k + 1] = l_ptrs[k] + nnz;
+        }
+
+        Self {
+            perm,
+            perm_inv,
+            parent,
+            l_ptrs,
+        }
+    }
+}
+
+// Minimum degree ordering computed on the block structure of the matrix. Most
+// problems have multidimensional variables, whose columns all share the same
+// sparsity pattern, so ordering blocks instead of scalars is much cheaper and
+// keeps each variable contiguous.
+fn block_min_degree(a: SparseColMatRef<usize, dtype>) -> Vec<usize> {
+    let n = a.ncols();
+
+    // Group consecutive columns with identical patterns into blocks
+    let mut block_of = vec![0; n];
+    let mut block_start = vec![0];
+    for (j, block) in block_of.iter_mut().enumerate().skip(1) {
+        if a.row_indices_of_col_raw(j) != a.row_indices_of_col_raw(j - 1) {
+            block_start.push(j);
+        }
+        *block = block_start.len() - 1;
+    }
+    let num_blocks = block_start.len();
+    block_start.push(n);
+
+    let mut adj = vec![BTreeSet::<usize>::new(); num_blocks];
+    for (b, cols) in block_start.windows(2).enumerate() {
+        for &row in a.row_indices_of_col_raw(cols[0]) {
+            let other = block_of[row];
+            if other != b {
+                adj[b].insert(other);
+                adj[other].insert(b);
+            }
+        }
+    }
+
+    min_degree(adj)
+        .into_iter()
+        .flat_map(|b| block_start[b]..block_start[b + 1])
+        .collect()
+}
+
+// ------------------------- Numeric Factorization ------------------------- //
+
+/// Numeric values of L (unit lower triangular) and D
+struct NumericLdlt {
+    l_rows: Vec<usize>,
+    l_values: Vec<dtype>,
+    d: Vec<dtype>,
+}
+
+impl NumericLdlt {
+    // Up-looking factorization, computing one row of L at a time. Returns None
+    // on a zero pivot, ie a singular matrix
+    fn new(symbolic: &SymbolicLdlt, a: SparseColMatRef<usize, dtype>) -> Option<Self> {
+        let n = a.ncols();
+        let nnz = symbolic.l_ptrs[n];
+        let mut l_rows = vec![0; nnz];
+        let mut l_values = vec![0.0; nnz];
+        let mut d = vec![0.0; n];
+
+        let mut y = vec![0.0; n];
+        let mut pattern = vec![0; n];
+        let mut flag = vec![0; n];
+        let mut l_nnz = vec![0; n];
+
+        for k in 0..n {
+            // Scatter column k of A, and find the pattern of row k of L
+            let mut top = n;
+            flag[k] = k;
+            let col = symbolic.perm[k];
+            let rows = a.row_indices_of_col_raw(col);
+            let values = a.values_of_col(col);
+            for (&row, &val) in rows.iter().zip(values.iter()) {
+                let mut i = symbolic.perm_inv[row];
+                if i > k {
+                    continue;
+                }
+                y[i] += val;
+                let mut len = 0;
+                while flag[i] != k {
+                    pattern[len] = i;
+                    len += 1;
+                    flag[i] = k;
+                    i = symbolic.parent[i].expect("Missing parent in elimination tree");
+                }
+                while len > 0 {
+                    top -= 1;
+                    len -= 1;
+                    pattern[top] = pattern[len];
+                }
+            }
+
+            // Sparse triangular solve for row k of L
+            d[k] = y[k];
+            y[k] = 0.0;
+            for &i in pattern[top..n].iter() {
+                let yi = y[i];
+                y[i] = 0.0;
+                let start = symbolic.l_ptrs[i];
+                let end = start + l_nnz[i];
+                for p in start..end {
+                    y[l_rows[p]] -= l_values[p] * yi;
+                }
+                let l_ki = yi / d[i];
+                d[k] -= l_ki * yi;
+                l_rows[end] = k;
+                l_values[end] = l_ki;
+                l_nnz[i] += 1;
+            }
+
+            if d[k] == 0.0 || !d[k].is_finite() {
+                return None;
+            }
+        }
+
+        Some(Self {
+            l_rows,
+            l_values,
+            d,
+        })
+    }
+
+    fn solve_in_place(&self, symbolic: &SymbolicLdlt, x: &mut [dtype]) {
+        let n = self.d.len();
+        let ptrs = &symbolic.l_ptrs;
+        for j in 0..n {
+            for p in ptrs[j]..ptrs[j + 1] {
+                x[self.l_rows[p]] -= self.l_values[p] * x[j];
+            }
+        }
+        for (xj, dj) in x.iter_mut().zip(self.d.iter()) {
+            *xj /= dj;
+        }
+        for j in (0..n).rev() {
+            for p in ptrs[j]..ptrs[j + 1] {
+                x[j] -= self.l_values[p] * x[self.l_rows[p]];
+            }
+        }
+    }
+}
+
+// ------------------------- LDLT Linear Solver ------------------------- //
+
+/// Sparse LDLᵀ linear solver
+///
+/// A hand-rolled simplicial $LDL^\top$ factorization, which unlike
+/// [CholeskySolver](super::CholeskySolver) needs no square roots, so also
+/// handles matrices that are only barely positive definite more gracefully.
+/// Columns are first grouped into blocks by their sparsity pattern, ie into
+/// variables, and ordered with a minimum degree heuristic on those blocks to
+/// reduce fill in. The ordering and structure of the factor are computed once
+/// and reused for every following solve.
+#[derive(Default)]
+pub struct LDLTSolver {
+    symbolic: Option<SymbolicLdlt>,
+    natural: bool,
+}
+
+impl LDLTSolver {
+    /// Create a solver that factors the columns in the order given
+    ///
+    /// Skips the minimum degree ordering, so variables are eliminated in the
+    /// order set by the optimizer's
+    /// [VariableOrdering](crate::containers::VariableOrdering).
+    pub fn natural() -> Self {
+        Self {
+            symbolic: None,
+            natural: true,
+        }
+    }
+}
+
+impl LinearSolver for LDLTSolver {
+    fn set_order(&mut self, _order: &ValuesOrder) {
+        self.symbolic = None;
+    }
+
+    fn solve_symmetric(
+        &mut self,
+        a: SparseColMatRef<usize, dtype>,
+        b: MatRef<dtype>,
+    ) -> Mat<dtype> {
+        self.try_solve_symmetric(a, b)
+            .expect("LDLT decomp failed, matrix is singular")
+    }
+
+    fn solve_lst_sq(&mut self, a: SparseColMatRef<usize, dtype>, b: MatRef<dtype>) -> Mat<dtype> {
+        self.try_solve_lst_sq(a, b)
+            .expect("LDLT decomp failed, matrix is singular")
+    }
+
+    fn try_solve_symmetric(
+        &mut self,
+        a: SparseColMatRef<usize, dtype>,
+        b: MatRef<dtype>,
+    ) -> Option<Mat<dtype>> {
+        let natural = self.natural;
+        let symbolic = self
+            .symbolic
+            .get_or_insert_with(|| SymbolicLdlt::new(a, natural));
+        let numeric = NumericLdlt::new(symbolic, a)?;
+
+        let n = a.ncols();
+        let mut x = Mat::zeros(n, b.ncols());
+        let mut work = vec![0.0; n];
+        for j in 0..b.ncols() {
+            for (w, &row) in work.iter_mut().zip(symbolic.perm.iter()) {
+                *w = b.read(row, j);
+            }
+            numeric.solve_in_place(symbolic, &mut work);
+            for (w, &row) in work.iter().zip(symbolic.perm.iter()) {
+                x.write(row, j, *w);
+            }
+        }
+        Some(x)
+    }
+
+    fn try_solve_lst_sq(
+        &mut self,
+        a: SparseColMatRef<usize, dtype>,
+        b: MatRef<dtype>,
+    ) -> Option<Mat<dtype>> {
+        let ata = a
+            .transpose()
+            .to_col_major()
+            .expect("Failed to transpose A matrix")
+            .mul(a);
+        let atb = a.transpose().mul(b);
+
+        self.try_solve_symmetric(ata.as_ref(), atb.as_ref())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use faer::{mat, sparse::SparseColMat};
+    use faer_ext::IntoNalgebra;
+    use matrixcompare::assert_matrix_eq;
+
+    use super::*;
+    use crate::linalg::MatrixX;
+
+    #[test]
+    fn block_arrow() {
+        // Four 2x2 blocks, with the first connected to all others. Eliminating
+        // it first would fill in everything, so it should be ordered last
+        let n = 8;
+        let mut dense = MatrixX::zeros(n, n);
+        for b in 0..4 {
+            for i in 0..2 {
+                for j in 0..2 {
+                    let v = if i == j { 10.0 + b as dtype } else { 0.5 };
+                    dense[(2 * b + i, 2 * b + j)] = v;
+                    if b > 0 {
+                        dense[(i, 2 * b + j)] = 1.0 - 0.1 * (i + j) as dtype;
+                        dense[(2 * b + j, i)] = 1.0 - 0.1 * (i + j) as dtype;
+                    }
+                }
+            }
+        }
+        let triplets = (0..n)
+            .flat_map(|i| (0..n).map(move |j| (i, j)))
+            .filter(|&(i, j)| dense[(i, j)] != 0.0)
+            .map(|(i, j)| (i, j, dense[(i, j)]))
+            .collect::<Vec<_>>();
+        let a = SparseColMat::<usize, dtype>::try_new_from_triplets(n, n, &triplets)
+            .expect("Failed to make sparse matrix");
+
+        let perm = block_min_degree(a.as_ref());
+        assert_eq!(&perm[6..], &[0, 1]);
+
+        let b = mat![[1.0], [2.0], [3.0], [4.0], [5.0], [6.0], [7.0], [8.0]];
+        let mut solver = LDLTSolver::default();
+        let x = solver.solve_symmetric(a.as_ref(), b.as_ref());
+        let expected = dense
+            .cholesky()
+            .expect("Matrix is not positive definite")
+            .solve(&b.as_ref().into_nalgebra().clone_owned());
+        assert_matrix_eq!(x.as_ref().into_nalgebra(), expected, comp = abs, tol = 1e-6);
+
+        // Reusing the symbolic factorization for new values
+        let triplets = triplets
+            .iter()
+            .map(|&(i, j, v)| (i, j, 2.0 * v))
+            .collect::<Vec<_>>();
+        let a = SparseColMat::<usize, dtype>::try_new_from_triplets(n, n, &triplets)
+            .expect("Failed to make sparse matrix");
+        let x = solver.solve_symmetric(a.as_ref(), b.as_ref());
+        let expected = expected / 2.0;
+        assert_matrix_eq!(x.as_ref().into_nalgebra(), expected, comp = abs, tol = 1e-6);
+
+        // Eliminating the hub first as given fills in everything below it
+        let mut natural = LDLTSolver::natural();
+        let x = natural.solve_symmetric(a.as_ref(), b.as_ref());
+        assert_matrix_eq!(x.as_ref().into_nalgebra(), expected, comp = abs, tol = 1e-6);
+        let fill = |s: &LDLTSolver| s.symbolic.as_ref().map_or(0, |s| s.l_ptrs[n]);
+        assert_eq!(fill(&natural), n * (n - 1) / 2);
+        assert!(fill(&solver) < fill(&natural));
+    }
+
+    #[test]
+    fn singular() {
+        // The second diagonal entry is zero, with nothing to fill it in
+        let a =
+            SparseColMat::<usize, dtype>::try_new_from_triplets(2, 2, &[(0, 0, 1.0), (1, 1, 0.0)])
+                .expect("Failed to make sparse matrix");
+        let b = mat![[1.0], [1.0]];
+        let mut solver = LDLTSolver::default();
+        assert!(solver.try_solve_symmetric(a.as_ref(), b.as_ref()).is_none());
+    }
+}
@@ -0,0 +1,199 @@
+use std::ops::Mul;
+
+use faer::{
+    prelude::SpSolver,
+    sparse::linalg::solvers::{Cholesky, SymbolicCholesky},
+    Mat,
+};
+use faer_ext::IntoNalgebra;
+
+use crate::{
+    containers::{Graph, Idx, Symbol, Values, ValuesOrder},
+    dtype,
+    linalg::{DiffResult, MatrixX},
+};
+
+/// Marginal covariances of variables in a graph
+///
+/// Linearizes the graph about a set of values (usually the result of an
+/// optimizer) and factors the information matrix $A^\top A$ once using a
+/// sparse Cholesky decomposition. Covariance blocks are then recovered by
+/// solving against the columns of the identity belonging to the requested
+/// variables, so only those blocks of $(A^\top A)^{-1}$ are ever formed, never
+/// the full dense inverse.
+///
+/// Covariances are in the tangent space of each variable. Construction returns
+/// `None` if the information matrix is singular, ie some variable isn't fully
+/// constrained, and lookups return `None` for keys missing from the values.
+///
+/// ```
+/// # use factrs::{
+///    assign_symbols,
+///    containers::{Graph, FactorBuilder, Values},
+///    linear::Marginals,
+///    noise::GaussianNoise,
+///    residuals::PriorResidual,
+///    traits::*,
+///    variables::SO3,
+/// };
+/// # assign_symbols!(X: SO3);
+/// let mut graph = Graph::new();
+/// let noise = GaussianNoise::<3>::from_scalar_sigma(0.1);
+/// let res = PriorResidual::new(SO3::identity());
+/// graph.add_factor(FactorBuilder::new1(res, X(0)).noise(noise).build());
+///
+/// let mut values = Values::new();
+/// values.insert(X(0), SO3::identity());
+///
+/// let marginals = Marginals::new(&graph, &values).expect("Singular system");
+/// let cov = marginals.marginal_covariance(X(0)).expect("Missing key");
+/// assert!((cov[(0, 0)] - 0.01).abs() < 1e-6);
+/// ```
+pub struct Marginals {
+    order: ValuesOrder,
+    cholesky: Cholesky<usize, dtype>,
+}
+
+impl Marginals {
+    pub fn new(graph: &Graph, values: &Values) -> Option<Self> {
+        let order = ValuesOrder::from_values(values);
+        let linear = graph.linearize(values);
+        let graph_order = linear.sparsity_pattern(order.clone());
+        let DiffResult { diff: j, .. } = linear.residual_jacobian(&graph_order);
+
+        let info = j
+            .as_ref()
+            .transpose()
+            .to_col_major()
+            .expect("J failed to transpose")
+            .mul(j.as_ref());
+
+        let symbolic = SymbolicCholesky::try_new(info.symbolic(), faer::Side::Lower).ok()?;
+        let cholesky =
+            Cholesky::try_new_with_symbolic(symbolic, info.as_ref(), faer::Side::Lower).ok()?;
+
+        Some(Self { order, cholesky })
+    }
+
+    /// Covariance of a single variable
+    pub fn marginal_covariance(&self, key: impl Symbol) -> Option<MatrixX> {
+        let idx = self.get(key)?.clone();
+        Some(self.covariance(&[idx]))
+    }
+
+    /// Joint covariance of two variables
+    ///
+    /// The result is ordered as `key1` then `key2`, so the diagonal blocks are
+    /// their marginal covariances and the off-diagonal blocks their
+    /// cross-covariance.
+    pub fn joint_covariance(&self, key1: impl Symbol, key2: impl Symbol) -> Option<MatrixX> {
+        let idx1 = self.get(key1)?.clone();
+        let idx2 = self.get(key2)?.clone();
+        Some(self.covariance(&[idx1, idx2]))
+    }
+
+    fn get(&self, key: impl Symbol) -> Option<&Idx> {
+        self.order.get(key)
+    }
+
+    fn covariance(&self, idx: &[Idx]) -> MatrixX {
+        let dim = idx.iter().map(|i| i.dim).sum();
+
+        // Columns of the identity for the requested variables
+        let mut rhs = Mat::<dtype>::zeros(self.order.dim(), dim);
+        let mut col = 0;
+        for i in idx {
+            for j in 0..i.dim {
+                rhs[(i.idx + j, col)] = 1.0;
+                col += 1;
+            }
+        }
+        let sol = self.cholesky.solve(&rhs);
+        let sol = sol.as_ref().into_nalgebra();
+
+        // Pull out the rows of the requested variables
+        let mut out = MatrixX::zeros(dim, dim);
+        let mut row = 0;
+        for i in idx {
+            out.rows_mut(row, i.dim).copy_from(&sol.rows(i.idx, i.dim));
+            row += i.dim;
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use matrixcompare::assert_matrix_eq;
+
+    use super::*;
+    use crate::{
+        assign_symbols,
+        containers::{FactorBuilder, Key},
+        noise::GaussianNoise,
+        residuals::{BetweenResidual, PriorResidual},
+        utils::crlb,
+        variables::{Variable, SE2},
+    };
+
+    assign_symbols!(X: SE2);
+
+    #[test]
+    fn matches_dense() {
+        let mut graph = Graph::new();
+        let noise = GaussianNoise::<3>::from_diag_sigmas(0.1, 0.2, 0.3);
+        let res = PriorResidual::new(SE2::identity());
+        graph.add_factor(FactorBuilder::new1(res, X(0)).noise(noise).build());
+        let noise = GaussianNoise::<3>::from_diag_sigmas(0.05, 0.5, 0.1);
+        for i in 0..3 {
+            let res = BetweenResidual::new(SE2::new(0.1, 1.0, 0.0));
+            let factor = FactorBuilder::new2(res, X(i), X(i + 1))
+                .noise(noise.clone())
+                .build();
+            graph.add_factor(factor);
+        }
+
+        let mut values = Values::new();
+        for i in 0..4 {
+            values.insert(X(i), SE2::new(0.1 * i as dtype, i as dtype, 0.0));
+        }
+
+        let marginals = Marginals::new(&graph, &values).expect("Singular system");
+        let marginal = |i| marginals.marginal_covariance(X(i)).expect("Missing key");
+        let dense = crlb(&graph, &values).expect("Information matrix is singular");
+        for i in 0..4 {
+            let key: Key = X(i).into();
+            assert_matrix_eq!(marginal(i), dense[&key], comp = abs, tol = 1e-6);
+        }
+
+        // Diagonal blocks of the joint match the marginals
+        let joint = marginals.joint_covariance(X(1), X(3)).expect("Missing key");
+        assert_matrix_eq!(
+            joint.view((0, 0), (3, 3)),
+            marginal(1),
+            comp = abs,
+            tol = 1e-6
+        );
+        assert_matrix_eq!(
+            joint.view((3, 3), (3, 3)),
+            marginal(3),
+            comp = abs,
+            tol = 1e-6
+        );
+        assert_matrix_eq!(joint, joint.transpose(), comp = abs, tol = 1e-6);
+    }
+
+    #[test]
+    fn singular() {
+        // Nothing constrains X(1)
+        let mut graph = Graph::new();
+        let res = PriorResidual::new(SE2::identity());
+        graph.add_factor(FactorBuilder::new1(res, X(0)).build());
+
+        let mut values = Values::new();
+        values.insert(X(0), SE2::identity());
+        values.insert(X(1), SE2::new(0.1, 1.0, 0.0));
+
+        assert!(Marginals::new(&graph, &values).is_none());
+    }
+}
@@ -6,8 +6,23 @@ pub use factor::LinearFactor;
 mod graph;
 pub use graph::LinearGraph;
 
+mod system;
+pub use system::{LinearSystem, NullDirection, SystemDiagnostics};
+
 mod values;
 pub use values::LinearValues;
 
 mod solvers;
-pub use solvers::{CholeskySolver, LUSolver, LinearSolver, QRSolver};
+pub use solvers::{CholeskySolver, LUSolver, LinearSolver, MixedCholeskySolver, QRSolver};
+
+mod ldlt;
+pub use ldlt::LDLTSolver;
+
+mod cg;
+pub use cg::{ConjugateGradient, Preconditioner};
+
+mod schur;
+pub use schur::SchurSolver;
+
+mod marginals;
+pub use marginals::Marginals;
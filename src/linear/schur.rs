@@ -0,0 +1,354 @@
+use std::ops::Mul;
+
+use faer::{
+    sparse::{SparseColMat, SparseColMatRef},
+    Mat, MatRef,
+};
+use faer_ext::IntoNalgebra;
+use foldhash::HashMap;
+
+use super::{CholeskySolver, LinearSolver};
+use crate::{
+    containers::{DefaultSymbolHandler, ValuesOrder},
+    dtype,
+    linalg::MatrixX,
+};
+
+/// Schur complement linear solver for bundle adjustment style problems
+///
+/// In bundle adjustment every landmark is only connected to the cameras that
+/// observe it, so the landmark block $H_{pp}$ of the normal equations
+/// $$
+/// \begin{bmatrix} H_{cc} & H_{cp} \\\\ H_{pc} & H_{pp} \end{bmatrix}
+/// \begin{bmatrix} x_c \\\\ x_p \end{bmatrix} =
+/// \begin{bmatrix} g_c \\\\ g_p \end{bmatrix}
+/// $$
+/// is block diagonal and cheap to invert. This solver eliminates the
+/// landmarks first, solves the much smaller reduced camera system
+/// $$
+/// (H_{cc} - H_{cp} H_{pp}^{-1} H_{pc}) x_c = g_c - H_{cp} H_{pp}^{-1} g_p
+/// $$
+/// with a sparse Cholesky, and then back-substitutes for the landmarks.
+///
+/// Variables to eliminate are picked by the character of their symbol, ie
+/// `SchurSolver::new(['L'])` eliminates every `L(j)`. Eliminated variables
+/// can't share a factor with each other, and the solve fails if they do. Pass
+/// it to an optimizer with `with_solver`, as the default eliminates nothing
+/// and reduces to a plain Cholesky solve.
+/// ```
+/// # use factrs::{core::{Graph, GaussNewton}, linear::SchurSolver};
+/// # let graph = Graph::new();
+/// let solver = SchurSolver::new(['L']);
+/// let opt: GaussNewton<SchurSolver> = GaussNewton::with_solver(graph, solver);
+/// ```
+#[derive(Default)]
+pub struct SchurSolver {
+    points: Vec<char>,
+    // Start and dimension of each eliminated block
+    blocks: Vec<(usize, usize)>,
+    // Column in the reduced system of each column that isn't eliminated
+    reduced: Vec<Option<usize>>,
+    num_reduced: usize,
+    solver: CholeskySolver,
+}
+
+impl SchurSolver {
+    pub fn new(points: impl IntoIterator<Item = char>) -> Self {
+        Self {
+            points: points.into_iter().collect(),
+            ..Default::default()
+        }
+    }
+
+    // Without an ordering, nothing is eliminated
+    fn check_order(&mut self, n: usize) {
+        if self.reduced.len() != n {
+            self.blocks.clear();
+            self.reduced = (0..n).map(Some).collect();
+            self.num_reduced = n;
+        }
+    }
+}
+
+// Everything needed to back-substitute for one eliminated block
+struct Eliminated {
+    cams: Vec<usize>,
+    w: MatrixX,
+    h_pp_inv: MatrixX,
+    g_p: MatrixX,
+}
+
+impl LinearSolver for SchurSolver {
+    fn solve_symmetric(
+        &mut self,
+        a: SparseColMatRef<usize, dtype>,
+        b: MatRef<dtype>,
+    ) -> Mat<dtype> {
+        self.try_solve_symmetric(a, b)
+            .expect("Schur complement solve failed")
+    }
+
+    fn solve_lst_sq(&mut self, a: SparseColMatRef<usize, dtype>, b: MatRef<dtype>) -> Mat<dtype> {
+        self.try_solve_lst_sq(a, b)
+            .expect("Schur complement solve failed")
+    }
+
+    // Fails if an eliminated block is singular or coupled to another, or the
+    // reduced system can't be factored
+    fn try_solve_symmetric(
+        &mut self,
+        a: SparseColMatRef<usize, dtype>,
+        b: MatRef<dtype>,
+    ) -> Option<Mat<dtype>> {
+        let n = a.ncols();
+        let k = b.ncols();
+        self.check_order(n);
+        let b = b.into_nalgebra().clone_owned();
+
+        // Start the reduced system with H_cc and g_c
+        let mut triplets = Vec::new();
+        let mut g_c = MatrixX::zeros(self.num_reduced, k);
+        for (col, red_col) in self.reduced.iter().enumerate() {
+            let Some(red_col) = red_col else { continue };
+            g_c.row_mut(*red_col).copy_from(&b.row(col));
+            for (row, v) in a.row_indices_of_col(col).zip(a.values_of_col(col)) {
+                if let Some(red_row) = self.reduced[row] {
+                    triplets.push((red_row, *red_col, *v));
+                }
+            }
+        }
+
+        // Eliminate each block, subtracting its contribution from the rest
+        let mut eliminated = Vec::with_capacity(self.blocks.len());
+        for &(start, dim) in self.blocks.iter() {
+            let mut local = HashMap::<usize, usize>::default();
+            let mut cams = Vec::new();
+            for col in start..start + dim {
+                for row in a.row_indices_of_col(col) {
+                    if let Some(r) = self.reduced[row] {
+                        local.entry(r).or_insert_with(|| {
+                            cams.push(r);
+                            cams.len() - 1
+                        });
+                    }
+                }
+            }
+
+            let mut h_pp = MatrixX::zeros(dim, dim);
+            let mut w = MatrixX::zeros(cams.len(), dim);
+            for j in 0..dim {
+                let col = start + j;
+                for (row, v) in a.row_indices_of_col(col).zip(a.values_of_col(col)) {
+                    match self.reduced[row] {
+                        Some(r) => w[(local[&r], j)] = *v,
+                        // Eliminated variables can't share a factor
+                        None if !(start..start + dim).contains(&row) => return None,
+                        None => h_pp[(row - start, j)] = *v,
+                    }
+                }
+            }
+
+            let h_pp_inv = h_pp.try_inverse()?;
+            let g_p = b.rows(start, dim).clone_owned();
+            let w_inv = &w * &h_pp_inv;
+            let h_update = &w_inv * w.transpose();
+            let g_update = &w_inv * &g_p;
+            for (i, ri) in cams.iter().enumerate() {
+                for (j, rj) in cams.iter().enumerate() {
+                    triplets.push((*ri, *rj, -h_update[(i, j)]));
+                }
+                let mut row = g_c.row_mut(*ri);
+                row -= g_update.row(i);
+            }
+
+            eliminated.push(Eliminated {
+                cams,
+                w,
+                h_pp_inv,
+                g_p,
+            });
+        }
+
+        // Solve the reduced system
+        let x_c = if self.num_reduced > 0 {
+            let s = SparseColMat::<usize, dtype>::try_new_from_triplets(
+                self.num_reduced,
+                self.num_reduced,
+                &triplets,
+            )
+            .expect("Failed to form reduced system");
+            let g_c = Mat::from_fn(self.num_reduced, k, |i, j| g_c[(i, j)]);
+            self.solver
+                .try_solve_symmetric(s.as_ref(), g_c.as_ref())?
+                .as_ref()
+                .into_nalgebra()
+                .clone_owned()
+        } else {
+            MatrixX::zeros(0, k)
+        };
+
+        // And back-substitute for the eliminated blocks
+        let mut x = MatrixX::zeros(n, k);
+        for (col, red_col) in self.reduced.iter().enumerate() {
+            if let Some(red_col) = red_col {
+                x.row_mut(col).copy_from(&x_c.row(*red_col));
+            }
+        }
+        for (&(start, dim), e) in self.blocks.iter().zip(eliminated) {
+            let x_cams = x_c.select_rows(e.cams.iter());
+            let x_p = e.h_pp_inv * (e.g_p - e.w.transpose() * x_cams);
+            x.rows_mut(start, dim).copy_from(&x_p);
+        }
+
+        Some(Mat::from_fn(n, k, |i, j| x[(i, j)]))
+    }
+
+    fn try_solve_lst_sq(
+        &mut self,
+        a: SparseColMatRef<usize, dtype>,
+        b: MatRef<dtype>,
+    ) -> Option<Mat<dtype>> {
+        let ata = a
+            .transpose()
+            .to_col_major()
+            .expect("Failed to transpose A matrix")
+            .mul(a);
+        let atb = a.transpose().mul(b);
+
+        self.try_solve_symmetric(ata.as_ref(), atb.as_ref())
+    }
+
+    fn set_order(&mut self, order: &ValuesOrder) {
+        let mut blocks = Vec::new();
+        let mut eliminated = vec![false; order.dim()];
+        for (key, idx) in order.iter() {
+            let (chr, _) = DefaultSymbolHandler::key_to_sym(*key);
+            if self.points.contains(&chr) {
+                blocks.push((idx.idx, idx.dim));
+                eliminated[idx.idx..idx.idx + idx.dim].fill(true);
+            }
+        }
+        blocks.sort();
+
+        let mut num_reduced = 0;
+        self.reduced = eliminated
+            .iter()
+            .map(|e| {
+                if *e {
+                    None
+                } else {
+                    num_reduced += 1;
+                    Some(num_reduced - 1)
+                }
+            })
+            .collect();
+        self.num_reduced = num_reduced;
+        self.blocks = blocks;
+        self.solver = CholeskySolver::default();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use matrixcompare::assert_matrix_eq;
+
+    use super::*;
+    use crate::{
+        assign_symbols,
+        containers::{FactorBuilder, Graph, Values},
+        linalg::{vectorx, Vector3, VectorX},
+        noise::GaussianNoise,
+        optimizers::{GaussNewton, Optimizer},
+        residuals::{PriorResidual, ProjectionResidual},
+        variables::{MatrixLieGroup, Variable, VectorVar2, VectorVar3, SE3, SO3},
+    };
+
+    assign_symbols!(C: SE3; L: VectorVar3);
+
+    #[test]
+    fn matches_direct() {
+        let cameras = [
+            SE3::identity(),
+            SE3::from_rot_trans(
+                SO3::exp(vectorx![0.0, -0.1, 0.0].as_view()),
+                Vector3::new(1.0, 0.0, 0.0),
+            ),
+            SE3::from_rot_trans(
+                SO3::exp(vectorx![0.05, 0.1, 0.0].as_view()),
+                Vector3::new(-1.0, 0.5, 0.0),
+            ),
+        ];
+        let landmarks = (0..9)
+            .map(|i| {
+                let x = (i % 3) as dtype - 1.0;
+                let y = (i / 3) as dtype - 1.0;
+                VectorVar3::new(x, y, 5.0 + 0.2 * x * y)
+            })
+            .collect::<Vec<_>>();
+
+        let mut graph = Graph::new();
+        let model = ProjectionResidual::new(VectorVar2::identity(), 500.0, 500.0, 0.0, 0.0);
+        for (i, cam) in cameras.iter().enumerate() {
+            for (j, l) in landmarks.iter().enumerate() {
+                let p_c = VectorVar3::from(cam.inverse().apply(l.0.as_view()));
+                let pixel = model.project(p_c).expect("Landmark behind camera");
+                let res = ProjectionResidual::new(pixel, 500.0, 500.0, 0.0, 0.0);
+                graph.add_factor(FactorBuilder::new2(res, C(i as u32), L(j as u32)).build());
+            }
+        }
+        let noise = GaussianNoise::<6>::from_scalar_sigma(1e-3);
+        let res = PriorResidual::new(cameras[0].clone());
+        graph.add_factor(FactorBuilder::new1(res, C(0)).noise(noise).build());
+        let noise = GaussianNoise::<3>::from_scalar_sigma(1e-3);
+        let res = PriorResidual::new(landmarks[0].clone());
+        graph.add_factor(FactorBuilder::new1(res, L(0)).noise(noise).build());
+
+        let mut values = Values::new();
+        for (i, cam) in cameras.iter().enumerate() {
+            let noise = vectorx![0.01, -0.02, 0.01, 0.05, -0.05, 0.02] * i as dtype;
+            values.insert(C(i as u32), cam.oplus(noise.as_view()));
+        }
+        for (j, l) in landmarks.iter().enumerate() {
+            let offset = 0.05 * (j as dtype - 4.0);
+            let noise = vectorx![offset, -offset, 0.5 * offset];
+            values.insert(L(j as u32), l.oplus(noise.as_view()));
+        }
+
+        let mut direct: GaussNewton = GaussNewton::new(graph.clone());
+        let expected = direct
+            .optimize(values.clone())
+            .expect("Optimization failed");
+        let solver = SchurSolver::new(['L']);
+        let mut schur: GaussNewton<SchurSolver> = GaussNewton::with_solver(graph, solver);
+        let got = schur.optimize(values).expect("Optimization failed");
+
+        for i in 0..cameras.len() as u32 {
+            let g: &SE3 = got.get(C(i)).expect("Missing camera");
+            let e: &SE3 = expected.get(C(i)).expect("Missing camera");
+            assert_matrix_eq!(g.ominus(e), VectorX::zeros(6), comp = abs, tol = 1e-6);
+        }
+        for j in 0..landmarks.len() as u32 {
+            let g: &VectorVar3 = got.get(L(j)).expect("Missing landmark");
+            let e: &VectorVar3 = expected.get(L(j)).expect("Missing landmark");
+            assert_matrix_eq!(g.ominus(e), VectorX::zeros(3), comp = abs, tol = 1e-6);
+        }
+    }
+
+    #[test]
+    fn shared_factor_fails() {
+        let mut values = Values::new();
+        values.insert(L(0), VectorVar3::identity());
+        values.insert(L(1), VectorVar3::identity());
+        let mut solver = SchurSolver::new(['L']);
+        solver.set_order(&ValuesOrder::from_values(&values));
+
+        // The two eliminated variables are coupled
+        let mut triplets = (0..6).map(|i| (i, i, 2.0)).collect::<Vec<_>>();
+        triplets.extend([(0, 3, 1.0), (3, 0, 1.0)]);
+        let a = SparseColMat::<usize, dtype>::try_new_from_triplets(6, 6, &triplets)
+            .expect("Failed to create matrix");
+        let b = Mat::<dtype>::zeros(6, 1);
+
+        assert!(solver.try_solve_symmetric(a.as_ref(), b.as_ref()).is_none());
+    }
+}
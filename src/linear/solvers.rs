@@ -2,11 +2,11 @@ use std::ops::Mul;
 
 use faer::{
     prelude::SpSolver,
-    sparse::{linalg::solvers, SparseColMatRef},
+    sparse::{linalg::solvers, SparseColMat, SparseColMatRef},
     Mat, MatRef,
 };
 
-use crate::dtype;
+use crate::{containers::ValuesOrder, dtype};
 
 /// Trait to solve sparse linear systems
 pub trait LinearSolver: Default {
@@ -22,6 +22,40 @@ pub trait LinearSolver: Default {
     /// Used by QR to solve Ax = b, where the number of rows in A is greater
     /// than the number of columns
     fn solve_lst_sq(&mut self, a: SparseColMatRef<usize, dtype>, b: MatRef<dtype>) -> Mat<dtype>;
+
+    /// Set the ordering of the variables in the columns of the system
+    ///
+    /// Called by the optimizers whenever the ordering is recomputed, which
+    /// also means the structure of the system may have changed. By default
+    /// this resets the solver, dropping any cached symbolic factorization.
+    fn set_order(&mut self, _order: &ValuesOrder) {
+        *self = Self::default();
+    }
+
+    /// Solve a symmetric linear system, returning None if it couldn't be
+    /// factored
+    ///
+    /// Solvers that can detect a failed factorization, such as a Cholesky of
+    /// an indefinite system, should override this. By default it never fails.
+    fn try_solve_symmetric(
+        &mut self,
+        a: SparseColMatRef<usize, dtype>,
+        b: MatRef<dtype>,
+    ) -> Option<Mat<dtype>> {
+        Some(self.solve_symmetric(a, b))
+    }
+
+    /// Solve a least squares problem, returning None if it couldn't be
+    /// factored
+    ///
+    /// See [try_solve_symmetric](Self::try_solve_symmetric).
+    fn try_solve_lst_sq(
+        &mut self,
+        a: SparseColMatRef<usize, dtype>,
+        b: MatRef<dtype>,
+    ) -> Option<Mat<dtype>> {
+        Some(self.solve_lst_sq(a, b))
+    }
 }
 
 // ------------------------- Cholesky Linear Solver ------------------------- //
@@ -38,25 +72,38 @@ impl LinearSolver for CholeskySolver {
         a: SparseColMatRef<usize, dtype>,
         b: MatRef<dtype>,
     ) -> Mat<dtype> {
+        self.try_solve_symmetric(a, b)
+            .expect("Cholesky decomp failed")
+    }
+
+    fn solve_lst_sq(&mut self, a: SparseColMatRef<usize, dtype>, b: MatRef<dtype>) -> Mat<dtype> {
+        self.try_solve_lst_sq(a, b).expect("Cholesky decomp failed")
+    }
+
+    fn try_solve_symmetric(
+        &mut self,
+        a: SparseColMatRef<usize, dtype>,
+        b: MatRef<dtype>,
+    ) -> Option<Mat<dtype>> {
         if self.sparsity_pattern.is_none() {
-            self.sparsity_pattern = Some(
-                solvers::SymbolicCholesky::try_new(a.symbolic(), faer::Side::Lower)
-                    .expect("Symbolic cholesky failed"),
-            );
+            self.sparsity_pattern =
+                solvers::SymbolicCholesky::try_new(a.symbolic(), faer::Side::Lower).ok();
         }
 
         solvers::Cholesky::try_new_with_symbolic(
-            self.sparsity_pattern
-                .clone()
-                .expect("Missing symbol cholesky"),
+            self.sparsity_pattern.clone()?,
             a,
             faer::Side::Lower,
         )
-        .expect("Cholesky decomp failed")
-        .solve(&b)
+        .ok()
+        .map(|chol| chol.solve(&b))
     }
 
-    fn solve_lst_sq(&mut self, a: SparseColMatRef<usize, dtype>, b: MatRef<dtype>) -> Mat<dtype> {
+    fn try_solve_lst_sq(
+        &mut self,
+        a: SparseColMatRef<usize, dtype>,
+        b: MatRef<dtype>,
+    ) -> Option<Mat<dtype>> {
         let ata = a
             .transpose()
             .to_col_major()
@@ -65,7 +112,114 @@ impl LinearSolver for CholeskySolver {
 
         let atb = a.transpose().mul(b);
 
-        self.solve_symmetric(ata.as_ref(), atb.as_ref())
+        self.try_solve_symmetric(ata.as_ref(), atb.as_ref())
+    }
+}
+
+// ------------------------- Mixed Precision Cholesky ------------------------- //
+
+/// Cholesky linear solver working in f64 regardless of [dtype]
+///
+/// With the `f32` feature, variables, residuals, and Jacobians are all f32,
+/// which saves memory but loses precision when forming and factoring the
+/// normal equations $J^\top J$, whose condition number is the square of that
+/// of $J$. This solver widens the system to f64 first, then forms $J^\top J$,
+/// factors, and solves in f64 before narrowing the step back to f32. The rest
+/// of the optimization stays in f32.
+///
+/// Least-squares solves, used by [GaussNewton](crate::optimizers::GaussNewton)
+/// and [Dogleg](crate::optimizers::Dogleg), get the full benefit. Optimizers
+/// such as [LevenMarquardt](crate::optimizers::LevenMarquardt) that form
+/// $J^\top J$ themselves only get the factorization in f64. Without the `f32`
+/// feature, this is identical to [CholeskySolver].
+/// ```
+/// # use factrs::{containers::Graph, optimizers::GaussNewton, linear::MixedCholeskySolver};
+/// # let graph = Graph::new();
+/// let opt: GaussNewton<MixedCholeskySolver> = GaussNewton::new(graph);
+/// ```
+#[derive(Default)]
+pub struct MixedCholeskySolver {
+    sparsity_pattern: Option<solvers::SymbolicCholesky<usize>>,
+}
+
+#[allow(clippy::unnecessary_cast)]
+fn widen_sparse(a: SparseColMatRef<usize, dtype>) -> SparseColMat<usize, f64> {
+    let symbolic = a.symbolic().to_owned().expect("Failed to copy sparsity");
+    let values = a.values().iter().map(|x| *x as f64).collect();
+    SparseColMat::new(symbolic, values)
+}
+
+#[allow(clippy::unnecessary_cast)]
+fn widen(b: MatRef<dtype>) -> Mat<f64> {
+    Mat::from_fn(b.nrows(), b.ncols(), |i, j| b.read(i, j) as f64)
+}
+
+#[allow(clippy::unnecessary_cast)]
+fn narrow(x: MatRef<f64>) -> Mat<dtype> {
+    Mat::from_fn(x.nrows(), x.ncols(), |i, j| x.read(i, j) as dtype)
+}
+
+impl MixedCholeskySolver {
+    fn try_solve_wide(
+        &mut self,
+        a: SparseColMatRef<usize, f64>,
+        b: MatRef<f64>,
+    ) -> Option<Mat<dtype>> {
+        if self.sparsity_pattern.is_none() {
+            self.sparsity_pattern =
+                solvers::SymbolicCholesky::try_new(a.symbolic(), faer::Side::Lower).ok();
+        }
+
+        solvers::Cholesky::try_new_with_symbolic(
+            self.sparsity_pattern.clone()?,
+            a,
+            faer::Side::Lower,
+        )
+        .ok()
+        .map(|chol| narrow(chol.solve(&b).as_ref()))
+    }
+}
+
+impl LinearSolver for MixedCholeskySolver {
+    fn solve_symmetric(
+        &mut self,
+        a: SparseColMatRef<usize, dtype>,
+        b: MatRef<dtype>,
+    ) -> Mat<dtype> {
+        self.try_solve_symmetric(a, b)
+            .expect("Cholesky decomp failed")
+    }
+
+    fn solve_lst_sq(&mut self, a: SparseColMatRef<usize, dtype>, b: MatRef<dtype>) -> Mat<dtype> {
+        self.try_solve_lst_sq(a, b).expect("Cholesky decomp failed")
+    }
+
+    fn try_solve_symmetric(
+        &mut self,
+        a: SparseColMatRef<usize, dtype>,
+        b: MatRef<dtype>,
+    ) -> Option<Mat<dtype>> {
+        let a = widen_sparse(a);
+        self.try_solve_wide(a.as_ref(), widen(b).as_ref())
+    }
+
+    fn try_solve_lst_sq(
+        &mut self,
+        a: SparseColMatRef<usize, dtype>,
+        b: MatRef<dtype>,
+    ) -> Option<Mat<dtype>> {
+        let a = widen_sparse(a);
+        let ata = a
+            .as_ref()
+            .transpose()
+            .to_col_major()
+            .expect("Failed to transpose A matrix")
+            .mul(a.as_ref());
+
+        let b = widen(b);
+        let atb = a.as_ref().transpose().mul(b.as_ref());
+
+        self.try_solve_wide(ata.as_ref(), atb.as_ref())
     }
 }
 
@@ -87,21 +241,34 @@ impl LinearSolver for QRSolver {
     }
 
     fn solve_lst_sq(&mut self, a: SparseColMatRef<usize, dtype>, b: MatRef<dtype>) -> Mat<dtype> {
+        self.try_solve_lst_sq(a, b).expect("QR failed")
+    }
+
+    fn try_solve_symmetric(
+        &mut self,
+        a: SparseColMatRef<usize, dtype>,
+        b: MatRef<dtype>,
+    ) -> Option<Mat<dtype>> {
+        self.try_solve_lst_sq(a, b)
+    }
+
+    fn try_solve_lst_sq(
+        &mut self,
+        a: SparseColMatRef<usize, dtype>,
+        b: MatRef<dtype>,
+    ) -> Option<Mat<dtype>> {
         if self.sparsity_pattern.is_none() {
             self.sparsity_pattern =
                 Some(solvers::SymbolicQr::try_new(a.symbolic()).expect("Symbolic QR failed"));
         }
 
         // TODO: I think we're doing an extra copy here from solution -> slice solution
-        solvers::Qr::try_new_with_symbolic(
+        let qr = solvers::Qr::try_new_with_symbolic(
             self.sparsity_pattern.clone().expect("Missing symbolic QR"),
             a,
         )
-        .expect("QR failed")
-        .solve(&b)
-        .as_ref()
-        .subrows(0, a.ncols())
-        .to_owned()
+        .ok()?;
+        Some(qr.solve(&b).as_ref().subrows(0, a.ncols()).to_owned())
     }
 }
 
@@ -119,6 +286,18 @@ impl LinearSolver for LUSolver {
         a: SparseColMatRef<usize, dtype>,
         b: MatRef<dtype>,
     ) -> Mat<dtype> {
+        self.try_solve_symmetric(a, b).expect("LU decomp failed")
+    }
+
+    fn solve_lst_sq(&mut self, a: SparseColMatRef<usize, dtype>, b: MatRef<dtype>) -> Mat<dtype> {
+        self.try_solve_lst_sq(a, b).expect("LU decomp failed")
+    }
+
+    fn try_solve_symmetric(
+        &mut self,
+        a: SparseColMatRef<usize, dtype>,
+        b: MatRef<dtype>,
+    ) -> Option<Mat<dtype>> {
         if self.sparsity_pattern.is_none() {
             self.sparsity_pattern =
                 Some(solvers::SymbolicLu::try_new(a.symbolic()).expect("Symbolic LU failed"));
@@ -128,11 +307,15 @@ impl LinearSolver for LUSolver {
             self.sparsity_pattern.clone().expect("Symbolic LU missing"),
             a.as_ref(),
         )
-        .expect("LU decomp failed")
-        .solve(&b)
+        .ok()
+        .map(|lu| lu.solve(&b))
     }
 
-    fn solve_lst_sq(&mut self, a: SparseColMatRef<usize, dtype>, b: MatRef<dtype>) -> Mat<dtype> {
+    fn try_solve_lst_sq(
+        &mut self,
+        a: SparseColMatRef<usize, dtype>,
+        b: MatRef<dtype>,
+    ) -> Option<Mat<dtype>> {
         let ata = a
             .transpose()
             .to_col_major()
@@ -140,13 +323,13 @@ impl LinearSolver for LUSolver {
             .mul(a);
         let atb = a.transpose().mul(b);
 
-        self.solve_symmetric(ata.as_ref(), atb.as_ref())
+        self.try_solve_symmetric(ata.as_ref(), atb.as_ref())
     }
 }
 
 #[cfg(test)]
 mod test {
-    use faer::{mat, sparse::SparseColMat};
+    use faer::mat;
     use matrixcompare::assert_matrix_eq;
 
     use super::*;
@@ -180,6 +363,38 @@ mod test {
         solve(&mut solver);
     }
 
+    #[test]
+    fn test_mixed_cholesky_solver() {
+        let mut solver = MixedCholeskySolver::default();
+        solve(&mut solver);
+    }
+
+    #[cfg(all(feature = "f32", not(feature = "left"), not(feature = "fake_exp")))]
+    #[test]
+    fn mixed_precision_m3500() {
+        use crate::{
+            optimizers::{GaussNewton, Optimizer},
+            utils::load_g20,
+        };
+
+        let (graph, init) = load_g20("examples/data/M3500.g2o");
+        let mut mixed: GaussNewton<MixedCholeskySolver> = GaussNewton::new(graph.clone());
+        let mixed = mixed.optimize(init.clone()).expect("Mixed failed");
+
+        // Factoring in f32 may not converge at all, but when it does, widening
+        // to f64 should be at least as good
+        let mut plain: GaussNewton<CholeskySolver> = GaussNewton::new(graph);
+        if let Ok(plain) = plain.optimize(init) {
+            let rel = (mixed.final_error - plain.final_error) / plain.final_error;
+            assert!(
+                rel < 0.01,
+                "mixed {} vs plain {}",
+                mixed.final_error,
+                plain.final_error
+            );
+        }
+    }
+
     #[test]
     fn test_qr_solver() {
         let mut solver = QRSolver::default();
@@ -191,4 +406,22 @@ mod test {
         let mut solver = LUSolver::default();
         solve(&mut solver);
     }
+
+    #[test]
+    fn test_ldlt_solver() {
+        let mut solver = crate::linear::LDLTSolver::default();
+        solve(&mut solver);
+    }
+
+    #[test]
+    fn test_cg_solver() {
+        let mut solver = crate::linear::ConjugateGradient::default();
+        solve(&mut solver);
+    }
+
+    #[test]
+    fn test_schur_solver() {
+        let mut solver = crate::linear::SchurSolver::default();
+        solve(&mut solver);
+    }
 }
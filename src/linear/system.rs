@@ -0,0 +1,279 @@
+use faer::sparse::SparseColMat;
+use faer_ext::IntoNalgebra;
+
+use crate::{
+    containers::{Graph, Key, Values, ValuesOrder},
+    dtype,
+    linalg::{DiffResult, MatrixX, VectorX},
+    noise::CONDITION_THRESHOLD,
+};
+
+/// The linearized least-squares system of a graph
+///
+/// Holds the stacked Jacobian $J$ and residual $r$ of every factor at a set
+/// of values, whitened by the noise models and weighted by the robust kernels,
+/// exactly as the optimizers see them. A Gauss-Newton step $\Delta$ solves
+/// $$
+/// J \Delta = -r
+/// $$
+/// Useful for experimenting with custom solvers or analyzing the problem
+/// without running a full optimization.
+///
+/// Columns are laid out per variable according to [order](Self::order), so
+/// the columns of variable `key` start at `order.get(key).idx`. Fixed
+/// variables have no columns.
+/// ```
+/// # use factrs::{
+/// #    assign_symbols,
+/// #    containers::{Graph, Values},
+/// #    fac,
+/// #    linear::LinearSystem,
+/// #    residuals::PriorResidual,
+/// #    traits::*,
+/// #    variables::SE2,
+/// # };
+/// # assign_symbols!(X: SE2);
+/// let mut graph = Graph::new();
+/// graph.add_factor(fac![PriorResidual::new(SE2::identity()), X(0)]);
+/// let mut values = Values::new();
+/// values.insert(X(0), SE2::new(0.1, 1.0, 2.0));
+///
+/// let system = LinearSystem::new(&graph, &values);
+/// assert_eq!(system.jacobian.nrows(), 3);
+/// assert_eq!(system.order.get(X(0)).unwrap().dim, 3);
+/// ```
+pub struct LinearSystem {
+    /// Stacked Jacobian $J$
+    pub jacobian: SparseColMat<usize, dtype>,
+    /// Stacked residual $r$
+    pub residual: faer::Mat<dtype>,
+    /// Column of each variable in the Jacobian
+    pub order: ValuesOrder,
+}
+
+impl LinearSystem {
+    /// Linearize `graph` at `values`
+    pub fn new(graph: &Graph, values: &Values) -> Self {
+        Self::with_order(graph, values, ValuesOrder::from_values(values))
+    }
+
+    /// Linearize `graph` at `values`, with the columns laid out by `order`
+    pub fn with_order(graph: &Graph, values: &Values, order: ValuesOrder) -> Self {
+        let graph_order = graph.sparsity_pattern(order);
+        let linear = graph.linearize(values);
+        let DiffResult {
+            value: b,
+            diff: jacobian,
+        } = linear.residual_jacobian(&graph_order);
+
+        // Linear factors store b = -r
+        let residual = faer::Mat::from_fn(b.nrows(), 1, |i, _| -b[(i, 0)]);
+        Self {
+            jacobian,
+            residual,
+            order: graph_order.order,
+        }
+    }
+
+    /// Dimension of the residual, ie the number of rows
+    pub fn dim_out(&self) -> usize {
+        self.jacobian.nrows()
+    }
+
+    /// Dimension of the variables, ie the number of columns
+    pub fn dim_in(&self) -> usize {
+        self.jacobian.ncols()
+    }
+
+    /// Diagnose how well the variables are constrained
+    ///
+    /// Computes the eigendecomposition of the information matrix $J^\top J$,
+    /// which is dense and so only meant for debugging small to medium sized
+    /// problems. See [SystemDiagnostics] for what's reported.
+    pub fn diagnostics(&self) -> SystemDiagnostics {
+        let j: MatrixX = self
+            .jacobian
+            .to_dense()
+            .as_ref()
+            .into_nalgebra()
+            .clone_owned();
+        let dim = j.ncols();
+        if dim == 0 {
+            return SystemDiagnostics {
+                condition: 1.0,
+                rank: 0,
+                dim,
+                null_space: Vec::new(),
+            };
+        }
+        let eig = (j.transpose() * &j).symmetric_eigen();
+
+        // Eigenvalues at or below round off are treated as exactly zero
+        let max = eig.eigenvalues.max();
+        let tol = max * dim as dtype * dtype::EPSILON;
+        let rank = eig.eigenvalues.iter().filter(|l| **l > tol).count();
+        let condition = if rank < dim {
+            dtype::INFINITY
+        } else {
+            max / eig.eigenvalues.min()
+        };
+
+        let mut null_space = eig
+            .eigenvalues
+            .iter()
+            .zip(eig.eigenvectors.column_iter())
+            .filter(|(l, _)| **l * CONDITION_THRESHOLD < max)
+            .map(|(l, v)| {
+                let mut components = self
+                    .order
+                    .iter()
+                    .map(|(key, idx)| (*key, v.rows(idx.idx, idx.dim).clone_owned()))
+                    .filter(|(_, c)| c.norm() > NULL_COMPONENT_TOL)
+                    .collect::<Vec<_>>();
+                components.sort_by(|a, b| b.1.norm().total_cmp(&a.1.norm()));
+                NullDirection {
+                    eigenvalue: if *l > tol { *l } else { 0.0 },
+                    components,
+                }
+            })
+            .collect::<Vec<_>>();
+        null_space.sort_by(|a, b| a.eigenvalue.total_cmp(&b.eigenvalue));
+
+        SystemDiagnostics {
+            condition,
+            rank,
+            dim,
+            null_space,
+        }
+    }
+}
+
+// Part of a unit eigenvector below which a variable is considered uninvolved
+const NULL_COMPONENT_TOL: dtype = 1e-3;
+
+/// A direction the variables are (nearly) unconstrained along
+///
+/// Moving the variables along this direction barely changes the error, such as
+/// the scale of a monocular bundle adjustment problem.
+#[derive(Debug, Clone)]
+pub struct NullDirection {
+    /// Eigenvalue of the information matrix, ie the curvature of the error
+    /// along this direction
+    pub eigenvalue: dtype,
+    /// Part of the direction in the tangent space of each involved variable,
+    /// largest first
+    pub components: Vec<(Key, VectorX)>,
+}
+
+/// Diagnostics of a [LinearSystem], see [LinearSystem::diagnostics]
+#[derive(Debug, Clone)]
+pub struct SystemDiagnostics {
+    /// Condition number of the information matrix, infinite if it's rank
+    /// deficient
+    pub condition: dtype,
+    /// Numerical rank of the information matrix
+    pub rank: usize,
+    /// Dimension of the information matrix, a rank below this means the
+    /// problem is degenerate
+    pub dim: usize,
+    /// Directions whose eigenvalue is more than [CONDITION_THRESHOLD] times
+    /// smaller than the largest, sorted from least to most constrained
+    pub null_space: Vec<NullDirection>,
+}
+
+impl SystemDiagnostics {
+    /// Check if the problem is degenerate or near-degenerate
+    pub fn is_degenerate(&self) -> bool {
+        !self.null_space.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use matrixcompare::assert_scalar_eq;
+
+    use super::*;
+    use crate::{
+        assign_symbols, fac,
+        linalg::{MatrixX, VectorX},
+        residuals::{BetweenResidual, PriorResidual},
+        variables::{Variable, VectorVar2},
+    };
+
+    assign_symbols!(X: VectorVar2);
+
+    #[test]
+    fn step_solves_linear() {
+        let mut graph = Graph::new();
+        graph.add_factor(fac![
+            PriorResidual::new(VectorVar2::new(1.0, 2.0)),
+            X(0),
+            0.1 as std
+        ]);
+        let res = BetweenResidual::new(VectorVar2::new(-1.0, 0.5));
+        graph.add_factor(fac![res, (X(0), X(1))]);
+
+        let mut values = Values::new();
+        values.insert(X(0), VectorVar2::identity());
+        values.insert(X(1), VectorVar2::identity());
+        values.fix(X(0));
+
+        let system = LinearSystem::new(&graph, &values);
+        assert_eq!(system.dim_out(), 4);
+        assert_eq!(system.dim_in(), 2);
+
+        // The problem is linear, so one step lands on the optimum of X(1)
+        let j: MatrixX = system
+            .jacobian
+            .to_dense()
+            .as_ref()
+            .into_nalgebra()
+            .clone_owned();
+        let r: VectorX = system
+            .residual
+            .as_ref()
+            .into_nalgebra()
+            .column(0)
+            .clone_owned();
+        let delta = (j.transpose() * &j)
+            .cholesky()
+            .expect("Singular")
+            .solve(&(-j.transpose() * r));
+
+        let x1: &VectorVar2 = values.get(X(1)).expect("Missing X(1)");
+        let got = x1.oplus(delta.as_view());
+        assert_scalar_eq!(got.0.x, -1.0, comp = abs, tol = 1e-6);
+        assert_scalar_eq!(got.0.y, 0.5, comp = abs, tol = 1e-6);
+    }
+
+    #[test]
+    fn diagnostics() {
+        let mut graph = Graph::new();
+        let res = BetweenResidual::new(VectorVar2::new(1.0, 0.0));
+        graph.add_factor(fac![res, (X(0), X(1))]);
+
+        let mut values = Values::new();
+        values.insert(X(0), VectorVar2::identity());
+        values.insert(X(1), VectorVar2::identity());
+
+        // Nothing anchors the pair, so they can shift together freely
+        let diag = LinearSystem::new(&graph, &values).diagnostics();
+        assert_eq!(diag.dim, 4);
+        assert_eq!(diag.rank, 2);
+        assert!(diag.is_degenerate());
+        assert_eq!(diag.condition, dtype::INFINITY);
+        assert_eq!(diag.null_space.len(), 2);
+        for dir in diag.null_space.iter() {
+            assert_eq!(dir.components.len(), 2);
+            let diff = &dir.components[0].1 - &dir.components[1].1;
+            assert!(diff.norm() < 1e-6);
+        }
+
+        // Anchoring it fixes that
+        graph.add_factor(fac![PriorResidual::new(VectorVar2::identity()), X(0)]);
+        let diag = LinearSystem::new(&graph, &values).diagnostics();
+        assert_eq!(diag.rank, 4);
+        assert!(!diag.is_degenerate());
+        assert!(diag.condition.is_finite());
+    }
+}
@@ -6,6 +6,33 @@ use crate::{
     linalg::{Const, Matrix, MatrixView, MatrixViewX, MatrixX, Vector, VectorView, VectorX},
 };
 
+/// Condition number above which a noise model is considered near-singular.
+#[cfg(not(feature = "f32"))]
+pub const CONDITION_THRESHOLD: dtype = 1e10;
+/// Condition number above which a noise model is considered near-singular.
+#[cfg(feature = "f32")]
+pub const CONDITION_THRESHOLD: dtype = 1e5;
+
+/// Error when building a [GaussianNoise] from a matrix
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NoiseError {
+    /// The covariance is singular, ie the measurement is degenerate
+    Singular,
+    /// The matrix isn't symmetric positive definite
+    NotPositiveDefinite,
+}
+
+impl fmt::Display for NoiseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NoiseError::Singular => write!(f, "covariance is singular"),
+            NoiseError::NotPositiveDefinite => write!(f, "matrix is not positive definite"),
+        }
+    }
+}
+
+impl std::error::Error for NoiseError {}
+
 /// A Gaussian noise model.
 ///
 /// This noise model is used to represent Gaussian noise in a factor graph. This
@@ -20,16 +47,28 @@ pub struct GaussianNoise<const N: usize> {
 impl<const N: usize> NoiseModel for GaussianNoise<N> {
     type Dim = Const<N>;
 
-    fn whiten_vec(&self, v: VectorX) -> VectorX {
-        let mut out = VectorX::zeros(v.len());
-        self.sqrt_inf.mul_to(&v, &mut out);
-        out
+    // Whitening is done in place using the static size, so the input
+    // buffer is reused and nothing is allocated
+    fn whiten_vec(&self, mut v: VectorX) -> VectorX {
+        self.whiten_vec_mut(&mut v);
+        v
+    }
+
+    fn whiten_mat(&self, mut m: MatrixX) -> MatrixX {
+        self.whiten_mat_mut(&mut m);
+        m
     }
 
-    fn whiten_mat(&self, m: MatrixX) -> MatrixX {
-        let mut out = MatrixX::zeros(m.nrows(), m.ncols());
-        self.sqrt_inf.mul_to(&m, &mut out);
-        out
+    fn whiten_vec_mut(&self, v: &mut VectorX) {
+        let out: Vector<N> = self.sqrt_inf * v.fixed_rows::<N>(0);
+        v.copy_from(&out);
+    }
+
+    fn whiten_mat_mut(&self, m: &mut MatrixX) {
+        for mut col in m.column_iter_mut() {
+            let out: Vector<N> = self.sqrt_inf * col.fixed_rows::<N>(0);
+            col.copy_from(&out);
+        }
     }
 }
 
@@ -105,26 +144,113 @@ impl<const N: usize> GaussianNoise<N> {
         Self { sqrt_inf }
     }
 
+    /// Create a diagonal Gaussian noise from a slice of sigmas.
+    ///
+    /// Useful for larger noise models, such as the 15 dimensions of an IMU
+    /// factor, where [from_diag_sigmas](GaussianNoise::from_diag_sigmas)
+    /// isn't available. Panics if the slice isn't of length N.
+    pub fn from_diag_sigmas_slice(sigmas: &[dtype]) -> Self {
+        assert_eq!(sigmas.len(), N, "Expected {} sigmas", N);
+        Self::from_vec_sigma(Vector::<N>::from_column_slice(sigmas).as_view())
+    }
+
+    /// Create a diagonal Gaussian noise from a slice of covariances.
+    ///
+    /// See [from_diag_sigmas_slice](Self::from_diag_sigmas_slice).
+    pub fn from_diag_covs_slice(covs: &[dtype]) -> Self {
+        assert_eq!(covs.len(), N, "Expected {} covariances", N);
+        Self::from_vec_cov(Vector::<N>::from_column_slice(covs).as_view())
+    }
+
     /// Create a Gaussian noise from a covariance matrix.
+    ///
+    /// Logs a warning if the covariance is near-singular, see
+    /// [is_ill_conditioned](Self::is_ill_conditioned). Panics if it's singular
+    /// or not positive definite, see [try_from_matrix_cov](Self::try_from_matrix_cov)
+    /// to handle these instead.
     pub fn from_matrix_cov(cov: MatrixView<N, N>) -> Self {
+        match Self::try_from_matrix_cov(cov) {
+            Ok(noise) => noise,
+            Err(e) => panic!("Failed to create sqrt information from covariance: {}", e),
+        }
+    }
+
+    /// Create a Gaussian noise from a covariance matrix, failing if it's
+    /// singular or not positive definite.
+    pub fn try_from_matrix_cov(cov: MatrixView<N, N>) -> Result<Self, NoiseError> {
         let sqrt_inf = cov
             .try_inverse()
-            .expect("Matrix inversion failed when creating sqrt covariance.")
+            .ok_or(NoiseError::Singular)?
             .cholesky()
-            .expect("Cholesky failed when creating sqrt information.")
+            .ok_or(NoiseError::NotPositiveDefinite)?
             .l()
             .transpose();
-        Self { sqrt_inf }
+        Ok(Self { sqrt_inf }.warn_if_ill_conditioned())
+    }
+
+    /// Create a Gaussian noise from a covariance matrix, regularizing it
+    /// first.
+    ///
+    /// Adds `epsilon` to the diagonal of the covariance, ie
+    /// $\Sigma + \epsilon I$, so singular or near-singular covariances from
+    /// degenerate measurements become usable. Directions with no uncertainty end up with a
+    /// variance of `epsilon`, and so an information of $1 / \epsilon$, which
+    /// should be chosen small relative to the rest of the covariance but large
+    /// enough to not dominate the problem. Still fails if the covariance has
+    /// eigenvalues below $-\epsilon$, as it's then not a covariance at all.
+    pub fn try_from_matrix_cov_regularized(
+        cov: MatrixView<N, N>,
+        epsilon: dtype,
+    ) -> Result<Self, NoiseError> {
+        let cov = cov + Matrix::<N, N>::from_diagonal_element(epsilon);
+        Self::try_from_matrix_cov(cov.as_view())
     }
 
     /// Create a Gaussian noise from an information matrix.
+    ///
+    /// Logs a warning if the information is near-singular, see
+    /// [is_ill_conditioned](Self::is_ill_conditioned).
     pub fn from_matrix_inf(inf: MatrixView<N, N>) -> Self {
         let sqrt_inf = inf
             .cholesky()
             .expect("Cholesky failed when creating sqrt information.")
             .l()
             .transpose();
-        Self { sqrt_inf }
+        Self { sqrt_inf }.warn_if_ill_conditioned()
+    }
+
+    /// Condition number of the covariance (equivalently, the information).
+    pub fn condition_number(&self) -> dtype {
+        let sv = MatrixX::from_iterator(N, N, self.sqrt_inf.iter().cloned()).singular_values();
+        let cond = sv.max() / sv.min();
+        cond * cond
+    }
+
+    /// Check if the covariance is near-singular.
+    ///
+    /// A near-singular covariance results in a huge information along some
+    /// direction, which can dominate the rest of the problem and destabilize
+    /// the optimization. This is flagged when the
+    /// [condition number](Self::condition_number) exceeds
+    /// [CONDITION_THRESHOLD].
+    pub fn is_ill_conditioned(&self) -> bool {
+        self.condition_number() > CONDITION_THRESHOLD
+    }
+
+    /// Whiten a fixed-size vector, without any heap allocation.
+    pub fn whiten_vec_fixed(&self, v: VectorView<N>) -> Vector<N> {
+        self.sqrt_inf * v
+    }
+
+    fn warn_if_ill_conditioned(self) -> Self {
+        if self.is_ill_conditioned() {
+            log::warn!(
+                "GaussianNoise{} is near-singular (condition number {:.3e})",
+                N,
+                self.condition_number()
+            );
+        }
+        self
     }
 }
 
@@ -235,8 +361,180 @@ make_gaussian_vector! {
     6, [s0, s1, s2, s3, s4, s5];
 }
 
+impl GaussianNoise<3> {
+    /// Create a diagonal Gaussian noise for an [SE2](crate::variables::SE2).
+    ///
+    /// The tangent space of SE2 is ordered rotation first, $(\theta, x, y)$,
+    /// so this has sigmas `[rot_sigma, trans_sigma, trans_sigma]`, with the
+    /// rotation in radians.
+    pub fn se2(rot_sigma: dtype, trans_sigma: dtype) -> Self {
+        Self::from_diag_sigmas(rot_sigma, trans_sigma, trans_sigma)
+    }
+}
+
+impl GaussianNoise<6> {
+    /// Create a diagonal Gaussian noise for an [SE3](crate::variables::SE3).
+    ///
+    /// The tangent space of SE3 is ordered rotation first, $(\omega, v)$, so
+    /// this has sigmas `[rot_sigma; 3]` followed by `[trans_sigma; 3]`, with
+    /// the rotation in radians. Note this is the opposite of the translation
+    /// first ordering used by some other libraries, such as g2o.
+    pub fn se3(rot_sigma: dtype, trans_sigma: dtype) -> Self {
+        Self::from_split_sigma(rot_sigma, trans_sigma)
+    }
+}
+
 impl<const N: usize> fmt::Display for GaussianNoise<N> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "GaussianNoise{}: {:}", self.dim(), self.sqrt_inf)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::{
+        sync::Mutex,
+        thread::{self, ThreadId},
+    };
+
+    use matrixcompare::assert_matrix_eq;
+
+    use super::*;
+    use crate::linalg::Matrix2;
+
+    // Records warnings along with the thread that logged them, so tests
+    // running in parallel don't see each other's warnings
+    struct WarnLogger(Mutex<Vec<(ThreadId, String)>>);
+
+    impl log::Log for WarnLogger {
+        fn enabled(&self, metadata: &log::Metadata) -> bool {
+            metadata.level() <= log::Level::Warn
+        }
+
+        fn log(&self, record: &log::Record) {
+            if self.enabled(record.metadata()) {
+                let msg = (thread::current().id(), record.args().to_string());
+                self.0.lock().expect("Poisoned logger").push(msg);
+            }
+        }
+
+        fn flush(&self) {}
+    }
+
+    static LOGGER: WarnLogger = WarnLogger(Mutex::new(Vec::new()));
+
+    fn warnings() -> Vec<String> {
+        let id = thread::current().id();
+        LOGGER
+            .0
+            .lock()
+            .expect("Poisoned logger")
+            .iter()
+            .filter(|(t, _)| *t == id)
+            .map(|(_, msg)| msg.clone())
+            .collect()
+    }
+
+    #[test]
+    fn ill_conditioned() {
+        // Only fails if another logger was set first
+        let _ = log::set_logger(&LOGGER);
+        log::set_max_level(log::LevelFilter::Warn);
+
+        let cov = Matrix2::new(1.0, 0.0, 0.0, 1e-11);
+        let noise = GaussianNoise::<2>::from_matrix_cov(cov.as_view());
+        assert!(noise.is_ill_conditioned());
+        let logged = warnings();
+        assert_eq!(logged.len(), 1);
+        assert!(logged[0].contains("near-singular"));
+
+        let cov = Matrix2::new(1.0, 0.2, 0.2, 0.5);
+        let noise = GaussianNoise::<2>::from_matrix_cov(cov.as_view());
+        assert!(!noise.is_ill_conditioned());
+        assert_eq!(warnings().len(), 1);
+    }
+
+    #[test]
+    fn diag_slice() {
+        let sigmas = [0.1, 0.2, 0.3];
+        let expected = GaussianNoise::<3>::from_diag_sigmas(0.1, 0.2, 0.3);
+        let noise = GaussianNoise::<3>::from_diag_sigmas_slice(&sigmas);
+        assert_matrix_eq!(noise.sqrt_inf, expected.sqrt_inf, comp = float);
+
+        let covs = sigmas.map(|s| s * s);
+        let noise = GaussianNoise::<3>::from_diag_covs_slice(&covs);
+        assert_matrix_eq!(noise.sqrt_inf, expected.sqrt_inf, comp = abs, tol = 1e-6);
+    }
+
+    #[test]
+    fn rank_deficient() {
+        // Perfectly correlated, so nothing is known along (1, -1)
+        let cov = Matrix2::new(1.0, 1.0, 1.0, 1.0);
+        assert!(GaussianNoise::<2>::try_from_matrix_cov(cov.as_view()).is_err());
+
+        let eps = 1e-3;
+        let noise = GaussianNoise::<2>::try_from_matrix_cov_regularized(cov.as_view(), eps)
+            .expect("Regularized covariance failed");
+        let along = noise.whiten_vec(VectorX::from_vec(vec![1.0, 1.0]));
+        let across = noise.whiten_vec(VectorX::from_vec(vec![1.0, -1.0]));
+        assert!((along.norm_squared() - 2.0 / (2.0 + eps)).abs() < 1e-3);
+        assert!((across.norm_squared() * eps - 2.0).abs() < 1e-3);
+
+        // Not a covariance, regularizing can't help
+        let cov = Matrix2::new(1.0, 0.0, 0.0, -1.0);
+        assert_eq!(
+            GaussianNoise::<2>::try_from_matrix_cov_regularized(cov.as_view(), eps).err(),
+            Some(NoiseError::NotPositiveDefinite)
+        );
+    }
+
+    #[test]
+    fn whiten() {
+        let cov = Matrix2::new(1.0, 0.2, 0.2, 0.5);
+        let noise = GaussianNoise::<2>::from_matrix_cov(cov.as_view());
+
+        let v = VectorX::from_vec(vec![1.0, -2.0]);
+        let expected = noise.sqrt_inf * Vector::<2>::new(1.0, -2.0);
+        assert_matrix_eq!(noise.whiten_vec(v), expected, comp = float);
+        assert_matrix_eq!(
+            noise.whiten_vec_fixed(Vector::<2>::new(1.0, -2.0).as_view()),
+            expected,
+            comp = float
+        );
+
+        let m = MatrixX::from_row_slice(2, 3, &[1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+        let expected = noise.sqrt_inf * &m;
+        assert_matrix_eq!(noise.whiten_mat(m.clone()), expected, comp = float);
+
+        // And in place
+        let mut m = m;
+        noise.whiten_mat_mut(&mut m);
+        assert_matrix_eq!(m, expected, comp = float);
+        let mut v = VectorX::from_vec(vec![1.0, -2.0]);
+        noise.whiten_vec_mut(&mut v);
+        assert_matrix_eq!(
+            v,
+            noise.sqrt_inf * Vector::<2>::new(1.0, -2.0),
+            comp = float
+        );
+    }
+
+    #[test]
+    fn se2_se3_rotation_first() {
+        let noise = GaussianNoise::<3>::se2(0.1, 2.0);
+        let expected = Vector::<3>::new(10.0, 0.5, 0.5);
+        assert_matrix_eq!(
+            noise.sqrt_inf,
+            Matrix::<3, 3>::from_diagonal(&expected),
+            comp = float
+        );
+
+        let noise = GaussianNoise::<6>::se3(0.1, 2.0);
+        let expected = Vector::<6>::new(10.0, 10.0, 10.0, 0.5, 0.5, 0.5);
+        assert_matrix_eq!(
+            noise.sqrt_inf,
+            Matrix::<6, 6>::from_diagonal(&expected),
+            comp = float
+        );
+    }
+}
@@ -1,7 +1,8 @@
 //! Noise model representations
 //!
-//! Represent Gaussian noise models in a factor graph, specifically used when
-//! constructing a [factor](crate::containers::Factor).
+//! Represent noise models in a factor graph, specifically used when
+//! constructing a [factor](crate::containers::Factor). Generally this will be
+//! a [GaussianNoise], with [StudentTNoise] available for heavy-tailed noise.
 
 use std::fmt::Debug;
 
@@ -17,18 +18,44 @@ pub trait NoiseModel: Debug + DynClone {
     where
         Self: Sized;
 
-    fn dim(&self) -> usize
-    where
-        Self: Sized,
-    {
-        Self::Dim::USIZE
-    }
+    /// The dimension of the noise model as a value
+    ///
+    /// Unlike [Dim](Self::Dim), this is available on trait objects. It's
+    /// implemented by [mark](factrs::mark) from [Dim](Self::Dim).
+    fn dim(&self) -> usize;
 
     /// Whiten a vector
     fn whiten_vec(&self, v: VectorX) -> VectorX;
 
     /// Whiten a matrix
     fn whiten_mat(&self, m: MatrixX) -> MatrixX;
+
+    /// Whiten a residual and its Jacobian together
+    ///
+    /// Used when linearizing a factor. Linear noise models whiten each
+    /// independently, which is the default. Nonlinear ones, such as
+    /// [StudentTNoise], need the residual to whiten the Jacobian.
+    fn whiten_linearized(&self, r: VectorX, a: MatrixX) -> (VectorX, MatrixX) {
+        (self.whiten_vec(r), self.whiten_mat(a))
+    }
+
+    /// Whiten a vector in place
+    ///
+    /// Useful for reusing a scratch buffer across many factors. The default
+    /// implementation forwards to [whiten_vec](Self::whiten_vec), which for
+    /// the built-in noise models doesn't allocate either.
+    fn whiten_vec_mut(&self, v: &mut VectorX) {
+        let out = self.whiten_vec(std::mem::replace(v, VectorX::zeros(0)));
+        *v = out;
+    }
+
+    /// Whiten a matrix in place
+    ///
+    /// See [whiten_vec_mut](Self::whiten_vec_mut).
+    fn whiten_mat_mut(&self, m: &mut MatrixX) {
+        let out = self.whiten_mat(std::mem::replace(m, MatrixX::zeros(0, 0)));
+        *m = out;
+    }
 }
 
 dyn_clone::clone_trait_object!(NoiseModel);
@@ -37,7 +64,10 @@ dyn_clone::clone_trait_object!(NoiseModel);
 pub use register_noisemodel as tag_noise;
 
 mod gaussian;
-pub use gaussian::GaussianNoise;
+pub use gaussian::{GaussianNoise, NoiseError, CONDITION_THRESHOLD};
 
 mod unit;
-pub use unit::UnitNoise;
+pub use unit::{DynUnitNoise, UnitNoise};
+
+mod student_t;
+pub use student_t::StudentTNoise;
@@ -0,0 +1,136 @@
+use std::fmt;
+
+use super::{GaussianNoise, NoiseModel};
+use crate::{
+    dtype,
+    linalg::{Const, Matrix, MatrixX, Vector, VectorX},
+};
+
+/// A multivariate Student-t noise model.
+///
+/// Heavy-tailed alternative to [GaussianNoise], parametrized by the degrees of
+/// freedom $\nu$ and a scale matrix, given as a [GaussianNoise]. With
+/// $e = \Sigma^{-1/2} r$ the residual whitened by the scale and $s = \|e\|^2$,
+/// the negative log-likelihood is, up to a constant,
+/// $$
+/// \rho(s) = (\nu + N) \log(1 + s / \nu)
+/// $$
+/// which approaches the Gaussian $s$ as $\nu \to \infty$.
+///
+/// Since $\rho$ isn't quadratic, whitening is nonlinear. The whitened
+/// residual is scaled so its squared norm is $\rho(s)$, so the error of a
+/// factor is the true likelihood. When linearizing, both the residual and
+/// Jacobian are scaled by $\sqrt{w}$, with weight
+/// $w = \rho'(s) = (\nu + N) / (\nu + s)$. This is the same iteratively
+/// reweighted least squares (IRLS) used by the [robust](crate::robust)
+/// kernels, so each step solves an ordinary least-squares problem with large
+/// residuals downweighted. As such, it should be paired with the default
+/// [L2](crate::robust::L2) kernel rather than another robust kernel.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StudentTNoise<const N: usize> {
+    nu: dtype,
+    sqrt_inf: Matrix<N, N>,
+}
+
+impl<const N: usize> StudentTNoise<N> {
+    /// Create a Student-t noise with `nu` degrees of freedom
+    pub fn new(nu: dtype, scale: GaussianNoise<N>) -> Self {
+        assert!(nu > 0.0, "Degrees of freedom must be positive");
+        let sqrt_inf = scale.whiten_mat(MatrixX::identity(N, N));
+        Self {
+            nu,
+            sqrt_inf: sqrt_inf.fixed_view::<N, N>(0, 0).into_owned(),
+        }
+    }
+
+    /// Get the degrees of freedom
+    pub fn nu(&self) -> dtype {
+        self.nu
+    }
+
+    // Whiten by the scale only
+    fn scale(&self, mut v: VectorX) -> VectorX {
+        let out: Vector<N> = self.sqrt_inf * v.fixed_rows::<N>(0);
+        v.copy_from(&out);
+        v
+    }
+}
+
+#[factrs::mark]
+impl<const N: usize> NoiseModel for StudentTNoise<N> {
+    type Dim = Const<N>;
+
+    fn whiten_vec(&self, v: VectorX) -> VectorX {
+        let e = self.scale(v);
+        let s = e.norm_squared();
+        if s == 0.0 {
+            return e;
+        }
+        let rho = (self.nu + N as dtype) * (s / self.nu).ln_1p();
+        e * (rho / s).sqrt()
+    }
+
+    // Without the residual, only the scale can be applied
+    fn whiten_mat(&self, mut m: MatrixX) -> MatrixX {
+        for mut col in m.column_iter_mut() {
+            let out: Vector<N> = self.sqrt_inf * col.fixed_rows::<N>(0);
+            col.copy_from(&out);
+        }
+        m
+    }
+
+    fn whiten_linearized(&self, r: VectorX, a: MatrixX) -> (VectorX, MatrixX) {
+        let e = self.scale(r);
+        let w = (self.nu + N as dtype) / (self.nu + e.norm_squared());
+        let w = w.sqrt();
+        (e * w, self.whiten_mat(a) * w)
+    }
+}
+
+impl<const N: usize> fmt::Display for StudentTNoise<N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "StudentTNoise{}(nu: {}): {:}", N, self.nu, self.sqrt_inf)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use matrixcompare::assert_matrix_eq;
+
+    use super::*;
+    use crate::linalg::Matrix2;
+
+    #[test]
+    fn large_nu_is_gaussian() {
+        let cov = Matrix2::new(1.0, 0.2, 0.2, 0.5);
+        let gaussian = GaussianNoise::<2>::from_matrix_cov(cov.as_view());
+        let student = StudentTNoise::new(1e9, gaussian.clone());
+
+        let r = VectorX::from_vec(vec![1.0, -2.0]);
+        let a = MatrixX::from_row_slice(2, 3, &[1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+        assert_matrix_eq!(
+            student.whiten_vec(r.clone()),
+            gaussian.whiten_vec(r.clone()),
+            comp = abs,
+            tol = 1e-6
+        );
+
+        let (r_s, a_s) = student.whiten_linearized(r.clone(), a.clone());
+        let (r_g, a_g) = gaussian.whiten_linearized(r, a);
+        assert_matrix_eq!(r_s, r_g, comp = abs, tol = 1e-6);
+        assert_matrix_eq!(a_s, a_g, comp = abs, tol = 1e-6);
+    }
+
+    #[test]
+    fn heavy_tails() {
+        let gaussian = GaussianNoise::<2>::from_scalar_sigma(1.0);
+        let student = StudentTNoise::new(3.0, gaussian.clone());
+
+        // Large residuals are penalized much less than under a Gaussian
+        let r = VectorX::from_vec(vec![10.0, -10.0]);
+        let s = student.whiten_vec(r.clone()).norm_squared();
+        let g = gaussian.whiten_vec(r).norm_squared();
+        assert!(s < 0.2 * g);
+    }
+}
@@ -22,6 +22,10 @@ impl<const N: usize> NoiseModel for UnitNoise<N> {
     fn whiten_mat(&self, m: MatrixX) -> MatrixX {
         m
     }
+
+    fn whiten_vec_mut(&self, _v: &mut VectorX) {}
+
+    fn whiten_mat_mut(&self, _m: &mut MatrixX) {}
 }
 
 impl<const N: usize> fmt::Display for UnitNoise<N> {
@@ -29,3 +33,49 @@ impl<const N: usize> fmt::Display for UnitNoise<N> {
         write!(f, "{:?}", self)
     }
 }
+
+/// A unit noise model with a dimension only known at runtime.
+///
+/// For residuals whose output dimension is only known at runtime, such as a
+/// [MarginalResidual](crate::residuals::MarginalResidual). As the dimension
+/// isn't known at compile time, it can only be used with
+/// [Factor::from_boxed](crate::containers::Factor::from_boxed), and its
+/// [Dim](NoiseModel::Dim) is a placeholder that won't match any residual.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DynUnitNoise {
+    dim: usize,
+}
+
+impl DynUnitNoise {
+    pub fn new(dim: usize) -> Self {
+        Self { dim }
+    }
+}
+
+#[factrs::mark]
+impl NoiseModel for DynUnitNoise {
+    type Dim = Const<0>;
+
+    fn dim(&self) -> usize {
+        self.dim
+    }
+
+    fn whiten_vec(&self, v: VectorX) -> VectorX {
+        v
+    }
+
+    fn whiten_mat(&self, m: MatrixX) -> MatrixX {
+        m
+    }
+
+    fn whiten_vec_mut(&self, _v: &mut VectorX) {}
+
+    fn whiten_mat_mut(&self, _m: &mut MatrixX) {}
+}
+
+impl fmt::Display for DynUnitNoise {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
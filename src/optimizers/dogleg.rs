@@ -0,0 +1,317 @@
+use std::{ops::Mul, sync::Arc};
+
+use faer_ext::IntoNalgebra;
+
+use super::{is_finite, GraphOptimizer, OptError, OptObserverVec, OptParams, OptResult, Optimizer};
+use crate::{
+    containers::{Graph, GraphOrder, Values, VariableOrdering},
+    dtype,
+    linalg::{DiffResult, VectorX},
+    linear::{CholeskySolver, LinearSolver, LinearValues},
+};
+
+/// Trust region parameters for [Dogleg]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DoglegParams {
+    /// Radius of the trust region at the start of optimization
+    pub radius_initial: dtype,
+    /// Smallest radius before giving up on a step
+    pub radius_min: dtype,
+    /// Largest radius the trust region can grow to
+    pub radius_max: dtype,
+    /// Gain ratio above which the trust region is grown
+    pub increase_threshold: dtype,
+    /// Gain ratio below which the trust region is shrunk
+    pub decrease_threshold: dtype,
+}
+
+impl Default for DoglegParams {
+    fn default() -> Self {
+        Self {
+            radius_initial: 1.0,
+            radius_min: 1e-10,
+            radius_max: 1e5,
+            increase_threshold: 0.75,
+            decrease_threshold: 0.25,
+        }
+    }
+}
+
+/// Powell's Dogleg optimizer
+///
+/// A trust region method that blends the Gauss-Newton step $h_{gn}$ with the
+/// steepest descent (Cauchy) step $h_{sd}$. If the Gauss-Newton step lies in
+/// the trust region of radius $\Delta$ it is taken directly, otherwise the
+/// step follows the "dogleg" path from $h_{sd}$ towards $h_{gn}$ until it hits
+/// the boundary of the trust region.
+///
+/// The radius is adapted each step using the ratio of actual to predicted
+/// error decrease, so unlike [LevenMarquardt](super::LevenMarquardt) there's no
+/// damping parameter to tune. Parameters can be modified using the
+/// `params_base` and `params_dogleg` fields, and observers add using
+/// `observers`. Additionally, is generic over the linear solver, but defaults
+/// to [CholeskySolver]. See the [linear](crate::linear) module for more linear
+/// solver options.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Dogleg<S: LinearSolver = CholeskySolver> {
+    graph: Arc<Graph>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    solver: S,
+    /// Basic parameters for the optimizer
+    pub params_base: OptParams,
+    /// Trust region parameters
+    pub params_dogleg: DoglegParams,
+    /// Observers for the optimizer
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub observers: OptObserverVec<Values>,
+    radius: dtype,
+    // For caching computation between steps
+    #[cfg_attr(feature = "serde", serde(skip))]
+    graph_order: Option<GraphOrder>,
+    ordering: VariableOrdering,
+    step_norm: dtype,
+}
+
+impl<S: LinearSolver> Dogleg<S> {
+    pub fn new(graph: impl Into<Arc<Graph>>) -> Self {
+        let params_dogleg = DoglegParams::default();
+        Self {
+            graph: graph.into(),
+            solver: S::default(),
+            params_base: OptParams::default(),
+            radius: params_dogleg.radius_initial,
+            params_dogleg,
+            observers: OptObserverVec::default(),
+            graph_order: None,
+            ordering: VariableOrdering::default(),
+            step_norm: 0.0,
+        }
+    }
+
+    /// Create the optimizer with an already configured linear solver
+    pub fn with_solver(graph: impl Into<Arc<Graph>>, solver: S) -> Self {
+        Self {
+            solver,
+            ..Self::new(graph)
+        }
+    }
+
+    /// Set the stopping criteria and iteration limit
+    pub fn with_params(mut self, params: OptParams) -> Self {
+        self.params_base = params;
+        self
+    }
+
+    /// Set how variables are ordered into the linear system
+    pub fn with_ordering(mut self, ordering: VariableOrdering) -> Self {
+        self.ordering = ordering;
+        self.graph_order = None;
+        self
+    }
+
+    pub fn graph(&self) -> &Graph {
+        &self.graph
+    }
+
+    /// Swap in a new graph
+    ///
+    /// If it has the [same structure](Graph::same_structure) as the current
+    /// one, the cached sparsity pattern and symbolic factorization are kept
+    /// for the next [optimize](Optimizer::optimize). Useful for repeatedly
+    /// solving the same shaped problem with different measurements.
+    pub fn set_graph(&mut self, graph: impl Into<Arc<Graph>>) {
+        let graph = graph.into();
+        if !self.graph.same_structure(&graph) {
+            self.graph_order = None;
+        }
+        self.graph = graph;
+    }
+
+    /// Current radius of the trust region
+    pub fn radius(&self) -> dtype {
+        self.radius
+    }
+}
+
+impl<S: LinearSolver> Optimizer for Dogleg<S> {
+    type Input = Values;
+
+    fn params(&self) -> &OptParams {
+        &self.params_base
+    }
+
+    fn error(&self, values: &Values) -> dtype {
+        self.graph.error(values)
+    }
+
+    fn residual_dim(&self) -> usize {
+        self.graph.dim_out()
+    }
+
+    fn step_norm(&self) -> dtype {
+        self.step_norm
+    }
+
+    fn init(&mut self, values: &Values) -> Result<(), OptError<Values>> {
+        if self.graph.is_empty() {
+            return Err(OptError::EmptyGraph);
+        }
+        self.radius = self.params_dogleg.radius_initial;
+        // Reuse the sparsity pattern and symbolic factorization if the
+        // structure hasn't changed since the last optimization
+        if self
+            .graph_order
+            .as_ref()
+            .is_some_and(|g| g.order.matches(values))
+        {
+            return Ok(());
+        }
+        let order = self.ordering.order(&self.graph, values);
+        self.solver.set_order(&order);
+        self.graph_order = Some(self.graph.sparsity_pattern(order));
+        Ok(())
+    }
+
+    fn step(&mut self, mut values: Values, idx: usize) -> OptResult<Values> {
+        let order = self
+            .graph_order
+            .as_ref()
+            .expect("Missing graph order")
+            .order
+            .clone();
+
+        let linear_graph = self.graph.linearize(&values);
+        let DiffResult { value: r, diff: j } =
+            linear_graph.residual_jacobian(self.graph_order.as_ref().expect("Missing graph order"));
+
+        // Gauss-Newton step
+        let h_gn: VectorX = match self.solver.try_solve_lst_sq(j.as_ref(), r.as_ref()) {
+            Some(h) if is_finite(&h) => h.as_ref().into_nalgebra().column(0).clone_owned(),
+            _ => {
+                log::error!("Failed to solve for the Gauss-Newton step of dogleg");
+                return Err(OptError::InvalidSystem);
+            }
+        };
+
+        // Steepest descent step, scaled to minimize along the gradient
+        let g = j.as_ref().transpose().mul(&r);
+        let jg = j.as_ref().mul(&g);
+        let g: VectorX = g.as_ref().into_nalgebra().column(0).clone_owned();
+        let jg: VectorX = jg.as_ref().into_nalgebra().column(0).clone_owned();
+        // Since g = J^T r, J g = 0 only when g = 0, ie we're already at a
+        // stationary point and the Cauchy step length would be 0 / 0
+        if jg.norm_squared() == 0.0 {
+            self.step_norm = 0.0;
+            self.observers.notify(&values, idx);
+            return Ok(values);
+        }
+        let alpha = g.norm_squared() / jg.norm_squared();
+        let h_sd = &g * alpha;
+
+        let error_old = self.graph.error(&values);
+        let error_linear_old = linear_graph.error(&LinearValues::zero_from_order(order.clone()));
+
+        loop {
+            // Find the step along the dogleg path
+            let h = if h_gn.norm() <= self.radius {
+                h_gn.clone()
+            } else if h_sd.norm() >= self.radius {
+                &g * (self.radius / g.norm())
+            } else {
+                // Solve |h_sd + beta (h_gn - h_sd)| = radius for beta
+                let d = &h_gn - &h_sd;
+                let a = d.norm_squared();
+                let b = 2.0 * h_sd.dot(&d);
+                let c = h_sd.norm_squared() - self.radius * self.radius;
+                let beta = (-b + (b * b - 4.0 * a * c).sqrt()) / (2.0 * a);
+                &h_sd + d * beta
+            };
+            let step_norm = h.norm();
+
+            // Compare actual vs predicted decrease
+            let dx = LinearValues::from_order_and_vector(order.clone(), h);
+            let predicted = error_linear_old - linear_graph.error(&dx);
+            // Already at a minimum of the linearized system, any decrease
+            // would be lost in round off
+            if predicted <= dtype::EPSILON * error_old {
+                self.step_norm = 0.0;
+                break;
+            }
+            let mut candidate = values.clone();
+            candidate.oplus_mut(&dx);
+            let actual = error_old - self.graph.error(&candidate);
+            let rho = actual / predicted;
+
+            if rho > self.params_dogleg.increase_threshold {
+                self.radius = self
+                    .radius
+                    .max(3.0 * step_norm)
+                    .min(self.params_dogleg.radius_max);
+            } else if rho < self.params_dogleg.decrease_threshold {
+                self.radius /= 2.0;
+            }
+
+            if rho > 0.0 {
+                self.step_norm = step_norm;
+                values = candidate;
+                break;
+            }
+
+            if self.radius < self.params_dogleg.radius_min {
+                return Err(OptError::FailedToStep);
+            }
+        }
+
+        self.observers.notify(&values, idx);
+
+        Ok(values)
+    }
+}
+
+impl<S: LinearSolver> GraphOptimizer for Dogleg<S> {
+    fn graph(&self) -> &Graph {
+        Dogleg::graph(self)
+    }
+
+    fn set_graph(&mut self, graph: Graph) {
+        Dogleg::set_graph(self, graph)
+    }
+
+    fn ordering(&self) -> &VariableOrdering {
+        &self.ordering
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        assign_symbols,
+        containers::FactorBuilder,
+        residuals::PriorResidual,
+        test_optimizer,
+        variables::{Variable, VectorVar2},
+    };
+
+    assign_symbols!(X: VectorVar2);
+
+    test_optimizer!(Dogleg);
+
+    #[test]
+    fn zero_gradient() {
+        let x = VectorVar2::new(1.0, 2.0);
+        let mut values = Values::new();
+        values.insert(X(0), x.clone());
+        let mut graph = Graph::new();
+        let res = PriorResidual::new(x.clone());
+        graph.add_factor(FactorBuilder::new1(res, X(0)).build());
+
+        // Already at the minimum, so the step should leave the values alone
+        let mut opt: Dogleg = Dogleg::new(graph);
+        opt.init(&values).expect("Failed to initialize");
+        let values = opt.step(values, 1).expect("Step failed");
+        assert_eq!(opt.step_norm(), 0.0);
+        let out = values.get(X(0)).expect("Missing X(0)");
+        assert_eq!(out.ominus(&x).norm(), 0.0);
+    }
+}
@@ -1,8 +1,15 @@
+use std::{ops::Mul, sync::Arc};
+
+use faer::{
+    sparse::{SparseColMat, SparseColMatRef},
+    Mat, MatRef,
+};
 use faer_ext::IntoNalgebra;
 
-use super::{OptObserverVec, OptParams, OptResult, Optimizer};
+use super::{is_finite, GraphOptimizer, OptError, OptObserverVec, OptParams, OptResult, Optimizer};
 use crate::{
-    containers::{Graph, GraphOrder, Values, ValuesOrder},
+    containers::{Graph, GraphOrder, Values, VariableOrdering},
+    dtype,
     linalg::DiffResult,
     linear::{CholeskySolver, LinearSolver, LinearValues},
 };
@@ -14,32 +21,120 @@ use crate::{
 /// `observers`. Additionally, is generic over the linear solver, but defaults
 /// to [CholeskySolver]. See the [linear](crate::linear) module for more linear
 /// solver options.
+///
+/// Near saddle points of highly nonlinear problems, the normal equations can
+/// become numerically indefinite and fail to factor. When this happens, a
+/// single damped step is taken instead, adding `fallback_damping` times the
+/// largest diagonal entry of $A^\top A$ to its diagonal. If even that fails,
+/// [OptError::InvalidSystem] is returned, and
+/// [LevenMarquardt](super::LevenMarquardt) is likely a better fit for the
+/// problem.
+///
+/// A common cause of a singular system is a graph without a prior, leaving
+/// the gauge freedom unfixed. Before damping, this is checked with
+/// [Graph::unanchored], and if any variables are unanchored
+/// [OptError::Unanchored] is returned with them instead.
 #[derive(Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct GaussNewton<S: LinearSolver = CholeskySolver> {
-    graph: Graph,
+    graph: Arc<Graph>,
+    #[cfg_attr(feature = "serde", serde(skip))]
     solver: S,
     /// Basic parameters for the optimizer
     pub params: OptParams,
     /// Observers for the optimizer
+    #[cfg_attr(feature = "serde", serde(skip))]
     pub observers: OptObserverVec<Values>,
+    /// Relative damping used when the system is indefinite
+    pub fallback_damping: dtype,
     // For caching computation between steps
+    #[cfg_attr(feature = "serde", serde(skip))]
     graph_order: Option<GraphOrder>,
+    ordering: VariableOrdering,
+    step_norm: dtype,
 }
 
 impl<S: LinearSolver> GaussNewton<S> {
-    pub fn new(graph: Graph) -> Self {
+    pub fn new(graph: impl Into<Arc<Graph>>) -> Self {
         Self {
-            graph,
+            graph: graph.into(),
             solver: S::default(),
             observers: OptObserverVec::default(),
             params: OptParams::default(),
+            fallback_damping: 1e-6,
             graph_order: None,
+            ordering: VariableOrdering::default(),
+            step_norm: 0.0,
+        }
+    }
+
+    /// Create the optimizer with an already configured linear solver
+    pub fn with_solver(graph: impl Into<Arc<Graph>>, solver: S) -> Self {
+        Self {
+            solver,
+            ..Self::new(graph)
         }
     }
 
+    /// Set the stopping criteria and iteration limit
+    pub fn with_params(mut self, params: OptParams) -> Self {
+        self.params = params;
+        self
+    }
+
+    /// Set how variables are ordered into the linear system
+    pub fn with_ordering(mut self, ordering: VariableOrdering) -> Self {
+        self.ordering = ordering;
+        self.graph_order = None;
+        self
+    }
+
     pub fn graph(&self) -> &Graph {
         &self.graph
     }
+
+    /// Swap in a new graph
+    ///
+    /// If it has the [same structure](Graph::same_structure) as the current
+    /// one, the cached sparsity pattern and symbolic factorization are kept
+    /// for the next [optimize](Optimizer::optimize). Useful for repeatedly
+    /// solving the same shaped problem with different measurements.
+    pub fn set_graph(&mut self, graph: impl Into<Arc<Graph>>) {
+        let graph = graph.into();
+        if !self.graph.same_structure(&graph) {
+            self.graph_order = None;
+        }
+        self.graph = graph;
+    }
+
+    // Solve the normal equations with a small amount of damping
+    fn damped_solve(
+        &mut self,
+        j: SparseColMatRef<usize, dtype>,
+        r: MatRef<dtype>,
+    ) -> Option<Mat<dtype>> {
+        let jtj = j
+            .transpose()
+            .to_col_major()
+            .expect("J failed to transpose")
+            .mul(j);
+        let jtr = j.transpose().mul(r);
+
+        let max_diag = (0..jtj.ncols())
+            .filter_map(|i| jtj.as_ref().get(i, i).copied())
+            .fold(0.0, dtype::max);
+        let lambda = self.fallback_damping * max_diag.max(1.0);
+        let triplets = (0..jtj.ncols()).map(|i| (i, i, lambda)).collect::<Vec<_>>();
+        let damping = SparseColMat::<usize, dtype>::try_new_from_triplets(
+            jtj.ncols(),
+            jtj.ncols(),
+            &triplets,
+        )
+        .expect("Failed to make damping terms");
+
+        let a = &jtj + &damping;
+        self.solver.try_solve_symmetric(a.as_ref(), jtr.as_ref())
+    }
 }
 
 impl<S: LinearSolver> Optimizer for GaussNewton<S> {
@@ -53,13 +148,35 @@ impl<S: LinearSolver> Optimizer for GaussNewton<S> {
         &self.params
     }
 
-    fn init(&mut self, _values: &Values) {
-        // TODO: Some way to manual specify how to computer ValuesOrder
-        // Precompute the sparsity pattern
-        self.graph_order = Some(
-            self.graph
-                .sparsity_pattern(ValuesOrder::from_values(_values)),
-        );
+    fn residual_dim(&self) -> usize {
+        self.graph.dim_out()
+    }
+
+    fn step_norm(&self) -> dtype {
+        self.step_norm
+    }
+
+    fn init(&mut self, values: &Values) -> Result<(), OptError<Values>> {
+        if self.graph.is_empty() {
+            return Err(OptError::EmptyGraph);
+        }
+        if !self.graph.unanchored(values).is_empty() {
+            log::warn!("Some variables have no prior or fixed variable, system may be gauge free");
+        }
+
+        // Reuse the sparsity pattern and symbolic factorization if the
+        // structure hasn't changed since the last optimization
+        if self
+            .graph_order
+            .as_ref()
+            .is_some_and(|g| g.order.matches(values))
+        {
+            return Ok(());
+        }
+        let order = self.ordering.order(&self.graph, values);
+        self.solver.set_order(&order);
+        self.graph_order = Some(self.graph.sparsity_pattern(order));
+        Ok(())
     }
 
     fn step(&mut self, mut values: Values, idx: usize) -> OptResult<Values> {
@@ -68,14 +185,34 @@ impl<S: LinearSolver> Optimizer for GaussNewton<S> {
         let DiffResult { value: r, diff: j } =
             linear_graph.residual_jacobian(self.graph_order.as_ref().expect("Missing graph order"));
 
-        // Solve Ax = b
-        let delta = self
-            .solver
-            .solve_lst_sq(j.as_ref(), r.as_ref())
-            .as_ref()
-            .into_nalgebra()
-            .column(0)
-            .clone_owned();
+        // Solve Ax = b, falling back to a damped step if it's indefinite
+        let delta = match self.solver.try_solve_lst_sq(j.as_ref(), r.as_ref()) {
+            Some(delta) if is_finite(&delta) => delta,
+            _ => {
+                let unanchored = self.graph.unanchored(&values);
+                if !unanchored.is_empty() {
+                    log::error!(
+                        "Gauss-Newton system is singular with {} unanchored variables, add a \
+                         prior or fix a variable",
+                        unanchored.len()
+                    );
+                    return Err(OptError::Unanchored(unanchored));
+                }
+                log::warn!("Gauss-Newton system is indefinite, taking a damped step");
+                match self.damped_solve(j.as_ref(), r.as_ref()) {
+                    Some(delta) if is_finite(&delta) => delta,
+                    _ => {
+                        log::error!(
+                            "Failed to solve damped Gauss-Newton system, consider using \
+                             Levenberg-Marquardt instead"
+                        );
+                        return Err(OptError::InvalidSystem);
+                    }
+                }
+            }
+        };
+        let delta = delta.as_ref().into_nalgebra().column(0).clone_owned();
+        self.step_norm = delta.norm();
 
         // Update the values
         let dx = LinearValues::from_order_and_vector(
@@ -94,10 +231,332 @@ impl<S: LinearSolver> Optimizer for GaussNewton<S> {
     }
 }
 
+impl<S: LinearSolver> GraphOptimizer for GaussNewton<S> {
+    fn graph(&self) -> &Graph {
+        GaussNewton::graph(self)
+    }
+
+    fn set_graph(&mut self, graph: Graph) {
+        GaussNewton::set_graph(self, graph)
+    }
+
+    fn ordering(&self) -> &VariableOrdering {
+        &self.ordering
+    }
+}
+
 #[cfg(test)]
 mod test {
+    use matrixcompare::assert_matrix_eq;
+
     use super::*;
-    use crate::test_optimizer;
+    use crate::{
+        assign_symbols,
+        containers::{Factor, FactorBuilder},
+        dtype,
+        noise::UnitNoise,
+        optimizers::{OptError, Termination},
+        residuals::{BetweenResidual, MaskedPriorResidual, PriorResidual},
+        robust::L2,
+        test_optimizer,
+        variables::{Variable, VectorVar2},
+    };
 
     test_optimizer!(GaussNewton);
+
+    assign_symbols!(P: VectorVar2);
+
+    #[test]
+    fn error_history() {
+        let mut values = Values::new();
+        values.insert(P(0), VectorVar2::identity());
+
+        let mut graph = Graph::new();
+        let res = PriorResidual::new(VectorVar2::new(1.0, 2.0));
+        graph.add_factor(FactorBuilder::new1(res, P(0)).build());
+
+        // Disable early stopping so we hit exactly max_iterations
+        let mut opt: GaussNewton = GaussNewton::new(graph);
+        opt.params.max_iterations = 3;
+        opt.params.error_tol = -1.0;
+        opt.params.error_tol_absolute = -1.0;
+        opt.params.error_tol_relative = -1.0;
+
+        let initial = opt.error(&values);
+        let result = match opt.optimize(values) {
+            Err(OptError::MaxIterations(result)) => result,
+            _ => panic!("Expected to hit max iterations"),
+        };
+        assert_eq!(result.iterations, opt.params.max_iterations);
+        assert_eq!(result.history.len(), result.iterations + 1);
+        assert_eq!(result.history[0], initial);
+    }
+
+    #[test]
+    fn callback() {
+        let mut values = Values::new();
+        values.insert(P(0), VectorVar2::identity());
+        let mut graph = Graph::new();
+        let res = PriorResidual::new(VectorVar2::new(3.0, 4.0));
+        graph.add_factor(FactorBuilder::new1(res, P(0)).build());
+
+        // Gauss-Newton solves a linear problem in a single step of length 5
+        let mut opt: GaussNewton = GaussNewton::new(graph);
+        let mut seen = Vec::new();
+        let result = opt.optimize_with(values.clone(), |info| {
+            seen.push((info.iteration, info.step_norm));
+            true
+        });
+        assert!(result.is_ok());
+        assert_eq!(seen[0], (0, 0.0));
+        assert_eq!(seen[1].0, 1);
+        assert!((seen[1].1 - 5.0).abs() < 1e-6);
+
+        // And can be stopped before taking any steps
+        let result = opt.optimize_with(values, |_| false);
+        let values = result.expect("Optimization failed");
+        let p: &VectorVar2 = values.get(P(0)).expect("Missing P(0)");
+        assert_matrix_eq!(p.0, VectorVar2::identity().0, comp = abs, tol = 1e-6);
+    }
+
+    #[test]
+    fn report() {
+        let mut values = Values::new();
+        values.insert(P(0), VectorVar2::identity());
+        let mut graph = Graph::new();
+        let res = PriorResidual::new(VectorVar2::new(3.0, 4.0));
+        graph.add_factor(FactorBuilder::new1(res, P(0)).build());
+
+        let mut opt: GaussNewton = GaussNewton::new(graph);
+        let result = opt.optimize(values.clone()).expect("Optimization failed");
+        assert!(result.converged());
+        assert_eq!(result.history.len(), result.iterations + 1);
+        assert_eq!(result.history[0], result.initial_error);
+        assert!(result.final_error < result.initial_error);
+
+        // Running out of iterations still reports how far it got
+        opt.params.max_iterations = 1;
+        opt.params.error_tol_absolute = -1.0;
+        opt.params.error_tol_relative = -1.0;
+        let Err(OptError::MaxIterations(result)) = opt.optimize(values) else {
+            panic!("Expected to hit max iterations");
+        };
+        assert_eq!(result.termination, Termination::MaxIterations);
+        assert_eq!(result.iterations, 1);
+        assert!(!result.converged());
+    }
+
+    #[test]
+    fn step_tol() {
+        let mut values = Values::new();
+        values.insert(P(0), VectorVar2::identity());
+        let mut graph = Graph::new();
+        let res = PriorResidual::new(VectorVar2::new(3.0, 4.0));
+        graph.add_factor(FactorBuilder::new1(res, P(0)).build());
+
+        // Only the step tolerance can stop this
+        let params = OptParams {
+            error_tol: -1.0,
+            error_tol_absolute: -1.0,
+            error_tol_relative: -1.0,
+            step_tol: 10.0,
+            ..Default::default()
+        };
+        let mut opt: GaussNewton = GaussNewton::new(graph).with_params(params);
+        let result = opt.optimize(values).expect("Optimization failed");
+        assert_eq!(result.termination, Termination::StepTol);
+        assert_eq!(result.iterations, 1);
+    }
+
+    #[test]
+    fn indefinite() {
+        let mut values = Values::new();
+        values.insert(P(0), VectorVar2::identity());
+
+        // Only the first dimension is constrained, so A^T A is singular
+        let mut graph = Graph::new();
+        let res = MaskedPriorResidual::new(VectorVar2::new(1.0, 2.0), &[true, false]);
+        graph.add_factor(Factor::from_boxed(
+            Box::new(res),
+            vec![P(0).into()],
+            Box::new(UnitNoise::<1>),
+            Box::new(L2),
+        ));
+
+        let mut opt: GaussNewton = GaussNewton::new(graph);
+        let result = opt.optimize(values).expect("Optimization failed");
+        let p: &VectorVar2 = result.get(P(0)).expect("Missing P(0)");
+        assert!((p.0.x - 1.0).abs() < 1e-5);
+        assert_eq!(p.0.y, 0.0);
+    }
+
+    #[test]
+    fn unanchored() {
+        let mut values = Values::new();
+        values.insert(P(0), VectorVar2::identity());
+        values.insert(P(1), VectorVar2::new(1.0, 0.0));
+
+        // Only relative measurements, so the pair can drift freely
+        let mut graph = Graph::new();
+        let res = BetweenResidual::new(VectorVar2::new(1.0, 2.0));
+        graph.add_factor(FactorBuilder::new2(res, P(0), P(1)).build());
+        let keys = vec![P(0).into(), P(1).into()];
+        assert_eq!(graph.unanchored(&values), keys);
+
+        let mut opt: GaussNewton = GaussNewton::new(graph);
+        let result = opt.optimize(values);
+        assert!(matches!(result, Err(OptError::Unanchored(k)) if k == keys));
+    }
+
+    #[test]
+    fn shared_graph() {
+        let mut values = Values::new();
+        values.insert(P(0), VectorVar2::identity());
+        let mut graph = Graph::new();
+        let res = PriorResidual::new(VectorVar2::new(3.0, 4.0));
+        graph.add_factor(FactorBuilder::new1(res, P(0)).build());
+
+        // Both optimizers use the same graph without copying it
+        let graph = Arc::new(graph);
+        let mut opt1: GaussNewton = GaussNewton::new(graph.clone());
+        let mut opt2: GaussNewton = GaussNewton::new(graph.clone());
+        assert_eq!(Arc::strong_count(&graph), 3);
+        assert!(std::ptr::eq(opt1.graph(), opt2.graph()));
+
+        let result1 = opt1.optimize(values.clone()).expect("Optimization failed");
+        let result2 = opt2.optimize(values).expect("Optimization failed");
+        assert_eq!(result1.final_error, result2.final_error);
+    }
+
+    #[test]
+    fn empty_graph() {
+        let mut values = Values::new();
+        values.insert(P(0), VectorVar2::identity());
+
+        let mut opt: GaussNewton = GaussNewton::new(Graph::new());
+        let result = opt.optimize(values);
+        assert!(matches!(result, Err(OptError::EmptyGraph)));
+    }
+
+    #[test]
+    fn non_finite() {
+        let mut values = Values::new();
+        values.insert(P(0), VectorVar2::new(dtype::NAN, 0.0));
+        let mut graph = Graph::new();
+        let res = PriorResidual::new(VectorVar2::new(3.0, 4.0));
+        graph.add_factor(FactorBuilder::new1(res, P(0)).build());
+
+        let mut opt: GaussNewton = GaussNewton::new(graph);
+        let result = opt.optimize(values);
+        assert!(matches!(result, Err(OptError::NonFinite)));
+    }
+
+    fn prior_graph(n: u32) -> (Graph, Values) {
+        let mut values = Values::new();
+        let mut graph = Graph::new();
+        for i in 0..n {
+            values.insert(P(i), VectorVar2::new(0.1, 0.1));
+            let res = PriorResidual::new(VectorVar2::identity());
+            graph.add_factor(FactorBuilder::new1(res, P(i)).build());
+        }
+        (graph, values)
+    }
+
+    #[test]
+    fn normalize_tol() {
+        // Each prior contributes an error of just over 0.01 from round off, or
+        // 0.005 per dimension, so the tolerance is set with some margin
+        for n in [1, 1000] {
+            let (graph, values) = prior_graph(n);
+            let mut opt: GaussNewton = GaussNewton::new(graph);
+            opt.params.error_tol = 0.011;
+            opt.params.normalize_tol = true;
+            let result = opt.optimize(values).expect("Optimization failed");
+            assert_eq!(result.history.len(), 1, "n = {n} didn't stop immediately");
+        }
+
+        // Without normalization, only the small problem is converged
+        let (graph, values) = prior_graph(1);
+        let mut opt: GaussNewton = GaussNewton::new(graph);
+        opt.params.error_tol = 0.011;
+        let result = opt.optimize(values).expect("Optimization failed");
+        assert_eq!(result.history.len(), 1);
+
+        let (graph, values) = prior_graph(1000);
+        let mut opt: GaussNewton = GaussNewton::new(graph);
+        opt.params.error_tol = 0.011;
+        let history = match opt.optimize(values) {
+            Ok(result) | Err(OptError::MaxIterations(result)) => result.history,
+            Err(e) => panic!("Optimization failed: {e}"),
+        };
+        assert!(history.len() > 1);
+    }
+
+    #[test]
+    fn reuse_structure() {
+        let (graph, values) = prior_graph(3);
+        let mut opt: GaussNewton = GaussNewton::new(graph);
+        opt.optimize(values.clone()).expect("Optimization failed");
+        assert!(opt.graph_order.is_some());
+
+        // New measurements with the same structure keep the cached pattern
+        let mut graph = Graph::new();
+        for i in 0..3 {
+            let res = PriorResidual::new(VectorVar2::new(1.0, i as dtype));
+            graph.add_factor(FactorBuilder::new1(res, P(i)).build());
+        }
+        opt.set_graph(graph);
+        assert!(opt.graph_order.is_some());
+        let result = opt.optimize(values.clone()).expect("Optimization failed");
+        let p: &VectorVar2 = result.get(P(2)).expect("Missing P(2)");
+        assert_matrix_eq!(p.0, VectorVar2::new(1.0, 2.0).0, comp = abs, tol = 1e-6);
+
+        // While a new structure is recomputed
+        let (graph, values) = prior_graph(4);
+        opt.set_graph(graph);
+        assert!(opt.graph_order.is_none());
+        opt.optimize(values).expect("Optimization failed");
+        assert_eq!(opt.graph_order.as_ref().map(|g| g.order.len()), Some(4));
+    }
+
+    #[test]
+    fn custom_ordering() {
+        let (graph, values) = prior_graph(3);
+        let ordering = VariableOrdering::from_keys([P(2), P(0), P(1)]);
+        let mut opt: GaussNewton = GaussNewton::new(graph).with_ordering(ordering);
+        let result = opt.optimize(values.clone()).expect("Optimization failed");
+
+        let order = &opt.graph_order.as_ref().expect("Missing graph order").order;
+        assert_eq!(order.get(P(2)).expect("Missing P(2)").idx, 0);
+        assert_eq!(order.get(P(1)).expect("Missing P(1)").idx, 4);
+        // Linearizing through the optimizer lays out the columns the same way
+        let system = opt.linearize(&values);
+        assert_eq!(system.order.get(P(2)).expect("Missing P(2)").idx, 0);
+        assert_eq!(system.order.get(P(1)).expect("Missing P(1)").idx, 4);
+        let p: &VectorVar2 = result.get(P(1)).expect("Missing P(1)");
+        assert_matrix_eq!(p.0, VectorVar2::identity().0, comp = abs, tol = 1e-6);
+    }
+
+    #[test]
+    fn fixed_anchor() {
+        // No prior, the fixed variable removes the gauge freedom instead
+        let mut graph = Graph::new();
+        let res = BetweenResidual::new(VectorVar2::new(1.0, 2.0));
+        graph.add_factor(FactorBuilder::new2(res, P(0), P(1)).build());
+
+        let mut values = Values::new();
+        values.insert(P(0), VectorVar2::new(0.5, 0.5));
+        values.insert(P(1), VectorVar2::identity());
+        values.fix(P(0));
+
+        let mut opt: GaussNewton = GaussNewton::new(graph);
+        let result = opt.optimize(values).expect("Optimization failed");
+        let order = &opt.graph_order.as_ref().expect("Missing graph order").order;
+        assert!(order.get(P(0)).is_none());
+
+        let p0: &VectorVar2 = result.get(P(0)).expect("Missing P(0)");
+        let p1: &VectorVar2 = result.get(P(1)).expect("Missing P(1)");
+        assert_matrix_eq!(p0.0, VectorVar2::new(0.5, 0.5).0, comp = float);
+        assert_matrix_eq!(p1.0, VectorVar2::new(1.5, 2.5).0, comp = abs, tol = 1e-6);
+    }
 }
@@ -0,0 +1,272 @@
+use super::{GraphOptimizer, LevenMarquardt, OptError, OptimizationResult, Termination};
+use crate::{
+    containers::{Category, Factor, Graph, Values},
+    dtype,
+    robust::{GncRobust, RobustCost, L2},
+};
+
+/// Parameters for [Gnc]
+#[derive(Debug, Clone)]
+pub struct GncParams {
+    /// Factor $\mu$ is stepped by after each outer iteration
+    pub mu_step: dtype,
+    /// Maximum number of outer iterations, ie surrogates optimized
+    pub max_iterations: usize,
+    /// Only robustify factors of this category, or every factor if None
+    pub category: Option<Category>,
+}
+
+impl Default for GncParams {
+    fn default() -> Self {
+        Self {
+            mu_step: 1.4,
+            max_iterations: 100,
+            category: None,
+        }
+    }
+}
+
+/// Graduated Non-Convexity optimizer
+///
+/// Robust kernels with constant asymptotic behavior are great at rejecting
+/// outliers, but are non-convex and so need a good initialization. GNC (Yang
+/// et al., 2020) avoids this by first solving the problem without a kernel,
+/// then optimizing a sequence of [surrogates](GncRobust::surrogate) of the
+/// kernel, starting from one that is convex over every residual and slowly
+/// moving to the kernel itself. Each surrogate is optimized to completion by
+/// the inner optimizer, initialized from the previous solution.
+///
+/// Kernels supporting GNC include [GemanMcClure](crate::robust::GemanMcClure)
+/// and [TruncatedLeastSquares](crate::robust::TruncatedLeastSquares).
+///
+/// The kernel replaces any kernel already on the robustified factors, which
+/// are either all factors or only those of [GncParams::category]. The graph
+/// of the inner optimizer is restored once finished.
+/// ```
+/// # use factrs::{
+/// #    containers::Graph,
+/// #    optimizers::{Gnc, LevenMarquardt},
+/// #    robust::GemanMcClure,
+/// # };
+/// # let graph = Graph::new();
+/// let inner: LevenMarquardt = LevenMarquardt::new(graph);
+/// let gnc = Gnc::new(inner, GemanMcClure::default());
+/// ```
+pub struct Gnc<K: GncRobust, O: GraphOptimizer = LevenMarquardt> {
+    inner: O,
+    graph: Graph,
+    kernel: K,
+    /// Parameters for the outer GNC iterations
+    pub params: GncParams,
+}
+
+impl<K: GncRobust, O: GraphOptimizer> Gnc<K, O> {
+    pub fn new(inner: O, kernel: K) -> Self {
+        let graph = inner.graph().clone();
+        Self {
+            inner,
+            graph,
+            kernel,
+            params: GncParams::default(),
+        }
+    }
+
+    /// Set the parameters of the outer iterations
+    pub fn with_params(mut self, params: GncParams) -> Self {
+        self.params = params;
+        self
+    }
+
+    /// Get the inner optimizer
+    pub fn inner(&self) -> &O {
+        &self.inner
+    }
+
+    fn selected(&self, factor: &Factor) -> bool {
+        match self.params.category {
+            Some(category) => factor.category() == category,
+            None => true,
+        }
+    }
+
+    // Copy of the graph with `robust` on every selected factor
+    fn robustify<C: RobustCost + Clone + 'static>(&self, robust: &C) -> Graph {
+        let mut graph = Graph::with_capacity(self.graph.len());
+        for mut factor in self.graph.clone() {
+            if self.selected(&factor) {
+                factor.set_robust(robust.clone());
+            }
+            graph.add_factor(factor);
+        }
+        graph
+    }
+
+    // Optimize fully with the inner optimizer, running out of iterations is fine
+    fn solve(
+        &mut self,
+        graph: Graph,
+        values: Values,
+    ) -> Result<OptimizationResult<Values>, OptError<Values>> {
+        self.inner.set_graph(graph);
+        match self.inner.optimize(values) {
+            Ok(result) | Err(OptError::MaxIterations(result)) => Ok(result),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Run GNC
+    ///
+    /// Each outer iteration optimizes one surrogate, with the reported error
+    /// being that of the graph with the final kernel. The termination is that
+    /// of the final inner optimization. If the surrogates haven't reached the
+    /// kernel within [GncParams::max_iterations], [OptError::MaxIterations] is
+    /// returned.
+    pub fn optimize(
+        &mut self,
+        values: Values,
+    ) -> Result<OptimizationResult<Values>, OptError<Values>> {
+        let target = self.robustify(&self.kernel);
+        let initial_error = target.error(&values);
+        let mut history = vec![initial_error];
+
+        // Start from the least squares solution
+        let graph = self.robustify(&L2);
+        let mut values = self.solve(graph, values)?.into_values();
+        let max_d2 = self
+            .graph
+            .iter()
+            .filter(|f| self.selected(f))
+            .map(|f| {
+                let r = f.residual().residual(&values, f.keys());
+                f.noise().whiten_vec(r).norm_squared()
+            })
+            .fold(0.0, dtype::max);
+        let mut mu = self.kernel.mu_init(max_d2);
+
+        log::info!("{:^5} | {:^12} | {:^12}", "GNC", "Mu", "Error");
+        let mut iterations = 0;
+        let termination = loop {
+            if iterations == self.params.max_iterations {
+                break None;
+            }
+
+            let graph = self.robustify(&self.kernel.surrogate(mu));
+            let result = self.solve(graph, values)?;
+            values = result.values;
+            iterations += 1;
+            history.push(target.error(&values));
+            log::info!(
+                "{:^5} | {:^12.4e} | {:^12.4e}",
+                iterations,
+                mu,
+                history[iterations]
+            );
+
+            if self.kernel.mu_converged(mu) {
+                break Some(result.termination);
+            }
+            mu = self.kernel.mu_update(mu, self.params.mu_step);
+        };
+        self.inner.set_graph(self.graph.clone());
+
+        let result = OptimizationResult {
+            values,
+            iterations,
+            initial_error,
+            final_error: history[history.len() - 1],
+            termination: termination.unwrap_or(Termination::MaxIterations),
+            history,
+        };
+        match termination {
+            Some(_) => Ok(result),
+            None => Err(OptError::MaxIterations(result)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use matrixcompare::assert_matrix_eq;
+
+    use super::*;
+    use crate::{
+        assign_symbols,
+        containers::FactorBuilder,
+        linalg::VectorX,
+        noise::GaussianNoise,
+        optimizers::{test::odometry_chain, Optimizer},
+        residuals::BetweenResidual,
+        robust::{GemanMcClure, TruncatedLeastSquares},
+        variables::{Variable, VectorVar2},
+    };
+
+    assign_symbols!(X: VectorVar2);
+
+    // Odometry along x, with two good and two bad loop closures
+    fn graph() -> (Graph, Values) {
+        let (mut graph, values) = odometry_chain(5);
+        let loops = [
+            (0, 5, VectorVar2::new(5.0, 0.0)),
+            (1, 4, VectorVar2::new(3.0, 0.0)),
+            (0, 3, VectorVar2::new(0.0, 3.0)),
+            (2, 5, VectorVar2::new(-3.0, 2.0)),
+        ];
+        for (i, j, delta) in loops {
+            let factor = FactorBuilder::new2(BetweenResidual::new(delta), X(i), X(j))
+                .noise(GaussianNoise::<2>::from_scalar_sigma(0.1))
+                .category(Category::LoopClosure)
+                .build();
+            graph.add_factor(factor);
+        }
+
+        (graph, values)
+    }
+
+    fn assert_odometry(values: &Values) {
+        for i in 0..6 {
+            let x: &VectorVar2 = values.get(X(i)).expect("Missing X");
+            let expected = VectorVar2::new(i as dtype, 0.0);
+            assert_matrix_eq!(
+                x.ominus(&expected),
+                VectorX::zeros(2),
+                comp = abs,
+                tol = 1e-2
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_bad_loop_closures() {
+        let (graph, values) = graph();
+
+        // Without a kernel, the bad loop closures pull the trajectory away
+        let mut plain: LevenMarquardt = LevenMarquardt::new(graph.clone());
+        let result = plain.optimize(values.clone()).expect("Optimization failed");
+        let x3: &VectorVar2 = result.get(X(3)).expect("Missing X(3)");
+        assert!((x3.0 - VectorVar2::new(3.0, 0.0).0).norm() > 0.5);
+
+        let inner: LevenMarquardt = LevenMarquardt::new(graph);
+        let params = GncParams {
+            category: Some(Category::LoopClosure),
+            ..Default::default()
+        };
+        let mut gnc = Gnc::new(inner, GemanMcClure::default()).with_params(params);
+        let result = gnc.optimize(values).expect("GNC failed");
+        assert!(result.iterations > 1);
+        assert_odometry(&result.values);
+    }
+
+    #[test]
+    fn tls_rejects_bad_loop_closures() {
+        let (graph, values) = graph();
+        let inner: LevenMarquardt = LevenMarquardt::new(graph);
+        let params = GncParams {
+            category: Some(Category::LoopClosure),
+            ..Default::default()
+        };
+        let mut gnc = Gnc::new(inner, TruncatedLeastSquares::default()).with_params(params);
+        let result = gnc.optimize(values).expect("GNC failed");
+        assert!(result.iterations > 1);
+        assert_odometry(&result.values);
+    }
+}
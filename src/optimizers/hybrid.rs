@@ -0,0 +1,340 @@
+use std::{ops::Mul, sync::Arc};
+
+use faer::{scale, sparse::SparseColMat, Mat};
+use faer_ext::IntoNalgebra;
+
+use super::{
+    is_finite, GraphOptimizer, LevenParams, OptError, OptObserverVec, OptParams, OptResult,
+    Optimizer,
+};
+use crate::{
+    containers::{Graph, GraphOrder, Values, VariableOrdering},
+    dtype,
+    linalg::{DiffResult, VectorX},
+    linear::{CholeskySolver, LinearSolver, LinearValues},
+};
+
+/// Parameters for the gradient descent fallback of [Hybrid]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GradientParams {
+    /// Maximum number of times the step is halved before giving up
+    pub max_backtracks: usize,
+    /// Factor the step is shrunk by at each backtrack
+    pub backtrack_factor: dtype,
+}
+
+impl Default for GradientParams {
+    fn default() -> Self {
+        Self {
+            max_backtracks: 20,
+            backtrack_factor: 0.5,
+        }
+    }
+}
+
+/// The Hybrid optimizer
+///
+/// Chains together progressively more conservative steps within a single
+/// iteration. First a Gauss-Newton step is attempted, and if that fails to
+/// reduce the error, a Levenberg-Marquardt damped step is tried. Finally, if
+/// that fails as well, a steepest descent step with backtracking is taken.
+///
+/// This gives the speed of Gauss-Newton near the solution, while staying
+/// robust far away from it, without having to pick a solver per problem.
+/// Parameters can be modified using the `params_base`, `params_leven` and
+/// `params_gradient` fields, and observers add using `observers`.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Hybrid<S: LinearSolver = CholeskySolver> {
+    graph: Arc<Graph>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    solver: S,
+    /// Basic parameters for the optimizer
+    pub params_base: OptParams,
+    /// Parameters for the Levenberg-Marquardt fallback
+    pub params_leven: LevenParams,
+    /// Parameters for the gradient descent fallback
+    pub params_gradient: GradientParams,
+    /// Observers for the optimizer
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub observers: OptObserverVec<Values>,
+    lambda: dtype,
+    // For caching computation between steps
+    #[cfg_attr(feature = "serde", serde(skip))]
+    graph_order: Option<GraphOrder>,
+    ordering: VariableOrdering,
+    step_norm: dtype,
+}
+
+impl<S: LinearSolver> Hybrid<S> {
+    pub fn new(graph: impl Into<Arc<Graph>>) -> Self {
+        Self {
+            graph: graph.into(),
+            solver: S::default(),
+            params_base: OptParams::default(),
+            params_leven: LevenParams::default(),
+            params_gradient: GradientParams::default(),
+            observers: OptObserverVec::default(),
+            lambda: 1e-5,
+            graph_order: None,
+            ordering: VariableOrdering::default(),
+            step_norm: 0.0,
+        }
+    }
+
+    /// Create the optimizer with an already configured linear solver
+    pub fn with_solver(graph: impl Into<Arc<Graph>>, solver: S) -> Self {
+        Self {
+            solver,
+            ..Self::new(graph)
+        }
+    }
+
+    /// Set the stopping criteria and iteration limit
+    pub fn with_params(mut self, params: OptParams) -> Self {
+        self.params_base = params;
+        self
+    }
+
+    /// Set how variables are ordered into the linear system
+    pub fn with_ordering(mut self, ordering: VariableOrdering) -> Self {
+        self.ordering = ordering;
+        self.graph_order = None;
+        self
+    }
+
+    pub fn graph(&self) -> &Graph {
+        &self.graph
+    }
+
+    /// Swap in a new graph
+    ///
+    /// If it has the [same structure](Graph::same_structure) as the current
+    /// one, the cached sparsity pattern and symbolic factorization are kept
+    /// for the next [optimize](Optimizer::optimize). Useful for repeatedly
+    /// solving the same shaped problem with different measurements.
+    pub fn set_graph(&mut self, graph: impl Into<Arc<Graph>>) {
+        let graph = graph.into();
+        if !self.graph.same_structure(&graph) {
+            self.graph_order = None;
+        }
+        self.graph = graph;
+    }
+
+    fn to_linear_values(&self, delta: VectorX) -> LinearValues {
+        LinearValues::from_order_and_vector(
+            self.graph_order
+                .as_ref()
+                .expect("Missing graph order")
+                .order
+                .clone(),
+            delta,
+        )
+    }
+
+    // Apply the step, returning the new values if they decreased the error
+    fn try_step(&mut self, values: &Values, delta: VectorX, error: dtype) -> Option<Values> {
+        let step_norm = delta.norm();
+        let dx = self.to_linear_values(delta);
+        let mut candidate = values.clone();
+        candidate.oplus_mut(&dx);
+        if self.graph.error(&candidate) < error {
+            self.step_norm = step_norm;
+            Some(candidate)
+        } else {
+            None
+        }
+    }
+}
+
+fn to_vector(m: Mat<dtype>) -> VectorX {
+    m.as_ref().into_nalgebra().column(0).clone_owned()
+}
+
+impl<S: LinearSolver> Optimizer for Hybrid<S> {
+    type Input = Values;
+
+    fn params(&self) -> &OptParams {
+        &self.params_base
+    }
+
+    fn error(&self, values: &Values) -> dtype {
+        self.graph.error(values)
+    }
+
+    fn residual_dim(&self) -> usize {
+        self.graph.dim_out()
+    }
+
+    fn step_norm(&self) -> dtype {
+        self.step_norm
+    }
+
+    fn init(&mut self, values: &Values) -> Result<(), OptError<Values>> {
+        if self.graph.is_empty() {
+            return Err(OptError::EmptyGraph);
+        }
+        // Reuse the sparsity pattern and symbolic factorization if the
+        // structure hasn't changed since the last optimization
+        if self
+            .graph_order
+            .as_ref()
+            .is_some_and(|g| g.order.matches(values))
+        {
+            return Ok(());
+        }
+        let order = self.ordering.order(&self.graph, values);
+        self.solver.set_order(&order);
+        self.graph_order = Some(self.graph.sparsity_pattern(order));
+        Ok(())
+    }
+
+    fn step(&mut self, values: Values, idx: usize) -> OptResult<Values> {
+        let error = self.graph.error(&values);
+
+        let linear_graph = self.graph.linearize(&values);
+        let DiffResult { value: r, diff: j } =
+            linear_graph.residual_jacobian(self.graph_order.as_ref().expect("Missing graph order"));
+
+        // ------------------------- Gauss-Newton ------------------------- //
+        // A singular system falls through to the damped steps
+        let delta = self
+            .solver
+            .try_solve_lst_sq(j.as_ref(), r.as_ref())
+            .filter(is_finite);
+        if let Some(values) = delta.and_then(|d| self.try_step(&values, to_vector(d), error)) {
+            log::debug!("Hybrid step {}: Gauss-Newton", idx);
+            self.observers.notify(&values, idx);
+            return Ok(values);
+        }
+
+        // ------------------------- Levenberg-Marquardt ------------------------- //
+        let jtj = j
+            .as_ref()
+            .transpose()
+            .to_col_major()
+            .expect("J failed to transpose")
+            .mul(j.as_ref());
+        let triplets_i = (0..jtj.ncols())
+            .map(|i| {
+                let d = if self.params_leven.diagonal_damping {
+                    jtj[(i, i)]
+                } else {
+                    1.0
+                };
+                (i as isize, i as isize, d)
+            })
+            .collect::<Vec<_>>();
+        let i = SparseColMat::<usize, dtype>::try_new_from_nonnegative_triplets(
+            jtj.ncols(),
+            jtj.ncols(),
+            &triplets_i,
+        )
+        .expect("Failed to make damping terms");
+        let b = j.as_ref().transpose().mul(&r);
+
+        let a = &jtj + (&i * scale(self.lambda));
+        let delta = self
+            .solver
+            .try_solve_symmetric(a.as_ref(), b.as_ref())
+            .filter(is_finite);
+        if let Some(values) = delta.and_then(|d| self.try_step(&values, to_vector(d), error)) {
+            log::debug!("Hybrid step {}: Levenberg-Marquardt", idx);
+            self.lambda =
+                (self.lambda / self.params_leven.lambda_factor).max(self.params_leven.lambda_min);
+            self.observers.notify(&values, idx);
+            return Ok(values);
+        }
+        self.lambda =
+            (self.lambda * self.params_leven.lambda_factor).min(self.params_leven.lambda_max);
+
+        // ------------------------- Gradient Descent ------------------------- //
+        // Steepest descent direction, with the step length that is optimal for
+        // the linearized problem (the Cauchy point)
+        let jg = to_vector(j.as_ref().mul(&b));
+        let g = to_vector(b);
+        let mut alpha = g.norm_squared() / jg.norm_squared();
+        if !alpha.is_finite() {
+            return Err(OptError::FailedToStep);
+        }
+
+        for _ in 0..self.params_gradient.max_backtracks {
+            if let Some(values) = self.try_step(&values, &g * alpha, error) {
+                log::debug!("Hybrid step {}: gradient descent", idx);
+                self.observers.notify(&values, idx);
+                return Ok(values);
+            }
+            alpha *= self.params_gradient.backtrack_factor;
+        }
+
+        Err(OptError::FailedToStep)
+    }
+}
+
+impl<S: LinearSolver> GraphOptimizer for Hybrid<S> {
+    fn graph(&self) -> &Graph {
+        Hybrid::graph(self)
+    }
+
+    fn set_graph(&mut self, graph: Graph) {
+        Hybrid::set_graph(self, graph)
+    }
+
+    fn ordering(&self) -> &VariableOrdering {
+        &self.ordering
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        assign_symbols,
+        containers::FactorBuilder,
+        linalg::{Const, ForwardProp, Numeric},
+        optimizers::GaussNewton,
+        residuals::Residual1,
+        test_optimizer,
+        variables::{Variable, VectorVar1},
+    };
+
+    test_optimizer!(Hybrid);
+
+    // Gauss-Newton famously diverges on atan(x) when started far enough away
+    // from the root, as the flat tails massively overshoot the step
+    #[derive(Debug, Clone)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    struct AtanResidual;
+
+    #[factrs::mark]
+    impl Residual1 for AtanResidual {
+        type DimIn = Const<1>;
+        type DimOut = Const<1>;
+        type V1 = VectorVar1;
+        type Differ = ForwardProp<Const<1>>;
+
+        fn residual1<T: Numeric>(&self, x: VectorVar1<T>) -> VectorX<T> {
+            VectorX::from_element(1, x[0].atan())
+        }
+    }
+
+    assign_symbols!(X: VectorVar1);
+
+    #[test]
+    fn gn_diverges() {
+        let mut values = Values::new();
+        values.insert(X(0), VectorVar1::new(2.0));
+
+        let mut graph = Graph::new();
+        graph.add_factor(FactorBuilder::new1(AtanResidual, X(0)).build());
+
+        let mut gn: GaussNewton = GaussNewton::new(graph.clone());
+        let gn_values = gn.optimize(values.clone()).expect("Optimization failed");
+        let gn_x: &VectorVar1 = gn_values.get(X(0)).expect("Missing X(0)");
+        assert!(gn_x[0].abs() > 1.0);
+
+        let mut hybrid: Hybrid = Hybrid::new(graph);
+        let values = hybrid.optimize(values).expect("Optimization failed");
+        let x: &VectorVar1 = values.get(X(0)).expect("Missing X(0)");
+        assert!(x[0].abs() < 1e-3);
+    }
+}
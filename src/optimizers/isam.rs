@@ -0,0 +1,424 @@
+use std::collections::hash_map::Entry;
+
+use faer::sparse::SymbolicSparseColMat;
+use faer_ext::IntoNalgebra;
+use foldhash::HashSet;
+
+use super::{is_finite, OptError};
+use crate::{
+    containers::{Factor, Graph, GraphOrder, Key, Values, ValuesOrder},
+    dtype,
+    linalg::{DiffResult, VectorX},
+    linear::{CholeskySolver, LinearGraph, LinearSolver, LinearValues},
+};
+
+/// Parameters for [Isam]
+pub struct IsamParams {
+    /// Relinearize a variable once any component of its update from the
+    /// linearization point exceeds this
+    pub relinearize_threshold: dtype,
+}
+
+impl Default for IsamParams {
+    fn default() -> Self {
+        Self {
+            relinearize_threshold: 0.1,
+        }
+    }
+}
+
+/// Incremental smoothing and mapping
+///
+/// An incremental optimizer for online problems, such as SLAM, where factors
+/// and variables arrive a few at a time. Rather than relinearizing the entire
+/// graph and iterating to convergence after every new measurement, each
+/// [update](Isam::update) performs a single Gauss-Newton step about a
+/// linearization point $\theta$ that is kept between calls.
+///
+/// Each factor's linearization is cached, and only recomputed when the factor
+/// is new or one of its variables is relinearized. A variable is relinearized
+/// ("fluid relinearization") once its update $\Delta$ from $\theta$ grows
+/// larger than
+/// [relinearize_threshold](IsamParams::relinearize_threshold), at which point
+/// $\theta \leftarrow \theta \oplus \Delta$.
+///
+/// New variables are appended after the existing ones in the variable order,
+/// and new factors after the existing rows, so the sparsity pattern of the
+/// system is extended rather than recomputed. The symbolic factorization of
+/// the linear solver is kept as long as no factors or variables are added.
+///
+/// The current estimate is always $\theta \oplus \Delta$.
+///
+/// ```
+/// # use factrs::{
+///    assign_symbols,
+///    containers::{Graph, FactorBuilder, Values},
+///    optimizers::Isam,
+///    residuals::{BetweenResidual, PriorResidual},
+///    traits::*,
+///    variables::SE2,
+/// };
+/// # assign_symbols!(X: SE2);
+/// let mut isam: Isam = Isam::new();
+///
+/// let mut graph = Graph::new();
+/// let res = PriorResidual::new(SE2::identity());
+/// graph.add_factor(FactorBuilder::new1(res, X(0)).build());
+/// let mut values = Values::new();
+/// values.insert(X(0), SE2::identity());
+/// isam.add_factors(graph);
+/// isam.add_values(values);
+/// isam.update().expect("iSAM update failed");
+///
+/// // Later, a new odometry measurement arrives
+/// let mut graph = Graph::new();
+/// let res = BetweenResidual::new(SE2::new(0.0, 1.0, 0.0));
+/// graph.add_factor(FactorBuilder::new2(res, X(0), X(1)).build());
+/// let mut values = Values::new();
+/// values.insert(X(1), SE2::new(0.0, 0.9, 0.1));
+/// isam.add_factors(graph);
+/// isam.add_values(values);
+/// let estimate = isam.update().expect("iSAM update failed");
+/// ```
+pub struct Isam<S: LinearSolver = CholeskySolver> {
+    factors: Vec<Factor>,
+    // Linearizations about theta of the first linear.len() factors
+    linear: LinearGraph,
+    // Cached linearizations that need to be recomputed
+    stale: Vec<usize>,
+    theta: Values,
+    estimate: Values,
+    delta: VectorX,
+    order: ValuesOrder,
+    solver: S,
+    /// Parameters for the optimizer
+    pub params: IsamParams,
+    // Jacobian sparsity entries and row count of the factors in linear
+    indices: Vec<(usize, usize)>,
+    rows: usize,
+    // Reset whenever the structure of the problem changes
+    graph_order: Option<GraphOrder>,
+}
+
+impl<S: LinearSolver> Default for Isam<S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S: LinearSolver> Isam<S> {
+    pub fn new() -> Self {
+        Self {
+            factors: Vec::new(),
+            linear: LinearGraph::new(),
+            stale: Vec::new(),
+            theta: Values::new(),
+            estimate: Values::new(),
+            delta: VectorX::zeros(0),
+            order: ValuesOrder::from_values(&Values::new()),
+            solver: S::default(),
+            params: IsamParams::default(),
+            indices: Vec::new(),
+            rows: 0,
+            graph_order: None,
+        }
+    }
+
+    /// Add new factors to the problem
+    ///
+    /// They will be linearized on the next [update](Isam::update).
+    pub fn add_factors(&mut self, graph: Graph) {
+        self.factors.extend(graph);
+    }
+
+    /// Add initial guesses for new variables
+    ///
+    /// Variables that are already part of the problem are left unchanged.
+    /// New variables are placed after all existing ones in the order, starting
+    /// with a zero update.
+    pub fn add_values(&mut self, values: Values) {
+        for (key, value) in values {
+            if let Entry::Vacant(e) = self.theta.entry(key) {
+                self.order.push(key, value.dim());
+                e.insert(value.clone());
+                self.estimate.entry(key).or_insert(value);
+                self.graph_order = None;
+            }
+        }
+        self.delta.resize_vertically_mut(self.order.dim(), 0.0);
+    }
+
+    /// Run a single incremental step, returning the current estimate
+    ///
+    /// If the linear system can't be solved, for example because a variable
+    /// isn't constrained yet, an error is returned and the previous estimate
+    /// is kept.
+    pub fn update(&mut self) -> Result<&Values, OptError<Values>> {
+        self.relinearize();
+
+        if self.factors.is_empty() {
+            return Ok(&self.estimate);
+        }
+
+        for i in std::mem::take(&mut self.stale) {
+            let linear = self.factors[i].linearize(&self.theta);
+            self.linear.replace_factor(i, linear);
+        }
+
+        // Existing variables never move columns, so new factors only append
+        // to the sparsity pattern
+        for factor in self.factors[self.linear.len()..].iter() {
+            let linear = factor.linearize(&self.theta);
+            for col in linear.keys.iter().filter_map(|key| self.order.get(*key)) {
+                for i in 0..linear.dim_out() {
+                    for j in 0..col.dim {
+                        self.indices.push((self.rows + i, col.idx + j));
+                    }
+                }
+            }
+            self.rows += linear.dim_out();
+            self.linear.add_factor(linear);
+            self.graph_order = None;
+        }
+
+        let graph_order = self.graph_order.get_or_insert_with(|| {
+            // The structure changed, so the symbolic factorization is stale
+            self.solver.set_order(&self.order);
+            let (sparsity_pattern, sparsity_order) = SymbolicSparseColMat::try_new_from_indices(
+                self.rows,
+                self.order.dim(),
+                &self.indices,
+            )
+            .expect("Failed to create sparse matrix");
+            GraphOrder {
+                order: self.order.clone(),
+                sparsity_pattern,
+                sparsity_order,
+            }
+        });
+
+        let DiffResult { value: r, diff: j } = self.linear.residual_jacobian(graph_order);
+        let delta = self
+            .solver
+            .try_solve_lst_sq(j.as_ref(), r.as_ref())
+            .ok_or(OptError::InvalidSystem)?;
+        if !is_finite(&delta) {
+            return Err(OptError::NonFinite);
+        }
+        self.delta = delta.as_ref().into_nalgebra().column(0).clone_owned();
+
+        self.estimate = self.theta.clone();
+        let dx = LinearValues::from_order_and_vector(self.order.clone(), self.delta.clone());
+        self.estimate.oplus_mut(&dx);
+
+        Ok(&self.estimate)
+    }
+
+    /// The current estimate
+    pub fn values(&self) -> &Values {
+        &self.estimate
+    }
+
+    /// Total error of all factors at the current estimate
+    pub fn error(&self) -> dtype {
+        self.factors.iter().map(|f| f.error(&self.estimate)).sum()
+    }
+
+    /// Number of factors in the problem
+    pub fn len(&self) -> usize {
+        self.factors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.factors.is_empty()
+    }
+
+    // Move variables whose update exceeds the threshold to their new
+    // estimate, and mark their factors for relinearization
+    fn relinearize(&mut self) {
+        let mut relin = self.delta.clone();
+        let mut keys = HashSet::<Key>::default();
+        for (key, idx) in self.order.iter() {
+            let mut d = relin.rows_mut(idx.idx, idx.dim);
+            if d.amax() > self.params.relinearize_threshold {
+                keys.insert(*key);
+            } else {
+                d.fill(0.0);
+            }
+        }
+
+        if keys.is_empty() {
+            return;
+        }
+
+        self.delta -= &relin;
+        let dx = LinearValues::from_order_and_vector(self.order.clone(), relin);
+        self.theta.oplus_mut(&dx);
+        for (i, factor) in self.factors[..self.linear.len()].iter().enumerate() {
+            if factor.keys().iter().any(|k| keys.contains(k)) {
+                self.stale.push(i);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use matrixcompare::assert_matrix_eq;
+
+    use super::*;
+    use crate::{
+        assign_symbols,
+        containers::FactorBuilder,
+        optimizers::{GaussNewton, Optimizer},
+        residuals::{BetweenResidual, PriorResidual},
+        variables::{Variable, VectorVar2, SE2},
+    };
+
+    assign_symbols!(X: SE2; P: VectorVar2);
+
+    #[test]
+    fn linear_matches_batch() {
+        // For a linear problem each update is exact
+        let mut isam: Isam = Isam::new();
+        let mut full = Graph::new();
+        let mut init = Values::new();
+
+        let mut graph = Graph::new();
+        let res = PriorResidual::new(VectorVar2::identity());
+        graph.add_factor(FactorBuilder::new1(res, P(0)).build());
+        let mut values = Values::new();
+        values.insert(P(0), VectorVar2::new(0.5, -0.5));
+        init.insert(P(0), VectorVar2::new(0.5, -0.5));
+        for f in graph.clone() {
+            full.add_factor(f);
+        }
+        isam.add_factors(graph);
+        isam.add_values(values);
+        isam.update().expect("iSAM update failed");
+
+        for i in 0..10 {
+            let mut graph = Graph::new();
+            let res = BetweenResidual::new(VectorVar2::new(1.0, 0.1 * i as dtype));
+            graph.add_factor(FactorBuilder::new2(res, P(i), P(i + 1)).build());
+            let mut values = Values::new();
+            values.insert(P(i + 1), VectorVar2::identity());
+            init.insert(P(i + 1), VectorVar2::identity());
+            for f in graph.clone() {
+                full.add_factor(f);
+            }
+            isam.add_factors(graph);
+            isam.add_values(values);
+            isam.update().expect("iSAM update failed");
+        }
+
+        let mut opt: GaussNewton = GaussNewton::new(full);
+        let batch = opt.optimize(init).expect("Optimization failed");
+        for i in 0..11 {
+            let got: &VectorVar2 = isam.values().get(P(i)).expect("Missing P");
+            let expected: &VectorVar2 = batch.get(P(i)).expect("Missing P");
+            assert_matrix_eq!(got.0, expected.0, comp = abs, tol = 1e-6);
+        }
+    }
+
+    #[test]
+    fn nonlinear_matches_batch() {
+        let mut isam: Isam = Isam::new();
+        isam.params.relinearize_threshold = 0.0;
+        let mut full = Graph::new();
+        let mut init = Values::new();
+
+        let mut graph = Graph::new();
+        let res = PriorResidual::new(SE2::identity());
+        graph.add_factor(FactorBuilder::new1(res, X(0)).build());
+        let mut values = Values::new();
+        values.insert(X(0), SE2::identity());
+        init.insert(X(0), SE2::identity());
+        for f in graph.clone() {
+            full.add_factor(f);
+        }
+        isam.add_factors(graph);
+        isam.add_values(values);
+        isam.update().expect("iSAM update failed");
+
+        for i in 0..10 {
+            let mut graph = Graph::new();
+            let res = BetweenResidual::new(SE2::new(0.3, 1.0, 0.0));
+            graph.add_factor(FactorBuilder::new2(res, X(i), X(i + 1)).build());
+            // Initialize from the previous estimate with some error
+            let prev: &SE2 = isam.values().get(X(i)).expect("Missing X");
+            let guess = prev.compose(&SE2::new(0.2, 0.8, 0.2));
+            let mut values = Values::new();
+            values.insert(X(i + 1), guess.clone());
+            init.insert(X(i + 1), guess);
+            for f in graph.clone() {
+                full.add_factor(f);
+            }
+            isam.add_factors(graph);
+            isam.add_values(values);
+            isam.update().expect("iSAM update failed");
+        }
+        // A few extra steps to converge
+        for _ in 0..5 {
+            isam.update().expect("iSAM update failed");
+        }
+
+        let mut opt: GaussNewton = GaussNewton::new(full);
+        let batch = opt.optimize(init).expect("Optimization failed");
+        for i in 0..11 {
+            let got: &SE2 = isam.values().get(X(i)).expect("Missing X");
+            let expected: &SE2 = batch.get(X(i)).expect("Missing X");
+            assert_matrix_eq!(
+                got.ominus(expected),
+                VectorX::zeros(3),
+                comp = abs,
+                tol = 1e-6
+            );
+        }
+    }
+
+    #[test]
+    fn singular_errors() {
+        // Nothing anchors the two variables
+        let mut isam: Isam = Isam::new();
+        let mut graph = Graph::new();
+        let res = BetweenResidual::new(VectorVar2::new(1.0, 0.0));
+        graph.add_factor(FactorBuilder::new2(res, P(0), P(1)).build());
+        let mut values = Values::new();
+        values.insert(P(0), VectorVar2::identity());
+        values.insert(P(1), VectorVar2::identity());
+        isam.add_factors(graph);
+        isam.add_values(values);
+
+        assert!(isam.update().is_err());
+    }
+
+    #[test]
+    fn relinearize_threshold() {
+        let mut isam: Isam = Isam::new();
+        let mut graph = Graph::new();
+        let res = PriorResidual::new(VectorVar2::new(1.0, 0.0));
+        graph.add_factor(FactorBuilder::new1(res, P(0)).build());
+        let mut values = Values::new();
+        values.insert(P(0), VectorVar2::identity());
+        isam.add_factors(graph);
+        isam.add_values(values);
+
+        // First update solves about the initial guess
+        isam.update().expect("iSAM update failed");
+        assert_eq!(isam.linear.len(), 1);
+        assert!(isam.stale.is_empty());
+        assert_matrix_eq!(
+            isam.delta,
+            VectorX::from_vec(vec![1.0, 0.0]),
+            comp = abs,
+            tol = 1e-6
+        );
+
+        // The step is above the threshold, so the variable is relinearized
+        isam.update().expect("iSAM update failed");
+        let theta: &VectorVar2 = isam.theta.get(P(0)).expect("Missing P");
+        assert_matrix_eq!(theta.0, VectorVar2::new(1.0, 0.0).0, comp = abs, tol = 1e-6);
+        assert!(isam.delta.norm() < 1e-6);
+    }
+}
@@ -43,5 +43,11 @@ macro_rules! test_optimizer {
             let f = |graph| <$o>::new(graph);
             $crate::optimizers::test::optimize_between::<$o, 6, 12, $crate::variables::SE3>(&f);
         }
+
+        #[test]
+        fn fixedvector3() {
+            let f = |graph| <$o>::new(graph);
+            $crate::optimizers::test::optimize_fixed::<$o>(&f);
+        }
     };
 }
@@ -0,0 +1,271 @@
+use std::hash::BuildHasher;
+
+use foldhash::{HashMap, HashSet};
+
+use crate::{
+    containers::{Boundary, Factor, FactorId, Graph, Key, Values},
+    dtype,
+    linalg::{MatrixX, VectorX},
+    noise::DynUnitNoise,
+    residuals::MarginalResidual,
+    robust::L2,
+};
+
+/// Marginalize variables out of a graph
+///
+/// Removes the variables in `keys` from `values`, along with every factor
+/// connected to them. The information those factors held about the remaining
+/// variables they touch (the separator) is kept as a single dense
+/// [MarginalResidual], found by linearizing the removed factors about `values`
+/// and taking the Schur complement,
+/// $$
+/// H_{ss} - H_{sm} H_{mm}^{-1} H_{ms}
+/// $$
+/// This keeps memory bounded in long running problems, such as a sliding
+/// window over old poses.
+///
+/// Directions the removed factors don't constrain are handled with a
+/// pseudo-inverse, and the marginal information is projected to be positive
+/// semidefinite before being factored, so round off can't make it indefinite.
+/// Returns the id of the new factor, or None if there is no information to
+/// keep, such as when the variables form their own disconnected subgraph.
+pub fn marginalize(graph: &mut Graph, values: &mut Values, keys: &[Key]) -> Option<FactorId> {
+    // Order the marginalized variables first, followed by the separator
+    let mut seen = HashSet::<Key>::default();
+    let mut order = keys
+        .iter()
+        .filter(|k| values.get_raw(**k).is_some() && seen.insert(**k))
+        .copied()
+        .collect::<Vec<_>>();
+    let num_marg = order.len();
+    if num_marg == 0 {
+        return None;
+    }
+
+    // Pull out all factors connected to the marginalized variables
+    let ids = graph
+        .ids()
+        .zip(graph.iter())
+        .filter(|(_, f)| f.keys().iter().any(|k| keys.contains(k)))
+        .map(|(id, _)| id)
+        .collect::<Vec<_>>();
+    let factors = ids
+        .into_iter()
+        .filter_map(|id| graph.remove_factor(id))
+        .collect::<Vec<Factor>>();
+    for f in factors.iter() {
+        order.extend(f.keys().iter().filter(|k| seen.insert(**k)));
+    }
+
+    let mut indices = HashMap::<Key, (usize, usize)>::default();
+    let mut dim = 0;
+    let mut dim_marg = 0;
+    for (i, key) in order.iter().enumerate() {
+        let d = values.get_raw(*key).expect("Key missing in values").dim();
+        indices.insert(*key, (dim, d));
+        dim += d;
+        if i + 1 == num_marg {
+            dim_marg = dim;
+        }
+    }
+    let dim_sep = dim - dim_marg;
+
+    // Form the dense normal equations of the removed factors
+    let mut h = MatrixX::zeros(dim, dim);
+    let mut g = VectorX::zeros(dim);
+    for f in factors.iter() {
+        let linear = f.linearize(values);
+        for (i, key_i) in linear.keys.iter().enumerate() {
+            let (idx_i, dim_i) = indices[key_i];
+            let a_i = linear.a.get_block(i);
+            let mut g_i = g.rows_mut(idx_i, dim_i);
+            g_i += a_i.transpose() * &linear.b;
+            for (j, key_j) in linear.keys.iter().enumerate() {
+                let (idx_j, dim_j) = indices[key_j];
+                let mut h_ij = h.view_mut((idx_i, idx_j), (dim_i, dim_j));
+                h_ij += a_i.transpose() * linear.a.get_block(j);
+            }
+        }
+    }
+
+    for key in order[..num_marg].iter() {
+        values.remove_raw(*key);
+    }
+    if dim_sep == 0 {
+        return None;
+    }
+
+    // Schur complement onto the separator
+    let h_mm_inv = pseudo_inverse(h.view((0, 0), (dim_marg, dim_marg)).clone_owned());
+    let h_sm = h.view((dim_marg, 0), (dim_sep, dim_marg));
+    let h_s =
+        h.view((dim_marg, dim_marg), (dim_sep, dim_sep)) - h_sm * &h_mm_inv * h_sm.transpose();
+    let g_s = g.rows(dim_marg, dim_sep) - h_sm * &h_mm_inv * g.rows(0, dim_marg);
+
+    // Factor as A^T A, keeping only the positive directions
+    let h_s = (&h_s + h_s.transpose()) / 2.0;
+    let eig = h_s.symmetric_eigen();
+    let tol = eig.eigenvalues.amax() * dim_sep as dtype * dtype::EPSILON;
+    let kept = (0..dim_sep)
+        .filter(|&i| eig.eigenvalues[i] > tol)
+        .collect::<Vec<_>>();
+    if kept.is_empty() {
+        return None;
+    }
+
+    let mut a = MatrixX::zeros(kept.len(), dim_sep);
+    let mut b = VectorX::zeros(kept.len());
+    for (row, &i) in kept.iter().enumerate() {
+        let sqrt = eig.eigenvalues[i].sqrt();
+        let u = eig.eigenvectors.column(i);
+        a.row_mut(row).copy_from(&(u.transpose() * sqrt));
+        b[row] = u.dot(&g_s) / sqrt;
+    }
+
+    let separator = order[num_marg..].to_vec();
+    let mut linearization = Values::new();
+    for key in separator.iter() {
+        let var = values.get_raw(*key).expect("Key missing in values");
+        linearization.entry(*key).or_insert(var.clone_box());
+    }
+
+    // Already whitened by the factorization above
+    let factor = Factor::from_boxed(
+        Box::new(MarginalResidual::new(a, b, linearization)),
+        separator,
+        Box::new(DynUnitNoise::new(kept.len())),
+        Box::new(L2),
+    );
+    Some(graph.add_factor(factor))
+}
+
+/// Extract a subgraph, summarizing everything outside it as a prior
+///
+/// Like [Graph::subgraph], but rather than dropping or fixing the factors
+/// straddling the boundary, every variable outside `keys` is
+/// [marginalized](marginalize) about its current value, leaving a single
+/// prior on the variables inside. This is dense in the variables outside, so
+/// is best suited to small problems.
+pub fn marginalize_subgraph<S: BuildHasher>(
+    graph: &Graph,
+    keys: &std::collections::HashSet<Key, S>,
+    values: &Values,
+) -> (Graph, Values) {
+    let mut seen = HashSet::<Key>::default();
+    let outside = graph
+        .iter()
+        .flat_map(|f| f.keys().iter().copied())
+        .filter(|k| !keys.contains(k) && seen.insert(*k))
+        .collect::<Vec<_>>();
+
+    // Everything left is inside, along with the new prior
+    let mut graph = graph.clone();
+    let mut values = values.clone();
+    marginalize(&mut graph, &mut values, &outside);
+    graph.subgraph(keys, &values, Boundary::Exclude)
+}
+
+fn pseudo_inverse(h: MatrixX) -> MatrixX {
+    let n = h.nrows();
+    let eig = h.symmetric_eigen();
+    let tol = eig.eigenvalues.amax() * n as dtype * dtype::EPSILON;
+    let inv = eig.eigenvalues.map(|l| if l > tol { 1.0 / l } else { 0.0 });
+    &eig.eigenvectors * MatrixX::from_diagonal(&inv) * eig.eigenvectors.transpose()
+}
+
+#[cfg(test)]
+mod test {
+    use matrixcompare::assert_matrix_eq;
+
+    use super::*;
+    use crate::{
+        assign_symbols,
+        containers::FactorBuilder,
+        noise::GaussianNoise,
+        optimizers::{GaussNewton, Optimizer},
+        residuals::{BetweenResidual, PriorResidual},
+        variables::{Variable, VectorVar2},
+    };
+
+    assign_symbols!(X: VectorVar2);
+
+    fn chain() -> (Graph, Values) {
+        let mut graph = Graph::new();
+        let noise = GaussianNoise::<2>::from_diag_sigmas(0.1, 0.2);
+        let res = PriorResidual::new(VectorVar2::new(0.0, 0.0));
+        graph.add_factor(FactorBuilder::new1(res, X(0)).noise(noise).build());
+        let noise = GaussianNoise::<2>::from_diag_sigmas(0.5, 0.3);
+        let res = BetweenResidual::new(VectorVar2::new(1.0, 0.2));
+        graph.add_factor(FactorBuilder::new2(res, X(0), X(1)).noise(noise).build());
+        let noise = GaussianNoise::<2>::from_diag_sigmas(0.2, 0.4);
+        let res = BetweenResidual::new(VectorVar2::new(1.1, -0.1));
+        graph.add_factor(FactorBuilder::new2(res, X(1), X(2)).noise(noise).build());
+        // Disagrees with the odometry, so the endpoints have to compromise
+        let res = PriorResidual::new(VectorVar2::new(2.5, 0.5));
+        graph.add_factor(FactorBuilder::new1(res, X(2)).build());
+
+        let mut values = Values::new();
+        for i in 0..3 {
+            values.insert(X(i), VectorVar2::new(0.3 * i as dtype, -0.1));
+        }
+        (graph, values)
+    }
+
+    #[test]
+    fn chain_map() {
+        let (graph, values) = chain();
+        let mut opt: GaussNewton = GaussNewton::new(graph.clone());
+        let expected = opt.optimize(values.clone()).expect("Optimization failed");
+
+        let (mut graph, mut values) = chain();
+        let id = marginalize(&mut graph, &mut values, &[X(1).into()]);
+        assert!(id.is_some());
+        assert_eq!(graph.len(), 3);
+        assert_eq!(values.len(), 2);
+
+        let mut opt: GaussNewton = GaussNewton::new(graph);
+        let got = opt.optimize(values).expect("Optimization failed");
+        for i in [0, 2] {
+            let g: &VectorVar2 = got.get(X(i)).expect("Missing X");
+            let e: &VectorVar2 = expected.get(X(i)).expect("Missing X");
+            assert_matrix_eq!(g.ominus(e), VectorX::zeros(2), comp = abs, tol = 1e-6);
+        }
+    }
+
+    #[test]
+    fn disconnected() {
+        let (mut graph, mut values) = chain();
+        let res = PriorResidual::new(VectorVar2::new(1.0, 1.0));
+        graph.add_factor(FactorBuilder::new1(res, X(3)).build());
+        values.insert(X(3), VectorVar2::identity());
+
+        // Nothing else depends on X(3), so there is nothing to keep
+        let id = marginalize(&mut graph, &mut values, &[X(3).into()]);
+        assert!(id.is_none());
+        assert_eq!(graph.len(), 4);
+        assert_eq!(values.len(), 3);
+    }
+
+    #[test]
+    fn subgraph() {
+        let (graph, values) = chain();
+        let mut opt: GaussNewton = GaussNewton::new(graph.clone());
+        let expected = opt.optimize(values.clone()).expect("Optimization failed");
+
+        // X0 becomes a full rank prior on X1
+        let window = [X(1).into(), X(2).into()]
+            .into_iter()
+            .collect::<std::collections::HashSet<Key>>();
+        let (sub, sub_values) = marginalize_subgraph(&graph, &window, &values);
+        assert_eq!(sub.len(), 3);
+        assert_eq!(sub_values.len(), 2);
+
+        let mut opt: GaussNewton = GaussNewton::new(sub);
+        let got = opt.optimize(sub_values).expect("Optimization failed");
+        for i in [1, 2] {
+            let g: &VectorVar2 = got.get(X(i)).expect("Missing X");
+            let e: &VectorVar2 = expected.get(X(i)).expect("Missing X");
+            assert_matrix_eq!(g.ominus(e), VectorX::zeros(2), comp = abs, tol = 1e-6);
+        }
+    }
+}
@@ -31,11 +31,27 @@
 //! of the optimization. A prebuilt [Rerun](https://rerun.io/) can be enabled via
 //! the `rerun` feature.
 //!
+//! The batch optimizers never modify their graph, so it's held in an
+//! [Arc](std::sync::Arc). Constructing one with an `Arc<Graph>` shares the
+//! graph instead of copying it, handy when running many optimizations over
+//! the same graph, such as in benchmarks or Monte Carlo trials.
+//!
+//! With the `serde` feature, the batch optimizers can be serialized to
+//! checkpoint a long running optimization. This saves the graph, parameters,
+//! and internal state such as the damping of [LevenMarquardt]. The linear
+//! solver and its cached factorization are rebuilt on the next step, and
+//! observers need to be added again. Along with the current
+//! [Values](crate::containers::Values), this is enough to resume where the
+//! optimization left off.
+//!
 //! If you desire to implement your own optimizer, we additionally recommend
 //! using the [test_optimizer](crate::test_optimizer) macro to run a handful of
 //! simple tests over a few different variable types to ensure correctness.
 mod traits;
-pub use traits::{OptError, OptObserver, OptObserverVec, OptParams, OptResult, Optimizer};
+pub use traits::{
+    GraphOptimizer, IterationInfo, OptError, OptObserver, OptObserverVec, OptParams, OptResult,
+    OptimizationError, OptimizationResult, Optimizer, Termination,
+};
 
 mod macros;
 
@@ -43,7 +59,28 @@ mod gauss_newton;
 pub use gauss_newton::GaussNewton;
 
 mod levenberg_marquardt;
-pub use levenberg_marquardt::LevenMarquardt;
+pub use levenberg_marquardt::{LambdaPolicy, LevenMarquardt, LevenParams};
+
+mod dogleg;
+pub use dogleg::{Dogleg, DoglegParams};
+
+mod hybrid;
+pub use hybrid::{GradientParams, Hybrid};
+
+mod isam;
+pub use isam::{Isam, IsamParams};
+
+mod gnc;
+pub use gnc::{Gnc, GncParams};
+
+mod marginalize;
+pub use marginalize::{marginalize, marginalize_subgraph};
+
+// Singular systems often solve "successfully" to NaN or infinity rather than
+// failing to factor
+fn is_finite(m: &faer::Mat<crate::dtype>) -> bool {
+    (0..m.ncols()).all(|j| (0..m.nrows()).all(|i| m.read(i, j).is_finite()))
+}
 
 // These aren't tests themselves, but are helpers to test optimizers
 #[cfg(test)]
@@ -56,9 +93,10 @@ pub mod test {
         containers::{FactorBuilder, Graph, Values},
         dtype,
         linalg::{AllocatorBuffer, Const, DualAllocator, DualVector, VectorX},
+        noise::GaussianNoise,
         residuals::{BetweenResidual, PriorResidual, Residual},
         symbols::X,
-        variables::VariableDtype,
+        variables::{Variable, VariableDtype, VectorVar2, VectorVar3},
     };
 
     pub fn optimize_prior<
@@ -84,7 +122,10 @@ pub mod test {
         graph.add_factor(factor);
 
         let mut opt = new(graph);
-        values = opt.optimize(values).expect("Optimization failed");
+        values = opt
+            .optimize(values)
+            .expect("Optimization failed")
+            .into_values();
 
         let out: &T = values.get_unchecked(X(0)).expect("Missing X(0)");
         assert_matrix_eq!(
@@ -134,7 +175,10 @@ pub mod test {
         graph.add_factor(factor);
 
         let mut opt = new(graph);
-        values = opt.optimize(values).expect("Optimization failed");
+        values = opt
+            .optimize(values)
+            .expect("Optimization failed")
+            .into_values();
 
         let out1: &T = values.get_unchecked(X(0)).expect("Missing X(0)");
         assert_matrix_eq!(
@@ -152,4 +196,61 @@ pub mod test {
             tol = 1e-6
         );
     }
+
+    pub fn optimize_fixed<O>(new: &dyn Fn(Graph) -> O)
+    where
+        O: Optimizer<Input = Values>,
+    {
+        let p1 = VectorVar3::new(0.5, -0.5, 1.0);
+        let p2 = VectorVar3::new(1.5, 0.5, -1.0);
+
+        // The fixed variable anchors the between, no prior needed
+        let mut values = Values::new();
+        values.insert_unchecked(X(0), p1.clone());
+        values.insert_unchecked(X(1), VectorVar3::identity());
+        values.fix(X(0));
+
+        let mut graph = Graph::new();
+        let res = BetweenResidual::new(p2.minus(&p1));
+        let factor = FactorBuilder::new2_unchecked(res, X(0), X(1)).build();
+        graph.add_factor(factor);
+
+        let mut opt = new(graph);
+        values = opt
+            .optimize(values)
+            .expect("Optimization failed")
+            .into_values();
+
+        let out1: &VectorVar3 = values.get_unchecked(X(0)).expect("Missing X(0)");
+        assert_eq!(out1.0, p1.0);
+
+        let out2: &VectorVar3 = values.get_unchecked(X(1)).expect("Missing X(1)");
+        assert_matrix_eq!(out2.0, p2.0, comp = abs, tol = 1e-6);
+    }
+
+    /// Unit steps along x from X(0) to X(len), with a tight prior on X(0)
+    ///
+    /// Shared by tests that add loop closures, good or bad, on top. The
+    /// values start at the odometry, ie the solution without loop closures.
+    pub fn odometry_chain(len: u32) -> (Graph, Values) {
+        let mut graph = Graph::new();
+        let mut values = Values::new();
+        let res = PriorResidual::new(VectorVar2::identity());
+        let noise = GaussianNoise::<2>::from_scalar_sigma(0.01);
+        let factor = FactorBuilder::new1_unchecked(res, X(0))
+            .noise(noise)
+            .build();
+        graph.add_factor(factor);
+        values.insert_unchecked(X(0), VectorVar2::identity());
+        for i in 0..len {
+            let res = BetweenResidual::new(VectorVar2::new(1.0, 0.0));
+            let noise = GaussianNoise::<2>::from_scalar_sigma(0.1);
+            let factor = FactorBuilder::new2_unchecked(res, X(i), X(i + 1))
+                .noise(noise)
+                .build();
+            graph.add_factor(factor);
+            values.insert_unchecked(X(i + 1), VectorVar2::new((i + 1) as dtype, 0.0));
+        }
+        (graph, values)
+    }
 }
@@ -1,24 +1,157 @@
-use crate::dtype;
+use std::{fmt, ops::Deref};
+
+use crate::{
+    containers::{Graph, Key, Values, VariableOrdering},
+    dtype,
+    linear::{LinearSystem, SystemDiagnostics},
+};
 
 /// Error types for optimizers
+///
+/// Returned by [Optimizer::optimize] in place of panicking, so a failed
+/// optimization can be recovered from, ie by retrying with a different
+/// optimizer or initialization.
 #[derive(Debug)]
 pub enum OptError<Input> {
-    MaxIterations(Input),
+    /// Hit the maximum number of iterations without converging, contains the
+    /// report so far
+    MaxIterations(OptimizationResult<Input>),
+    /// The linear system was singular or otherwise couldn't be factored
     InvalidSystem,
+    /// No step decreasing the error could be found
     FailedToStep,
+    /// The linear system was singular and nothing anchors these variables,
+    /// see [Graph::unanchored]. Add a prior or [fix](Values::fix) one of them.
+    Unanchored(Vec<Key>),
+    /// The error or step became NaN or infinite
+    NonFinite,
+    /// The graph has no factors, so there is nothing to optimize
+    EmptyGraph,
+}
+
+/// Alias of [OptError]
+pub type OptimizationError<Input> = OptError<Input>;
+
+impl<I> fmt::Display for OptError<I> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OptError::MaxIterations(result) => write!(
+                f,
+                "failed to converge within {} iterations, final error {:.4e}",
+                result.iterations, result.final_error
+            ),
+            OptError::InvalidSystem => write!(f, "linear system is singular"),
+            OptError::FailedToStep => write!(f, "failed to find a step decreasing the error"),
+            OptError::Unanchored(keys) => {
+                write!(f, "{} variables are unanchored", keys.len())
+            }
+            OptError::NonFinite => write!(f, "error or step is NaN or infinite"),
+            OptError::EmptyGraph => write!(f, "graph has no factors"),
+        }
+    }
 }
 
+impl<I: fmt::Debug> std::error::Error for OptError<I> {}
+
 /// Result type for optimizers
 pub type OptResult<Input> = Result<Input, OptError<Input>>;
 
+// ------------------------- Optimizer Report ------------------------- //
+/// Reason an optimization stopped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Termination {
+    /// Error dropped below [error_tol](OptParams::error_tol)
+    ErrorTol,
+    /// Error decrease dropped below
+    /// [error_tol_absolute](OptParams::error_tol_absolute)
+    AbsoluteTol,
+    /// Error decrease dropped below
+    /// [error_tol_relative](OptParams::error_tol_relative)
+    RelativeTol,
+    /// Step norm dropped below [step_tol](OptParams::step_tol)
+    StepTol,
+    /// Stopped by the callback passed to [Optimizer::optimize_with]
+    Callback,
+    /// Hit [max_iterations](OptParams::max_iterations)
+    MaxIterations,
+}
+
+/// Report returned by [Optimizer::optimize]
+///
+/// Holds the optimized values along with a summary of how the optimization
+/// went. It dereferences to the values, so it can be used directly in place
+/// of them, or they can be taken with [into_values](Self::into_values).
+#[derive(Debug, Clone)]
+pub struct OptimizationResult<I> {
+    /// Optimized values
+    pub values: I,
+    /// Number of iterations taken
+    pub iterations: usize,
+    /// Error of the initial values
+    pub initial_error: dtype,
+    /// Error of the final values
+    pub final_error: dtype,
+    /// Why the optimization stopped
+    pub termination: Termination,
+    /// Error at each iteration, starting with the initial error, so this has
+    /// length iterations + 1
+    pub history: Vec<dtype>,
+}
+
+impl<I> OptimizationResult<I> {
+    /// Take the optimized values
+    pub fn into_values(self) -> I {
+        self.values
+    }
+
+    /// Whether a convergence tolerance was reached
+    pub fn converged(&self) -> bool {
+        matches!(
+            self.termination,
+            Termination::ErrorTol
+                | Termination::AbsoluteTol
+                | Termination::RelativeTol
+                | Termination::StepTol
+        )
+    }
+}
+
+impl<I> Deref for OptimizationResult<I> {
+    type Target = I;
+
+    fn deref(&self) -> &I {
+        &self.values
+    }
+}
+
+// Display just the values, so results print the same as before
+impl<I: fmt::Display> fmt::Display for OptimizationResult<I> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.values.fmt(f)
+    }
+}
+
 // ------------------------- Optimizer Params ------------------------- //
 /// Parameters for the optimizer
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct OptParams {
     pub max_iterations: usize,
     pub error_tol_relative: dtype,
     pub error_tol_absolute: dtype,
     pub error_tol: dtype,
+    /// Stop once the norm of the step taken drops below this, see
+    /// [Optimizer::step_norm]. Disabled by default.
+    pub step_tol: dtype,
+    /// Normalize tolerances by the problem size
+    ///
+    /// The total error grows with the number of residuals, so a fixed
+    /// `error_tol` or `error_tol_absolute` means very different things for a
+    /// graph with 10 factors and one with a million. If enabled, both are
+    /// compared against the error divided by the total residual dimension (see
+    /// [Optimizer::residual_dim]), ie the average error per residual
+    /// dimension. `error_tol_relative` is already scale-free and unaffected.
+    pub normalize_tol: bool,
 }
 
 impl Default for OptParams {
@@ -28,6 +161,8 @@ impl Default for OptParams {
             error_tol_relative: 1e-6,
             error_tol_absolute: 1e-6,
             error_tol: 0.0,
+            step_tol: 0.0,
+            normalize_tol: false,
         }
     }
 }
@@ -71,6 +206,20 @@ impl<I> Default for OptObserverVec<I> {
     }
 }
 
+// ------------------------- Iteration Callback ------------------------- //
+/// Summary of a single iteration, passed to [Optimizer::optimize_with]
+#[derive(Debug)]
+pub struct IterationInfo<'a, I> {
+    /// Iteration number, with 0 being the initial values
+    pub iteration: usize,
+    /// Error after this iteration
+    pub error: dtype,
+    /// Norm of the step taken this iteration, see [Optimizer::step_norm]
+    pub step_norm: dtype,
+    /// Values after this iteration
+    pub values: &'a I,
+}
+
 // ------------------------- Actual Trait Impl ------------------------- //
 /// Trait for optimization algorithms
 ///
@@ -90,78 +239,215 @@ pub trait Optimizer {
     fn error(&self, values: &Self::Input) -> dtype;
 
     /// Initialize the optimizer, optional
-    fn init(&mut self, _values: &Self::Input) {}
+    ///
+    /// Called once at the start of [optimize_with](Self::optimize_with), an
+    /// error here stops the optimization before any steps are taken.
+    fn init(&mut self, _values: &Self::Input) -> Result<(), OptError<Self::Input>> {
+        Ok(())
+    }
+
+    /// Total dimension of the residuals, used for
+    /// [normalize_tol](OptParams::normalize_tol). Defaults to 1, ie no
+    /// normalization.
+    fn residual_dim(&self) -> usize {
+        1
+    }
+
+    /// Norm of the tangent space step taken by the last
+    /// [step](Self::step). Defaults to NaN for optimizers that don't track it.
+    fn step_norm(&self) -> dtype {
+        dtype::NAN
+    }
 
-    // TODO: Custom logging based on optimizer
     /// Main optimization call function
-    fn optimize(&mut self, mut values: Self::Input) -> OptResult<Self::Input> {
+    ///
+    /// Returns an [OptimizationResult] with the optimized values and a summary
+    /// of the optimization. If the maximum number of iterations is hit,
+    /// [OptError::MaxIterations] is returned with the same summary. If the
+    /// error becomes NaN or infinite, [OptError::NonFinite] is returned.
+    fn optimize(
+        &mut self,
+        values: Self::Input,
+    ) -> Result<OptimizationResult<Self::Input>, OptError<Self::Input>> {
+        self.optimize_with(values, |_| true)
+    }
+
+    /// Optimize, calling `callback` after every iteration
+    ///
+    /// The callback is first called with the initial values as iteration 0,
+    /// and then after each step with an [IterationInfo], making it handy for
+    /// logging or visualizing the optimization as it happens. Returning
+    /// `false` stops the optimization early with the current values, allowing
+    /// for custom stopping criteria.
+    // TODO: Custom logging based on optimizer
+    fn optimize_with<F>(
+        &mut self,
+        mut values: Self::Input,
+        mut callback: F,
+    ) -> Result<OptimizationResult<Self::Input>, OptError<Self::Input>>
+    where
+        F: FnMut(&IterationInfo<Self::Input>) -> bool,
+    {
         // Setup up everything from our values
-        self.init(&values);
+        self.init(&values)?;
+
+        // Scale for the absolute tolerances
+        let scale = if self.params().normalize_tol {
+            self.residual_dim().max(1) as dtype
+        } else {
+            1.0
+        };
 
         // Check if we need to optimize at all
-        let mut error_old = self.error(&values);
-        if error_old <= self.params().error_tol {
-            log::info!("Error is already below tolerance, skipping optimization");
-            return Ok(values);
+        let initial_error = self.error(&values);
+        if !initial_error.is_finite() {
+            log::error!(
+                "Initial error is {}, check the initial values",
+                initial_error
+            );
+            return Err(OptError::NonFinite);
         }
+        let mut history = vec![initial_error];
+        let mut iterations = 0;
+        let termination = 'opt: {
+            let info = IterationInfo {
+                iteration: 0,
+                error: initial_error,
+                step_norm: 0.0,
+                values: &values,
+            };
+            if !callback(&info) {
+                log::info!("Stopped by callback");
+                break 'opt Termination::Callback;
+            }
+            if initial_error / scale <= self.params().error_tol {
+                log::info!("Error is already below tolerance, skipping optimization");
+                break 'opt Termination::ErrorTol;
+            }
 
-        log::info!(
-            "{:^5} | {:^12} | {:^12} | {:^12}",
-            "Iter",
-            "Error",
-            "ErrorAbs",
-            "ErrorRel"
-        );
-        log::info!(
-            "{:^5} | {:^12} | {:^12} | {:^12}",
-            "-----",
-            "------------",
-            "------------",
-            "------------"
-        );
-        log::info!(
-            "{:^5} | {:^12.4e} | {:^12} | {:^12}",
-            0,
-            error_old,
-            "-",
-            "-"
-        );
-
-        // Begin iterations
-        let mut error_new = error_old;
-        for i in 1..self.params().max_iterations + 1 {
-            error_old = error_new;
-            values = self.step(values, i)?;
-
-            // Evaluate error again to see how we did
-            error_new = self.error(&values);
-
-            let error_decrease_abs = error_old - error_new;
-            let error_decrease_rel = error_decrease_abs / error_old;
-
             log::info!(
-                "{:^5} | {:^12.4e} | {:^12.4e} | {:^12.4e}",
-                i,
-                error_new,
-                error_decrease_abs,
-                error_decrease_rel
+                "{:^5} | {:^12} | {:^12} | {:^12}",
+                "Iter",
+                "Error",
+                "ErrorAbs",
+                "ErrorRel"
+            );
+            log::info!(
+                "{:^5} | {:^12} | {:^12} | {:^12}",
+                "-----",
+                "------------",
+                "------------",
+                "------------"
+            );
+            log::info!(
+                "{:^5} | {:^12.4e} | {:^12} | {:^12}",
+                0,
+                initial_error,
+                "-",
+                "-"
             );
 
-            // Check if we need to stop
-            if error_new <= self.params().error_tol {
-                log::info!("Error is below tolerance, stopping optimization");
-                return Ok(values);
-            }
-            if error_decrease_abs <= self.params().error_tol_absolute {
-                log::info!("Error decrease is below absolute tolerance, stopping optimization");
-                return Ok(values);
-            }
-            if error_decrease_rel <= self.params().error_tol_relative {
-                log::info!("Error decrease is below relative tolerance, stopping optimization");
-                return Ok(values);
+            // Begin iterations
+            let mut error_new = initial_error;
+            for i in 1..self.params().max_iterations + 1 {
+                let error_old = error_new;
+                values = self.step(values, i)?;
+
+                // Evaluate error again to see how we did
+                error_new = self.error(&values);
+                if !error_new.is_finite() {
+                    log::error!("Error became {} at iteration {}", error_new, i);
+                    return Err(OptError::NonFinite);
+                }
+                iterations = i;
+                history.push(error_new);
+
+                let error_decrease_abs = error_old - error_new;
+                let error_decrease_rel = error_decrease_abs / error_old;
+
+                log::info!(
+                    "{:^5} | {:^12.4e} | {:^12.4e} | {:^12.4e}",
+                    i,
+                    error_new,
+                    error_decrease_abs,
+                    error_decrease_rel
+                );
+
+                // Check if we need to stop
+                let info = IterationInfo {
+                    iteration: i,
+                    error: error_new,
+                    step_norm: self.step_norm(),
+                    values: &values,
+                };
+                if !callback(&info) {
+                    log::info!("Stopped by callback");
+                    break 'opt Termination::Callback;
+                }
+                if error_new / scale <= self.params().error_tol {
+                    log::info!("Error is below tolerance, stopping optimization");
+                    break 'opt Termination::ErrorTol;
+                }
+                if error_decrease_abs / scale <= self.params().error_tol_absolute {
+                    log::info!("Error decrease is below absolute tolerance, stopping optimization");
+                    break 'opt Termination::AbsoluteTol;
+                }
+                if error_decrease_rel <= self.params().error_tol_relative {
+                    log::info!("Error decrease is below relative tolerance, stopping optimization");
+                    break 'opt Termination::RelativeTol;
+                }
+                if info.step_norm < self.params().step_tol {
+                    log::info!("Step is below tolerance, stopping optimization");
+                    break 'opt Termination::StepTol;
+                }
             }
+
+            Termination::MaxIterations
+        };
+
+        let result = OptimizationResult {
+            values,
+            iterations,
+            initial_error,
+            final_error: history[history.len() - 1],
+            termination,
+            history,
+        };
+        match termination {
+            Termination::MaxIterations => Err(OptError::MaxIterations(result)),
+            _ => Ok(result),
         }
+    }
+}
+
+/// Optimizer over a nonlinear [Graph]
+///
+/// Gives access to the graph being optimized, so wrappers such as
+/// [Gnc](super::Gnc) can re-run an inner optimizer on a modified version of
+/// it. Implemented by all the batch optimizers.
+pub trait GraphOptimizer: Optimizer<Input = Values> {
+    /// Get the graph being optimized
+    fn graph(&self) -> &Graph;
+
+    /// Swap in a new graph
+    fn set_graph(&mut self, graph: Graph);
+
+    /// How variables are ordered into the linear system
+    fn ordering(&self) -> &VariableOrdering;
+
+    /// Linearize the graph at `values` without solving
+    ///
+    /// The columns are laid out by the optimizer's [ordering](Self::ordering).
+    /// See [LinearSystem] for details.
+    fn linearize(&self, values: &Values) -> LinearSystem {
+        let order = self.ordering().order(self.graph(), values);
+        LinearSystem::with_order(self.graph(), values, order)
+    }
 
-        Err(OptError::MaxIterations(values))
+    /// Diagnose how well the variables are constrained at `values`
+    ///
+    /// See [LinearSystem::diagnostics] for details.
+    fn diagnostics(&self, values: &Values) -> SystemDiagnostics {
+        self.linearize(values).diagnostics()
     }
 }
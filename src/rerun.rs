@@ -1,12 +1,18 @@
 use rerun::{
-    components::RotationQuat, Arrows2D, Arrows3D, AsComponents, Points2D, Points3D, Quaternion,
-    Rotation3D, Transform3D, Vec2D, Vec3D,
+    components::{LineStrip2D, LineStrip3D, RotationQuat},
+    Arrows2D, Arrows3D, AsComponents, Ellipsoids3D, LineStrips2D, LineStrips3D, Points2D, Points3D,
+    Quaternion, Rotation3D, Transform3D, Vec2D, Vec3D,
 };
 
 use crate::{
-    containers::Values,
+    containers::{Category, DefaultSymbolHandler, Graph, Values},
+    dtype,
+    linalg::{Matrix, Matrix2, Matrix3, MatrixX, Vector2},
     optimizers::OptObserver,
-    variables::{MatrixLieGroup, VariableDtype, VectorVar2, VectorVar3, SE2, SE3, SO2, SO3},
+    residuals::BetweenResidual,
+    variables::{
+        MatrixLieGroup, VariableDtype, VariableSafe, VectorVar2, VectorVar3, SE2, SE3, SO2, SO3,
+    },
 };
 
 // ------------------------- 2D Objects ------------------------- //
@@ -360,6 +366,239 @@ impl<'a> FromIterator<&'a SE3> for Points3D {
     }
 }
 
+// ------------------------- Trajectories ------------------------- //
+// Connect poses in the order given as a single strip
+impl<'a> FromIterator<&'a SE2> for LineStrips2D {
+    fn from_iter<I: IntoIterator<Item = &'a SE2>>(iter: I) -> LineStrips2D {
+        let strip = LineStrip2D(iter.into_iter().map(Vec2D::from).collect());
+        LineStrips2D::new([strip])
+    }
+}
+
+impl<'a> FromIterator<&'a SE3> for LineStrips3D {
+    fn from_iter<I: IntoIterator<Item = &'a SE3>>(iter: I) -> LineStrips3D {
+        let strip = LineStrip3D(iter.into_iter().map(Vec3D::from).collect());
+        LineStrips3D::new([strip])
+    }
+}
+
+// Variables of one symbol sorted by index, split wherever an index is missing
+fn trajectory<V: VariableSafe>(values: &Values, chr: char) -> Vec<Vec<&V>> {
+    let mut poses = values
+        .iter()
+        .filter_map(|(key, var)| {
+            let (c, idx) = DefaultSymbolHandler::key_to_sym(*key);
+            let pose = var.downcast_ref::<V>()?;
+            (c == chr).then_some((idx, pose))
+        })
+        .collect::<Vec<_>>();
+    poses.sort_by_key(|(idx, _)| *idx);
+
+    let mut strips: Vec<Vec<&V>> = Vec::new();
+    let mut last = None;
+    for (idx, pose) in poses {
+        match strips.last_mut() {
+            Some(strip) if last.map(|l| l + 1) == Some(idx) => strip.push(pose),
+            _ => strips.push(vec![pose]),
+        }
+        last = Some(idx);
+    }
+    strips
+}
+
+/// Trajectory of the [SE2] poses of a symbol as connected line strips
+///
+/// Poses with symbol `chr`, ie `X(i)` for `'X'`, are connected in order of
+/// their index. A missing index breaks the trajectory, starting a new strip.
+/// Unlike collecting into [Arrows2D], this shows the path itself, such as
+/// odometry drifting between loop closures.
+pub fn trajectory_2d(values: &Values, chr: char) -> LineStrips2D {
+    let strips = trajectory::<SE2>(values, chr)
+        .into_iter()
+        .map(|strip| LineStrip2D(strip.into_iter().map(Vec2D::from).collect()));
+    LineStrips2D::new(strips)
+}
+
+/// Trajectory of the [SE3] poses of a symbol as connected line strips
+///
+/// See [trajectory_2d].
+pub fn trajectory_3d(values: &Values, chr: char) -> LineStrips3D {
+    let strips = trajectory::<SE3>(values, chr)
+        .into_iter()
+        .map(|strip| LineStrip3D(strip.into_iter().map(Vec3D::from).collect()));
+    LineStrips3D::new(strips)
+}
+
+// ------------------------- Factor Edges ------------------------- //
+/// Edges of the [BetweenResidual] factors of a graph, see [edges_3d]
+pub struct Edges3D {
+    /// Edges of odometry factors
+    pub odometry: LineStrips3D,
+    /// Edges of loop closure factors
+    pub loop_closures: LineStrips3D,
+}
+
+// Green when the measurement agrees, through yellow, to red at 3 sigma
+fn residual_color(sigmas: dtype) -> [u8; 3] {
+    let t = (sigmas / 3.0).clamp(0.0, 1.0) as f32;
+    let r = (2.0 * t).min(1.0);
+    let g = (2.0 - 2.0 * t).min(1.0);
+    [(255.0 * r) as u8, (255.0 * g) as u8, 0]
+}
+
+/// Edges of every [SE3] [BetweenResidual] in a graph, drawn between the poses
+///
+/// Each edge connects the translations of its two poses in `values`, so it's
+/// drawn in the world rather than in an abstract graph layout. Edges are
+/// colored by the norm of the whitened residual, ie how many standard
+/// deviations the measurement is off by, going from green to red at 3 sigma,
+/// which makes bad loop closures obvious.
+///
+/// Factors with an [Odometry](Category::Odometry) or
+/// [LoopClosure](Category::LoopClosure) category are split accordingly.
+/// Otherwise, edges between consecutive indices are taken as odometry and the
+/// rest as loop closures.
+pub fn edges_3d(graph: &Graph, values: &Values) -> Edges3D {
+    let mut odometry = (Vec::new(), Vec::new());
+    let mut loop_closures = (Vec::new(), Vec::new());
+
+    for factor in graph.iter() {
+        if factor
+            .residual()
+            .downcast_ref::<BetweenResidual<SE3>>()
+            .is_none()
+        {
+            continue;
+        }
+        let keys = factor.keys();
+        let (Some(a), Some(b)) = (
+            values.get_unchecked::<_, SE3>(keys[0]),
+            values.get_unchecked::<_, SE3>(keys[1]),
+        ) else {
+            continue;
+        };
+
+        let r = factor.residual().residual(values, keys);
+        let sigmas = factor.noise().whiten_vec(r).norm();
+        let is_loop = match factor.category() {
+            Category::Odometry => false,
+            Category::LoopClosure => true,
+            _ => {
+                let (_, i) = DefaultSymbolHandler::key_to_sym(keys[0]);
+                let (_, j) = DefaultSymbolHandler::key_to_sym(keys[1]);
+                i.abs_diff(j) != 1
+            }
+        };
+
+        let (strips, colors) = if is_loop {
+            &mut loop_closures
+        } else {
+            &mut odometry
+        };
+        strips.push(LineStrip3D(vec![Vec3D::from(a), Vec3D::from(b)]));
+        colors.push(residual_color(sigmas));
+    }
+
+    Edges3D {
+        odometry: LineStrips3D::new(odometry.0).with_colors(odometry.1),
+        loop_closures: LineStrips3D::new(loop_closures.0).with_colors(loop_closures.1),
+    }
+}
+
+// ------------------------- Uncertainty ------------------------- //
+// Number of segments used to draw a covariance ellipse
+const ELLIPSE_SEGMENTS: usize = 64;
+
+// Covariance of the translation of a SE2, to first order in the tangent
+fn translation_cov_2d(pose: &SE2, cov: &MatrixX) -> Matrix2 {
+    assert_eq!(cov.shape(), (3, 3), "SE2 covariance must be 3x3");
+    let cov: Matrix3 = cov.fixed_view::<3, 3>(0, 0).into_owned();
+
+    let mut jac = Matrix::<2, 3>::zeros();
+    if cfg!(feature = "left") {
+        // t' = t + theta * t_perp + v
+        jac.set_column(0, &Vector2::new(-pose.y(), pose.x()));
+        jac.fixed_view_mut::<2, 2>(0, 1)
+            .copy_from(&Matrix2::identity());
+    } else {
+        // t' = t + R v
+        jac.fixed_view_mut::<2, 2>(0, 1)
+            .copy_from(&pose.rot().to_matrix());
+    }
+    jac * cov * jac.transpose()
+}
+
+// Covariance of the translation of a SE3, to first order in the tangent
+fn translation_cov_3d(pose: &SE3, cov: &MatrixX) -> Matrix3 {
+    assert_eq!(cov.shape(), (6, 6), "SE3 covariance must be 6x6");
+    let cov: Matrix<6, 6> = cov.fixed_view::<6, 6>(0, 0).into_owned();
+
+    let mut jac = Matrix::<3, 6>::zeros();
+    if cfg!(feature = "left") {
+        // t' = t + w x t + v
+        jac.fixed_view_mut::<3, 3>(0, 0)
+            .copy_from(&-SO3::hat(pose.xyz()));
+        jac.fixed_view_mut::<3, 3>(0, 3)
+            .copy_from(&Matrix3::identity());
+    } else {
+        // t' = t + R v
+        jac.fixed_view_mut::<3, 3>(0, 3)
+            .copy_from(&pose.rot().to_matrix());
+    }
+    jac * cov * jac.transpose()
+}
+
+/// Uncertainty ellipse of the translation of a [SE2]
+///
+/// `cov` is the covariance of the pose in its tangent space, as returned by
+/// [Marginals::marginal_covariance](crate::linear::Marginals::marginal_covariance).
+/// The translation block is mapped into the world frame, and its
+/// eigendecomposition gives the axes of the ellipse, scaled to `sigma`
+/// standard deviations. Rerun has no 2D ellipse, so it's drawn as a closed line
+/// strip.
+#[allow(clippy::unnecessary_cast)]
+pub fn covariance_2d(pose: &SE2, cov: &MatrixX, sigma: dtype) -> LineStrips2D {
+    let eig = translation_cov_2d(pose, cov).symmetric_eigen();
+    let axes = eig.eigenvalues.map(|l| sigma * l.max(0.0).sqrt());
+    let axes = eig.eigenvectors * Matrix2::from_diagonal(&axes);
+
+    let strip = (0..=ELLIPSE_SEGMENTS).map(|i| {
+        let t = std::f64::consts::TAU as dtype * i as dtype / ELLIPSE_SEGMENTS as dtype;
+        let p = axes * Vector2::new(t.cos(), t.sin());
+        Vec2D::new((pose.x() + p.x) as f32, (pose.y() + p.y) as f32)
+    });
+    LineStrips2D::new([LineStrip2D(strip.collect())])
+}
+
+/// Uncertainty ellipsoid of the translation of a [SE3]
+///
+/// See [covariance_2d], with the ellipsoid centered at the pose and its half
+/// sizes being `sigma` standard deviations along each axis.
+#[allow(clippy::unnecessary_cast)]
+pub fn covariance_3d(pose: &SE3, cov: &MatrixX, sigma: dtype) -> Ellipsoids3D {
+    let eig = translation_cov_3d(pose, cov).symmetric_eigen();
+    let half_sizes = eig.eigenvalues.map(|l| (sigma * l.max(0.0).sqrt()) as f32);
+
+    // Eigenvectors may form a reflection, flip one to get a rotation
+    let mut axes = eig.eigenvectors;
+    if axes.determinant() < 0.0 {
+        axes.set_column(2, &-axes.column(2));
+    }
+    let rot = SO3::from_matrix(axes.as_view());
+    let quat = Quaternion::from_xyzw([
+        rot.x() as f32,
+        rot.y() as f32,
+        rot.z() as f32,
+        rot.w() as f32,
+    ]);
+
+    Ellipsoids3D::from_centers_and_half_sizes(
+        [Vec3D::from(pose)],
+        [Vec3D::new(half_sizes[0], half_sizes[1], half_sizes[2])],
+    )
+    .with_quaternions([quat])
+}
+
 // ------------------------- Streamer ------------------------- //
 /// Rerun optimizer observer
 ///
@@ -414,3 +653,93 @@ where
             .expect("Failed to log topic");
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        assign_symbols, fac,
+        linalg::{Vector3, VectorX},
+        variables::Variable,
+    };
+
+    assign_symbols!(X: SE2; Y: SE2; P: SE3);
+
+    #[test]
+    fn trajectory_gaps() {
+        let mut values = Values::new();
+        for i in [0, 1, 2, 4, 5, 7] {
+            values.insert(X(i), SE2::new(0.0, i as crate::dtype, 0.0));
+        }
+        values.insert(Y(3), SE2::identity());
+
+        let lens = trajectory::<SE2>(&values, 'X')
+            .iter()
+            .map(|strip| strip.len())
+            .collect::<Vec<_>>();
+        assert_eq!(lens, vec![3, 2, 1]);
+        assert_eq!(trajectory_2d(&values, 'X').strips.len(), 3);
+    }
+
+    #[test]
+    fn edges() {
+        let mut values = Values::new();
+        let mut graph = Graph::new();
+        let step = SE3::from_rot_trans(SO3::identity(), Vector3::new(1.0, 0.0, 0.0));
+        values.insert(P(0), SE3::identity());
+        for i in 0..3 {
+            let next = values.get(P(i)).expect("Missing pose").compose(&step);
+            values.insert(P(i + 1), next);
+            let res = BetweenResidual::new(step.clone());
+            graph.add_factor(fac![res, (P(i), P(i + 1)), 0.1 as std]);
+        }
+        // A good and a bad loop closure
+        let res = BetweenResidual::new(step.compose(&step).compose(&step));
+        graph.add_factor(fac![res, (P(0), P(3)), 0.1 as std]);
+        let res = BetweenResidual::new(SE3::identity());
+        graph.add_factor(fac![res, (P(1), P(3)), 0.1 as std]);
+
+        let edges = edges_3d(&graph, &values);
+        assert_eq!(edges.odometry.strips.len(), 3);
+        assert_eq!(edges.loop_closures.strips.len(), 2);
+
+        let colors = edges.loop_closures.colors.expect("Missing colors");
+        assert_eq!(colors[0], residual_color(0.0).into());
+        assert_eq!(colors[1], residual_color(100.0).into());
+    }
+
+    #[cfg(not(feature = "left"))]
+    #[test]
+    fn covariance_ellipse() {
+        // Uncertain along the body x axis, which points along world y
+        let pose = SE2::new(std::f64::consts::FRAC_PI_2 as dtype, 1.0, 2.0);
+        let cov = MatrixX::from_diagonal(&VectorX::from_vec(vec![0.1, 4.0, 1.0]));
+        let ellipse = covariance_2d(&pose, &cov, 2.0);
+
+        let points = &ellipse.strips[0].0;
+        assert_eq!(points.len(), ELLIPSE_SEGMENTS + 1);
+        let max_x = points
+            .iter()
+            .map(|p| (p.x() - 1.0).abs())
+            .fold(0.0, f32::max);
+        let max_y = points
+            .iter()
+            .map(|p| (p.y() - 2.0).abs())
+            .fold(0.0, f32::max);
+        assert!((max_x - 2.0).abs() < 1e-3);
+        assert!((max_y - 4.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn covariance_ellipsoid() {
+        let cov = MatrixX::from_diagonal(&VectorX::from_vec(vec![0.1, 0.1, 0.1, 1.0, 4.0, 9.0]));
+        let ellipsoid = covariance_3d(&SE3::identity(), &cov, 1.0);
+
+        let half = ellipsoid.half_sizes[0].0;
+        let mut sizes = [half.x(), half.y(), half.z()];
+        sizes.sort_by(f32::total_cmp);
+        for (got, expected) in sizes.iter().zip([1.0, 2.0, 3.0]) {
+            assert!((got - expected).abs() < 1e-4);
+        }
+    }
+}
@@ -18,6 +18,13 @@ use crate::{
 /// $$
 ///
 /// where $z$ is the measured value.
+///
+/// For poses, $z$ is the pose of $v_2$ expressed in the frame of $v_1$, ie
+/// ${}_1 T_2 = T_1^{-1} T_2$ when $T_i$ maps from frame $i$ to the world.
+/// Odometry and loop closures from scan matching are usually already given
+/// this way, and can be passed to [new](Self::new) or
+/// [from_relative](Self::from_relative) directly. A measurement of
+/// ${}_2 T_1$ instead must be inverted first.
 #[derive(Clone, Debug)]
 #[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 pub struct BetweenResidual<P: Variable> {
@@ -28,6 +35,26 @@ impl<P: Variable> BetweenResidual<P> {
     pub fn new(delta: P) -> Self {
         Self { delta }
     }
+
+    /// Create from a measured relative pose ${}_1 T_2$
+    ///
+    /// Identical to [new](Self::new), named to make the frame convention
+    /// explicit at the call site.
+    /// ```
+    /// # use factrs::{assign_symbols, fac, residuals::BetweenResidual, variables::SE2};
+    /// # assign_symbols!(X: SE2);
+    /// // X(1) is one unit ahead of X(0), in X(0)'s frame
+    /// let a_t_b = SE2::new(0.0, 1.0, 0.0);
+    /// let factor = fac![BetweenResidual::from_relative(a_t_b), (X(0), X(1)), 0.1 as std];
+    /// ```
+    pub fn from_relative(a_t_b: P) -> Self {
+        Self::new(a_t_b)
+    }
+
+    /// Get the measured value
+    pub fn delta(&self) -> &P {
+        &self.delta
+    }
 }
 
 #[factrs::mark]
@@ -0,0 +1,98 @@
+use nalgebra::{DimNameAdd, DimNameSum};
+
+use crate::{
+    linalg::{
+        AllocatorBuffer, DefaultAllocator, DualAllocator, DualVector, ForwardProp, Numeric, VectorX,
+    },
+    residuals::Residual2,
+    variables::{Variable, VariableDtype},
+};
+
+/// Binary factor on the composition of two variables.
+///
+/// This residual is used when the composition of two variables is measured
+/// directly, such as the end-effector of a serial kinematic chain.
+/// Specifically it computes
+///
+/// $$
+/// r = z \ominus (v_1 v_2)
+/// $$
+///
+/// where $z$ is the measured value. Compare this to
+/// [BetweenResidual](super::BetweenResidual), which measures $v_1^{-1} v_2$.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct CompositionResidual<P: Variable> {
+    meas: P,
+}
+
+impl<P: Variable> CompositionResidual<P> {
+    pub fn new(meas: P) -> Self {
+        Self { meas }
+    }
+}
+
+#[factrs::mark]
+impl<P: VariableDtype + 'static> Residual2 for CompositionResidual<P>
+where
+    AllocatorBuffer<DimNameSum<P::Dim, P::Dim>>: Sync + Send,
+    DefaultAllocator: DualAllocator<DimNameSum<P::Dim, P::Dim>>,
+    DualVector<DimNameSum<P::Dim, P::Dim>>: Copy,
+    P::Dim: DimNameAdd<P::Dim>,
+{
+    type Differ = ForwardProp<DimNameSum<P::Dim, P::Dim>>;
+    type V1 = P;
+    type V2 = P;
+    type DimOut = P::Dim;
+    type DimIn = DimNameSum<P::Dim, P::Dim>;
+
+    fn residual2<T: Numeric>(&self, v1: P::Alias<T>, v2: P::Alias<T>) -> VectorX<T> {
+        let meas = self.meas.cast::<T>();
+        meas.ominus(&v1.compose(&v2))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use matrixcompare::assert_matrix_eq;
+
+    use super::*;
+    use crate::{
+        assign_symbols,
+        containers::{Graph, Values},
+        fac,
+        optimizers::{GaussNewton, Optimizer},
+        residuals::PriorResidual,
+        variables::SE2,
+    };
+
+    assign_symbols!(L: SE2);
+
+    #[test]
+    fn two_link_chain() {
+        // Base link is known, the second link is only observed through the
+        // end-effector pose
+        let link1 = SE2::new(0.5, 1.0, 0.0);
+        let link2 = SE2::new(-0.3, 0.8, 0.1);
+        let end_effector = link1.compose(&link2);
+
+        let mut graph = Graph::new();
+        graph.add_factor(fac![PriorResidual::new(link1.clone()), L(0)]);
+        graph.add_factor(fac![CompositionResidual::new(end_effector), (L(0), L(1))]);
+
+        let mut values = Values::new();
+        values.insert(L(0), SE2::identity());
+        values.insert(L(1), SE2::identity());
+
+        let mut opt: GaussNewton = GaussNewton::new(graph);
+        let result = opt.optimize(values).expect("Optimization failed");
+
+        let got: &SE2 = result.get(L(1)).expect("Missing second link");
+        assert_matrix_eq!(
+            got.ominus(&link2),
+            VectorX::zeros(3),
+            comp = abs,
+            tol = 1e-6
+        );
+    }
+}
@@ -0,0 +1,168 @@
+use crate::{
+    dtype,
+    linalg::{Const, ForwardProp, Numeric, VectorX},
+    residuals::Residual4,
+    variables::{Variable, VectorVar6, SE3},
+};
+
+/// Constant velocity prior between consecutive poses.
+///
+/// Links two [SE3] poses $x_k, x_{k+1}$ and their body-frame twists
+/// $\xi_k, \xi_{k+1}$, given as [VectorVar6] ordered like the tangent of
+/// [SE3], ie rotation then translation. Assuming smooth motion over the time
+/// step $\Delta t$, it computes
+/// $$
+/// r = \begin{bmatrix}
+///     x_{k+1} \ominus (x_k \exp(\xi_k \Delta t)) \\\\
+///     \xi_{k+1} - \xi_k
+/// \end{bmatrix}
+/// $$
+/// so the pose follows the twist, and the twist changes slowly. This is a
+/// common regularizer for segments with few measurements, where it keeps the
+/// trajectory from bending freely. The keys are ordered as $x_k, \xi_k,
+/// x_{k+1}, \xi_{k+1}$.
+///
+/// If only the velocity should be smooth, such as the linear velocities used
+/// by the [IMU](crate::residuals::imu_preint), a
+/// [BetweenResidual](crate::residuals::BetweenResidual) with a zero delta is
+/// enough.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ConstantVelocityResidual {
+    dt: dtype,
+}
+
+impl ConstantVelocityResidual {
+    pub fn new(dt: dtype) -> Self {
+        Self { dt }
+    }
+
+    /// Get the time step
+    pub fn dt(&self) -> dtype {
+        self.dt
+    }
+}
+
+#[factrs::mark]
+impl Residual4 for ConstantVelocityResidual {
+    type Differ = ForwardProp<Const<24>>;
+    type V1 = SE3;
+    type V2 = VectorVar6;
+    type V3 = SE3;
+    type V4 = VectorVar6;
+    type DimIn = Const<24>;
+    type DimOut = Const<12>;
+
+    fn residual4<T: Numeric>(
+        &self,
+        x1: SE3<T>,
+        v1: VectorVar6<T>,
+        x2: SE3<T>,
+        v2: VectorVar6<T>,
+    ) -> VectorX<T> {
+        let xi = VectorX::from_column_slice((v1.0 * T::from(self.dt)).as_slice());
+        let predict = x1.compose(&SE3::exp(xi.as_view()));
+
+        let mut r = VectorX::zeros(12);
+        r.rows_mut(0, 6).copy_from(&x2.ominus(&predict));
+        r.rows_mut(6, 6).copy_from(&(v2.0 - v1.0));
+        r
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use matrixcompare::assert_matrix_eq;
+
+    use super::*;
+    use crate::{
+        assign_symbols,
+        containers::{FactorBuilder, Graph, Values},
+        fac,
+        linalg::{vectorx, Diff, NumericalDiff, Vector3},
+        noise::GaussianNoise,
+        optimizers::{GaussNewton, Optimizer},
+        residuals::PriorResidual,
+        variables::SO3,
+    };
+
+    #[cfg(not(feature = "f32"))]
+    const PWR: i32 = 6;
+    #[cfg(not(feature = "f32"))]
+    const TOL: f64 = 1e-6;
+
+    #[cfg(feature = "f32")]
+    const PWR: i32 = 3;
+    #[cfg(feature = "f32")]
+    const TOL: f32 = 1e-3;
+
+    assign_symbols!(X: SE3; V: VectorVar6);
+
+    #[test]
+    fn jacobian() {
+        let res = ConstantVelocityResidual::new(0.1);
+        let x1 = SE3::exp(vectorx![0.1, -0.2, 0.3, 1.0, 0.5, -0.5].as_view());
+        let v1 = VectorVar6::new(0.2, 0.1, -0.3, 1.0, 0.0, 0.5);
+        let x2 = SE3::exp(vectorx![0.2, -0.1, 0.2, 1.1, 0.4, -0.4].as_view());
+        let v2 = VectorVar6::new(0.1, 0.2, -0.2, 0.9, 0.1, 0.4);
+
+        let mut values = Values::new();
+        values.insert(X(0), x1.clone());
+        values.insert(V(0), v1.clone());
+        values.insert(X(1), x2.clone());
+        values.insert(V(1), v2.clone());
+        let keys = [X(0).into(), V(0).into(), X(1).into(), V(1).into()];
+        let got = res.residual4_jacobian(&values, &keys);
+
+        let f = |x1: SE3, v1: VectorVar6, x2: SE3, v2: VectorVar6| res.residual4(x1, v1, x2, v2);
+        let expected = NumericalDiff::<PWR>::jacobian_4(f, &x1, &v1, &x2, &v2);
+
+        assert_matrix_eq!(got.value, expected.value, comp = abs, tol = TOL);
+        assert_matrix_eq!(got.diff, expected.diff, comp = abs, tol = TOL);
+    }
+
+    #[test]
+    fn smooth_trajectory() {
+        // Only the ends are measured, the rest is filled in by the prior
+        let start = SE3::identity();
+        let end = SE3::from_rot_trans(SO3::identity(), Vector3::new(4.0, 0.0, 0.0));
+
+        let mut graph = Graph::new();
+        graph.add_factor(fac![PriorResidual::new(start), X(0), 0.01 as std]);
+        graph.add_factor(fac![PriorResidual::new(end), X(4), 0.01 as std]);
+        for i in 0..4 {
+            let factor = FactorBuilder::new4(
+                ConstantVelocityResidual::new(1.0),
+                X(i),
+                V(i),
+                X(i + 1),
+                V(i + 1),
+            )
+            .noise(GaussianNoise::<12>::from_scalar_sigma(0.1))
+            .build();
+            graph.add_factor(factor);
+        }
+
+        // Start from a zig-zag at rest
+        let mut values = Values::new();
+        for i in 0..5 {
+            let y = if i % 2 == 0 { 0.0 } else { 1.0 };
+            let x = SE3::from_rot_trans(SO3::identity(), Vector3::new(i as dtype, y, 0.0));
+            values.insert(X(i), x);
+            values.insert(V(i), VectorVar6::identity());
+        }
+
+        let mut opt: GaussNewton = GaussNewton::new(graph);
+        let result = opt.optimize(values).expect("Optimization failed");
+
+        for i in 0..5 {
+            let x: &SE3 = result.get(X(i)).expect("Missing X");
+            let expected = Vector3::new(i as dtype, 0.0, 0.0);
+            assert_matrix_eq!(x.xyz(), expected, comp = abs, tol = 1e-4);
+
+            let v: &VectorVar6 = result.get(V(i)).expect("Missing V");
+            let expected = vectorx![0.0, 0.0, 0.0, 1.0, 0.0, 0.0];
+            assert_matrix_eq!(v.0, expected, comp = abs, tol = 1e-4);
+        }
+    }
+}
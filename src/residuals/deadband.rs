@@ -0,0 +1,208 @@
+use crate::{
+    containers::{Key, Values},
+    dtype,
+    linalg::{DiffResult, MatrixX, VectorX},
+    residuals::Residual,
+};
+
+/// Wrapper adding a deadband to any residual.
+///
+/// Models sensors with an insensitivity zone, where small errors produce no
+/// signal. Each component $r_i$ of the wrapped residual is replaced by
+/// $$
+/// \tilde{r}_i = \begin{cases}
+/// 0 & |r_i| \leq w \\\\
+/// \text{sign}(r_i) \frac{(|r_i| - w)^2}{2m} & w < |r_i| < w + m \\\\
+/// \text{sign}(r_i) (|r_i| - w - m / 2) & |r_i| \geq w + m
+/// \end{cases}
+/// $$
+/// where $w$ is the width of the deadband on either side of zero, and $m$ the
+/// margin over which the residual blends in quadratically. Both the residual
+/// and its Jacobian are then continuous, with the Jacobian zero inside the
+/// band, ramping up over the margin, and unchanged past it. A margin of zero
+/// gives the plain soft threshold, which has a kink at the edge of the band.
+///
+/// The deadband is applied to the raw residual, before any whitening by the
+/// noise model.
+///
+/// If serde is enabled, each wrapped residual needs tagging, see
+/// [serialization](crate::residuals#serialization).
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DeadbandResidual<R> {
+    residual: R,
+    width: dtype,
+    margin: dtype,
+}
+
+impl<R> DeadbandResidual<R> {
+    pub fn new(residual: R, width: dtype, margin: dtype) -> Self {
+        assert!(width >= 0.0, "Deadband width must be nonnegative");
+        assert!(margin >= 0.0, "Deadband margin must be nonnegative");
+        Self {
+            residual,
+            width,
+            margin,
+        }
+    }
+
+    /// Width of the deadband on either side of zero
+    pub fn width(&self) -> dtype {
+        self.width
+    }
+
+    /// Width of the quadratic blend past the edge of the deadband
+    pub fn margin(&self) -> dtype {
+        self.margin
+    }
+
+    // Returns the deadbanded residual and its derivative
+    fn deadband(&self, r: dtype) -> (dtype, dtype) {
+        let excess = r.abs() - self.width;
+        if excess <= 0.0 {
+            (0.0, 0.0)
+        } else if excess < self.margin {
+            let value = excess * excess / (2.0 * self.margin);
+            (value.copysign(r), excess / self.margin)
+        } else {
+            ((excess - self.margin / 2.0).copysign(r), 1.0)
+        }
+    }
+}
+
+#[cfg_attr(feature = "serde", typetag::serde)]
+impl<
+        #[cfg(feature = "serde")] R: Residual + Clone + 'static + typetag::Tagged,
+        #[cfg(not(feature = "serde"))] R: Residual + Clone + 'static,
+    > Residual for DeadbandResidual<R>
+{
+    fn dim_in(&self) -> usize {
+        self.residual.dim_in()
+    }
+
+    fn dim_out(&self) -> usize {
+        self.residual.dim_out()
+    }
+
+    fn residual(&self, values: &Values, keys: &[Key]) -> VectorX {
+        self.residual
+            .residual(values, keys)
+            .map(|r| self.deadband(r).0)
+    }
+
+    fn residual_jacobian(&self, values: &Values, keys: &[Key]) -> DiffResult<VectorX, MatrixX> {
+        let DiffResult {
+            mut value,
+            diff: mut jac,
+        } = self.residual.residual_jacobian(values, keys);
+
+        for (i, r) in value.iter_mut().enumerate() {
+            let (rd, d) = self.deadband(*r);
+            *r = rd;
+            jac.row_mut(i).scale_mut(d);
+        }
+
+        DiffResult { value, diff: jac }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use matrixcompare::assert_matrix_eq;
+
+    use super::*;
+    use crate::{
+        assign_symbols,
+        containers::Graph,
+        fac,
+        optimizers::{GaussNewton, Optimizer},
+        residuals::PriorResidual,
+        variables::{Variable, VectorVar2},
+    };
+
+    assign_symbols!(X: VectorVar2);
+
+    fn eval(x: VectorVar2) -> DiffResult<VectorX, MatrixX> {
+        let inner = PriorResidual::new(VectorVar2::identity());
+        let res = DeadbandResidual::new(inner, 0.5, 0.2);
+
+        let mut values = Values::new();
+        values.insert(X(0), x);
+        res.residual_jacobian(&values, &[X(0).into()])
+    }
+
+    #[test]
+    fn inside_band() {
+        let DiffResult { value, diff } = eval(VectorVar2::new(0.3, -0.5));
+        assert_matrix_eq!(value, VectorX::zeros(2), comp = float);
+        assert_matrix_eq!(diff, MatrixX::zeros(2, 2), comp = float);
+    }
+
+    #[test]
+    fn outside_band() {
+        // Prior on zero gives r = -x, one component in the band and one past
+        // the margin
+        let DiffResult { value, diff } = eval(VectorVar2::new(0.2, -2.0));
+        assert_matrix_eq!(
+            value,
+            VectorX::from_vec(vec![0.0, 1.4]),
+            comp = abs,
+            tol = 1e-6
+        );
+        let expected = MatrixX::from_row_slice(2, 2, &[0.0, 0.0, 0.0, -1.0]);
+        assert_matrix_eq!(diff, expected, comp = abs, tol = 1e-6);
+    }
+
+    #[test]
+    fn margin() {
+        // Halfway through the margin
+        let DiffResult { value, diff } = eval(VectorVar2::new(-0.6, 0.6));
+        assert_matrix_eq!(
+            value,
+            VectorX::from_vec(vec![0.025, -0.025]),
+            comp = abs,
+            tol = 1e-6
+        );
+        let expected = MatrixX::from_row_slice(2, 2, &[-0.5, 0.0, 0.0, -0.5]);
+        assert_matrix_eq!(diff, expected, comp = abs, tol = 1e-6);
+    }
+
+    #[test]
+    fn smooth() {
+        // Both the residual and Jacobian are continuous at either end of the
+        // margin
+        let eps = 1e-6;
+        for edge in [0.5, 0.7] {
+            let inside = eval(VectorVar2::new(edge - eps, -edge + eps));
+            let outside = eval(VectorVar2::new(edge + eps, -edge - eps));
+            assert_matrix_eq!(inside.value, outside.value, comp = abs, tol = 1e-5);
+            assert_matrix_eq!(inside.diff, outside.diff, comp = abs, tol = 1e-4);
+        }
+    }
+
+    #[test]
+    fn optimize() {
+        // A deadband prior at zero, against a prior outside it
+        let mut graph = Graph::new();
+        let inner = PriorResidual::new(VectorVar2::identity());
+        let res = DeadbandResidual::new(inner, 0.5, 0.1);
+        graph.add_factor(fac![res, X(0)]);
+        let res = PriorResidual::new(VectorVar2::new(2.0, 0.2));
+        graph.add_factor(fac![res, X(0)]);
+
+        let mut values = Values::new();
+        values.insert(X(0), VectorVar2::new(2.0, 0.2));
+        let mut opt: GaussNewton = GaussNewton::new(graph);
+        let result = opt.optimize(values).expect("Optimization failed");
+        let x: &VectorVar2 = result.get(X(0)).expect("Missing X(0)");
+
+        // Only the excess past the band is pulled back, y is left alone
+        let expected = VectorVar2::new((2.0 + 0.5 + 0.05) / 2.0, 0.2);
+        assert_matrix_eq!(
+            x.ominus(&expected),
+            VectorX::zeros(2),
+            comp = abs,
+            tol = 1e-6
+        );
+    }
+}
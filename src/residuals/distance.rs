@@ -0,0 +1,124 @@
+use crate::{
+    dtype,
+    linalg::{Const, ForwardProp, Numeric, VectorX},
+    residuals::Residual2,
+    variables::VectorVar3,
+};
+
+/// Binary factor fixing the distance between two points.
+///
+/// Unlike a range measurement, this is meant for known design constraints,
+/// such as two sensors rigidly mounted a known distance apart on a rig.
+/// Specifically it computes
+///
+/// $$
+/// r = \|p_1 - p_2\| - d
+/// $$
+///
+/// where $d$ is the known distance.
+///
+/// factrs has no constrained optimizer, so the constraint is enforced softly.
+/// Give the factor a large information (ie a tiny sigma) relative to the rest
+/// of the graph, such as `1e-4 as std` in [fac](crate::fac), to make it
+/// behave like a hard constraint. Note the residual isn't differentiable when
+/// the two points coincide, so they should be initialized apart.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct FixedDistanceResidual {
+    distance: dtype,
+}
+
+impl FixedDistanceResidual {
+    pub fn new(distance: dtype) -> Self {
+        Self { distance }
+    }
+
+    /// Get the known distance
+    pub fn distance(&self) -> dtype {
+        self.distance
+    }
+}
+
+#[factrs::mark]
+impl Residual2 for FixedDistanceResidual {
+    type Differ = ForwardProp<Const<6>>;
+    type V1 = VectorVar3;
+    type V2 = VectorVar3;
+    type DimOut = Const<1>;
+    type DimIn = Const<6>;
+
+    fn residual2<T: Numeric>(&self, p1: VectorVar3<T>, p2: VectorVar3<T>) -> VectorX<T> {
+        let d = (p1.0 - p2.0).norm();
+        VectorX::from_element(1, d - T::from(self.distance))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use matrixcompare::assert_matrix_eq;
+
+    use super::*;
+    use crate::{
+        assign_symbols,
+        containers::{Graph, Values},
+        fac,
+        linalg::{Diff, NumericalDiff},
+        optimizers::{GaussNewton, Optimizer},
+        residuals::PriorResidual,
+    };
+
+    #[cfg(not(feature = "f32"))]
+    const PWR: i32 = 6;
+    #[cfg(not(feature = "f32"))]
+    const TOL: f64 = 1e-6;
+
+    #[cfg(feature = "f32")]
+    const PWR: i32 = 3;
+    #[cfg(feature = "f32")]
+    const TOL: f32 = 1e-3;
+
+    assign_symbols!(P: VectorVar3);
+
+    #[test]
+    fn jacobian() {
+        let res = FixedDistanceResidual::new(2.0);
+        let p1 = VectorVar3::new(0.5, -0.2, 1.0);
+        let p2 = VectorVar3::new(-1.0, 0.3, 0.4);
+
+        let mut values = Values::new();
+        values.insert(P(0), p1.clone());
+        values.insert(P(1), p2.clone());
+        let got = res
+            .residual2_jacobian(&values, &[P(0).into(), P(1).into()])
+            .diff;
+
+        let f = |p1: VectorVar3, p2: VectorVar3| res.residual2(p1, p2);
+        let expected = NumericalDiff::<PWR>::jacobian_2(f, &p1, &p2).diff;
+
+        assert_matrix_eq!(got, expected, comp = abs, tol = TOL);
+    }
+
+    #[test]
+    fn pulls_to_distance() {
+        let p1 = VectorVar3::new(0.0, 0.0, 0.0);
+        let p2 = VectorVar3::new(1.0, 0.0, 0.0);
+
+        // Weak prior on the second point keeps its direction determined
+        let mut graph = Graph::new();
+        graph.add_factor(fac![PriorResidual::new(p1.clone()), P(0), 1e-3 as std]);
+        graph.add_factor(fac![PriorResidual::new(p2.clone()), P(1), 1.0 as std]);
+        let res = FixedDistanceResidual::new(2.0);
+        graph.add_factor(fac![res, (P(0), P(1)), 1e-4 as std]);
+
+        let mut values = Values::new();
+        values.insert(P(0), p1);
+        values.insert(P(1), VectorVar3::new(1.0, 0.2, -0.1));
+
+        let mut opt: GaussNewton = GaussNewton::new(graph);
+        let result = opt.optimize(values).expect("Optimization failed");
+        let p1: &VectorVar3 = result.get(P(0)).expect("Missing P(0)");
+        let p2: &VectorVar3 = result.get(P(1)).expect("Missing P(1)");
+
+        assert!(((p1.0 - p2.0).norm() - 2.0).abs() < 1e-6);
+    }
+}
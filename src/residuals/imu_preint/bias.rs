@@ -0,0 +1,90 @@
+use crate::{
+    linalg::{Const, ForwardProp, Numeric, VectorX},
+    residuals::Residual2,
+    variables::{ImuBias, Variable},
+};
+
+/// Binary factor modeling the random walk of an IMU bias.
+///
+/// IMU biases drift slowly over time, which is modeled as a random walk
+/// between consecutive bias variables. Specifically it computes
+/// $$
+/// r = b_2 - b_1
+/// $$
+/// with the gyro bias in the first three rows and the accelerometer bias in
+/// the last three. Pair it with a small sigma that grows with the time
+/// between the two biases, usually split between the gyro and accelerometer,
+/// ie `(1e-4, 1e-3) as std` in [fac](crate::fac).
+///
+/// Note the [ImuPreintegrator](super::ImuPreintegrator) factor already
+/// includes this random walk between the biases it connects, so this is for
+/// biases that aren't otherwise linked, such as across a gap in IMU data.
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BiasBetweenResidual;
+
+impl BiasBetweenResidual {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+#[factrs::mark]
+impl Residual2 for BiasBetweenResidual {
+    type Differ = ForwardProp<Const<12>>;
+    type V1 = ImuBias;
+    type V2 = ImuBias;
+    type DimIn = Const<12>;
+    type DimOut = Const<6>;
+
+    fn residual2<T: Numeric>(&self, b1: ImuBias<T>, b2: ImuBias<T>) -> VectorX<T> {
+        b2.ominus(&b1)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use matrixcompare::assert_matrix_eq;
+
+    use super::*;
+    use crate::{
+        assign_symbols,
+        containers::{Graph, Values},
+        dtype, fac,
+        optimizers::{GaussNewton, Optimizer},
+        residuals::{Accel, Gyro, PriorResidual},
+    };
+
+    assign_symbols!(B: ImuBias);
+
+    #[test]
+    fn chain_stays_near_zero() {
+        let mut graph = Graph::new();
+        graph.add_factor(fac![
+            PriorResidual::new(ImuBias::zeros()),
+            B(0),
+            1e-4 as std
+        ]);
+        for i in 0..5 {
+            let res = BiasBetweenResidual::new();
+            graph.add_factor(fac![res, (B(i), B(i + 1)), (1e-3, 1e-2) as std]);
+        }
+
+        let mut values = Values::new();
+        for i in 0..6 {
+            let offset = 0.1 * i as dtype;
+            let bias = ImuBias::new(
+                Gyro::new(offset, -offset, 0.0),
+                Accel::new(0.0, offset, 1.0),
+            );
+            values.insert(B(i), bias);
+        }
+
+        let mut opt: GaussNewton = GaussNewton::new(graph);
+        let result = opt.optimize(values).expect("Optimization failed");
+        for i in 0..6 {
+            let bias: &ImuBias = result.get(B(i)).expect("Missing bias");
+            assert_matrix_eq!(bias.log(), VectorX::zeros(6), comp = abs, tol = 1e-6);
+        }
+    }
+}
@@ -8,3 +8,6 @@ mod delta;
 
 mod residual;
 pub use residual::{ImuCovariance, ImuPreintegrator};
+
+mod bias;
+pub use bias::BiasBetweenResidual;
@@ -0,0 +1,91 @@
+use crate::{
+    containers::{Key, Values},
+    linalg::{DiffResult, MatrixX, VectorX},
+    residuals::Residual,
+};
+
+/// Dense linear prior left over from marginalization.
+///
+/// Captures the information a set of marginalized variables had on the
+/// variables they were connected to, as a whitened linear system $A, b$
+/// computed about a fixed linearization point $\bar{x}$. Specifically it
+/// computes
+/// $$
+/// r = A (x \ominus \bar{x}) - b
+/// $$
+/// where $x \ominus \bar{x}$ stacks the tangent space offsets of each variable
+/// in key order. As is standard, the Jacobian is kept fixed at $A$ rather than
+/// relinearized, so the prior is most accurate near $\bar{x}$.
+///
+/// The residual is already whitened, so should be used with unit noise.
+/// Usually created with [marginalize](crate::optimizers::marginalize), rather
+/// than by hand.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MarginalResidual {
+    a: MatrixX,
+    b: VectorX,
+    linearization: Values,
+}
+
+impl MarginalResidual {
+    pub fn new(a: MatrixX, b: VectorX, linearization: Values) -> Self {
+        assert!(
+            a.nrows() == b.len(),
+            "Mismatch between matrix and vector in MarginalResidual::new"
+        );
+        Self {
+            a,
+            b,
+            linearization,
+        }
+    }
+
+    /// Values of the variables the prior was linearized about
+    pub fn linearization(&self) -> &Values {
+        &self.linearization
+    }
+
+    fn delta(&self, values: &Values, keys: &[Key]) -> VectorX {
+        let deltas = keys
+            .iter()
+            .map(|key| {
+                let x = values.get_raw(*key).expect("Key missing in values");
+                let x_bar = self
+                    .linearization
+                    .get_raw(*key)
+                    .expect("Key missing in linearization point");
+                x.ominus_dyn(x_bar)
+            })
+            .collect::<Vec<_>>();
+        let mut delta = VectorX::zeros(self.a.ncols());
+        let mut idx = 0;
+        for d in deltas {
+            delta.rows_mut(idx, d.len()).copy_from(&d);
+            idx += d.len();
+        }
+        delta
+    }
+}
+
+#[cfg_attr(feature = "serde", typetag::serde)]
+impl Residual for MarginalResidual {
+    fn dim_in(&self) -> usize {
+        self.a.ncols()
+    }
+
+    fn dim_out(&self) -> usize {
+        self.a.nrows()
+    }
+
+    fn residual(&self, values: &Values, keys: &[Key]) -> VectorX {
+        &self.a * self.delta(values, keys) - &self.b
+    }
+
+    fn residual_jacobian(&self, values: &Values, keys: &[Key]) -> DiffResult<VectorX, MatrixX> {
+        DiffResult {
+            value: self.residual(values, keys),
+            diff: self.a.clone(),
+        }
+    }
+}
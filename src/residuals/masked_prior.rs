@@ -0,0 +1,140 @@
+use crate::{
+    containers::{Key, Values},
+    linalg::{DiffResult, MatrixX, VectorX},
+    residuals::{PriorResidual, Residual},
+    variables::VariableDtype,
+};
+
+/// Prior on only a subset of the degrees of freedom of a variable.
+///
+/// Computes the same residual as [PriorResidual], $z \ominus v$, but keeps
+/// only the selected components of the tangent space, ie
+/// $$
+/// S (z \ominus v)
+/// $$
+/// where $S$ is the selection matrix. The Jacobian is likewise $S$ times the
+/// full prior Jacobian. Useful for partial measurements, such as a prior on
+/// only the height and yaw of an [SE3](crate::variables::SE3).
+///
+/// The mask is in the tangent space ordering of the variable, eg rotation
+/// first for [SE3](crate::variables::SE3).
+///
+/// If serde is enabled, each variable type needs tagging, see
+/// [serialization](crate::residuals#serialization).
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MaskedPriorResidual<P> {
+    prior: PriorResidual<P>,
+    selected: Vec<usize>,
+}
+
+impl<P: VariableDtype> MaskedPriorResidual<P> {
+    pub fn new(prior: P, mask: &[bool]) -> Self {
+        assert!(
+            mask.len() == P::DIM,
+            "Mask length must match the dimension of the variable"
+        );
+        let selected: Vec<usize> = (0..P::DIM).filter(|&i| mask[i]).collect();
+        assert!(
+            !selected.is_empty(),
+            "Mask must select at least one dimension"
+        );
+        Self {
+            prior: PriorResidual::new(prior),
+            selected,
+        }
+    }
+
+    /// Get the prior value
+    pub fn prior(&self) -> &P {
+        self.prior.prior()
+    }
+
+    /// Indices of the constrained tangent space dimensions
+    pub fn selected(&self) -> &[usize] {
+        &self.selected
+    }
+}
+
+#[cfg_attr(feature = "serde", typetag::serde)]
+impl<
+        #[cfg(feature = "serde")] P: VariableDtype + 'static + typetag::Tagged,
+        #[cfg(not(feature = "serde"))] P: VariableDtype + 'static,
+    > Residual for MaskedPriorResidual<P>
+where
+    PriorResidual<P>: Residual,
+{
+    fn dim_in(&self) -> usize {
+        self.prior.dim_in()
+    }
+
+    fn dim_out(&self) -> usize {
+        self.selected.len()
+    }
+
+    fn residual(&self, values: &Values, keys: &[Key]) -> VectorX {
+        self.prior
+            .residual(values, keys)
+            .select_rows(self.selected.iter())
+    }
+
+    fn residual_jacobian(&self, values: &Values, keys: &[Key]) -> DiffResult<VectorX, MatrixX> {
+        let DiffResult { value, diff } = self.prior.residual_jacobian(values, keys);
+        DiffResult {
+            value: value.select_rows(self.selected.iter()),
+            diff: diff.select_rows(self.selected.iter()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use matrixcompare::assert_matrix_eq;
+
+    use super::*;
+    use crate::{
+        assign_symbols,
+        linalg::vectorx,
+        variables::{Variable, SE3},
+    };
+
+    assign_symbols!(X: SE3);
+
+    // Tangent space of SE3 is rotation then translation
+    const Z_YAW: [bool; 6] = [false, false, true, false, false, true];
+
+    #[test]
+    fn jacobian() {
+        let target = SE3::exp(vectorx![0.1, -0.2, 0.3, 1.0, 2.0, 3.0].as_view());
+        let x = SE3::exp(vectorx![0.2, 0.1, -0.1, 0.5, -1.0, 2.0].as_view());
+        let mut values = Values::new();
+        values.insert(X(0), x);
+        let keys = [X(0).into()];
+
+        let full = PriorResidual::new(target.clone()).residual_jacobian(&values, &keys);
+        let got = MaskedPriorResidual::new(target, &Z_YAW).residual_jacobian(&values, &keys);
+
+        assert_eq!(got.value.len(), 2);
+        assert_matrix_eq!(got.value, full.value.select_rows(&[2, 5]), comp = float);
+        assert_matrix_eq!(got.diff, full.diff.select_rows(&[2, 5]), comp = float);
+    }
+
+    #[test]
+    fn z_yaw_only() {
+        let res = MaskedPriorResidual::new(SE3::identity(), &Z_YAW);
+
+        // Moving in any of the unselected directions is free
+        let x = SE3::exp(vectorx![0.1, 0.2, 0.0, 1.0, 2.0, 0.0].as_view());
+        let mut values = Values::new();
+        values.insert(X(0), x);
+        let r = res.residual(&values, &[X(0).into()]);
+        assert_matrix_eq!(r, VectorX::zeros(2), comp = abs, tol = 1e-6);
+
+        // But not in z or yaw
+        let x = SE3::exp(vectorx![0.0, 0.0, 0.3, 0.0, 0.0, 1.5].as_view());
+        let mut values = Values::new();
+        values.insert(X(0), x);
+        let r = res.residual(&values, &[X(0).into()]);
+        assert_matrix_eq!(r.abs(), vectorx![0.3, 1.5], comp = abs, tol = 1e-6);
+    }
+}
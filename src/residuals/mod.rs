@@ -46,6 +46,63 @@
 //!     }
 //! }
 //! ```
+//!
+//! Residuals over more variables work the same way, with [Residual1] through
+//! [Residual6] supporting up to six. `DimIn` is the sum of the dimensions of
+//! all the variables, and the variables are passed to the residual in the
+//! order of the keys of the factor. For example, a constraint that two pairs of
+//! poses moved the same, such as two robots driving in formation, touches four
+//! poses.
+//!
+//! ```
+//! use factrs::{
+//!     linalg::{Const, ForwardProp, Numeric, VectorX},
+//!     residuals,
+//!     traits::Variable,
+//!     variables::SE2,
+//! };
+//!
+//! #[derive(Debug, Clone)]
+//! # #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+//! struct SameMotion;
+//!
+//! #[factrs::mark]
+//! impl residuals::Residual4 for SameMotion {
+//!     type DimIn = Const<12>;
+//!     type DimOut = Const<3>;
+//!     type V1 = SE2;
+//!     type V2 = SE2;
+//!     type V3 = SE2;
+//!     type V4 = SE2;
+//!     type Differ = ForwardProp<Const<12>>;
+//!
+//!     fn residual4<T: Numeric>(
+//!         &self,
+//!         x1: SE2<T>,
+//!         x2: SE2<T>,
+//!         x3: SE2<T>,
+//!         x4: SE2<T>,
+//!     ) -> VectorX<T> {
+//!         let a = x1.inverse().compose(&x2);
+//!         let b = x3.inverse().compose(&x4);
+//!         a.ominus(&b)
+//!     }
+//! }
+//! ```
+//!
+//! Past six variables, implement [Residual] directly.
+//!
+//! # Serialization
+//! With the `serde` feature, residuals are serialized as trait objects, so
+//! every concrete type has to be registered with `tag_residual`. The
+//! [mark](factrs::mark) macro handles residuals without generics, and tags
+//! [PriorResidual], [BetweenResidual] and [CompositionResidual] for each marked
+//! variable. Any other generic residual, such as [MaskedPriorResidual], or
+//! [ScaledResidual], [DeadbandResidual] and [SwitchableResidual] wrapping
+//! another residual, has to be tagged for each type it's used with, ie
+//! `tag_residual!(ScaledResidual<MyResidual>)`. Generic robust kernels like
+//! [ComposedRobust](crate::robust::ComposedRobust) are the same, using
+//! `tag_robust`.
 mod traits;
 #[cfg(feature = "serde")]
 pub use traits::tag_residual;
@@ -57,5 +114,44 @@ pub use prior::PriorResidual;
 mod between;
 pub use between::BetweenResidual;
 
+mod rotation_between;
+pub use rotation_between::RotationBetweenResidual;
+
+mod composition;
+pub use composition::CompositionResidual;
+
+mod projection;
+pub use projection::ProjectionResidual;
+
+mod position;
+pub use position::PositionPriorResidual;
+
+mod distance;
+pub use distance::FixedDistanceResidual;
+
+mod point_to_plane;
+pub use point_to_plane::PointToPlaneResidual;
+
+mod point_to_point;
+pub use point_to_point::PointToPointResidual;
+
+mod masked_prior;
+pub use masked_prior::MaskedPriorResidual;
+
+mod marginal;
+pub use marginal::MarginalResidual;
+
+mod deadband;
+pub use deadband::DeadbandResidual;
+
+mod scaled;
+pub use scaled::ScaledResidual;
+
+mod switchable;
+pub use switchable::SwitchableResidual;
+
+mod constant_velocity;
+pub use constant_velocity::ConstantVelocityResidual;
+
 pub mod imu_preint;
-pub use imu_preint::{Accel, Gravity, Gyro, ImuCovariance, ImuPreintegrator};
+pub use imu_preint::{Accel, BiasBetweenResidual, Gravity, Gyro, ImuCovariance, ImuPreintegrator};
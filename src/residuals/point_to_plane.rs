@@ -0,0 +1,127 @@
+use crate::{
+    dtype,
+    linalg::{vectorx, Const, ForwardProp, Numeric, Vector3, VectorX},
+    residuals::Residual1,
+    variables::{MatrixLieGroup, UnitVector3, SE3},
+};
+
+/// Unary factor for the distance of a point to a plane.
+///
+/// Used for lidar odometry, this residual connects a pose ${}_w T_b$ (body
+/// to world) with a point ${}_b p$ measured in the body frame, that is known
+/// to lie on a plane in the world. With the plane given by its unit normal
+/// $n$ and offset $d$, ie the points $x$ with $n^\top x = d$, it computes the
+/// signed distance
+/// $$
+/// r = n^\top ({}_w T_b \; {}_b p) - d
+/// $$
+///
+/// The normal is stored as a [UnitVector3], so it stays unit-norm and the
+/// residual is a true distance.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PointToPlaneResidual {
+    point: Vector3,
+    normal: UnitVector3,
+    offset: dtype,
+}
+
+impl PointToPlaneResidual {
+    pub fn new(point: Vector3, normal: UnitVector3, offset: dtype) -> Self {
+        Self {
+            point,
+            normal,
+            offset,
+        }
+    }
+}
+
+#[factrs::mark]
+impl Residual1 for PointToPlaneResidual {
+    type Differ = ForwardProp<Const<6>>;
+    type V1 = SE3;
+    type DimIn = Const<6>;
+    type DimOut = Const<1>;
+
+    fn residual1<T: Numeric>(&self, v: SE3<T>) -> VectorX<T> {
+        let p = v.apply(self.point.cast::<T>().as_view());
+        let n = self.normal.xyz().cast::<T>();
+        vectorx![n.dot(&p) - T::from(self.offset)]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use matrixcompare::assert_matrix_eq;
+
+    use super::*;
+    use crate::{
+        assign_symbols,
+        containers::{Graph, Values},
+        fac,
+        linalg::{Diff, NumericalDiff},
+        optimizers::{LevenMarquardt, Optimizer},
+        variables::Variable,
+    };
+
+    #[cfg(not(feature = "f32"))]
+    const PWR: i32 = 6;
+    #[cfg(not(feature = "f32"))]
+    const TOL: f64 = 1e-6;
+
+    #[cfg(feature = "f32")]
+    const PWR: i32 = 3;
+    #[cfg(feature = "f32")]
+    const TOL: f32 = 1e-3;
+
+    assign_symbols!(X: SE3);
+
+    #[test]
+    fn jacobian() {
+        let normal = UnitVector3::from_xyz(1.0, -2.0, 0.5);
+        let res = PointToPlaneResidual::new(Vector3::new(1.0, 2.0, 3.0), normal, 0.7);
+        let x = SE3::exp(vectorx![0.1, -0.2, 0.3, 1.0, 0.5, -0.5].as_view());
+
+        let mut values = Values::new();
+        values.insert(X(0), x.clone());
+        let got = res.residual1_jacobian(&values, &[X(0).into()]).diff;
+
+        let f = |v: SE3| res.residual1(v);
+        let expected = NumericalDiff::<PWR>::jacobian_1(f, &x).diff;
+
+        assert_matrix_eq!(got, expected, comp = abs, tol = TOL);
+    }
+
+    #[test]
+    fn recovers_pose() {
+        let pose = SE3::exp(vectorx![0.1, -0.2, 0.15, 0.3, -0.2, 0.5].as_view());
+        let planes = [
+            (UnitVector3::from_xyz(1.0, 0.0, 0.0), 2.0),
+            (UnitVector3::from_xyz(0.0, 1.0, 0.0), -1.0),
+            (UnitVector3::from_xyz(0.0, 0.0, 1.0), 3.0),
+            (UnitVector3::from_xyz(1.0, 1.0, 1.0), 1.0),
+        ];
+
+        // Points spread over each plane, measured in the body frame
+        let mut graph = Graph::new();
+        for (normal, offset) in planes {
+            let n = *normal.xyz();
+            let u = n.cross(&Vector3::new(0.3, 0.5, 0.7)).normalize();
+            let w = n.cross(&u);
+            for (a, b) in [(0.0, 0.0), (2.0, 0.0), (0.0, 2.0), (-1.5, -1.0)] {
+                let world = n * offset + u * a + w * b;
+                let body = pose.inverse().apply(world.as_view());
+                let res = PointToPlaneResidual::new(body, normal.clone(), offset);
+                graph.add_factor(fac![res, X(0), 0.01 as std]);
+            }
+        }
+
+        let mut values = Values::new();
+        values.insert(X(0), SE3::identity());
+        let mut opt: LevenMarquardt = LevenMarquardt::new(graph);
+        let result = opt.optimize(values).expect("Optimization failed");
+        let x: &SE3 = result.get(X(0)).expect("Missing X(0)");
+
+        assert_matrix_eq!(x.ominus(&pose), VectorX::zeros(6), comp = abs, tol = 1e-6);
+    }
+}
@@ -0,0 +1,119 @@
+use crate::{
+    linalg::{Const, ForwardProp, Numeric, Vector3, VectorX},
+    residuals::Residual1,
+    variables::{MatrixLieGroup, SE3},
+};
+
+/// Unary factor for the distance between a measured and a known point.
+///
+/// The building block of ICP and point cloud registration, this residual
+/// connects a pose ${}_w T_b$ (body to world) with a point ${}_b p$ measured
+/// in the body frame, that corresponds to the known point ${}_w q$ in the
+/// world. Specifically it computes
+/// $$
+/// r = {}_w T_b \; {}_b p - {}_w q
+/// $$
+///
+/// Correspondences in ICP are often wrong, so this is usually paired with a
+/// [robust](crate::robust) kernel to reject outliers.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PointToPointResidual {
+    point: Vector3,
+    target: Vector3,
+}
+
+impl PointToPointResidual {
+    pub fn new(point: Vector3, target: Vector3) -> Self {
+        Self { point, target }
+    }
+}
+
+#[factrs::mark]
+impl Residual1 for PointToPointResidual {
+    type Differ = ForwardProp<Const<6>>;
+    type V1 = SE3;
+    type DimIn = Const<6>;
+    type DimOut = Const<3>;
+
+    fn residual1<T: Numeric>(&self, v: SE3<T>) -> VectorX<T> {
+        let r = v.apply(self.point.cast::<T>().as_view()) - self.target.cast::<T>();
+        VectorX::from_column_slice(r.as_slice())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use matrixcompare::assert_matrix_eq;
+
+    use super::*;
+    use crate::{
+        assign_symbols,
+        containers::{Graph, Values},
+        fac,
+        linalg::{vectorx, Diff, NumericalDiff},
+        optimizers::{LevenMarquardt, Optimizer},
+        robust::Huber,
+        variables::Variable,
+    };
+
+    #[cfg(not(feature = "f32"))]
+    const PWR: i32 = 6;
+    #[cfg(not(feature = "f32"))]
+    const TOL: f64 = 1e-6;
+
+    #[cfg(feature = "f32")]
+    const PWR: i32 = 3;
+    #[cfg(feature = "f32")]
+    const TOL: f32 = 1e-3;
+
+    assign_symbols!(X: SE3);
+
+    #[test]
+    fn jacobian() {
+        let res =
+            PointToPointResidual::new(Vector3::new(1.0, 2.0, 3.0), Vector3::new(0.5, 0.0, -1.0));
+        let x = SE3::exp(vectorx![0.1, -0.2, 0.3, 1.0, 0.5, -0.5].as_view());
+
+        let mut values = Values::new();
+        values.insert(X(0), x.clone());
+        let got = res.residual1_jacobian(&values, &[X(0).into()]).diff;
+
+        let f = |v: SE3| res.residual1(v);
+        let expected = NumericalDiff::<PWR>::jacobian_1(f, &x).diff;
+
+        assert_matrix_eq!(got, expected, comp = abs, tol = TOL);
+    }
+
+    #[test]
+    fn registration() {
+        let pose = SE3::exp(vectorx![0.2, -0.1, 0.3, 1.0, -0.5, 0.25].as_view());
+        let cloud = [
+            Vector3::new(1.0, 0.0, 0.0),
+            Vector3::new(0.0, 2.0, 0.0),
+            Vector3::new(0.0, 0.0, -1.5),
+            Vector3::new(1.0, 1.0, 1.0),
+            Vector3::new(-2.0, 0.5, 1.0),
+            Vector3::new(0.5, -1.0, 2.0),
+        ];
+
+        // Second cloud is the first moved into the body frame
+        let mut graph = Graph::new();
+        for target in cloud.iter() {
+            let point = pose.inverse().apply(target.as_view());
+            let res = PointToPointResidual::new(point, *target);
+            graph.add_factor(fac![res, X(0), 0.01 as std, Huber::default()]);
+        }
+        // A bad correspondence that the kernel should downweight
+        let res = PointToPointResidual::new(Vector3::new(5.0, 5.0, 5.0), cloud[0]);
+        graph.add_factor(fac![res, X(0), 0.01 as std, Huber::default()]);
+
+        let mut values = Values::new();
+        values.insert(X(0), SE3::identity());
+        let mut opt: LevenMarquardt = LevenMarquardt::new(graph);
+        let result = opt.optimize(values).expect("Optimization failed");
+        let x: &SE3 = result.get(X(0)).expect("Missing X(0)");
+
+        assert_matrix_eq!(x.ominus(&pose), VectorX::zeros(6), comp = abs, tol = 1e-2);
+    }
+}
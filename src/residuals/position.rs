@@ -0,0 +1,127 @@
+use crate::{
+    linalg::{Const, ForwardProp, Numeric, Vector3, VectorX},
+    residuals::Residual1,
+    variables::SE3,
+};
+
+/// Unary factor for a prior on the position of a pose.
+///
+/// Constrains only the translation of an [SE3] pose, leaving the rotation
+/// free, such as from an absolute GPS fix. Specifically it computes
+/// $$
+/// z - p
+/// $$
+/// where $z$ is the measured position in the world frame and $p$ the
+/// translation of the pose being estimated.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PositionPriorResidual {
+    position: Vector3,
+}
+
+impl PositionPriorResidual {
+    pub fn new(position: Vector3) -> Self {
+        Self { position }
+    }
+}
+
+#[factrs::mark]
+impl Residual1 for PositionPriorResidual {
+    type Differ = ForwardProp<Const<6>>;
+    type V1 = SE3;
+    type DimIn = Const<6>;
+    type DimOut = Const<3>;
+
+    fn residual1<T: Numeric>(&self, v: SE3<T>) -> VectorX<T> {
+        let r = self.position.cast::<T>() - v.xyz();
+        VectorX::from_column_slice(r.as_slice())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use matrixcompare::assert_matrix_eq;
+
+    use super::*;
+    use crate::{
+        assign_symbols,
+        containers::{Graph, Values},
+        fac,
+        linalg::{vectorx, Diff, NumericalDiff},
+        optimizers::{GaussNewton, Optimizer},
+        robust::Huber,
+        variables::{Variable, SO3},
+    };
+
+    #[cfg(not(feature = "f32"))]
+    const PWR: i32 = 6;
+    #[cfg(not(feature = "f32"))]
+    const TOL: f64 = 1e-6;
+
+    #[cfg(feature = "f32")]
+    const PWR: i32 = 3;
+    #[cfg(feature = "f32")]
+    const TOL: f32 = 1e-3;
+
+    assign_symbols!(X: SE3);
+
+    // Prior on only the rotation of a pose
+    #[derive(Clone, Debug)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    struct RotationPrior {
+        rot: SO3,
+    }
+
+    #[factrs::mark]
+    impl Residual1 for RotationPrior {
+        type Differ = ForwardProp<Const<6>>;
+        type V1 = SE3;
+        type DimIn = Const<6>;
+        type DimOut = Const<3>;
+
+        fn residual1<T: Numeric>(&self, v: SE3<T>) -> VectorX<T> {
+            self.rot.cast::<T>().ominus(v.rot())
+        }
+    }
+
+    #[test]
+    fn jacobian() {
+        let res = PositionPriorResidual::new(Vector3::new(1.0, 2.0, 3.0));
+        let x = SE3::exp(vectorx![0.1, -0.2, 0.3, 1.0, 0.5, -0.5].as_view());
+
+        let mut values = Values::new();
+        values.insert(X(0), x.clone());
+        let got = res.residual1_jacobian(&values, &[X(0).into()]).diff;
+
+        let f = |v: SE3| res.residual1(v);
+        let expected = NumericalDiff::<PWR>::jacobian_1(f, &x).diff;
+
+        assert_matrix_eq!(got, expected, comp = abs, tol = TOL);
+    }
+
+    #[test]
+    fn determines_pose() {
+        let position = Vector3::new(1.0, 2.0, 3.0);
+        let rot = SO3::exp(vectorx![0.3, -0.1, 0.2].as_view());
+
+        let mut graph = Graph::new();
+        let res = PositionPriorResidual::new(position);
+        graph.add_factor(fac![res, X(0), 0.1 as std, Huber::default()]);
+        graph.add_factor(fac![RotationPrior { rot: rot.clone() }, X(0), 0.01 as std]);
+
+        let mut values = Values::new();
+        values.insert(X(0), SE3::identity());
+
+        let mut opt: GaussNewton = GaussNewton::new(graph);
+        let result = opt.optimize(values).expect("Optimization failed");
+        let x: &SE3 = result.get(X(0)).expect("Missing X(0)");
+
+        assert_matrix_eq!(x.xyz(), position, comp = abs, tol = 1e-6);
+        assert_matrix_eq!(
+            x.rot().ominus(&rot),
+            VectorX::zeros(3),
+            comp = abs,
+            tol = 1e-6
+        );
+    }
+}
@@ -1,8 +1,12 @@
 use crate::{
+    dtype,
     linalg::{
-        AllocatorBuffer, DefaultAllocator, DualAllocator, DualVector, ForwardProp, Numeric, VectorX,
+        AllocatorBuffer, Const, DefaultAllocator, DualAllocator, DualVector, ForwardProp, Matrix,
+        Numeric, VectorX,
     },
+    noise::GaussianNoise,
     residuals::Residual1,
+    utils::frechet_mean,
     variables::{Variable, VariableDtype},
 };
 
@@ -23,6 +27,40 @@ impl<P: VariableDtype> PriorResidual<P> {
     pub fn new(prior: P) -> Self {
         Self { prior }
     }
+
+    /// Get the prior value
+    pub fn prior(&self) -> &P {
+        &self.prior
+    }
+
+    /// Fit a prior to a set of repeated measurements of a variable.
+    ///
+    /// The prior is the [Fréchet mean](crate::utils::frechet_mean) of the
+    /// samples, and the noise model is built from the unbiased sample
+    /// covariance of the samples in the tangent space of that mean. Requires
+    /// at least two samples.
+    pub fn from_samples<const N: usize>(samples: &[P]) -> (Self, GaussianNoise<N>)
+    where
+        P: Variable<Dim = Const<N>>,
+    {
+        assert!(
+            samples.len() > 1,
+            "At least two samples are needed to estimate a covariance"
+        );
+
+        let mean = frechet_mean(samples);
+        let cov = samples
+            .iter()
+            .map(|s| {
+                let d = Matrix::<N>::from_iterator(s.ominus(&mean).iter().cloned());
+                d * d.transpose()
+            })
+            .fold(Matrix::<N, N>::zeros(), |acc, c| acc + c)
+            / (samples.len() - 1) as dtype;
+
+        let noise = GaussianNoise::from_matrix_cov(cov.as_view());
+        (Self::new(mean), noise)
+    }
 }
 
 #[factrs::mark]
@@ -52,6 +90,7 @@ mod test {
     use crate::{
         containers::Values,
         linalg::{vectorx, DefaultAllocator, Diff, DualAllocator, NumericalDiff},
+        noise::NoiseModel,
         symbols::X,
         variables::{VectorVar3, SE3, SO3},
     };
@@ -114,4 +153,34 @@ mod test {
         let prior = SE3::exp(vectorx![0.1, 0.2, 0.3, 1.0, 2.0, 3.0].as_view());
         test_prior_jacobian(prior);
     }
+
+    #[test]
+    fn from_samples() {
+        // Samples clustered symmetrically about the center along each axis
+        let center = SO3::exp(vectorx![0.3, -0.2, 0.1].as_view());
+        let samples: Vec<SO3> = (0..3)
+            .flat_map(|i| {
+                let mut delta = VectorX::zeros(3);
+                delta[i] = 0.1;
+                [
+                    center.oplus(delta.as_view()),
+                    center.oplus((-delta).as_view()),
+                ]
+            })
+            .collect();
+
+        let (prior, noise) = PriorResidual::from_samples(&samples);
+        assert_matrix_eq!(
+            prior.prior.ominus(&center),
+            VectorX::zeros(3),
+            comp = abs,
+            tol = TOL
+        );
+
+        // Each axis has two samples at 0.1, so variance 2 * 0.1^2 / (6 - 1)
+        let var = 2.0 * 0.01 / 5.0;
+        let whitened = noise.whiten_vec(vectorx![1.0, 1.0, 1.0]);
+        let expected = VectorX::from_element(3, 1.0 / var.sqrt());
+        assert_matrix_eq!(whitened, expected, comp = abs, tol = 1e-3);
+    }
 }
@@ -0,0 +1,151 @@
+use crate::{
+    dtype,
+    linalg::{Const, ForwardProp, Numeric, VectorX},
+    residuals::Residual2,
+    variables::{MatrixLieGroup, Variable, VectorVar2, VectorVar3, SE3},
+};
+
+/// Reprojection error of a landmark into a pinhole camera.
+///
+/// Used for bundle adjustment, this residual connects a camera pose
+/// ${}_w T_c$ (camera to world) and a landmark ${}_w p$ with a pixel
+/// measurement $z$. Specifically it computes
+///
+/// $$
+/// r = \pi({}_w T_c^{-1} {}_w p) - z
+/// $$
+///
+/// where $\pi$ is the pinhole projection using the intrinsics $f_x, f_y, c_x,
+/// c_y$, with the camera looking down its $z$-axis.
+///
+/// If the landmark is at or behind the camera, the projection is meaningless.
+/// Rather than returning NaN, both components are set to a penalty on the
+/// depth $z$ of the landmark in the camera frame,
+/// $$
+/// r = k (1 - z)
+/// $$
+/// with $k$ = [BEHIND_CAMERA](Self::BEHIND_CAMERA). This is at least $k$, and
+/// grows the further behind the camera the landmark is, so its gradient pulls
+/// the landmark back in front of the camera.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct ProjectionResidual {
+    meas: VectorVar2,
+    fx: dtype,
+    fy: dtype,
+    cx: dtype,
+    cy: dtype,
+}
+
+impl ProjectionResidual {
+    /// Scale of the depth penalty for landmarks with nonpositive depth
+    pub const BEHIND_CAMERA: dtype = 1e4;
+
+    pub fn new(meas: VectorVar2, fx: dtype, fy: dtype, cx: dtype, cy: dtype) -> Self {
+        Self {
+            meas,
+            fx,
+            fy,
+            cx,
+            cy,
+        }
+    }
+
+    /// Project a point in the camera frame to pixel coordinates
+    ///
+    /// Returns None if the point is at or behind the camera.
+    pub fn project<T: Numeric>(&self, p: VectorVar3<T>) -> Option<VectorVar2<T>> {
+        let (x, y, z) = (p[0], p[1], p[2]);
+        if z <= T::from(0.0) {
+            return None;
+        }
+        let u = T::from(self.fx) * x / z + T::from(self.cx);
+        let v = T::from(self.fy) * y / z + T::from(self.cy);
+        Some(VectorVar2::new(u, v))
+    }
+}
+
+#[factrs::mark]
+impl Residual2 for ProjectionResidual {
+    type Differ = ForwardProp<Const<9>>;
+    type V1 = SE3;
+    type V2 = VectorVar3;
+    type DimOut = Const<2>;
+    type DimIn = Const<9>;
+
+    fn residual2<T: Numeric>(&self, pose: SE3<T>, landmark: VectorVar3<T>) -> VectorX<T> {
+        let p_c = VectorVar3::from(pose.inverse().apply(landmark.0.as_view()));
+        let depth = p_c[2];
+        match self.project(p_c) {
+            Some(pixel) => pixel.ominus(&self.meas.cast::<T>()),
+            None => {
+                let penalty = T::from(Self::BEHIND_CAMERA) * (T::from(1.0) - depth);
+                VectorX::from_element(2, penalty)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use matrixcompare::assert_matrix_eq;
+
+    use super::*;
+    use crate::{
+        assign_symbols,
+        containers::Values,
+        linalg::{vectorx, Diff, NumericalDiff},
+    };
+
+    #[cfg(not(feature = "f32"))]
+    const PWR: i32 = 6;
+    #[cfg(not(feature = "f32"))]
+    const TOL: f64 = 1e-6;
+
+    #[cfg(feature = "f32")]
+    const PWR: i32 = 3;
+    #[cfg(feature = "f32")]
+    const TOL: f32 = 1e-3;
+
+    assign_symbols!(X: SE3; L: VectorVar3);
+
+    #[test]
+    fn jacobian() {
+        // Small intrinsics to keep the numerical differentiation accurate
+        let res = ProjectionResidual::new(VectorVar2::new(0.1, -0.2), 2.0, 3.0, 0.1, 0.2);
+        let pose = SE3::exp(vectorx![0.1, -0.2, 0.05, 0.3, 0.1, -0.5].as_view());
+        let landmark = VectorVar3::new(0.5, 0.2, 4.0);
+
+        let mut values = Values::new();
+        values.insert(X(0), pose.clone());
+        values.insert(L(0), landmark.clone());
+        let got = res
+            .residual2_jacobian(&values, &[X(0).into(), L(0).into()])
+            .diff;
+
+        let f = |p: SE3, l: VectorVar3| res.residual2(p, l);
+        let expected = NumericalDiff::<PWR>::jacobian_2(f, &pose, &landmark).diff;
+
+        assert_matrix_eq!(got, expected, comp = abs, tol = TOL);
+    }
+
+    #[test]
+    fn behind_camera() {
+        let meas = VectorVar2::new(330.0, 250.0);
+        let res = ProjectionResidual::new(meas, 500.0, 500.0, 320.0, 240.0);
+        let pose = SE3::identity();
+        let landmark = VectorVar3::new(0.5, 0.2, -4.0);
+
+        let mut values = Values::new();
+        values.insert(X(0), pose);
+        values.insert(L(0), landmark);
+        let got = res.residual2_jacobian(&values, &[X(0).into(), L(0).into()]);
+        assert!(got.value.iter().all(|r| r.is_finite()));
+        let k = ProjectionResidual::BEHIND_CAMERA;
+        assert_matrix_eq!(got.value, VectorX::from_element(2, 5.0 * k), comp = float);
+
+        // Moving the landmark forward reduces the penalty
+        let expected = VectorX::from_element(2, -k);
+        assert_matrix_eq!(got.diff.column(8), expected, comp = float);
+    }
+}
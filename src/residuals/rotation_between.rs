@@ -0,0 +1,130 @@
+use crate::{
+    linalg::{Const, ForwardProp, Numeric, VectorX},
+    residuals::Residual2,
+    variables::{Variable, SE3, SO3},
+};
+
+/// Binary factor between the rotations of two poses.
+///
+/// Constrains only the relative rotation of two [SE3] poses, leaving their
+/// translations free, such as from integrating a gyroscope between keyframes.
+/// Specifically it computes
+/// $$
+/// r = (R_1 z) \ominus R_2
+/// $$
+/// where $R_1, R_2$ are the rotations of the poses and $z$ the measured
+/// relative rotation. This is the same as a
+/// [BetweenResidual](crate::residuals::BetweenResidual) on [SO3], but
+/// connected to the [SE3] keys directly.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RotationBetweenResidual {
+    delta: SO3,
+}
+
+impl RotationBetweenResidual {
+    pub fn new(delta: SO3) -> Self {
+        Self { delta }
+    }
+
+    /// Get the measured relative rotation
+    pub fn delta(&self) -> &SO3 {
+        &self.delta
+    }
+}
+
+#[factrs::mark]
+impl Residual2 for RotationBetweenResidual {
+    type Differ = ForwardProp<Const<12>>;
+    type V1 = SE3;
+    type V2 = SE3;
+    type DimIn = Const<12>;
+    type DimOut = Const<3>;
+
+    fn residual2<T: Numeric>(&self, v1: SE3<T>, v2: SE3<T>) -> VectorX<T> {
+        let delta = self.delta.cast::<T>();
+        v1.rot().compose(&delta).ominus(v2.rot())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use matrixcompare::assert_matrix_eq;
+
+    use super::*;
+    use crate::{
+        assign_symbols,
+        containers::{Graph, Values},
+        fac,
+        linalg::{vectorx, MatrixX, Vector3},
+        optimizers::{GaussNewton, Optimizer},
+        residuals::{BetweenResidual, PositionPriorResidual, PriorResidual},
+    };
+
+    assign_symbols!(X: SE3; R: SO3);
+
+    #[test]
+    fn matches_so3_between() {
+        let delta = SO3::exp(vectorx![0.3, -0.1, 0.2].as_view());
+        let x1 = SE3::exp(vectorx![0.1, -0.2, 0.3, 1.0, 0.5, -0.5].as_view());
+        let x2 = SE3::exp(vectorx![-0.2, 0.1, 0.4, 2.0, -1.0, 0.5].as_view());
+
+        let mut values = Values::new();
+        values.insert(X(0), x1.clone());
+        values.insert(X(1), x2.clone());
+        values.insert(R(0), x1.rot().clone());
+        values.insert(R(1), x2.rot().clone());
+
+        let res = RotationBetweenResidual::new(delta.clone());
+        let got = res.residual2_jacobian(&values, &[X(0).into(), X(1).into()]);
+        let so3 = BetweenResidual::new(delta);
+        let expected = so3.residual2_jacobian(&values, &[R(0).into(), R(1).into()]);
+        assert_matrix_eq!(got.value, expected.value, comp = abs, tol = 1e-6);
+
+        // Translations don't affect the residual
+        assert_matrix_eq!(
+            got.diff.columns(3, 3),
+            MatrixX::zeros(3, 3),
+            comp = abs,
+            tol = 1e-6
+        );
+        assert_matrix_eq!(
+            got.diff.columns(9, 3),
+            MatrixX::zeros(3, 3),
+            comp = abs,
+            tol = 1e-6
+        );
+    }
+
+    #[test]
+    fn translation_free() {
+        let delta = SO3::exp(vectorx![0.0, 0.0, 0.5].as_view());
+        let t = Vector3::new(1.0, -2.0, 0.5);
+
+        // The second pose only has a position prior, so its rotation comes
+        // solely from the rotation between
+        let mut graph = Graph::new();
+        graph.add_factor(fac![PriorResidual::new(SE3::identity()), X(0)]);
+        graph.add_factor(fac![
+            RotationBetweenResidual::new(delta.clone()),
+            (X(0), X(1))
+        ]);
+        graph.add_factor(fac![PositionPriorResidual::new(t), X(1)]);
+
+        let mut values = Values::new();
+        values.insert(X(0), SE3::identity());
+        values.insert(X(1), SE3::identity());
+
+        let mut opt: GaussNewton = GaussNewton::new(graph);
+        let result = opt.optimize(values).expect("Optimization failed");
+        let x1: &SE3 = result.get(X(1)).expect("Missing X(1)");
+
+        assert_matrix_eq!(x1.xyz(), t, comp = abs, tol = 1e-6);
+        assert_matrix_eq!(
+            x1.rot().ominus(&delta),
+            VectorX::zeros(3),
+            comp = abs,
+            tol = 1e-6
+        );
+    }
+}
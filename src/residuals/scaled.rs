@@ -0,0 +1,135 @@
+use crate::{
+    containers::{Key, Values},
+    dtype,
+    linalg::{DiffResult, MatrixX, VectorX},
+    residuals::Residual,
+};
+
+/// Wrapper scaling the output of any residual by a fixed gain.
+///
+/// Each component $r_i$ of the wrapped residual is multiplied by its gain
+/// $k_i$, ie $\tilde{r} = k \odot r$, with the rows of the Jacobian scaled to
+/// match. Useful when a known gain, such as a sensor calibration, is part of
+/// the measurement model itself.
+///
+/// Unlike scaling the noise model, this changes the residual, so also changes
+/// its value before any robust kernel or whitening is applied.
+///
+/// If serde is enabled, each wrapped residual needs tagging, see
+/// [serialization](crate::residuals#serialization).
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ScaledResidual<R> {
+    residual: R,
+    gain: VectorX,
+}
+
+impl<R: Residual> ScaledResidual<R> {
+    /// Scale each component of the residual by its own gain
+    pub fn new(residual: R, gain: VectorX) -> Self {
+        assert!(
+            gain.len() == residual.dim_out(),
+            "Gain must match the output dimension of the residual"
+        );
+        Self { residual, gain }
+    }
+
+    /// Scale all components of the residual by the same gain
+    pub fn new_scalar(residual: R, gain: dtype) -> Self {
+        let gain = VectorX::from_element(residual.dim_out(), gain);
+        Self { residual, gain }
+    }
+
+    pub fn gain(&self) -> &VectorX {
+        &self.gain
+    }
+}
+
+#[cfg_attr(feature = "serde", typetag::serde)]
+impl<
+        #[cfg(feature = "serde")] R: Residual + Clone + 'static + typetag::Tagged,
+        #[cfg(not(feature = "serde"))] R: Residual + Clone + 'static,
+    > Residual for ScaledResidual<R>
+{
+    fn dim_in(&self) -> usize {
+        self.residual.dim_in()
+    }
+
+    fn dim_out(&self) -> usize {
+        self.residual.dim_out()
+    }
+
+    fn residual(&self, values: &Values, keys: &[Key]) -> VectorX {
+        self.residual
+            .residual(values, keys)
+            .component_mul(&self.gain)
+    }
+
+    fn residual_jacobian(&self, values: &Values, keys: &[Key]) -> DiffResult<VectorX, MatrixX> {
+        let DiffResult {
+            value,
+            diff: mut jac,
+        } = self.residual.residual_jacobian(values, keys);
+
+        for (mut row, k) in jac.row_iter_mut().zip(self.gain.iter()) {
+            row *= *k;
+        }
+
+        DiffResult {
+            value: value.component_mul(&self.gain),
+            diff: jac,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use matrixcompare::assert_matrix_eq;
+
+    use super::*;
+    use crate::{
+        assign_symbols,
+        residuals::PriorResidual,
+        variables::{Variable, VectorVar2},
+    };
+
+    assign_symbols!(X: VectorVar2);
+
+    #[test]
+    fn pre_scaled() {
+        let gain = VectorX::from_vec(vec![2.0, -0.5]);
+        let z = VectorVar2::new(1.0, 3.0);
+        let x = VectorVar2::new(0.2, -0.4);
+        let scaled = ScaledResidual::new(PriorResidual::new(z.clone()), gain.clone());
+
+        let mut values = Values::new();
+        values.insert(X(0), x.clone());
+        let got = scaled.residual_jacobian(&values, &[X(0).into()]);
+
+        // Same as scaling both the measurement and the variable beforehand
+        let z_scaled = VectorVar2::from(z.0.component_mul(&gain.fixed_rows::<2>(0)));
+        let x_scaled = VectorVar2::from(x.0.component_mul(&gain.fixed_rows::<2>(0)));
+        let mut values_scaled = Values::new();
+        values_scaled.insert(X(0), x_scaled);
+        let expected =
+            PriorResidual::new(z_scaled).residual_jacobian(&values_scaled, &[X(0).into()]);
+        assert_matrix_eq!(got.value, expected.value, comp = abs, tol = 1e-6);
+
+        // With the Jacobian picking up the gain through the chain rule
+        let expected = MatrixX::from_diagonal(&gain) * expected.diff;
+        assert_matrix_eq!(got.diff, expected, comp = abs, tol = 1e-6);
+    }
+
+    #[test]
+    fn scalar() {
+        let res = PriorResidual::new(VectorVar2::new(1.0, 3.0));
+        let scaled = ScaledResidual::new_scalar(res.clone(), 3.0);
+
+        let mut values = Values::new();
+        values.insert(X(0), VectorVar2::identity());
+        let keys = [X(0).into()];
+        let got = scaled.residual(&values, &keys);
+        let expected = res.residual(&values, &keys) * 3.0;
+        assert_matrix_eq!(got, expected, comp = abs, tol = 1e-6);
+    }
+}
@@ -0,0 +1,182 @@
+use crate::{
+    containers::{Factor, Key, TypedSymbol, Values},
+    dtype, fac,
+    linalg::{DiffResult, MatrixX, VectorX},
+    residuals::{PriorResidual, Residual},
+    variables::VectorVar1,
+};
+
+/// Wrapper making any residual a switchable constraint.
+///
+/// Switchable constraints (Sünderhauf & Protzel, 2012) add a switch variable
+/// $s$, a [VectorVar1], to a factor that may be an outlier, such as a loop
+/// closure. The wrapped residual is scaled by the switch,
+/// $$
+/// \tilde{r} = s \cdot r
+/// $$
+/// so the optimizer can turn the constraint off by driving $s$ toward 0. To
+/// keep it from doing so for free, the switch needs a prior pulling it toward
+/// 1, made with [switch_prior](SwitchableResidual::switch_prior). The sigma
+/// of that prior sets how expensive it is to reject the constraint.
+///
+/// The switch is optimized jointly with the rest of the graph, so its key is
+/// passed last after the keys of the wrapped residual, and its value must be
+/// in the [Values]. The switch isn't clamped, but in practice ends up close to
+/// 0 for outliers and 1 for inliers.
+///
+/// If serde is enabled, each wrapped residual needs tagging, see
+/// [serialization](crate::residuals#serialization).
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SwitchableResidual<R> {
+    residual: R,
+}
+
+impl<R> SwitchableResidual<R> {
+    pub fn new(residual: R) -> Self {
+        Self { residual }
+    }
+
+    /// Get the wrapped residual
+    pub fn inner(&self) -> &R {
+        &self.residual
+    }
+
+    /// Prior pulling the switch toward 1, ie the constraint being on
+    pub fn switch_prior<K>(&self, switch: K, sigma: dtype) -> Factor
+    where
+        K: TypedSymbol<VectorVar1>,
+    {
+        fac![
+            PriorResidual::new(VectorVar1::new(1.0)),
+            switch,
+            sigma as std
+        ]
+    }
+}
+
+// Value of the switch, always the last key
+fn switch(values: &Values, keys: &[Key]) -> dtype {
+    let key = keys
+        .last()
+        .expect("Switchable residual is missing its switch");
+    let s: &VectorVar1 = values
+        .get_unchecked(*key)
+        .expect("Switch must be a VectorVar1 in the values");
+    s.0.x
+}
+
+#[cfg_attr(feature = "serde", typetag::serde)]
+impl<
+        #[cfg(feature = "serde")] R: Residual + Clone + 'static + typetag::Tagged,
+        #[cfg(not(feature = "serde"))] R: Residual + Clone + 'static,
+    > Residual for SwitchableResidual<R>
+{
+    fn dim_in(&self) -> usize {
+        self.residual.dim_in() + 1
+    }
+
+    fn dim_out(&self) -> usize {
+        self.residual.dim_out()
+    }
+
+    fn residual(&self, values: &Values, keys: &[Key]) -> VectorX {
+        let (_, base) = keys.split_last().expect("Missing switch key");
+        self.residual.residual(values, base) * switch(values, keys)
+    }
+
+    fn residual_jacobian(&self, values: &Values, keys: &[Key]) -> DiffResult<VectorX, MatrixX> {
+        let (_, base) = keys.split_last().expect("Missing switch key");
+        let DiffResult { value, diff } = self.residual.residual_jacobian(values, base);
+        let s = switch(values, keys);
+
+        // Product rule, with the switch column being the unscaled residual
+        let n = diff.ncols();
+        let mut jac = MatrixX::zeros(diff.nrows(), n + 1);
+        jac.columns_mut(0, n).copy_from(&(diff * s));
+        jac.column_mut(n).copy_from(&value);
+
+        DiffResult {
+            value: value * s,
+            diff: jac,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use matrixcompare::assert_matrix_eq;
+
+    use super::*;
+    use crate::{
+        assign_symbols,
+        linalg::NumericalDiff,
+        noise::GaussianNoise,
+        optimizers::{test::odometry_chain, LevenMarquardt, Optimizer},
+        residuals::BetweenResidual,
+        robust::L2,
+        variables::{Variable, VectorVar2},
+    };
+
+    #[cfg(not(feature = "f32"))]
+    const PWR: i32 = 6;
+    #[cfg(not(feature = "f32"))]
+    const TOL: f64 = 1e-6;
+
+    #[cfg(feature = "f32")]
+    const PWR: i32 = 3;
+    #[cfg(feature = "f32")]
+    const TOL: f32 = 1e-3;
+
+    assign_symbols!(X: VectorVar2; S: VectorVar1);
+
+    #[test]
+    fn jacobian() {
+        let res = SwitchableResidual::new(BetweenResidual::new(VectorVar2::new(1.0, -0.5)));
+        let x0 = VectorVar2::new(0.2, 0.3);
+        let x1 = VectorVar2::new(1.5, -0.1);
+        let s = VectorVar1::new(0.7);
+
+        let mut values = Values::new();
+        values.insert(X(0), x0.clone());
+        values.insert(X(1), x1.clone());
+        values.insert(S(0), s.clone());
+        let keys = [X(0).into(), X(1).into(), S(0).into()];
+        let got = res.residual_jacobian(&values, &keys);
+
+        let f = |x0: VectorVar2, x1: VectorVar2, s: VectorVar1| {
+            let mut values = Values::new();
+            values.insert(X(0), x0);
+            values.insert(X(1), x1);
+            values.insert(S(0), s);
+            res.residual(&values, &keys)
+        };
+        let expected = NumericalDiff::<PWR>::jacobian_3(f, &x0, &x1, &s);
+
+        assert_matrix_eq!(got.value, expected.value, comp = abs, tol = TOL);
+        assert_matrix_eq!(got.diff, expected.diff, comp = abs, tol = TOL);
+    }
+
+    #[test]
+    fn corrupted_loop_closure() {
+        // A loop closure claiming the end is at the start
+        let (mut graph, mut values) = odometry_chain(4);
+        let res = SwitchableResidual::new(BetweenResidual::new(VectorVar2::identity()));
+        graph.add_factor(res.switch_prior(S(0), 1.0));
+        graph.add_factor(Factor::from_boxed(
+            Box::new(res),
+            vec![X(0).into(), X(4).into(), S(0).into()],
+            Box::new(GaussianNoise::<2>::from_scalar_sigma(0.1)),
+            Box::new(L2),
+        ));
+        values.insert(S(0), VectorVar1::new(1.0));
+
+        let mut opt: LevenMarquardt = LevenMarquardt::new(graph);
+        let result = opt.optimize(values).expect("Optimization failed");
+        let s: &VectorVar1 = result.get(S(0)).expect("Missing S(0)");
+        let x: &VectorVar2 = result.get(X(4)).expect("Missing X(4)");
+
+        assert!(s.0.x.abs() < 0.1);
+        assert!((x.0.x - 4.0).abs() < 1e-2);
+    }
+}
@@ -1,5 +1,6 @@
 use std::fmt::Debug;
 
+use downcast_rs::{impl_downcast, Downcast};
 use dyn_clone::DynClone;
 
 use crate::{
@@ -17,7 +18,7 @@ type Alias<V, T> = <V as Variable>::Alias<T>;
 /// implement one of the `ResidualN` traits, and then [mark](factrs::mark) it to
 /// implement this.
 #[cfg_attr(feature = "serde", typetag::serde(tag = "tag"))]
-pub trait Residual: Debug + DynClone {
+pub trait Residual: Debug + DynClone + Downcast {
     fn dim_in(&self) -> usize;
 
     fn dim_out(&self) -> usize;
@@ -28,6 +29,7 @@ pub trait Residual: Debug + DynClone {
 }
 
 dyn_clone::clone_trait_object!(Residual);
+impl_downcast!(Residual);
 
 // -------------- Use Macro to create residuals with set sizes -------------- //
 use paste::paste;
@@ -16,10 +16,20 @@
 //! | Geman-McClure| $\frac{c^2 x^2}{2} / (c^2 + x^2)$ | $c^2 / (c^2 + x^2)^2$ | Constant            |
 //! | Welsch       | $\frac{c^2}{2}\left(1 - \exp(-(x/c)^2)\right)$ | $\exp(-(x/c)^2)$ | Constant            |
 //! | Tukey $\begin{cases} \|x\| \leq c \\\\ \|x\| > c \end{cases}$ | $\begin{cases} \frac{c^2}{6}\left(1 - \left(1 - (x/c)^2\right)^3\right) \\\\ \frac{c^2}{6} \end{cases}$ | $\begin{cases} \left(1 - (x/c)^2\right)^2 \\\\ 0 \end{cases}$ | Constant            |
+//! | Barron       | $c^2 \frac{|\alpha-2|}{\alpha}\left(\left(\frac{(x/c)^2}{|\alpha-2|} + 1\right)^{\alpha/2} - 1\right)$ | $\left(\frac{(x/c)^2}{|\alpha-2|} + 1\right)^{\alpha/2 - 1}$ | Depends on $\alpha$ |
+//! | DCS $\begin{cases} x^2 \leq \Phi \\\\ x^2 > \Phi \end{cases}$ | $\begin{cases} x^2/2 \\\\ \frac{3\Phi}{2} - \frac{2\Phi^2}{\Phi + x^2} \end{cases}$ | $\begin{cases} 1 \\\\ \left(\frac{2\Phi}{\Phi + x^2}\right)^2 \end{cases}$ | Constant            |
+//! | TLS $\begin{cases} x^2 \leq c^2 \\\\ x^2 > c^2 \end{cases}$ | $\begin{cases} x^2/2 \\\\ c^2/2 \end{cases}$ | $\begin{cases} 1 \\\\ 0 \end{cases}$ | Constant            |
+//! | Composed     | $\rho_A$ inside a threshold, scaled and shifted $\rho_B$ past it | $w_A$ inside, scaled $w_B$ past it | Same as $\rho_B$ |
 //!
 //! Generally constant asymptotic behavior is the best at outlier rejection, but
-//! relies heavily on good initialization. Some work, such as Graduated
-//! Non-Convexity (GNC), has been shown to circumvent this requirement.
+//! relies heavily on good initialization. Graduated Non-Convexity (GNC) has
+//! been shown to circumvent this requirement, and is available via the
+//! [Gnc](crate::optimizers::Gnc) optimizer for kernels implementing
+//! [GncRobust].
+//!
+//! Kernels are applied per [factor](crate::containers::Factor), to the squared
+//! Mahalanobis norm of its whitened residual $\|r\|^2_\Sigma$, rather than
+//! element-wise. All elements of a residual thus share a single weight.
 
 use std::fmt::Debug;
 
@@ -33,6 +43,12 @@ use crate::dtype;
 /// use x^2 in some form, so rather than passing x, we pass x^2. If you'd like
 /// to implement your own kernel, we recommend using
 /// [test_robust](crate::test_robust) to ensure weight = loss'(d) / d
+///
+/// Both the loss and the weight are required. The weight is used directly for
+/// the iteratively reweighted least squares when a
+/// [factor](crate::containers::Factor) is linearized, so kernels should
+/// provide it in closed form, and no numerical differentiation is done by the
+/// optimizers.
 #[cfg_attr(feature = "serde", typetag::serde(tag = "tag"))]
 pub trait RobustCost: Debug + DynClone {
     /// Compute the loss \rho(x^2)
@@ -47,6 +63,33 @@ dyn_clone::clone_trait_object!(RobustCost);
 #[cfg(feature = "serde")]
 pub use register_robustcost as tag_robust;
 
+/// Robust cost function supporting Graduated Non-Convexity
+///
+/// GNC, as used by [Gnc](crate::optimizers::Gnc), replaces a kernel with a
+/// family of surrogates indexed by a control parameter $\mu$. It starts from
+/// a surrogate that is convex over every residual, and steps $\mu$ until the
+/// surrogate matches the kernel itself, re-optimizing at each step. See Yang et
+/// al., "Graduated Non-Convexity for Robust Spatial Perception", 2020.
+pub trait GncRobust: RobustCost + Clone + 'static {
+    /// Surrogate of the kernel for the control parameter $\mu$
+    fn surrogate(&self, mu: dtype) -> Self;
+
+    /// Initial $\mu$, making the surrogate convex for squared residuals up to
+    /// `max_d2`
+    fn mu_init(&self, max_d2: dtype) -> dtype;
+
+    /// Step $\mu$ toward the kernel by a factor of `step`
+    fn mu_update(&self, mu: dtype, step: dtype) -> dtype;
+
+    /// Whether the surrogate at $\mu$ has reached the kernel
+    fn mu_converged(&self, mu: dtype) -> bool;
+
+    /// Weight of the surrogate at $\mu$
+    fn surrogate_weight(&self, d2: dtype, mu: dtype) -> dtype {
+        self.surrogate(mu).weight(d2)
+    }
+}
+
 // We'll implement a custom debug on a bunch of these to remove pretty printing
 
 // ------------------------- L2 Norm ------------------------- //
@@ -252,6 +295,26 @@ impl Debug for GemanMcClure {
     }
 }
 
+// The surrogate is Geman-McClure with its threshold scaled by sqrt(mu), which
+// is convex for mu large and matches the kernel at mu = 1
+impl GncRobust for GemanMcClure {
+    fn surrogate(&self, mu: dtype) -> Self {
+        GemanMcClure { c2: self.c2 * mu }
+    }
+
+    fn mu_init(&self, max_d2: dtype) -> dtype {
+        (2.0 * max_d2 / self.c2).max(1.0)
+    }
+
+    fn mu_update(&self, mu: dtype, step: dtype) -> dtype {
+        (mu / step).max(1.0)
+    }
+
+    fn mu_converged(&self, mu: dtype) -> bool {
+        mu <= 1.0
+    }
+}
+
 // ------------------------- Welsch ------------------------- //
 #[derive(Clone)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
@@ -336,6 +399,325 @@ impl Debug for Tukey {
     }
 }
 
+// ------------------------- Barron ------------------------- //
+/// Barron's general and adaptive robust loss
+///
+/// From "A General and Adaptive Robust Loss Function" by Jonathan T. Barron.
+/// The shape parameter $\alpha$ smoothly interpolates between a number of
+/// other kernels, with a scale $c$,
+/// - $\alpha = 2$: [L2]
+/// - $\alpha = 1$: Charbonnier (pseudo-Huber)
+/// - $\alpha = 0$: [Cauchy] with scale $\sqrt{2} c$
+/// - $\alpha = -2$: [GemanMcClure] with scale $2 c$
+/// - $\alpha \to -\infty$: [Welsch] with scale $\sqrt{2} c$
+///
+/// Smaller values of $\alpha$ are more robust to outliers. The shape can be
+/// changed after construction with [set_alpha](Barron::set_alpha), for example
+/// to sweep it or anneal it during optimization.
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Barron {
+    alpha: dtype,
+    c2: dtype,
+}
+
+impl Barron {
+    pub fn new(alpha: dtype, c: dtype) -> Self {
+        Barron { alpha, c2: c * c }
+    }
+
+    /// Get the shape parameter
+    pub fn alpha(&self) -> dtype {
+        self.alpha
+    }
+
+    /// Set the shape parameter
+    pub fn set_alpha(&mut self, alpha: dtype) {
+        self.alpha = alpha;
+    }
+}
+
+impl Default for Barron {
+    fn default() -> Self {
+        Barron {
+            alpha: 1.0,
+            c2: 1.0,
+        }
+    }
+}
+
+#[factrs::mark]
+impl RobustCost for Barron {
+    fn loss(&self, d2: dtype) -> dtype {
+        let z = d2 / self.c2;
+        let b = (self.alpha - 2.0).abs();
+        let loss = if b < dtype::EPSILON {
+            z / 2.0
+        } else if self.alpha == 0.0 {
+            (z / 2.0).ln_1p()
+        } else if self.alpha == dtype::NEG_INFINITY {
+            -(-z / 2.0).exp_m1()
+        } else {
+            // exp_m1/ln_1p keep precision as alpha approaches 0
+            b / self.alpha * ((self.alpha / 2.0) * (z / b).ln_1p()).exp_m1()
+        };
+        self.c2 * loss
+    }
+
+    fn weight(&self, d2: dtype) -> dtype {
+        let z = d2 / self.c2;
+        let b = (self.alpha - 2.0).abs();
+        if b < dtype::EPSILON {
+            1.0
+        } else if self.alpha == dtype::NEG_INFINITY {
+            (-z / 2.0).exp()
+        } else {
+            ((self.alpha / 2.0 - 1.0) * (z / b).ln_1p()).exp()
+        }
+    }
+}
+
+impl Debug for Barron {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Barron {{ alpha: {}, c: {} }}",
+            self.alpha,
+            self.c2.sqrt()
+        )
+    }
+}
+
+// ------------------------- Dynamic Covariance Scaling ------------------------- //
+/// Dynamic Covariance Scaling
+///
+/// From "Robust Map Optimization using Dynamic Covariance Scaling" by Agarwal
+/// et al. Residuals with $x^2 > \Phi$ have their covariance scaled by
+/// $1 / s^2$, with $s = 2 \Phi / (\Phi + x^2)$, which is equivalent to the
+/// weight $w = s^2$.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Dcs {
+    phi: dtype,
+}
+
+impl Dcs {
+    pub fn new(phi: dtype) -> Self {
+        Dcs { phi }
+    }
+}
+
+impl Default for Dcs {
+    fn default() -> Self {
+        Dcs { phi: 1.0 }
+    }
+}
+
+#[factrs::mark]
+impl RobustCost for Dcs {
+    fn loss(&self, d2: dtype) -> dtype {
+        if d2 <= self.phi {
+            d2 / 2.0
+        } else {
+            1.5 * self.phi - 2.0 * self.phi * self.phi / (self.phi + d2)
+        }
+    }
+
+    fn weight(&self, d2: dtype) -> dtype {
+        if d2 <= self.phi {
+            1.0
+        } else {
+            let s = 2.0 * self.phi / (self.phi + d2);
+            s * s
+        }
+    }
+}
+
+// ------------------------- Truncated Least Squares ------------------------- //
+/// Truncated Least Squares
+///
+/// Quadratic for $x^2 \leq c^2$ and constant past it, so residuals past the
+/// threshold are rejected outright. On its own it's a hard truncation and
+/// needs a good initialization, so it's mainly meant to be used with
+/// [Gnc](crate::optimizers::Gnc).
+///
+/// The GNC surrogate from Yang et al. is quadratic for
+/// $x^2 \leq \frac{\mu}{\mu + 1} c^2$, constant for
+/// $x^2 \geq \frac{\mu + 1}{\mu} c^2$, and in between
+/// $$
+/// \rho_\mu(x) = c|x|\sqrt{\mu(\mu + 1)} - \frac{\mu}{2}(c^2 + x^2)
+/// $$
+/// As $\mu \to 0$ no residual is truncated and the surrogate becomes convex,
+/// while as $\mu \to \infty$ it approaches the hard truncation. Unlike
+/// [GemanMcClure], $\mu$ is increased over the GNC iterations.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TruncatedLeastSquares {
+    c2: dtype,
+    mu: Option<dtype>,
+}
+
+impl TruncatedLeastSquares {
+    /// Largest $\mu$ GNC steps to, past which the surrogate is a truncation
+    const MU_MAX: dtype = 1e4;
+
+    pub fn new(c: dtype) -> Self {
+        TruncatedLeastSquares {
+            c2: c * c,
+            mu: None,
+        }
+    }
+
+    /// Get the control parameter of the surrogate, None if a hard truncation
+    pub fn mu(&self) -> Option<dtype> {
+        self.mu
+    }
+
+    // Band where the surrogate transitions from quadratic to constant
+    fn bounds(&self, mu: dtype) -> (dtype, dtype) {
+        (mu / (mu + 1.0) * self.c2, (mu + 1.0) / mu * self.c2)
+    }
+}
+
+impl Default for TruncatedLeastSquares {
+    fn default() -> Self {
+        TruncatedLeastSquares::new(3.0)
+    }
+}
+
+#[factrs::mark]
+impl RobustCost for TruncatedLeastSquares {
+    fn loss(&self, d2: dtype) -> dtype {
+        let Some(mu) = self.mu else {
+            return d2.min(self.c2) / 2.0;
+        };
+        let (lo, hi) = self.bounds(mu);
+        if d2 <= lo {
+            d2 / 2.0
+        } else if d2 >= hi {
+            self.c2 / 2.0
+        } else {
+            let s = (mu * (mu + 1.0)).sqrt();
+            self.c2.sqrt() * d2.sqrt() * s - mu * (self.c2 + d2) / 2.0
+        }
+    }
+
+    fn weight(&self, d2: dtype) -> dtype {
+        let Some(mu) = self.mu else {
+            return if d2 <= self.c2 { 1.0 } else { 0.0 };
+        };
+        let (lo, hi) = self.bounds(mu);
+        if d2 <= lo {
+            1.0
+        } else if d2 >= hi {
+            0.0
+        } else {
+            let s = (mu * (mu + 1.0)).sqrt();
+            (self.c2 / d2).sqrt() * s - mu
+        }
+    }
+}
+
+impl GncRobust for TruncatedLeastSquares {
+    fn surrogate(&self, mu: dtype) -> Self {
+        TruncatedLeastSquares {
+            c2: self.c2,
+            mu: Some(mu),
+        }
+    }
+
+    fn mu_init(&self, max_d2: dtype) -> dtype {
+        // Every residual is already within the threshold
+        if 2.0 * max_d2 <= self.c2 {
+            return Self::MU_MAX;
+        }
+        (self.c2 / (2.0 * max_d2 - self.c2)).min(Self::MU_MAX)
+    }
+
+    fn mu_update(&self, mu: dtype, step: dtype) -> dtype {
+        (mu * step).min(Self::MU_MAX)
+    }
+
+    fn mu_converged(&self, mu: dtype) -> bool {
+        mu >= Self::MU_MAX
+    }
+}
+
+// ------------------------- Composed ------------------------- //
+/// Combination of two robust kernels
+///
+/// Applies the `inner` kernel for $|x| \leq t$ and the `outer` kernel past
+/// the threshold $t$, for example [Huber] for moderate errors and [Tukey] to
+/// cap gross outliers. To keep the result continuous, the outer kernel is
+/// scaled and shifted,
+/// $$
+/// \rho(x) = \begin{cases}
+/// \rho_A(x) & |x| \leq t \\\\
+/// s \rho_B(x) + \rho_A(t) - s \rho_B(t) & |x| > t
+/// \end{cases}
+/// $$
+/// with $s = w_A(t) / w_B(t)$, so both the loss and weight are continuous at
+/// the threshold. If the outer weight is zero at the threshold, $s = 1$ and only
+/// the loss is continuous.
+///
+/// If serde is enabled, each combination needs tagging, see
+/// [serialization](crate::residuals#serialization).
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ComposedRobust<A, B> {
+    inner: A,
+    outer: B,
+    t2: dtype,
+    scale: dtype,
+    offset: dtype,
+}
+
+impl<A: RobustCost, B: RobustCost> ComposedRobust<A, B> {
+    pub fn new(inner: A, outer: B, threshold: dtype) -> Self {
+        let t2 = threshold * threshold;
+        let w = outer.weight(t2);
+        let scale = if w == 0.0 { 1.0 } else { inner.weight(t2) / w };
+        let offset = inner.loss(t2) - scale * outer.loss(t2);
+        ComposedRobust {
+            inner,
+            outer,
+            t2,
+            scale,
+            offset,
+        }
+    }
+
+    /// Get the threshold between the two kernels
+    pub fn threshold(&self) -> dtype {
+        self.t2.sqrt()
+    }
+}
+
+#[cfg_attr(feature = "serde", typetag::serde)]
+impl<
+        #[cfg(feature = "serde")] A: RobustCost + Clone + 'static + typetag::Tagged,
+        #[cfg(not(feature = "serde"))] A: RobustCost + Clone + 'static,
+        #[cfg(feature = "serde")] B: RobustCost + Clone + 'static + typetag::Tagged,
+        #[cfg(not(feature = "serde"))] B: RobustCost + Clone + 'static,
+    > RobustCost for ComposedRobust<A, B>
+{
+    fn loss(&self, d2: dtype) -> dtype {
+        if d2 <= self.t2 {
+            self.inner.loss(d2)
+        } else {
+            self.scale * self.outer.loss(d2) + self.offset
+        }
+    }
+
+    fn weight(&self, d2: dtype) -> dtype {
+        if d2 <= self.t2 {
+            self.inner.weight(d2)
+        } else {
+            self.scale * self.outer.weight(d2)
+        }
+    }
+}
+
 // Helpers for making sure robust costs are implemented correctly
 use matrixcompare::assert_scalar_eq;
 
@@ -401,5 +783,216 @@ macro_rules! test_robust {
 mod test {
     use super::*;
 
-    test_robust!(L2, L1, Huber, Fair, Cauchy, GemanMcClure, Welsch, Tukey);
+    test_robust!(
+        L2,
+        L1,
+        Huber,
+        Fair,
+        Cauchy,
+        GemanMcClure,
+        Welsch,
+        Tukey,
+        Barron,
+        Dcs,
+        TruncatedLeastSquares
+    );
+
+    #[test]
+    fn cauchy_scale() {
+        for c in [0.5, 1.0, 5.0] {
+            let robust = Cauchy::new(c);
+            for d in [0.1, 1.0, 10.0] {
+                test_weight(&robust, d);
+
+                let expected = c * c / 2.0 * (1.0 + (d / c) * (d / c)).ln();
+                assert_scalar_eq!(robust.loss(d * d), expected, comp = abs, tol = TOL);
+            }
+        }
+    }
+
+    fn assert_same(a: &impl RobustCost, b: &impl RobustCost, tol: dtype) {
+        for d in [0.1, 1.0, 3.0] {
+            let d2: dtype = d * d;
+            assert_scalar_eq!(a.loss(d2), b.loss(d2), comp = abs, tol = tol);
+            assert_scalar_eq!(a.weight(d2), b.weight(d2), comp = abs, tol = tol);
+        }
+    }
+
+    #[test]
+    fn barron_special_cases() {
+        let c: dtype = 1.5;
+        let sqrt2 = (2.0 as dtype).sqrt();
+        assert_same(&Barron::new(2.0, c), &L2, TOL);
+        assert_same(&Barron::new(0.0, c), &Cauchy::new(sqrt2 * c), TOL);
+        assert_same(&Barron::new(-2.0, c), &GemanMcClure::new(2.0 * c), TOL);
+        let welsch = Welsch::new(sqrt2 * c);
+        assert_same(&Barron::new(dtype::NEG_INFINITY, c), &welsch, TOL);
+
+        // Approaching the special cases converges to them
+        assert_same(&Barron::new(1e-8, c), &Cauchy::new(sqrt2 * c), 1e-5);
+        assert_same(&Barron::new(2.0 - 1e-8, c), &L2, 1e-5);
+    }
+
+    #[test]
+    fn barron_weight() {
+        for alpha in [-5.0, -2.0, -0.5, 0.0, 0.5, 1.0, 1.5] {
+            let robust = Barron::new(alpha, 1.5);
+            test_weight(&robust, 0.5);
+            test_weight(&robust, 5.0);
+        }
+    }
+
+    #[test]
+    fn tukey_band() {
+        let c = 2.0;
+        let robust = Tukey::new(c);
+
+        // Inside the band matches the derivative of the loss
+        for d in [0.1, 0.5, 1.0, 1.9] {
+            test_weight(&robust, d);
+        }
+
+        // Smoothly reaches zero at the edge
+        assert_scalar_eq!(robust.weight(c * c), 0.0, comp = float);
+
+        // And is exactly zero, with a flat loss, past it
+        for d in [2.1, 5.0, 1e3] {
+            assert_eq!(robust.weight(d * d), 0.0);
+            assert_eq!(robust.loss(d * d), c * c / 6.0);
+        }
+    }
+
+    #[test]
+    fn dcs_bad_loop_closure() {
+        use crate::{
+            assign_symbols, fac,
+            optimizers::{test::odometry_chain, GaussNewton, Optimizer},
+            residuals::BetweenResidual,
+            variables::{Variable, VectorVar2},
+        };
+
+        assign_symbols!(X: VectorVar2);
+
+        // A loop closure claiming the end is at the start
+        fn solve(robust: impl RobustCost + 'static) -> dtype {
+            let (mut graph, values) = odometry_chain(4);
+            let res = BetweenResidual::new(VectorVar2::identity());
+            graph.add_factor(fac![res, (X(0), X(4)), 0.1 as std, robust]);
+
+            let mut opt: GaussNewton = GaussNewton::new(graph);
+            let result = opt.optimize(values).expect("Optimization failed");
+            let x: &VectorVar2 = result.get(X(4)).expect("Missing X(4)");
+            x.0.x
+        }
+
+        assert!((solve(L2) - 4.0).abs() > 0.5);
+        assert!((solve(Dcs::default()) - 4.0).abs() < 1e-2);
+    }
+
+    #[test]
+    fn composed_continuous() {
+        let t: dtype = 2.0;
+        let robust = ComposedRobust::new(Huber::new(1.0), Cauchy::new(1.5), t);
+        let eps = 1e-6;
+        let (below, above) = ((t - eps) * (t - eps), (t + eps) * (t + eps));
+        assert_scalar_eq!(
+            robust.loss(below),
+            robust.loss(above),
+            comp = abs,
+            tol = 1e-5
+        );
+        assert_scalar_eq!(
+            robust.weight(below),
+            robust.weight(above),
+            comp = abs,
+            tol = 1e-5
+        );
+
+        // Each side is still a valid kernel
+        assert_scalar_eq!(robust.loss(0.0), 0.0, comp = float);
+        test_weight(&robust, 0.5);
+        test_weight(&robust, 5.0);
+    }
+
+    #[test]
+    fn composed_cap() {
+        // Huber with a hard cap past the Tukey band
+        let robust = ComposedRobust::new(Huber::new(1.0), Tukey::new(3.0), 2.0);
+        let t2: dtype = 4.0;
+        assert_scalar_eq!(robust.loss(t2), Huber::new(1.0).loss(t2), comp = float);
+        let cap = robust.loss(9.0);
+        for d in [3.0, 10.0, 1e3] {
+            assert_scalar_eq!(robust.loss(d * d), cap, comp = abs, tol = 1e-6);
+            assert_eq!(robust.weight(d * d), 0.0);
+        }
+    }
+
+    #[test]
+    fn tls_truncation() {
+        let robust = TruncatedLeastSquares::new(2.0);
+        assert_scalar_eq!(robust.loss(3.0), 1.5, comp = float);
+        assert_scalar_eq!(robust.weight(3.0), 1.0, comp = float);
+        for d2 in [5.0, 100.0, 1e6] {
+            assert_scalar_eq!(robust.loss(d2), 2.0, comp = float);
+            assert_eq!(robust.weight(d2), 0.0);
+        }
+    }
+
+    #[test]
+    fn tls_surrogate() {
+        let robust = TruncatedLeastSquares::new(2.0);
+        for mu in [0.1, 1.0, 10.0] {
+            let surrogate = robust.surrogate(mu);
+            for d in [0.5, 1.5, 2.0, 3.0, 10.0] {
+                test_weight(&surrogate, d);
+                let w = robust.surrogate_weight(d * d, mu);
+                assert_scalar_eq!(w, surrogate.weight(d * d), comp = float);
+            }
+
+            // Continuous at the edges of the band
+            let (lo, hi) = surrogate.bounds(mu);
+            for t in [lo, hi] {
+                let eps = 1e-6;
+                let (below, above) = (t - eps, t + eps);
+                let (l_below, l_above) = (surrogate.loss(below), surrogate.loss(above));
+                assert_scalar_eq!(l_below, l_above, comp = abs, tol = 1e-5);
+                let (w_below, w_above) = (surrogate.weight(below), surrogate.weight(above));
+                assert_scalar_eq!(w_below, w_above, comp = abs, tol = 1e-3);
+            }
+        }
+    }
+
+    #[test]
+    fn tls_surrogate_convex() {
+        // With a tiny mu nothing is truncated, and the loss is convex up to
+        // a curvature of -mu
+        let mu = 1e-8;
+        let surrogate = TruncatedLeastSquares::new(2.0).surrogate(mu);
+        let h: dtype = 0.1;
+        for i in 1..500 {
+            let d = i as dtype * h;
+            assert!(surrogate.weight(d * d) > 0.0);
+
+            let loss = |d: dtype| surrogate.loss(d * d);
+            let curvature = (loss(d + h) - 2.0 * loss(d) + loss(d - h)) / (h * h);
+            assert!(curvature > -TOL);
+        }
+    }
+
+    #[test]
+    fn tls_surrogate_truncates() {
+        // With a huge mu the surrogate matches the hard truncation
+        let robust = TruncatedLeastSquares::new(2.0);
+        let surrogate = robust.surrogate(1e8);
+        for d in [0.1, 1.0, 1.9, 2.1, 5.0, 50.0] {
+            let d2: dtype = d * d;
+            assert_scalar_eq!(surrogate.loss(d2), robust.loss(d2), comp = abs, tol = 1e-6);
+            assert_scalar_eq!(
+                surrogate.weight(d2),
+                robust.weight(d2),
+                comp = abs,
+                tol = 1e-6
+            );
+        }
+    }
 }
@@ -1,30 +1,108 @@
 //! Misc utilities
 use std::{
+    fmt::Write,
     fs::File,
+    hash::BuildHasher,
     io::{BufRead, BufReader},
 };
 
+use faer_ext::IntoNalgebra;
+use foldhash::HashMap;
+
 use crate::{
     assign_symbols,
-    containers::{FactorBuilder, Graph, Values},
+    containers::{DefaultSymbolHandler, FactorBuilder, FactorId, Graph, Key, Values, ValuesOrder},
     dtype, fac,
-    linalg::{Matrix3, Matrix6, Vector3},
+    linalg::{vectorx, DiffResult, Matrix, Matrix3, MatrixX, Vector3, VectorX},
     noise::GaussianNoise,
-    residuals::{BetweenResidual, PriorResidual},
+    residuals::{BetweenResidual, PriorResidual, ProjectionResidual},
     variables::*,
 };
 
-assign_symbols!(X: SE2, SE3);
+assign_symbols!(X: SE2, SE3; L: VectorVar3);
+
+// Permutations from g2o (translation first) to factrs (rotation first) order
+const SE2_PERM: [usize; 3] = [1, 2, 0];
+const SE3_PERM: [usize; 6] = [3, 4, 5, 0, 1, 2];
+
+fn parse(part: &str) -> dtype {
+    part.parse::<dtype>().expect("Failed to parse g20")
+}
+
+// Read the upper triangle of a g2o information matrix into factrs order
+fn parse_inf<const N: usize>(parts: &[&str], perm: [usize; N]) -> Matrix<N, N> {
+    let mut inf = Matrix::<N, N>::zeros();
+    let mut k = 0;
+    for i in 0..N {
+        for j in i..N {
+            let v = parse(parts[k]);
+            inf[(perm[i], perm[j])] = v;
+            inf[(perm[j], perm[i])] = v;
+            k += 1;
+        }
+    }
+    inf
+}
+
+// Write the upper triangle of an information matrix in g2o order
+fn write_inf(inf: &MatrixX, perm: &[usize]) -> String {
+    let mut out = Vec::new();
+    for i in 0..perm.len() {
+        for j in i..perm.len() {
+            out.push(inf[(perm[i], perm[j])].to_string());
+        }
+    }
+    out.join(" ")
+}
+
+fn parse_se2(parts: &[&str]) -> SE2 {
+    SE2::new(parse(parts[2]), parse(parts[0]), parse(parts[1]))
+}
+
+fn write_se2(var: &SE2) -> String {
+    format!("{} {} {}", var.x(), var.y(), var.theta())
+}
+
+fn parse_se3(parts: &[&str]) -> SE3 {
+    let rot = SO3::from_xyzw(
+        parse(parts[3]),
+        parse(parts[4]),
+        parse(parts[5]),
+        parse(parts[6]),
+    );
+    let xyz = Vector3::new(parse(parts[0]), parse(parts[1]), parse(parts[2]));
+    SE3::from_rot_trans(rot, xyz)
+}
+
+fn write_se3(var: &SE3) -> String {
+    let (xyz, rot) = (var.xyz(), var.rot());
+    format!(
+        "{} {} {} {} {} {} {}",
+        xyz[0],
+        xyz[1],
+        xyz[2],
+        rot.x(),
+        rot.y(),
+        rot.z(),
+        rot.w()
+    )
+}
 
 /// Load a g2o file
 ///
 /// Currently supports only SE2 and SE3 pose graphs. Will autodetect which one
 /// it is, so mixed graph type isn't allowed.
+///
+/// Prior edges (`EDGE_PRIOR_SE2` and `EDGE_SE3_PRIOR`) are loaded as
+/// [PriorResidual] factors, with SE3 prior offsets assumed to be the identity.
+/// If the file has none, a tight prior is added to fix the gauge freedom.
 pub fn load_g20(file: &str) -> (Graph, Values) {
     let file = File::open(file).expect("File not found!");
 
     let mut values: Values = Values::new();
     let mut graph = Graph::new();
+    let mut default_prior = None;
+    let mut has_prior = false;
 
     for line in BufReader::new(file).lines() {
         let line = line.expect("Missing line");
@@ -32,17 +110,13 @@ pub fn load_g20(file: &str) -> (Graph, Values) {
         match parts[0] {
             "VERTEX_SE2" => {
                 let id = parts[1].parse::<u32>().expect("Failed to parse g20");
-                let x = parts[2].parse::<dtype>().expect("Failed to parse g20");
-                let y = parts[3].parse::<dtype>().expect("Failed to parse g20");
-                let theta = parts[4].parse::<dtype>().expect("Failed to parse g20");
-
-                let var = SE2::new(theta, x, y);
+                let var = parse_se2(&parts[2..5]);
                 let key = X(id);
 
                 // Add prior on whatever the first variable is
                 if values.len() == 1 {
                     let factor = fac![PriorResidual::new(var.clone()), key, 1e-6 as cov];
-                    graph.add_factor(factor);
+                    default_prior = Some(factor);
                 }
 
                 values.insert(key, var);
@@ -51,46 +125,30 @@ pub fn load_g20(file: &str) -> (Graph, Values) {
             "EDGE_SE2" => {
                 let id_prev = parts[1].parse::<u32>().expect("Failed to parse g20");
                 let id_curr = parts[2].parse::<u32>().expect("Failed to parse g20");
-                let x = parts[3].parse::<dtype>().expect("Failed to parse g20");
-                let y = parts[4].parse::<dtype>().expect("Failed to parse g20");
-                let theta = parts[5].parse::<dtype>().expect("Failed to parse g20");
-
-                let m11 = parts[6].parse::<dtype>().expect("Failed to parse g20");
-                let m12 = parts[7].parse::<dtype>().expect("Failed to parse g20");
-                let m13 = parts[8].parse::<dtype>().expect("Failed to parse g20");
-                let m22 = parts[9].parse::<dtype>().expect("Failed to parse g20");
-                let m23 = parts[10].parse::<dtype>().expect("Failed to parse g20");
-                let m33 = parts[11].parse::<dtype>().expect("Failed to parse g20");
-                // Note have to permute here - g2o stores with translation first, factrs with
-                // rotation first
-                #[rustfmt::skip]
-                let inf = Matrix3::new(
-                    m33, m13, m23,
-                    m13, m11, m12,
-                    m23, m12, m22,
-                );
+                let var = parse_se2(&parts[3..6]);
+                let inf = parse_inf(&parts[6..12], SE2_PERM);
 
                 let key1 = X(id_prev);
                 let key2 = X(id_curr);
-                let var = SE2::new(theta, x, y);
                 let noise = GaussianNoise::from_matrix_inf(inf.as_view());
                 let factor = fac![BetweenResidual::new(var), (key1, key2), noise];
                 graph.add_factor(factor);
             }
 
+            "EDGE_PRIOR_SE2" => {
+                let id = parts[1].parse::<u32>().expect("Failed to parse g20");
+                let var = parse_se2(&parts[2..5]);
+                let inf = parse_inf(&parts[5..11], SE2_PERM);
+
+                let noise = GaussianNoise::from_matrix_inf(inf.as_view());
+                let factor = fac![PriorResidual::new(var), X(id), noise];
+                graph.add_factor(factor);
+                has_prior = true;
+            }
+
             "VERTEX_SE3:QUAT" => {
                 let id = parts[1].parse::<u32>().expect("Failed to parse g20");
-                let x = parts[2].parse::<dtype>().expect("Failed to parse g20");
-                let y = parts[3].parse::<dtype>().expect("Failed to parse g20");
-                let z = parts[4].parse::<dtype>().expect("Failed to parse g20");
-                let qx = parts[5].parse::<dtype>().expect("Failed to parse g20");
-                let qy = parts[6].parse::<dtype>().expect("Failed to parse g20");
-                let qz = parts[7].parse::<dtype>().expect("Failed to parse g20");
-                let qw = parts[8].parse::<dtype>().expect("Failed to parse g20");
-
-                let rot = SO3::from_xyzw(qx, qy, qz, qw);
-                let xyz = Vector3::new(x, y, z);
-                let var = SE3::from_rot_trans(rot, xyz);
+                let var = parse_se3(&parts[2..9]);
                 let key = X(id);
 
                 // Add prior on whatever the first variable is
@@ -98,7 +156,7 @@ pub fn load_g20(file: &str) -> (Graph, Values) {
                     let noise =
                         GaussianNoise::<6>::from_diag_covs(1e-6, 1e-6, 1e-6, 1e-4, 1e-4, 1e-4);
                     let factor = fac![PriorResidual::new(var.clone()), key, noise];
-                    graph.add_factor(factor);
+                    default_prior = Some(factor);
                 }
 
                 values.insert(key, var);
@@ -107,50 +165,8 @@ pub fn load_g20(file: &str) -> (Graph, Values) {
             "EDGE_SE3:QUAT" => {
                 let id_prev = parts[1].parse::<u32>().expect("Failed to parse g20");
                 let id_curr = parts[2].parse::<u32>().expect("Failed to parse g20");
-                let x = parts[3].parse::<dtype>().expect("Failed to parse g20");
-                let y = parts[4].parse::<dtype>().expect("Failed to parse g20");
-                let z = parts[5].parse::<dtype>().expect("Failed to parse g20");
-                let qx = parts[6].parse::<dtype>().expect("Failed to parse g20");
-                let qy = parts[7].parse::<dtype>().expect("Failed to parse g20");
-                let qz = parts[8].parse::<dtype>().expect("Failed to parse g20");
-                let qw = parts[9].parse::<dtype>().expect("Failed to parse g20");
-
-                let m11 = parts[10].parse::<dtype>().expect("Failed to parse g20");
-                let m12 = parts[11].parse::<dtype>().expect("Failed to parse g20");
-                let m13 = parts[12].parse::<dtype>().expect("Failed to parse g20");
-                let m14 = parts[13].parse::<dtype>().expect("Failed to parse g20");
-                let m15 = parts[14].parse::<dtype>().expect("Failed to parse g20");
-                let m16 = parts[15].parse::<dtype>().expect("Failed to parse g20");
-                let m22 = parts[16].parse::<dtype>().expect("Failed to parse g20");
-                let m23 = parts[17].parse::<dtype>().expect("Failed to parse g20");
-                let m24 = parts[18].parse::<dtype>().expect("Failed to parse g20");
-                let m25 = parts[19].parse::<dtype>().expect("Failed to parse g20");
-                let m26 = parts[20].parse::<dtype>().expect("Failed to parse g20");
-                let m33 = parts[21].parse::<dtype>().expect("Failed to parse g20");
-                let m34 = parts[22].parse::<dtype>().expect("Failed to parse g20");
-                let m35 = parts[23].parse::<dtype>().expect("Failed to parse g20");
-                let m36 = parts[24].parse::<dtype>().expect("Failed to parse g20");
-                let m44 = parts[25].parse::<dtype>().expect("Failed to parse g20");
-                let m45 = parts[26].parse::<dtype>().expect("Failed to parse g20");
-                let m46 = parts[27].parse::<dtype>().expect("Failed to parse g20");
-                let m55 = parts[28].parse::<dtype>().expect("Failed to parse g20");
-                let m56 = parts[29].parse::<dtype>().expect("Failed to parse g20");
-                let m66 = parts[30].parse::<dtype>().expect("Failed to parse g20");
-                // Note have to permute here - g2o stores with translation first, factrs with
-                // rotation first
-                #[rustfmt::skip]
-                let inf = Matrix6::new(
-                    m44, m45, m46, m14, m24, m34,
-                    m45, m55, m56, m15, m25, m35,
-                    m46, m56, m66, m16, m25, m36,
-                    m14, m15, m16, m11, m12, m13,
-                    m24, m25, m26, m12, m22, m23,
-                    m34, m35, m36, m13, m23, m33,
-                );
-
-                let rot = SO3::from_xyzw(qx, qy, qz, qw);
-                let xyz = Vector3::new(x, y, z);
-                let var = SE3::from_rot_trans(rot, xyz);
+                let var = parse_se3(&parts[3..10]);
+                let inf = parse_inf(&parts[10..31], SE3_PERM);
 
                 let key1 = X(id_prev);
                 let key2 = X(id_curr);
@@ -161,11 +177,887 @@ pub fn load_g20(file: &str) -> (Graph, Values) {
                 graph.add_factor(factor);
             }
 
+            "EDGE_SE3_PRIOR" => {
+                let id = parts[1].parse::<u32>().expect("Failed to parse g20");
+                let var = parse_se3(&parts[3..10]);
+                let inf = parse_inf(&parts[10..31], SE3_PERM);
+
+                let noise = GaussianNoise::from_matrix_inf(inf.as_view());
+                let factor = fac![PriorResidual::new(var), X(id), noise];
+                graph.add_factor(factor);
+                has_prior = true;
+            }
+
+            // Only used for prior offsets, which are assumed identity
+            "PARAMS_SE3OFFSET" => {}
+
             _ => {
                 println!(",Unknown line: {}", parts.join(" "));
             }
         }
     }
 
+    if let (false, Some(factor)) = (has_prior, default_prior) {
+        graph.add_factor(factor);
+    }
+
     (graph, values)
 }
+
+/// Load a TORO file
+///
+/// Currently supports only SE2 pose graphs, ie `VERTEX2` and `EDGE2` lines,
+/// returning the same graph as the equivalent g2o file would with [load_g20].
+/// As there, a tight prior is added to the second variable to fix the gauge
+/// freedom.
+pub fn load_toro(file: &str) -> (Graph, Values) {
+    // TORO stores the upper triangle of the information matrix as
+    // xx xy yy tt xt yt, while g2o uses xx xy xt yy yt tt
+    const TORO_ORDER: [usize; 6] = [0, 1, 4, 2, 5, 3];
+
+    let file = File::open(file).expect("File not found!");
+
+    let mut values: Values = Values::new();
+    let mut graph = Graph::new();
+    let mut default_prior = None;
+
+    for line in BufReader::new(file).lines() {
+        let line = line.expect("Missing line");
+        let parts = line.split_whitespace().collect::<Vec<&str>>();
+        match parts.first() {
+            Some(&"VERTEX2") => {
+                let id = parts[1].parse::<u32>().expect("Failed to parse toro");
+                let var = parse_se2(&parts[2..5]);
+                let key = X(id);
+
+                // Add prior on the same variable as load_g20
+                if values.len() == 1 {
+                    let factor = fac![PriorResidual::new(var.clone()), key, 1e-6 as cov];
+                    default_prior = Some(factor);
+                }
+
+                values.insert(key, var);
+            }
+
+            Some(&"EDGE2") => {
+                let id_prev = parts[1].parse::<u32>().expect("Failed to parse toro");
+                let id_curr = parts[2].parse::<u32>().expect("Failed to parse toro");
+                let var = parse_se2(&parts[3..6]);
+                let inf = TORO_ORDER.map(|i| parts[6 + i]);
+                let inf = parse_inf(&inf, SE2_PERM);
+
+                let key1 = X(id_prev);
+                let key2 = X(id_curr);
+                let noise = GaussianNoise::from_matrix_inf(inf.as_view());
+                let factor = fac![BetweenResidual::new(var), (key1, key2), noise];
+                graph.add_factor(factor);
+            }
+
+            None => {}
+
+            _ => {
+                log::warn!("Unknown line: {}", parts.join(" "));
+            }
+        }
+    }
+
+    if let Some(factor) = default_prior {
+        graph.add_factor(factor);
+    }
+
+    (graph, values)
+}
+
+/// Intrinsics of a camera from a BAL file
+///
+/// BAL cameras project a point $p$ in normalized coordinates to $f r(p) p$,
+/// with the radial distortion $r(p) = 1 + k_1 |p|^2 + k_2 |p|^4$.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BalIntrinsics {
+    pub f: dtype,
+    pub k1: dtype,
+    pub k2: dtype,
+}
+
+impl BalIntrinsics {
+    /// Remove the radial distortion from a pixel measurement
+    pub fn undistort(&self, u: dtype, v: dtype) -> (dtype, dtype) {
+        let (xd, yd) = (u / self.f, v / self.f);
+        let (mut x, mut y) = (xd, yd);
+        // Fixed point iteration, converges quickly for reasonable distortion
+        for _ in 0..20 {
+            let r2 = x * x + y * y;
+            let r = 1.0 + self.k1 * r2 + self.k2 * r2 * r2;
+            (x, y) = (xd / r, yd / r);
+        }
+        (self.f * x, self.f * y)
+    }
+}
+
+/// Load a Bundle Adjustment in the Large (BAL) file
+///
+/// Cameras are keyed as `X(i)` and stored as camera to world [SE3] poses, and
+/// landmarks as `L(j)` [VectorVar3], both indexed as in the file. Each
+/// observation is added as a [ProjectionResidual] with unit noise.
+///
+/// BAL cameras look down their negative $z$-axis with $y$ pointing up, so
+/// poses are rotated to the usual pinhole convention of looking down $z$ with
+/// $y$ pointing down, and the $v$ pixel coordinate is flipped to match.
+/// [ProjectionResidual] has no distortion, so measurements are undistorted
+/// with the intrinsics of their camera, which are returned as well.
+///
+/// No prior is added, so the problem has a gauge freedom.
+pub fn load_bal(file: &str) -> (Graph, Values, Vec<BalIntrinsics>) {
+    let contents = std::fs::read_to_string(file).expect("File not found!");
+    let mut tokens = contents.split_whitespace().map(parse);
+    let mut next = || tokens.next().expect("BAL file ended early");
+
+    let num_cameras = next() as usize;
+    let num_points = next() as usize;
+    let num_obs = next() as usize;
+
+    let obs = (0..num_obs)
+        .map(|_| (next() as u32, next() as u32, next(), next()))
+        .collect::<Vec<_>>();
+
+    // Rotates from the BAL camera frame to the pinhole one
+    let flip = SE3::from_rot_trans(SO3::from_xyzw(1.0, 0.0, 0.0, 0.0), Vector3::zeros());
+
+    let mut values = Values::new();
+    let mut intrinsics = Vec::with_capacity(num_cameras);
+    for i in 0..num_cameras {
+        let rot = SO3::exp(vectorx![next(), next(), next()].as_view());
+        let xyz = Vector3::new(next(), next(), next());
+        // BAL stores world to camera
+        let pose = flip.compose(&SE3::from_rot_trans(rot, xyz)).inverse();
+        values.insert(X(i as u32), pose);
+        intrinsics.push(BalIntrinsics {
+            f: next(),
+            k1: next(),
+            k2: next(),
+        });
+    }
+
+    for j in 0..num_points {
+        values.insert(L(j as u32), VectorVar3::new(next(), next(), next()));
+    }
+
+    let mut graph = Graph::new();
+    for (cam, point, u, v) in obs {
+        let cal = &intrinsics[cam as usize];
+        let (u, v) = cal.undistort(u, v);
+        let res = ProjectionResidual::new(VectorVar2::new(u, -v), cal.f, cal.f, 0.0, 0.0);
+        graph.add_factor(fac![res, (X(cam), L(point))]);
+    }
+
+    (graph, values, intrinsics)
+}
+
+/// Save a g2o file
+///
+/// The inverse of [load_g20], supporting SE2 and SE3 variables, with
+/// [BetweenResidual] factors written as edges and [PriorResidual] factors as
+/// `EDGE_PRIOR_SE2` and `EDGE_SE3_PRIOR` edges. Vertex ids are the indices of
+/// the keys, ie `X(5)` becomes vertex 5. Information matrices are recovered
+/// from the noise model of each factor. Any other variables or factors are
+/// skipped.
+pub fn save_g20(file: &str, graph: &Graph, values: &Values) {
+    let id = |key: Key| DefaultSymbolHandler::key_to_sym(key).1;
+    let mut out = String::new();
+
+    let mut keys = values.iter().map(|(k, _)| *k).collect::<Vec<_>>();
+    keys.sort_by_key(|k| id(*k));
+    for key in keys {
+        let var = values.get_raw(key).expect("Key missing in values");
+        if let Some(var) = var.downcast_ref::<SE2>() {
+            writeln!(out, "VERTEX_SE2 {} {}", id(key), write_se2(var))
+                .expect("Writing to a String can't fail");
+        } else if let Some(var) = var.downcast_ref::<SE3>() {
+            writeln!(out, "VERTEX_SE3:QUAT {} {}", id(key), write_se3(var))
+                .expect("Writing to a String can't fail");
+        } else {
+            log::warn!("Skipping unsupported variable: {:?}", var);
+        }
+    }
+
+    // SE3 priors reference an offset parameter
+    let se3_prior = graph
+        .iter()
+        .any(|f| f.residual().downcast_ref::<PriorResidual<SE3>>().is_some());
+    if se3_prior {
+        writeln!(out, "PARAMS_SE3OFFSET 0 0 0 0 0 0 0 1").expect("Writing to a String can't fail");
+    }
+
+    for factor in graph.iter() {
+        let ids = factor.keys().iter().map(|k| id(*k)).collect::<Vec<_>>();
+        let dim = factor.dim_out();
+        let w = factor.noise().whiten_mat(MatrixX::identity(dim, dim));
+        let inf = w.transpose() * w;
+
+        let residual = factor.residual();
+        if let Some(r) = residual.downcast_ref::<BetweenResidual<SE2>>() {
+            let (meas, inf) = (write_se2(r.delta()), write_inf(&inf, &SE2_PERM));
+            writeln!(out, "EDGE_SE2 {} {} {} {}", ids[0], ids[1], meas, inf)
+                .expect("Writing to a String can't fail");
+        } else if let Some(r) = residual.downcast_ref::<PriorResidual<SE2>>() {
+            let (meas, inf) = (write_se2(r.prior()), write_inf(&inf, &SE2_PERM));
+            writeln!(out, "EDGE_PRIOR_SE2 {} {} {}", ids[0], meas, inf)
+                .expect("Writing to a String can't fail");
+        } else if let Some(r) = residual.downcast_ref::<BetweenResidual<SE3>>() {
+            let (meas, inf) = (write_se3(r.delta()), write_inf(&inf, &SE3_PERM));
+            writeln!(out, "EDGE_SE3:QUAT {} {} {} {}", ids[0], ids[1], meas, inf)
+                .expect("Writing to a String can't fail");
+        } else if let Some(r) = residual.downcast_ref::<PriorResidual<SE3>>() {
+            let (meas, inf) = (write_se3(r.prior()), write_inf(&inf, &SE3_PERM));
+            writeln!(out, "EDGE_SE3_PRIOR {} 0 {} {}", ids[0], meas, inf)
+                .expect("Writing to a String can't fail");
+        } else {
+            log::warn!("Skipping unsupported factor: {:?}", factor);
+        }
+    }
+
+    std::fs::write(file, out).expect("Failed to write g20");
+}
+
+// ------------------------- Serde ------------------------- //
+// Graph and values saved together in a single file
+#[cfg(feature = "json")]
+#[derive(serde::Serialize)]
+struct ProblemRef<'a> {
+    graph: &'a Graph,
+    values: &'a Values,
+}
+
+#[cfg(feature = "json")]
+#[derive(serde::Deserialize)]
+struct Problem {
+    graph: Graph,
+    values: Values,
+}
+
+/// Save a graph and values as JSON
+///
+/// Factors, noise models, robust kernels, and variables are boxed, so each is
+/// saved along with its type tag. All the types provided by factrs are tagged
+/// already, but [marked](crate::mark) custom types with generics have to be
+/// [tagged](crate::serde) by hand to be loaded again with [load_graph_json].
+#[cfg(feature = "json")]
+#[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+pub fn save_graph_json(file: &str, graph: &Graph, values: &Values) {
+    let writer = std::io::BufWriter::new(File::create(file).expect("Failed to create file"));
+    serde_json::to_writer_pretty(writer, &ProblemRef { graph, values })
+        .expect("Failed to serialize graph");
+}
+
+/// Load a graph and values saved with [save_graph_json]
+#[cfg(feature = "json")]
+#[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+pub fn load_graph_json(file: &str) -> (Graph, Values) {
+    let reader = BufReader::new(File::open(file).expect("File not found!"));
+    let problem: Problem = serde_json::from_reader(reader).expect("Failed to deserialize graph");
+    (problem.graph, problem.values)
+}
+
+/// Compute the Cramér-Rao lower bound of every variable
+///
+/// Linearizes the graph about `values` (usually the ground truth) and inverts
+/// the resulting Fisher information $J^\top J$, giving the smallest covariance
+/// any unbiased estimator can achieve. Covariances are returned in the tangent
+/// space of each variable, keyed by its [Key].
+///
+/// This forms the dense inverse of the full information matrix, so is meant
+/// for experiment design on small problems rather than large graphs. Returns
+/// `None` if the information matrix is singular, ie some variable isn't fully
+/// constrained by the graph.
+pub fn crlb(graph: &Graph, values: &Values) -> Option<HashMap<Key, MatrixX>> {
+    let order = ValuesOrder::from_values(values);
+    let linear = graph.linearize(values);
+    let graph_order = linear.sparsity_pattern(order);
+    let DiffResult { diff: jac, .. } = linear.residual_jacobian(&graph_order);
+
+    let jac = jac.to_dense().as_ref().into_nalgebra().clone_owned();
+    let info = jac.transpose() * &jac;
+    let cov = info.try_inverse()?;
+
+    let covs = graph_order
+        .order
+        .iter()
+        .map(|(key, idx)| {
+            let block = cov.view((idx.idx, idx.idx), (idx.dim, idx.dim));
+            (*key, block.clone_owned())
+        })
+        .collect();
+    Some(covs)
+}
+
+/// Compute the Fréchet (aka Karcher) mean of a set of variables
+///
+/// This is the element minimizing the sum of squared tangent space distances to
+/// all samples. It is found iteratively, by averaging the samples in the
+/// tangent space of the current estimate and applying the result, until the
+/// update is negligible. For vector spaces this is simply the arithmetic mean.
+pub fn frechet_mean<V: Variable<T = dtype>>(samples: &[V]) -> V {
+    assert!(!samples.is_empty(), "Can't compute mean of no samples");
+
+    let mut mean = samples[0].clone();
+    for _ in 0..100 {
+        let delta = samples
+            .iter()
+            .map(|s| s.ominus(&mean))
+            .fold(VectorX::zeros(V::DIM), |acc, d| acc + d)
+            / samples.len() as dtype;
+        mean = mean.oplus(delta.as_view());
+        if delta.norm() < 1e-10 {
+            break;
+        }
+    }
+    mean
+}
+
+/// Find the rigid transform best aligning two sets of points
+///
+/// Uses the closed form solution of Umeyama, returning the $T$ minimizing
+/// $\sum_i \| T p_i - q_i \|^2$ for source points $p_i$ and destination points
+/// $q_i$. Requires at least 3 correspondences that aren't colinear.
+pub fn umeyama(src: &[Vector3], dst: &[Vector3]) -> SE3 {
+    assert!(
+        src.len() == dst.len(),
+        "Mismatched number of points in umeyama"
+    );
+    assert!(src.len() >= 3, "Need at least 3 points to align");
+
+    let n = src.len() as dtype;
+    let mu_src = src.iter().sum::<Vector3>() / n;
+    let mu_dst = dst.iter().sum::<Vector3>() / n;
+    let cov = src
+        .iter()
+        .zip(dst)
+        .map(|(p, q)| (q - mu_dst) * (p - mu_src).transpose())
+        .sum::<Matrix3>()
+        / n;
+
+    // Flip the smallest singular direction if needed to avoid a reflection
+    let svd = cov.svd(true, true);
+    let u = svd.u.expect("Failed to compute SVD");
+    let v_t = svd.v_t.expect("Failed to compute SVD");
+    let mut s = Matrix3::identity();
+    if u.determinant() * v_t.determinant() < 0.0 {
+        s[(2, 2)] = -1.0;
+    }
+    let rot = u * s * v_t;
+    let xyz = mu_dst - rot * mu_src;
+
+    SE3::from_rot_trans(SO3::from_matrix(rot.as_view()), xyz)
+}
+
+/// Transform a solution into a global frame defined by GPS fixes
+///
+/// Fits the rigid transform aligning the estimated positions of the poses in
+/// `gps_fixes` to their measured global positions using [umeyama], and
+/// applies it to every [SE3] pose and [VectorVar3] point in `values`. All
+/// other variables are copied unchanged. This is the usual georeferencing step
+/// after optimizing a locally consistent pose graph.
+pub fn align_to_global<S: BuildHasher>(
+    values: &Values,
+    gps_fixes: &std::collections::HashMap<Key, Vector3, S>,
+) -> Values {
+    let (src, dst): (Vec<_>, Vec<_>) = gps_fixes
+        .iter()
+        .map(|(key, gps)| {
+            let pose = values
+                .get_raw(*key)
+                .and_then(|v| v.downcast_ref::<SE3>())
+                .expect("GPS fix doesn't correspond to an SE3 pose in values");
+            (pose.xyz().clone_owned(), *gps)
+        })
+        .unzip();
+    let transform = umeyama(&src, &dst);
+
+    let mut aligned = Values::new();
+    for (key, var) in values.iter() {
+        let var: Box<dyn VariableSafe> = if let Some(pose) = var.downcast_ref::<SE3>() {
+            Box::new(&transform * pose)
+        } else if let Some(point) = var.downcast_ref::<VectorVar3>() {
+            Box::new(VectorVar3::from(transform.apply(point.0.as_view())))
+        } else {
+            var.clone_box()
+        };
+        aligned.entry(*key).or_insert(var);
+    }
+    aligned
+}
+
+// ------------------------- Chi-squared test ------------------------- //
+
+// Lanczos approximation of ln(Gamma(x)), accurate for x >= 0.5
+fn ln_gamma(x: dtype) -> dtype {
+    const G: [dtype; 9] = [
+        0.999_999_999_999_809_9,
+        676.520_368_121_885_1,
+        -1_259.139_216_722_402_8,
+        771.323_428_777_653_1,
+        -176.615_029_162_140_6,
+        12.507_343_278_686_905,
+        -0.138_571_095_265_720_12,
+        9.984_369_578_019_572e-6,
+        1.505_632_735_149_311_6e-7,
+    ];
+    let x = x - 1.0;
+    let t = x + 7.5;
+    let a = G
+        .iter()
+        .enumerate()
+        .skip(1)
+        .fold(G[0], |a, (i, g)| a + g / (x + i as dtype));
+    // ln(sqrt(2 pi))
+    0.918_938_533_204_672_8 + (x + 0.5) * t.ln() - t + a.ln()
+}
+
+// Regularized lower incomplete gamma function P(a, x)
+fn gamma_p(a: dtype, x: dtype) -> dtype {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    let scale = (a * x.ln() - x - ln_gamma(a)).exp();
+
+    // Series converges quickly below a + 1, continued fraction above
+    if x < a + 1.0 {
+        let mut term = 1.0 / a;
+        let mut sum = term;
+        for n in 1..1000 {
+            term *= x / (a + n as dtype);
+            sum += term;
+            if term.abs() < sum.abs() * dtype::EPSILON {
+                break;
+            }
+        }
+        sum * scale
+    } else {
+        let tiny = dtype::MIN_POSITIVE / dtype::EPSILON;
+        let mut b = x + 1.0 - a;
+        let mut c = 1.0 / tiny;
+        let mut d = 1.0 / b;
+        let mut h = d;
+        for i in 1..1000 {
+            let an = -(i as dtype) * (i as dtype - a);
+            b += 2.0;
+            d = an * d + b;
+            if d.abs() < tiny {
+                d = tiny;
+            }
+            c = b + an / c;
+            if c.abs() < tiny {
+                c = tiny;
+            }
+            d = 1.0 / d;
+            let delta = d * c;
+            h *= delta;
+            if (delta - 1.0).abs() < dtype::EPSILON {
+                break;
+            }
+        }
+        1.0 - scale * h
+    }
+}
+
+/// Compute the quantile of the chi-squared distribution
+///
+/// Returns the $x$ with $P(\chi^2_k \leq x) = p$ for `dof` degrees of freedom
+/// $k$, ie the threshold a chi-squared test at confidence $p$ compares
+/// against. Found by bisection on the cumulative distribution function.
+pub fn chi2_quantile(dof: usize, p: dtype) -> dtype {
+    assert!(dof > 0, "Chi-squared needs at least one degree of freedom");
+    assert!(0.0 < p && p < 1.0, "Quantile must be in (0, 1)");
+
+    let k = dof as dtype / 2.0;
+    let cdf = |x: dtype| gamma_p(k, x / 2.0);
+    let mut lo = 0.0;
+    let mut hi = dof as dtype;
+    while cdf(hi) < p {
+        lo = hi;
+        hi *= 2.0;
+    }
+    for _ in 0..100 {
+        let mid = 0.5 * (lo + hi);
+        if cdf(mid) < p {
+            lo = mid;
+        } else {
+            hi = mid;
+        }
+    }
+    0.5 * (lo + hi)
+}
+
+/// Flag factors that fail a chi-squared consistency test
+///
+/// For each factor, the squared norm of its whitened residual
+/// $\|r\|^2_\Sigma$ follows a chi-squared distribution with as many degrees
+/// of freedom as the residual has dimensions, if the measurement matches its
+/// noise model. Returns the [FactorId] and $\chi^2$ value of every factor
+/// exceeding the quantile at `confidence` (ie 0.95), sorted by id. Robust
+/// kernels are ignored, so this is also a way to check what a kernel is
+/// downweighting.
+pub fn chi2_outliers(graph: &Graph, values: &Values, confidence: dtype) -> Vec<(FactorId, dtype)> {
+    let mut thresholds = HashMap::<usize, dtype>::default();
+    graph
+        .ids()
+        .zip(graph.iter())
+        .filter_map(|(id, factor)| {
+            let r = factor.residual().residual(values, factor.keys());
+            let chi2 = factor.noise().whiten_vec(r).norm_squared();
+            let dof = factor.dim_out();
+            let threshold = *thresholds
+                .entry(dof)
+                .or_insert_with(|| chi2_quantile(dof, confidence));
+            (chi2 > threshold).then_some((id, chi2))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use matrixcompare::{assert_matrix_eq, assert_scalar_eq};
+
+    use super::*;
+    use crate::optimizers::{LevenMarquardt, OptError, Optimizer};
+
+    assign_symbols!(P: VectorVar2);
+
+    #[test]
+    fn crlb_triangulation() {
+        // Two sensors observe a point, each only informative along one axis
+        let point = VectorVar2::new(1.0, 2.0);
+        let mut graph = Graph::new();
+        let noise1 = GaussianNoise::<2>::from_diag_sigmas(0.1, 1.0);
+        graph.add_factor(fac![PriorResidual::new(point.clone()), P(0), noise1]);
+        let noise2 = GaussianNoise::<2>::from_diag_sigmas(1.0, 0.1);
+        graph.add_factor(fac![PriorResidual::new(point.clone()), P(0), noise2]);
+
+        let mut values = Values::new();
+        values.insert_unchecked(P(0), point);
+
+        // Information adds, 100 + 1 in each direction
+        let got = crlb(&graph, &values).expect("Information matrix is singular");
+        let key: Key = P(0).into();
+        let expected = MatrixX::identity(2, 2) / 101.0;
+        assert_matrix_eq!(got[&key], expected, comp = abs, tol = 1e-6);
+    }
+
+    #[test]
+    fn crlb_unconstrained() {
+        // Only a relative measurement, so nothing anchors the pair
+        let point = VectorVar2::new(1.0, 2.0);
+        let mut graph = Graph::new();
+        let res = BetweenResidual::new(VectorVar2::identity());
+        graph.add_factor(fac![res, (P(0), P(1))]);
+
+        let mut values = Values::new();
+        values.insert_unchecked(P(0), point.clone());
+        values.insert_unchecked(P(1), point);
+
+        assert!(crlb(&graph, &values).is_none());
+    }
+
+    fn information(factor: &crate::containers::Factor) -> MatrixX {
+        let dim = factor.dim_out();
+        let w = factor.noise().whiten_mat(MatrixX::identity(dim, dim));
+        w.transpose() * w
+    }
+
+    fn check_round_trip(name: &str, graph: &Graph, values: &Values) {
+        let file = std::env::temp_dir().join(name);
+        let file = file.to_str().expect("Invalid path");
+        save_g20(file, graph, values);
+        let (graph_new, values_new) = load_g20(file);
+
+        assert_eq!(values.len(), values_new.len());
+        for (key, var) in values.iter() {
+            let var_new = values_new.get_raw(*key).expect("Missing variable");
+            let diff = var_new.ominus_dyn(var.as_ref());
+            assert_matrix_eq!(diff, VectorX::zeros(diff.len()), comp = abs, tol = 1e-6);
+        }
+
+        assert_graphs_eq(graph, &graph_new, values);
+    }
+
+    // Measurements are compared through the residuals
+    fn assert_graphs_eq(graph: &Graph, graph_new: &Graph, values: &Values) {
+        assert_eq!(graph.len(), graph_new.len());
+        for (f, f_new) in graph.iter().zip(graph_new.iter()) {
+            assert_eq!(f.keys(), f_new.keys());
+            assert_matrix_eq!(information(f), information(f_new), comp = abs, tol = 1e-6);
+            let r = f.residual().residual(values, f.keys());
+            let r_new = f_new.residual().residual(values, f_new.keys());
+            assert_matrix_eq!(r, r_new, comp = abs, tol = 1e-6);
+        }
+    }
+
+    #[test]
+    fn g20_round_trip_se2() {
+        let mut values = Values::new();
+        values.insert(X(0), SE2::new(0.1, 1.0, 2.0));
+        values.insert(X(1), SE2::new(-0.2, 2.0, 2.5));
+        values.insert(X(2), SE2::new(0.3, 3.0, 1.5));
+
+        #[rustfmt::skip]
+        let inf = Matrix::<3, 3>::new(
+            10.0, 1.0, 2.0,
+            1.0, 20.0, 3.0,
+            2.0, 3.0, 30.0,
+        );
+        let mut graph = Graph::new();
+        let noise = GaussianNoise::<3>::from_diag_sigmas(0.1, 0.2, 0.3);
+        graph.add_factor(fac![PriorResidual::new(SE2::identity()), X(0), noise]);
+        for i in 0..2 {
+            let noise = GaussianNoise::from_matrix_inf(inf.as_view());
+            let res = BetweenResidual::new(SE2::new(0.1 * i as dtype, 1.0, -0.5));
+            graph.add_factor(fac![res, (X(i), X(i + 1)), noise]);
+        }
+
+        check_round_trip("factrs_round_trip_se2.g2o", &graph, &values);
+    }
+
+    #[test]
+    fn g20_round_trip_se3() {
+        let xi = VectorX::from_fn(6, |i, _| (i as dtype + 1.0) / 10.0);
+        let mut values = Values::new();
+        values.insert(X(0), SE3::identity());
+        values.insert(X(1), SE3::exp(xi.as_view()));
+
+        let a = Matrix::<6, 6>::from_fn(|i, j| ((i * 7 + j * 3) % 5) as dtype);
+        let inf = a.transpose() * a + Matrix::<6, 6>::identity();
+        let mut graph = Graph::new();
+        let noise = GaussianNoise::<6>::from_diag_sigmas(0.1, 0.1, 0.1, 0.2, 0.2, 0.2);
+        graph.add_factor(fac![PriorResidual::new(SE3::identity()), X(0), noise]);
+        let noise = GaussianNoise::from_matrix_inf(inf.as_view());
+        let res = BetweenResidual::new(SE3::exp((xi * 0.9).as_view()));
+        graph.add_factor(fac![res, (X(0), X(1)), noise]);
+
+        check_round_trip("factrs_round_trip_se3.g2o", &graph, &values);
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn json_round_trip() {
+        let mut values = Values::new();
+        let mut graph = Graph::new();
+        values.insert(X(0), SE2::new(0.1, 1.0, 2.0));
+        graph.add_factor(fac![PriorResidual::new(SE2::identity()), X(0), 0.1 as std]);
+        for i in 0..3 {
+            values.insert(X(i + 1), SE2::new(0.2 * i as dtype, 2.0 + i as dtype, 1.5));
+            let res = BetweenResidual::new(SE2::new(0.1, 1.0, -0.5));
+            let noise = GaussianNoise::<3>::from_diag_sigmas(0.1, 0.2, 0.3);
+            let robust = crate::robust::Huber::default();
+            graph.add_factor(fac![res, (X(i), X(i + 1)), noise, robust]);
+        }
+        values.insert(L(0), VectorVar3::new(1.0, 2.0, 3.0));
+        graph.add_factor(fac![
+            PriorResidual::new(VectorVar3::new(1.5, 2.0, 2.5)),
+            L(0),
+            0.5 as cov
+        ]);
+
+        let check = |graph_new: Graph, values_new: Values| {
+            assert_eq!(values.len(), values_new.len());
+            assert_eq!(graph.len(), graph_new.len());
+            let error = graph.error(&values);
+            assert_scalar_eq!(graph_new.error(&values_new), error, comp = abs, tol = 1e-6);
+            assert_scalar_eq!(graph_new.error(&values), error, comp = abs, tol = 1e-6);
+        };
+
+        let file = std::env::temp_dir().join("factrs_round_trip.json");
+        let file = file.to_str().expect("Invalid path");
+        save_graph_json(file, &graph, &values);
+        let (graph_new, values_new) = load_graph_json(file);
+        check(graph_new, values_new);
+    }
+
+    #[test]
+    fn toro_matches_g20() {
+        let dir = std::env::temp_dir();
+        let toro = dir.join("factrs_small.graph");
+        std::fs::write(
+            &toro,
+            "VERTEX2 0 0.0 0.0 0.0\n\
+             VERTEX2 1 1.0 0.1 0.05\n\
+             VERTEX2 2 2.1 0.0 -0.1\n\
+             EDGE2 0 1 1.0 0.0 0.0 10 1 20 30 2 3\n\
+             EDGE2 1 2 1.0 -0.1 -0.1 40 4 50 60 5 6\n",
+        )
+        .expect("Failed to write toro");
+
+        // Same graph, but with g2o's ordering of the information matrix
+        let g20 = dir.join("factrs_small.g2o");
+        std::fs::write(
+            &g20,
+            "VERTEX_SE2 0 0.0 0.0 0.0\n\
+             VERTEX_SE2 1 1.0 0.1 0.05\n\
+             VERTEX_SE2 2 2.1 0.0 -0.1\n\
+             EDGE_SE2 0 1 1.0 0.0 0.0 10 1 2 20 3 30\n\
+             EDGE_SE2 1 2 1.0 -0.1 -0.1 40 4 5 50 6 60\n",
+        )
+        .expect("Failed to write g2o");
+
+        let (graph_toro, values_toro) = load_toro(toro.to_str().expect("Invalid path"));
+        let (graph_g20, values_g20) = load_g20(g20.to_str().expect("Invalid path"));
+        assert_eq!(values_toro.len(), 3);
+        assert_eq!(values_g20.len(), 3);
+        assert_graphs_eq(&graph_toro, &graph_g20, &values_toro);
+
+        // And explicitly check the first edge, in factrs' (theta, x, y) order
+        let inf = information(graph_toro.iter().next().expect("Missing edge"));
+        #[rustfmt::skip]
+        let expected = MatrixX::from_row_slice(3, 3, &[
+            30.0, 2.0, 3.0,
+            2.0, 10.0, 1.0,
+            3.0, 1.0, 20.0,
+        ]);
+        assert_matrix_eq!(inf, expected, comp = abs, tol = 1e-6);
+    }
+
+    #[test]
+    fn bal_smoke() {
+        // Two cameras looking at four points from about 5m away
+        let cameras = [
+            ([0.01, -0.02, 0.03], [0.0, 0.0, -5.0]),
+            ([0.0, 0.1, 0.0], [1.0, 0.0, -5.0]),
+        ];
+        let cal = BalIntrinsics {
+            f: 500.0,
+            k1: -0.05,
+            k2: 0.01,
+        };
+        let points = [
+            [0.5, 0.2, 0.1],
+            [-0.4, 0.3, -0.2],
+            [0.1, -0.5, 0.3],
+            [-0.2, -0.1, 0.0],
+        ];
+
+        // Project with the BAL camera model
+        let write_bal = |perturb: dtype| {
+            let mut out = String::from("2 4 8\n");
+            for (i, (aa, t)) in cameras.iter().enumerate() {
+                let rot = SO3::exp(vectorx![aa[0], aa[1], aa[2]].as_view());
+                let pose = SE3::from_rot_trans(rot, Vector3::new(t[0], t[1], t[2]));
+                for (j, p) in points.iter().enumerate() {
+                    let p = pose.apply(Vector3::new(p[0], p[1], p[2]).as_view());
+                    let (x, y) = (-p[0] / p[2], -p[1] / p[2]);
+                    let r2 = x * x + y * y;
+                    let r = 1.0 + cal.k1 * r2 + cal.k2 * r2 * r2;
+                    writeln!(out, "{i} {j} {} {}", cal.f * r * x, cal.f * r * y).unwrap();
+                }
+            }
+            for (aa, t) in cameras.iter() {
+                for x in aa.iter().chain(t) {
+                    writeln!(out, "{}", x + perturb).unwrap();
+                }
+                writeln!(out, "{}\n{}\n{}", cal.f, cal.k1, cal.k2).unwrap();
+            }
+            for p in points.iter() {
+                for x in p {
+                    writeln!(out, "{}", x + 5.0 * perturb).unwrap();
+                }
+            }
+            out
+        };
+
+        let file = std::env::temp_dir().join("factrs_small_bal.txt");
+        let file = file.to_str().expect("Invalid path");
+
+        // At the ground truth all the residuals vanish
+        std::fs::write(file, write_bal(0.0)).expect("Failed to write bal");
+        let (graph, values, intrinsics) = load_bal(file);
+        assert_eq!(graph.len(), 8);
+        assert_eq!(values.len(), 6);
+        assert_eq!(intrinsics, vec![cal.clone(); 2]);
+        assert!(graph.error(&values) < 1e-6);
+
+        // And from a perturbed guess, a few iterations stay finite
+        std::fs::write(file, write_bal(0.01)).expect("Failed to write bal");
+        let (graph, values, _) = load_bal(file);
+        let error_init = graph.error(&values);
+        let mut opt: LevenMarquardt = LevenMarquardt::new(graph.clone());
+        opt.params_base.max_iterations = 5;
+        let values = match opt.optimize(values) {
+            Ok(result) | Err(OptError::MaxIterations(result)) => result.into_values(),
+            Err(e) => panic!("Optimization failed: {:?}", e),
+        };
+        let error = graph.error(&values);
+        assert!(error.is_finite());
+        assert!(error < error_init);
+    }
+
+    #[test]
+    fn align_shifted_graph() {
+        let global = (0..4)
+            .map(|i| {
+                let xi = vectorx![
+                    0.1 * i as dtype,
+                    0.0,
+                    0.2,
+                    i as dtype,
+                    0.5,
+                    -0.3 * i as dtype
+                ];
+                SE3::exp(xi.as_view())
+            })
+            .collect::<Vec<_>>();
+        let landmark = Vector3::new(1.0, 2.0, 3.0);
+
+        // Same solution, but expressed in some arbitrary local frame
+        let offset = SE3::exp(vectorx![0.3, -0.2, 1.0, 5.0, -2.0, 0.5].as_view());
+        let mut values = Values::new();
+        // Any hasher works, not just the crate's
+        let mut gps_fixes = std::collections::HashMap::new();
+        for (i, pose) in global.iter().enumerate() {
+            values.insert(X(i as u32), &offset * pose);
+            gps_fixes.insert(Key::from(X(i as u32)), pose.xyz().clone_owned());
+        }
+        let local = offset.apply(landmark.as_view());
+        values.insert(L(0), VectorVar3::from(local));
+        values.insert(P(0), VectorVar2::new(1.0, 2.0));
+
+        let aligned = align_to_global(&values, &gps_fixes);
+        assert_eq!(aligned.len(), values.len());
+        for (i, pose) in global.iter().enumerate() {
+            let got: &SE3 = aligned.get(X(i as u32)).expect("Missing pose");
+            assert_matrix_eq!(got.ominus(pose), VectorX::zeros(6), comp = abs, tol = 1e-6);
+        }
+        let got: &VectorVar3 = aligned.get(L(0)).expect("Missing landmark");
+        assert_matrix_eq!(got.0, landmark, comp = abs, tol = 1e-6);
+        let got: &VectorVar2 = aligned.get(P(0)).expect("Missing variable");
+        assert_matrix_eq!(got.0, VectorVar2::new(1.0, 2.0).0, comp = abs, tol = 1e-6);
+    }
+
+    #[test]
+    fn chi2_quantiles() {
+        // Reference values from standard chi-squared tables
+        assert_scalar_eq!(chi2_quantile(1, 0.95), 3.841459, comp = abs, tol = 1e-4);
+        assert_scalar_eq!(chi2_quantile(3, 0.95), 7.814728, comp = abs, tol = 1e-4);
+        assert_scalar_eq!(chi2_quantile(6, 0.99), 16.811894, comp = abs, tol = 1e-4);
+        assert_scalar_eq!(chi2_quantile(2, 0.5), 1.386294, comp = abs, tol = 1e-4);
+    }
+
+    #[test]
+    fn chi2_flags_outlier() {
+        let mut graph = Graph::new();
+        let mut values = Values::new();
+        let mut ids = Vec::new();
+        for i in 0..5 {
+            let point = VectorVar2::new(i as dtype, 0.0);
+            ids.push(graph.add_factor(fac![PriorResidual::new(point.clone()), P(i), 0.1 as std]));
+            values.insert_unchecked(P(i), point.oplus(vectorx![0.05, -0.05].as_view()));
+        }
+
+        // Inflate one measurement to 10 sigma
+        let res = PriorResidual::new(VectorVar2::new(1.0, 1.0));
+        let bad = graph.add_factor(fac![res, P(1), 0.1 as std]);
+
+        let flagged = chi2_outliers(&graph, &values, 0.95);
+        assert_eq!(flagged.len(), 1);
+        assert_eq!(flagged[0].0, bad);
+        assert!(flagged[0].1 > chi2_quantile(2, 0.95));
+        assert!(!ids.contains(&flagged[0].0));
+    }
+}
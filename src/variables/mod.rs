@@ -68,4 +68,7 @@ pub use vector::{
 mod imu_bias;
 pub use imu_bias::ImuBias;
 
+mod unit_vector;
+pub use unit_vector::UnitVector3;
+
 mod macros;
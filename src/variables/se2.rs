@@ -30,6 +30,16 @@ impl<T: Numeric> SE2<T> {
         }
     }
 
+    /// Create a new SE2 from x, y coordinates and an angle
+    pub fn from_xytheta(x: T, y: T, theta: T) -> Self {
+        Self::new(theta, x, y)
+    }
+
+    /// Get the x, y coordinates and angle
+    pub fn xytheta(&self) -> (T, T, T) {
+        (self.x(), self.y(), self.theta())
+    }
+
     pub fn xy(&self) -> VectorView2<T> {
         self.xy.as_view()
     }
@@ -132,6 +142,18 @@ impl<T: Numeric> Variable for SE2<T> {
         vectorx![theta, xy[0], xy[1]]
     }
 
+    fn to_params(&self) -> VectorX<T> {
+        let rot = self.rot.to_params();
+        vectorx![rot[0], rot[1], self.xy[0], self.xy[1]]
+    }
+
+    fn from_params(params: VectorViewX<T>) -> Self {
+        SE2 {
+            rot: SO2::from_params(params.rows(0, 2)),
+            xy: Vector2::new(params[2], params[3]),
+        }
+    }
+
     fn cast<TT: Numeric + SupersetOf<Self::T>>(&self) -> Self::Alias<TT> {
         SE2 {
             rot: self.rot.cast(),
@@ -270,4 +292,18 @@ mod tests {
     test_variable!(SO2);
 
     test_lie!(SO2);
+
+    #[test]
+    fn matrix_round_trip() {
+        let x = SE2::from_xytheta(1.0, -2.0, 0.3);
+        let mat = x.to_matrix();
+        assert_eq!(mat[(0, 2)], 1.0);
+        assert_eq!(mat[(1, 2)], -2.0);
+        assert_eq!(mat[(2, 2)], 1.0);
+
+        let (x, y, theta) = SE2::from_matrix(mat.as_view()).xytheta();
+        assert!((x - 1.0).abs() < 1e-10);
+        assert!((y + 2.0).abs() < 1e-10);
+        assert!((theta - 0.3).abs() < 1e-10);
+    }
 }
@@ -34,6 +34,57 @@ impl<T: Numeric> SE3<T> {
     pub fn xyz(&self) -> VectorView3<T> {
         self.xyz.as_view()
     }
+
+    /// Weighted geodesic distance to another pose
+    ///
+    /// Defined as $\sqrt{w^2 \theta^2 + \| t_1 - t_2 \|^2}$, where $\theta$ is
+    /// the [geodesic distance](SO3::geodesic_distance) between the rotations
+    /// and $w$ converts radians into units of translation.
+    pub fn geodesic_distance(&self, other: &Self, weight: dtype) -> T {
+        let theta = self.rot.geodesic_distance(&other.rot) * T::from(weight);
+        let dt = (self.xyz - other.xyz).norm();
+        (theta * theta + dt * dt).sqrt()
+    }
+
+    /// Weighted chordal distance to another pose
+    ///
+    /// Defined as $\sqrt{w^2 \| R_1 - R_2 \|_F^2 + \| t_1 - t_2 \|^2}$, where
+    /// $w$ converts the [chordal distance](SO3::chordal_distance) between
+    /// the rotations into units of translation.
+    pub fn chordal_distance(&self, other: &Self, weight: dtype) -> T {
+        let rot = self.rot.chordal_distance(&other.rot) * T::from(weight);
+        let dt = (self.xyz - other.xyz).norm();
+        (rot * rot + dt * dt).sqrt()
+    }
+
+    /// Interpolate rotation and translation separately
+    ///
+    /// Rotation is slerped, $R_1 \exp(t \log(R_1^{-1} R_2))$, and translation
+    /// is linearly interpolated, $(1 - t) p_1 + t p_2$, with $t \in [0, 1]$.
+    ///
+    /// This is cheaper and often what is wanted for trajectories, but it is
+    /// *not* the geodesic on SE(3), $x_1 \exp(t \log(x_1^{-1} x_2))$, which
+    /// follows a screw motion where the translation curves along with the
+    /// rotation. The two agree at the endpoints and when the rotation is
+    /// constant, but diverge for a large rotation paired with a large
+    /// translation.
+    pub fn lerp_translation_slerp_rotation(&self, other: &Self, t: T) -> Self {
+        let rot = self.rot.slerp(&other.rot, t);
+        let xyz = self.xyz * (T::from(1.0) - t) + other.xyz * t;
+        SE3 { rot, xyz }
+    }
+
+    /// Interpolate along the geodesic to another pose
+    ///
+    /// Computes $x_1 \exp(t \log(x_1^{-1} x_2))$ with $t \in [0, 1]$, the
+    /// constant velocity screw motion from `self` to `other`. The rotation
+    /// matches [SO3::slerp], while the translation curves along with it. See
+    /// [lerp_translation_slerp_rotation](SE3::lerp_translation_slerp_rotation)
+    /// for interpolating translation linearly instead.
+    pub fn interpolate(&self, other: &Self, t: T) -> Self {
+        let xi = other.minus(self).log() * t;
+        self.compose(&SE3::exp(xi.as_view()))
+    }
 }
 
 #[factrs::mark]
@@ -78,8 +129,8 @@ impl<T: Numeric> Variable for SE3<T> {
             let B;
             let C;
             if w2 < T::from(1e-5) {
-                B = T::from(0.5);
-                C = T::from(1.0 / 6.0);
+                B = T::from(0.5) - w2 / T::from(24.0);
+                C = T::from(1.0 / 6.0) - w2 / T::from(120.0);
             } else {
                 let w = w2.sqrt();
                 let A = w.sin() / w;
@@ -103,24 +154,21 @@ impl<T: Numeric> Variable for SE3<T> {
         let xyz = if cfg!(feature = "fake_exp") {
             self.xyz
         } else {
+            // Closed form of V^-1 = I - wx / 2 + D wx^2, avoiding the
+            // precision lost by inverting V for small rotations
             let w2 = xi_theta.norm_squared();
-            let B;
-            let C;
-            if w2 < T::from(1e-5) {
-                B = T::from(0.5);
-                C = T::from(1.0 / 6.0);
+            let D = if w2 < T::from(1e-5) {
+                T::from(1.0 / 12.0) + w2 / T::from(720.0)
             } else {
                 let w = w2.sqrt();
                 let A = w.sin() / w;
-                B = (T::from(1.0) - w.cos()) / w2;
-                C = (T::from(1.0) - A) / w2;
+                let B = (T::from(1.0) - w.cos()) / w2;
+                (T::from(1.0) - A / (T::from(2.0) * B)) / w2
             };
 
             let I = Matrix3::identity();
             let wx = SO3::hat(xi_theta.as_view());
-            let V = I + wx * B + wx * wx * C;
-
-            let Vinv = V.try_inverse().expect("V is not invertible");
+            let Vinv = I - wx * T::from(0.5) + wx * wx * D;
             Vinv * self.xyz
         };
 
@@ -130,6 +178,25 @@ impl<T: Numeric> Variable for SE3<T> {
         xi
     }
 
+    fn to_params(&self) -> VectorX<T> {
+        let mut params = VectorX::zeros(7);
+        params.as_mut_slice()[0..4].clone_from_slice(self.rot.xyzw.as_slice());
+        params.as_mut_slice()[4..7].clone_from_slice(self.xyz.as_slice());
+        params
+    }
+
+    fn from_params(params: VectorViewX<T>) -> Self {
+        SE3 {
+            rot: SO3::from_params(params.rows(0, 4)),
+            xyz: Vector3::new(params[4], params[5], params[6]),
+        }
+    }
+
+    fn is_valid(&self) -> bool {
+        // Also false if the translation has any NaNs
+        self.rot.is_valid() && self.xyz.norm() < T::from(dtype::INFINITY)
+    }
+
     fn cast<TT: Numeric + SupersetOf<Self::T>>(&self) -> Self::Alias<TT> {
         SE3 {
             rot: self.rot.cast(),
@@ -279,10 +346,100 @@ impl<T: Numeric> fmt::Debug for SE3<T> {
 
 #[cfg(test)]
 mod tests {
+    use matrixcompare::{assert_matrix_eq, assert_scalar_eq};
+
     use super::*;
-    use crate::{test_lie, test_variable};
+    use crate::{linalg::vectorx, test_lie, test_variable};
 
     test_variable!(SE3);
 
     test_lie!(SE3);
+
+    #[test]
+    fn geodesic_distance() {
+        let rot = SO3::exp(Vector3::new(0.0, 0.3, 0.0).as_view());
+        let x1 = SE3::from_rot_trans(rot, Vector3::new(1.0, 2.0, 3.0));
+        let x2 = SE3::identity();
+
+        let theta: dtype = 0.3;
+        // |(1, 2, 3)| = sqrt(14)
+        let dt = (14.0 as dtype).sqrt();
+        let got = x1.geodesic_distance(&x2, 2.0);
+        let exp = (4.0 * theta * theta + dt * dt).sqrt();
+        assert_scalar_eq!(got, exp, comp = abs, tol = 1e-4);
+    }
+
+    #[test]
+    fn params_round_trip() {
+        let rot = SO3::exp(Vector3::new(0.1, -0.4, 0.3).as_view());
+        let x = SE3::from_rot_trans(rot, Vector3::new(1.0, 2.0, 3.0));
+
+        let params = x.to_params();
+        assert_eq!(params.len(), 7);
+
+        let got = SE3::from_params(params.as_view());
+        assert_matrix_eq!(got.to_matrix(), x.to_matrix(), comp = float);
+    }
+
+    #[test]
+    #[cfg(not(feature = "f32"))]
+    fn small_motion_drift() {
+        let xi = vectorx![1e-7, -2e-7, 3e-7, 1e-3, -2e-3, 5e-4];
+        let step = SE3::exp(xi.as_view());
+        assert_matrix_eq!(step.log(), xi, comp = abs, tol = 1e-10);
+
+        // Repeatedly round trip through the tangent space while integrating
+        let mut x = SE3::identity();
+        for _ in 0..1000 {
+            x = SE3::exp(x.log().as_view()).compose(&step);
+        }
+        assert_matrix_eq!(x.log(), xi * 1000.0, comp = abs, tol = 1e-10);
+    }
+
+    #[test]
+    fn interpolate() {
+        let x1 = SE3::exp(vectorx![0.1, -0.2, 0.3, 1.0, 2.0, -1.0].as_view());
+        let x2 = x1.compose(&SE3::exp(vectorx![0.0, 0.4, 1.0, 2.0, 0.0, 0.5].as_view()));
+
+        let start = x1.interpolate(&x2, 0.0);
+        assert_matrix_eq!(start.to_matrix(), x1.to_matrix(), comp = abs, tol = 1e-6);
+        let end = x1.interpolate(&x2, 1.0);
+        assert_matrix_eq!(end.to_matrix(), x2.to_matrix(), comp = abs, tol = 1e-6);
+
+        // Taking the half step twice lands on the end
+        let half = x1.interpolate(&x2, 0.5).minus(&x1);
+        let twice = x1.compose(&half).compose(&half);
+        assert_matrix_eq!(twice.to_matrix(), x2.to_matrix(), comp = abs, tol = 1e-6);
+    }
+
+    #[test]
+    fn lerp_vs_geodesic() {
+        let x1 = SE3::identity();
+        let rot = SO3::exp(Vector3::new(0.0, 0.0, 1.5).as_view());
+        let x2 = SE3::from_rot_trans(rot, Vector3::new(2.0, 0.0, 0.0));
+
+        // Endpoints match
+        let start = x1.lerp_translation_slerp_rotation(&x2, 0.0);
+        assert_matrix_eq!(start.to_matrix(), x1.to_matrix(), comp = abs, tol = 1e-6);
+        let end = x1.lerp_translation_slerp_rotation(&x2, 1.0);
+        assert_matrix_eq!(end.to_matrix(), x2.to_matrix(), comp = abs, tol = 1e-6);
+
+        // Halfway, the rotations agree but the translations do not
+        let lerp = x1.lerp_translation_slerp_rotation(&x2, 0.5);
+        let geodesic = x1.interpolate(&x2, 0.5);
+
+        assert_matrix_eq!(
+            lerp.xyz(),
+            Vector3::new(1.0, 0.0, 0.0),
+            comp = abs,
+            tol = 1e-6
+        );
+        assert_matrix_eq!(
+            lerp.rot().to_matrix(),
+            geodesic.rot().to_matrix(),
+            comp = abs,
+            tol = 1e-6
+        );
+        assert!((lerp.xyz() - geodesic.xyz()).norm() > 0.1);
+    }
 }
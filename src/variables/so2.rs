@@ -35,6 +35,32 @@ impl<T: Numeric> SO2<T> {
     pub fn to_theta(&self) -> T {
         self.b.atan2(self.a)
     }
+
+    /// Create a new SO2 from the cosine and sine of the angle
+    ///
+    /// The inputs are normalized, so any nonzero multiple of (cos, sin), such
+    /// as an unnormalized direction vector, is valid. A zero input falls back
+    /// to the identity.
+    pub fn from_cos_sin(c: T, s: T) -> Self {
+        let norm = (c * c + s * s).sqrt();
+        if norm == T::from(0.0) {
+            return Self::identity();
+        }
+        SO2 {
+            a: c / norm,
+            b: s / norm,
+        }
+    }
+
+    /// Cosine of the angle, without any trigonometric evaluation
+    pub fn cos(&self) -> T {
+        self.a
+    }
+
+    /// Sine of the angle, without any trigonometric evaluation
+    pub fn sin(&self) -> T {
+        self.b
+    }
 }
 
 #[factrs::mark]
@@ -73,6 +99,17 @@ impl<T: Numeric> Variable for SO2<T> {
         vectorx![self.b.atan2(self.a)]
     }
 
+    fn to_params(&self) -> VectorX<T> {
+        vectorx![self.a, self.b]
+    }
+
+    fn from_params(params: VectorViewX<T>) -> Self {
+        SO2 {
+            a: params[0],
+            b: params[1],
+        }
+    }
+
     fn cast<TT: Numeric + SupersetOf<Self::T>>(&self) -> Self::Alias<TT> {
         SO2 {
             a: TT::from_subset(&self.a),
@@ -175,10 +212,36 @@ impl<T: Numeric> fmt::Debug for SO2<T> {
 
 #[cfg(test)]
 mod tests {
+    use matrixcompare::{assert_matrix_eq, assert_scalar_eq};
+
     use super::*;
     use crate::{test_lie, test_variable};
 
     test_variable!(SO2);
 
     test_lie!(SO2);
+
+    #[test]
+    fn cos_sin() {
+        let theta = 0.7;
+        let from_theta = SO2::from_theta(theta);
+        // Unnormalized input is scaled back onto the unit circle
+        let from_cos_sin = SO2::from_cos_sin(3.0 * theta.cos(), 3.0 * theta.sin());
+
+        assert_scalar_eq!(from_cos_sin.cos(), theta.cos(), comp = float);
+        assert_scalar_eq!(from_cos_sin.sin(), theta.sin(), comp = float);
+
+        let p = Vector2::new(1.5, -2.0);
+        assert_matrix_eq!(
+            from_cos_sin.apply(p.as_view()),
+            from_theta.apply(p.as_view()),
+            comp = float
+        );
+    }
+
+    #[test]
+    fn cos_sin_zero() {
+        let r = SO2::<dtype>::from_cos_sin(0.0, 0.0);
+        assert_scalar_eq!(r.to_theta(), 0.0, comp = float);
+    }
 }
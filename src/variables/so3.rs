@@ -15,6 +15,12 @@ use crate::{
 /// Implementation of SO(3) for 3D rotations. Specifically, we use quaternions
 /// to represent rotations due to their underlying efficiency when computing
 /// log/exp maps.
+///
+/// Quaternions are never renormalized automatically. All operations work on
+/// the raw coefficients, so any floating point drift in the norm is left
+/// untouched, keeping results reproducible and free of hidden work. If drift
+/// is a concern (e.g. after composing many rotations), opt in by calling
+/// [normalize](SO3::normalize) or [normalize_mut](SO3::normalize_mut).
 #[derive(Clone)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SO3<T: Numeric = dtype> {
@@ -50,6 +56,48 @@ impl<T: Numeric> SO3<T> {
         self.xyzw[3]
     }
 
+    /// Return a copy with the quaternion rescaled to unit norm
+    pub fn normalize(&self) -> Self {
+        SO3 {
+            xyzw: self.xyzw / self.xyzw.norm(),
+        }
+    }
+
+    /// Rescale the quaternion to unit norm in place
+    pub fn normalize_mut(&mut self) {
+        self.xyzw /= self.xyzw.norm();
+    }
+
+    /// Geodesic distance to another rotation
+    ///
+    /// Defined as the angle of the relative rotation, $\| \log(R_2^{-1} R_1)
+    /// \|$, in radians. This is always in $[0, \pi]$ and is equal to the norm
+    /// of [ominus](Variable::ominus).
+    pub fn geodesic_distance(&self, other: &Self) -> T {
+        other.inverse().compose(self).log().norm()
+    }
+
+    /// Chordal distance to another rotation
+    ///
+    /// Defined as the Frobenius norm of the difference of the rotation
+    /// matrices, $\| R_1 - R_2 \|_F$. For a relative angle $\theta$ this is
+    /// $2 \sqrt{2} \sin(\theta / 2)$.
+    pub fn chordal_distance(&self, other: &Self) -> T {
+        (self.to_matrix() - other.to_matrix()).norm()
+    }
+
+    /// Spherical linear interpolation to another rotation
+    ///
+    /// Computes the quaternion slerp $q_1 (q_1^{-1} q_2)^t$, rotating at
+    /// constant angular velocity from `self` at $t = 0$ to `other` at $t = 1$.
+    /// Since $q$ and $-q$ are the same rotation, this always follows the
+    /// shorter of the two arcs. Evaluated through [log](Variable::log) and
+    /// [exp](Variable::exp), so it is well behaved for nearby rotations.
+    pub fn slerp(&self, other: &Self, t: T) -> Self {
+        let xi = other.minus(self).log() * t;
+        self.compose(&SO3::exp(xi.as_view()))
+    }
+
     pub fn dexp(xi: VectorView3<T>) -> Matrix3<T> {
         if cfg!(feature = "left") {
             Self::dexp_left(xi)
@@ -177,6 +225,18 @@ impl<T: Numeric> Variable for SO3<T> {
         xi * scale
     }
 
+    fn to_params(&self) -> VectorX<T> {
+        vectorx![self.xyzw.x, self.xyzw.y, self.xyzw.z, self.xyzw.w]
+    }
+
+    fn from_params(params: VectorViewX<T>) -> Self {
+        SO3::from_xyzw(params[0], params[1], params[2], params[3])
+    }
+
+    fn is_valid(&self) -> bool {
+        (self.xyzw.norm() - T::from(1.0)).abs() < T::from(1e-6)
+    }
+
     fn cast<TT: Numeric + SupersetOf<Self::T>>(&self) -> Self::Alias<TT> {
         SO3 {
             xyzw: self.xyzw.cast(),
@@ -373,7 +433,7 @@ impl<T: Numeric> fmt::Debug for SO3<T> {
 
 #[cfg(test)]
 mod tests {
-    use matrixcompare::assert_matrix_eq;
+    use matrixcompare::{assert_matrix_eq, assert_scalar_eq};
 
     use super::*;
     use crate::{linalg::NumericalDiff, test_lie, test_variable, variables::VectorVar3};
@@ -392,6 +452,25 @@ mod tests {
     #[cfg(feature = "f32")]
     const TOL: f32 = 1e-3;
 
+    #[test]
+    fn slerp() {
+        let r1 = SO3::exp(Vector3::new(0.1, -0.2, 0.3).as_view());
+        let r2 = r1.compose(&SO3::exp(Vector3::new(0.0, 0.0, 1.0).as_view()));
+
+        let start = r1.slerp(&r2, 0.0);
+        assert_matrix_eq!(start.to_matrix(), r1.to_matrix(), comp = abs, tol = TOL);
+        let end = r1.slerp(&r2, 1.0);
+        assert_matrix_eq!(end.to_matrix(), r2.to_matrix(), comp = abs, tol = TOL);
+
+        // Halfway is half the angle, regardless of the sign of the quaternion
+        let expected = r1.compose(&SO3::exp(Vector3::new(0.0, 0.0, 0.5).as_view()));
+        let mid = r1.slerp(&r2, 0.5);
+        assert_matrix_eq!(mid.to_matrix(), expected.to_matrix(), comp = abs, tol = TOL);
+        let r2_flipped = SO3::from_vec(-r2.xyzw);
+        let mid = r1.slerp(&r2_flipped, 0.5);
+        assert_matrix_eq!(mid.to_matrix(), expected.to_matrix(), comp = abs, tol = TOL);
+    }
+
     #[test]
     fn dexp() {
         let xi = Vector3::new(0.1, 0.2, 0.3);
@@ -407,4 +486,65 @@ mod tests {
         println!("exp: {}", exp);
         assert_matrix_eq!(got, exp, comp = abs, tol = TOL);
     }
+
+    #[test]
+    fn geodesic_distance() {
+        let r1 = SO3::exp(Vector3::new(0.1, -0.4, 0.3).as_view());
+        let r2 = SO3::exp(Vector3::new(-0.2, 0.5, 0.1).as_view());
+
+        let got = r1.geodesic_distance(&r2);
+        let exp = r1.ominus(&r2).norm();
+        assert_scalar_eq!(got, exp, comp = abs, tol = TOL);
+
+        // Should be symmetric
+        assert_scalar_eq!(got, r2.geodesic_distance(&r1), comp = abs, tol = TOL);
+    }
+
+    #[test]
+    fn chordal_distance() {
+        let theta = 0.7;
+        let r1 = SO3::identity();
+        let r2 = SO3::exp(Vector3::new(0.0, 0.0, theta).as_view());
+
+        let got = r1.chordal_distance(&r2);
+        let exp = 2.0 * (2.0 as dtype).sqrt() * (theta / 2.0).sin();
+        assert_scalar_eq!(got, exp, comp = abs, tol = TOL);
+    }
+
+    #[test]
+    fn params_round_trip() {
+        let rot = SO3::exp(Vector3::new(0.1, -0.4, 0.3).as_view());
+
+        let params = rot.to_params();
+        assert_eq!(params.len(), 4);
+        assert_matrix_eq!(params, rot.xyzw, comp = float);
+
+        let got = SO3::from_params(params.as_view());
+        assert_matrix_eq!(got.xyzw, rot.xyzw, comp = float);
+    }
+
+    #[test]
+    fn normalize_drift() {
+        // Slightly non-unit quaternion to exaggerate drift
+        let xi = Vector3::new(0.01, 0.02, -0.01);
+        let step = SO3::from_vec(SO3::exp(xi.as_view()).xyzw * 1.001);
+
+        let mut raw = SO3::identity();
+        let mut normalized = SO3::identity();
+        for _ in 0..100 {
+            raw = &raw * &step;
+            normalized = (&normalized * &step).normalize();
+        }
+
+        // Raw mode leaves the drift alone
+        let raw_drift = (raw.xyzw.norm() - 1.0).abs();
+        assert!(raw_drift > 0.05);
+
+        // Normalized mode stays on the manifold
+        let normalized_drift = (normalized.xyzw.norm() - 1.0).abs();
+        assert_scalar_eq!(normalized_drift, 0.0, comp = abs, tol = TOL);
+
+        // And both represent the same rotation
+        assert_matrix_eq!(raw.normalize().xyzw, normalized.xyzw, comp = abs, tol = TOL);
+    }
 }
@@ -36,6 +36,33 @@ pub trait Variable: Clone + Sized + Display + Debug {
     /// Logarithm map (trivial if a vector space)
     fn log(&self) -> VectorX<Self::T>;
 
+    /// Flatten into ambient parameters
+    ///
+    /// These are the parameters the group element is stored with, for example
+    /// the quaternion of SO(3), and are distinct from the tangent space given
+    /// by [log](Self::log). Defaults to [log](Self::log), which is exact for
+    /// vector spaces.
+    fn to_params(&self) -> VectorX<Self::T> {
+        self.log()
+    }
+
+    /// Create from ambient parameters
+    ///
+    /// Inverse of [to_params](Self::to_params). No normalization is done on
+    /// the parameters.
+    fn from_params(params: VectorViewX<Self::T>) -> Self {
+        Self::exp(params)
+    }
+
+    /// Check that the variable is well-formed
+    ///
+    /// For example, that a quaternion has unit norm. Used by
+    /// [Values::validate](crate::containers::Values::validate) to catch
+    /// corrupt inputs before optimizing. Defaults to true.
+    fn is_valid(&self) -> bool {
+        true
+    }
+
     /// Conversion to dual space
     ///
     /// Simply convert all interior values of dtype to DD.
@@ -183,6 +210,18 @@ pub trait VariableSafe: Debug + Display + Downcast {
     fn dim(&self) -> usize;
 
     fn oplus_mut(&mut self, delta: VectorViewX);
+
+    /// See [Variable::to_params]
+    fn to_params(&self) -> VectorX;
+
+    /// Overwrite in place with ambient parameters, see [Variable::from_params]
+    fn set_params(&mut self, params: VectorViewX);
+
+    /// See [Variable::ominus], panics if `y` is a different variable type
+    fn ominus_dyn(&self, y: &dyn VariableSafe) -> VectorX;
+
+    /// See [Variable::is_valid]
+    fn is_valid(&self) -> bool;
 }
 
 #[cfg_attr(feature = "serde", typetag::serde)]
@@ -198,6 +237,25 @@ impl<V: Variable<T = dtype> + 'static> VariableSafe for V {
     fn oplus_mut(&mut self, delta: VectorViewX) {
         *self = self.oplus(delta);
     }
+
+    fn to_params(&self) -> VectorX {
+        Variable::to_params(self)
+    }
+
+    fn set_params(&mut self, params: VectorViewX) {
+        *self = V::from_params(params);
+    }
+
+    fn ominus_dyn(&self, y: &dyn VariableSafe) -> VectorX {
+        let y = y
+            .downcast_ref::<V>()
+            .expect("Mismatched variable types in ominus");
+        self.ominus(y)
+    }
+
+    fn is_valid(&self) -> bool {
+        Variable::is_valid(self)
+    }
 }
 
 impl_downcast!(VariableSafe);
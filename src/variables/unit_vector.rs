@@ -0,0 +1,295 @@
+use std::fmt;
+
+use crate::{
+    dtype,
+    linalg::{
+        vectorx, AllocatorBuffer, Const, DefaultAllocator, Derivative, DimName, DualAllocator,
+        DualVector, Numeric, SupersetOf, Vector3, VectorDim, VectorView3, VectorViewX, VectorX,
+    },
+    variables::Variable,
+};
+
+/// Unit vector in 3D, ie a point on the sphere $S^2$
+///
+/// Useful for bearings, directions, and normals, where only the direction of
+/// a vector is meaningful. The tangent space is the 2D plane tangent to the
+/// sphere, so optimizing keeps the norm at 1 rather than having an extra
+/// unconstrained direction as with a [VectorVar3](crate::variables::VectorVar3).
+///
+/// The identity is $e_z = (0, 0, 1)$, and exp/log are the usual maps of the
+/// sphere at $e_z$,
+/// $$
+/// \exp(\xi) = \left(\frac{\sin \theta}{\theta} \xi, \cos \theta\right),
+/// \quad \theta = \|\xi\|
+/// $$
+/// Every unit vector $u$ is associated with $R_u$, the minimal rotation taking
+/// $e_z$ to $u$, and composition is $u \cdot v = R_u v$. Hence $u \oplus \xi =
+/// R_u \exp(\xi)$ retracts along the tangent plane at $u$, with the basis given
+/// by the first two columns of $R_u$.
+///
+/// Note $S^2$ isn't a group, so composition is only associative for unit
+/// vectors on a shared great circle through $e_z$. This is enough for $\oplus$
+/// and $\ominus$, but [minus](Variable::minus) and the `left` feature don't
+/// have a meaningful interpretation. $R_u$ is discontinuous at $u = -e_z$, so
+/// the tangent basis flips there.
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct UnitVector3<T: Numeric = dtype> {
+    xyz: Vector3<T>,
+}
+
+impl<T: Numeric> UnitVector3<T> {
+    /// Create a new unit vector, normalizing `xyz`
+    pub fn new(xyz: Vector3<T>) -> Self {
+        UnitVector3 {
+            xyz: xyz / xyz.norm(),
+        }
+    }
+
+    /// Create a new unit vector from x, y, z, normalizing them
+    pub fn from_xyz(x: T, y: T, z: T) -> Self {
+        Self::new(Vector3::new(x, y, z))
+    }
+
+    /// Get the unit vector
+    pub fn xyz(&self) -> &Vector3<T> {
+        &self.xyz
+    }
+
+    /// Angle to another unit vector, in $[0, \pi]$
+    pub fn angle(&self, other: &Self) -> T {
+        self.xyz
+            .cross(&other.xyz)
+            .norm()
+            .atan2(self.xyz.dot(&other.xyz))
+    }
+
+    /// Rotate `v` by $R_u$, the minimal rotation taking $e_z$ to `self`
+    pub fn rotate(&self, v: VectorView3<T>) -> Vector3<T> {
+        let u = &self.xyz;
+        let c = T::from(1.0) + u.z;
+        if c < T::from(1e-6) {
+            // Antipode of the identity, any half turn works
+            return Vector3::new(v[0], -v[1], -v[2]);
+        }
+
+        // Rodrigues with axis e_z x u, R = I + [a]x + [a]x^2 / (1 + cos)
+        let a = Vector3::new(-u.y, u.x, T::from(0.0));
+        let av = a.cross(&v);
+        v + av + a.cross(&av) / c
+    }
+}
+
+#[factrs::mark]
+impl<T: Numeric> Variable for UnitVector3<T> {
+    type T = T;
+    type Dim = Const<2>;
+    type Alias<TT: Numeric> = UnitVector3<TT>;
+
+    fn identity() -> Self {
+        UnitVector3 { xyz: Vector3::z() }
+    }
+
+    fn inverse(&self) -> Self {
+        UnitVector3 {
+            xyz: Vector3::new(-self.xyz.x, -self.xyz.y, self.xyz.z),
+        }
+    }
+
+    fn compose(&self, other: &Self) -> Self {
+        // Renormalized, as rotate loses precision near the antipode
+        UnitVector3 {
+            xyz: self.rotate(other.xyz.as_view()).normalize(),
+        }
+    }
+
+    fn exp(xi: VectorViewX<T>) -> Self {
+        let theta2 = xi[0] * xi[0] + xi[1] * xi[1];
+
+        let (scale, z) = if theta2 < T::from(1e-6) {
+            // sin(theta) / theta \approx 1 - theta^2 / 6
+            (
+                T::from(1.0) - theta2 / T::from(6.0),
+                T::from(1.0) - theta2 / T::from(2.0),
+            )
+        } else {
+            let theta = theta2.sqrt();
+            (theta.sin() / theta, theta.cos())
+        };
+
+        UnitVector3 {
+            xyz: Vector3::new(xi[0] * scale, xi[1] * scale, z),
+        }
+    }
+
+    fn log(&self) -> VectorX<T> {
+        let (x, y, z) = (self.xyz.x, self.xyz.y, self.xyz.z);
+        let sin2 = x * x + y * y;
+
+        let scale = if sin2 < T::from(1e-6) && z.is_sign_positive() {
+            // theta / sin(theta) \approx 1 + sin(theta)^2 / 6
+            T::from(1.0) + sin2 / T::from(6.0)
+        } else if sin2 == T::from(0.0) {
+            // Antipode of the identity, every direction is equally far
+            return vectorx![T::pi(), T::from(0.0)];
+        } else {
+            let sin = sin2.sqrt();
+            sin.atan2(z) / sin
+        };
+
+        vectorx![x * scale, y * scale]
+    }
+
+    fn to_params(&self) -> VectorX<T> {
+        vectorx![self.xyz.x, self.xyz.y, self.xyz.z]
+    }
+
+    fn from_params(params: VectorViewX<T>) -> Self {
+        UnitVector3 {
+            xyz: Vector3::new(params[0], params[1], params[2]),
+        }
+    }
+
+    fn is_valid(&self) -> bool {
+        // Loosened in f32, where round off alone can reach 1e-6
+        let tol = (1e3 * dtype::EPSILON).max(1e-6);
+        (self.xyz.norm() - T::from(1.0)).abs() < T::from(tol)
+    }
+
+    fn cast<TT: Numeric + SupersetOf<Self::T>>(&self) -> Self::Alias<TT> {
+        UnitVector3 {
+            xyz: self.xyz.cast(),
+        }
+    }
+
+    fn dual_exp<N: DimName>(idx: usize) -> Self::Alias<DualVector<N>>
+    where
+        AllocatorBuffer<N>: Sync + Send,
+        DefaultAllocator: DualAllocator<N>,
+        DualVector<N>: Copy,
+    {
+        // To first order, exp(xi) = (xi, 1)
+        let mut x = DualVector::<N>::from_re(0.0);
+        let mut eps = VectorDim::<N>::zeros();
+        eps[idx] = 1.0;
+        x.eps = Derivative::new(Some(eps));
+
+        let mut y = DualVector::<N>::from_re(0.0);
+        let mut eps = VectorDim::<N>::zeros();
+        eps[idx + 1] = 1.0;
+        y.eps = Derivative::new(Some(eps));
+
+        let z = DualVector::<N>::from_re(1.0);
+
+        UnitVector3 {
+            xyz: Vector3::new(x, y, z),
+        }
+    }
+}
+
+impl<T: Numeric> fmt::Display for UnitVector3<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let precision = f.precision().unwrap_or(3);
+        write!(
+            f,
+            "UnitVector3({:.p$}, {:.p$}, {:.p$})",
+            self.xyz.x,
+            self.xyz.y,
+            self.xyz.z,
+            p = precision
+        )
+    }
+}
+
+impl<T: Numeric> fmt::Debug for UnitVector3<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let precision = f.precision().unwrap_or(3);
+        write!(
+            f,
+            "UnitVector3 {{ x: {:.p$}, y: {:.p$}, z: {:.p$} }}",
+            self.xyz.x,
+            self.xyz.y,
+            self.xyz.z,
+            p = precision
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use matrixcompare::{assert_matrix_eq, assert_scalar_eq};
+
+    use super::*;
+    use crate::{
+        linalg::{Diff, ForwardProp, NumericalDiff},
+        test_variable,
+    };
+
+    test_variable!(UnitVector3);
+
+    #[cfg(not(feature = "f32"))]
+    const PWR: i32 = 6;
+    #[cfg(not(feature = "f32"))]
+    const TOL: f64 = 1e-6;
+
+    #[cfg(feature = "f32")]
+    const PWR: i32 = 3;
+    #[cfg(feature = "f32")]
+    const TOL: f32 = 1e-3;
+
+    #[test]
+    fn oplus_unit_norm() {
+        let starts = [
+            UnitVector3::identity(),
+            UnitVector3::from_xyz(1.0, -2.0, 0.5),
+            UnitVector3::from_xyz(0.1, 0.0, -1.0),
+            UnitVector3::from_xyz(0.0, 0.0, -1.0),
+        ];
+        for u in starts {
+            for xi in [vectorx![0.1, -0.2], vectorx![1.5, 2.0], vectorx![-4.0, 0.3]] {
+                let out = u.oplus(xi.as_view());
+                assert_scalar_eq!(out.xyz().norm(), 1.0, comp = abs, tol = TOL);
+                assert!(out.is_valid());
+            }
+        }
+    }
+
+    #[test]
+    fn rotate() {
+        let u = UnitVector3::from_xyz(1.0, 2.0, -0.5);
+        let got = u.rotate(Vector3::z().as_view());
+        assert_matrix_eq!(got, *u.xyz(), comp = abs, tol = TOL);
+
+        // Rotations preserve angles
+        let v = Vector3::new(0.3, -0.1, 0.8);
+        let w = Vector3::new(-0.5, 0.2, 0.1);
+        let (rv, rw) = (u.rotate(v.as_view()), u.rotate(w.as_view()));
+        assert_scalar_eq!(rv.dot(&rw), v.dot(&w), comp = abs, tol = TOL);
+    }
+
+    #[cfg(not(feature = "left"))]
+    #[test]
+    fn oplus_ominus() {
+        let x = UnitVector3::from_xyz(0.4, -1.0, 0.2);
+        let y = UnitVector3::from_xyz(-0.3, 0.5, 1.0);
+        let delta = x.ominus(&y);
+        assert_scalar_eq!(delta.norm(), x.angle(&y), comp = abs, tol = TOL);
+
+        let got = y.oplus(delta.as_view());
+        assert_matrix_eq!(*got.xyz(), *x.xyz(), comp = abs, tol = TOL);
+    }
+
+    #[cfg(not(feature = "left"))]
+    #[test]
+    fn jacobian() {
+        fn bearing<T: Numeric>(u: UnitVector3<T>) -> VectorX<T> {
+            let v = Vector3::new(T::from(1.0), T::from(-0.5), T::from(2.0));
+            vectorx![u.xyz().dot(&v)]
+        }
+
+        let u = UnitVector3::from_xyz(0.3, -0.6, 0.7);
+        let got = ForwardProp::<Const<2>>::jacobian_1(bearing, &u).diff;
+        let expected = NumericalDiff::<PWR>::jacobian_1(bearing, &u).diff;
+        assert_matrix_eq!(got, expected, comp = abs, tol = TOL);
+    }
+}
@@ -1,4 +1,5 @@
 use std::{
+    array::TryFromSliceError,
     fmt::{Debug, Display},
     ops::Index,
 };
@@ -74,7 +75,7 @@ impl<const N: usize, T: Numeric> Variable for VectorVar<N, T> {
 const _: () = {
     use factrs::{
         core::{BetweenResidual, PriorResidual},
-        residuals::Residual,
+        residuals::{CompositionResidual, Residual},
         variables::VariableSafe,
     };
 
@@ -136,6 +137,22 @@ const _: () = {
         BetweenResidual<VectorVar<14>>,
         BetweenResidual<VectorVar<15>>,
         BetweenResidual<VectorVar<16>>,
+        CompositionResidual<VectorVar<1>>,
+        CompositionResidual<VectorVar<2>>,
+        CompositionResidual<VectorVar<3>>,
+        CompositionResidual<VectorVar<4>>,
+        CompositionResidual<VectorVar<5>>,
+        CompositionResidual<VectorVar<6>>,
+        CompositionResidual<VectorVar<7>>,
+        CompositionResidual<VectorVar<8>>,
+        CompositionResidual<VectorVar<9>>,
+        CompositionResidual<VectorVar<10>>,
+        CompositionResidual<VectorVar<11>>,
+        CompositionResidual<VectorVar<12>>,
+        CompositionResidual<VectorVar<13>>,
+        CompositionResidual<VectorVar<14>>,
+        CompositionResidual<VectorVar<15>>,
+        CompositionResidual<VectorVar<16>>,
     }
 };
 
@@ -170,6 +187,28 @@ impl<const N: usize, T: Numeric> From<VectorVar<N, T>> for Vector<N, T> {
     }
 }
 
+impl<const N: usize, T: Numeric> From<[T; N]> for VectorVar<N, T> {
+    fn from(v: [T; N]) -> Self {
+        VectorVar(Vector::from(v))
+    }
+}
+
+impl<const N: usize, T: Numeric> From<VectorVar<N, T>> for [T; N] {
+    fn from(v: VectorVar<N, T>) -> Self {
+        v.0.into()
+    }
+}
+
+/// Fails if the length of the slice doesn't match `N`.
+impl<const N: usize, T: Numeric> TryFrom<&[T]> for VectorVar<N, T> {
+    type Error = TryFromSliceError;
+
+    fn try_from(v: &[T]) -> Result<Self, Self::Error> {
+        let arr: [T; N] = v.try_into()?;
+        Ok(arr.into())
+    }
+}
+
 impl<const N: usize, T: Numeric> Display for VectorVar<N, T> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let precision = f.precision().unwrap_or(3);
@@ -218,4 +257,24 @@ mod tests {
 
     // Be lazy and only test Vector6 - others should work the same
     test_variable!(VectorVar6);
+
+    #[test]
+    fn from_array() {
+        let v = VectorVar3::from([1.0, 2.0, 3.0]);
+        assert_eq!(v.0, Vector::<3>::new(1.0, 2.0, 3.0));
+
+        let arr: [dtype; 3] = v.into();
+        assert_eq!(arr, [1.0, 2.0, 3.0]);
+    }
+
+    #[test]
+    fn try_from_slice() {
+        let data: Vec<dtype> = vec![1.0, 2.0, 3.0, 4.0];
+
+        let v = VectorVar4::try_from(data.as_slice()).expect("Length should match");
+        assert_eq!(v.0, Vector::<4>::new(1.0, 2.0, 3.0, 4.0));
+
+        assert!(VectorVar3::try_from(data.as_slice()).is_err());
+        assert!(VectorVar6::try_from(data.as_slice()).is_err());
+    }
 }
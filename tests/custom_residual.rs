@@ -1,14 +1,20 @@
 use core::fmt;
 
 use factrs::{
-    dtype,
+    assign_symbols,
+    containers::{FactorBuilder, Graph, Values},
+    dtype, fac,
     linalg::{vectorx, ForwardProp, Numeric, VectorX},
-    residuals::Residual1,
+    noise::GaussianNoise,
+    optimizers::{GaussNewton, Optimizer},
+    residuals::{PriorResidual, Residual1, Residual4},
     traits::Variable,
     variables::SE2,
 };
 use nalgebra::Const;
 
+assign_symbols!(P: SE2);
+
 #[derive(Clone, Debug, Default)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct XPrior {
@@ -40,11 +46,64 @@ impl fmt::Display for XPrior {
     }
 }
 
-// TODO: Some tests to make sure it optimizes
+// Relative motion from x1 to x2 matches that from x3 to x4
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SameMotion;
+
+#[factrs::mark]
+impl Residual4 for SameMotion {
+    type Differ = ForwardProp<<Self as Residual4>::DimIn>;
+    type V1 = SE2;
+    type V2 = SE2;
+    type V3 = SE2;
+    type V4 = SE2;
+    type DimIn = Const<12>;
+    type DimOut = Const<3>;
+
+    fn residual4<T: Numeric>(&self, x1: SE2<T>, x2: SE2<T>, x3: SE2<T>, x4: SE2<T>) -> VectorX<T> {
+        let a = x1.inverse().compose(&x2);
+        let b = x3.inverse().compose(&x4);
+        a.ominus(&b)
+    }
+}
+
+#[test]
+fn test_residual4_optimizes() {
+    // Fix three poses, the fourth is determined by the motion of the first two
+    let poses = [
+        SE2::new(0.0, 0.0, 0.0),
+        SE2::new(0.5, 1.0, 0.5),
+        SE2::new(1.0, 3.0, -1.0),
+    ];
+    let mut graph = Graph::new();
+    let mut values = Values::new();
+    for (i, pose) in poses.iter().enumerate() {
+        graph.add_factor(fac![
+            PriorResidual::new(pose.clone()),
+            P(i as u32),
+            0.01 as std
+        ]);
+        values.insert(P(i as u32), pose.clone());
+    }
+    let factor = FactorBuilder::new4(SameMotion, P(0), P(1), P(2), P(3))
+        .noise(GaussianNoise::<3>::from_scalar_sigma(0.1))
+        .build();
+    graph.add_factor(factor);
+    values.insert(P(3), SE2::identity());
+
+    let mut opt: GaussNewton = GaussNewton::new(graph);
+    let result = opt.optimize(values).expect("Optimization failed");
+
+    let delta = poses[0].inverse().compose(&poses[1]);
+    let expected = poses[2].compose(&delta);
+    let x3: &SE2 = result.get(P(3)).expect("Missing P(3)");
+    assert!(x3.ominus(&expected).norm() < 1e-6);
+}
 
 #[cfg(feature = "serde")]
 mod ser_de {
-    use factrs::{containers::Values, symbols::X, traits::Residual};
+    use factrs::{symbols::X, traits::Residual};
 
     use super::*;
 
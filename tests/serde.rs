@@ -1,7 +1,12 @@
 #[cfg(feature = "serde")]
 mod ser_de {
     use factrs::{
-        containers::Values, residuals::PriorResidual, symbols::X, traits::Residual,
+        containers::{FactorBuilder, Graph, Values},
+        linalg::VectorX,
+        noise::GaussianNoise,
+        residuals::PriorResidual,
+        symbols::X,
+        traits::{NoiseModel, Residual},
         variables::VectorVar1,
     };
 
@@ -27,4 +32,64 @@ mod ser_de {
         assert_eq!(trait_object.dim_out(), 1);
         assert_eq!(error, 0.0);
     }
+
+    #[test]
+    fn test_noise_15() {
+        // Size of an IMU factor
+        let sigmas: Vec<_> = (1..=15).map(|i| i as factrs::dtype / 10.0).collect();
+        let noise = GaussianNoise::<15>::from_diag_sigmas_slice(&sigmas);
+        let trait_object = &noise as &dyn NoiseModel;
+        let json = serde_json::to_string(trait_object).unwrap();
+        let trait_object: Box<dyn NoiseModel> = serde_json::from_str(&json).unwrap();
+
+        let v = VectorX::from_element(15, 1.0);
+        let diff = trait_object.whiten_vec(v.clone()) - noise.whiten_vec(v);
+        assert_eq!(trait_object.dim(), 15);
+        assert!(diff.norm() < 1e-4);
+    }
+
+    fn three_factors() -> Graph {
+        let mut graph = Graph::new();
+        for i in 0..3 {
+            let res = PriorResidual::new(VectorVar1::new(i as factrs::dtype));
+            graph.add_factor(FactorBuilder::new1(res, X(0)).build());
+        }
+        graph
+    }
+
+    #[test]
+    fn test_graph_ids() {
+        let mut graph = three_factors();
+        let ids: Vec<_> = graph.ids().collect();
+        graph.remove_factor(ids[1]);
+
+        let json = serde_json::to_string(&graph).unwrap();
+        let mut graph: Graph = serde_json::from_str(&json).unwrap();
+        assert_eq!(graph.ids().collect::<Vec<_>>(), vec![ids[0], ids[2]]);
+        let new = graph.add_factor(
+            FactorBuilder::new1(PriorResidual::new(VectorVar1::new(0.0)), X(0)).build(),
+        );
+        assert!(new > ids[2]);
+    }
+
+    #[test]
+    fn test_graph_without_ids() {
+        // Graphs serialized before factors had ids
+        let mut json = serde_json::to_value(three_factors()).unwrap();
+        let obj = json.as_object_mut().unwrap();
+        obj.remove("ids");
+        obj.remove("next_id");
+
+        let graph: Graph = serde_json::from_value(json).unwrap();
+        assert_eq!(graph.len(), 3);
+        assert_eq!(graph.ids().count(), 3);
+    }
+
+    #[test]
+    fn test_graph_mismatched_ids() {
+        let mut json = serde_json::to_value(three_factors()).unwrap();
+        json["ids"].as_array_mut().unwrap().pop();
+
+        assert!(serde_json::from_value::<Graph>(json).is_err());
+    }
 }
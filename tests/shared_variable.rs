@@ -0,0 +1,68 @@
+use factrs::{
+    assign_symbols,
+    containers::{Graph, Values},
+    fac,
+    linalg::{Const, ForwardProp, Numeric, VectorX},
+    optimizers::GaussNewton,
+    residuals::{PriorResidual, Residual2},
+    traits::*,
+    variables::SE2,
+};
+use matrixcompare::assert_matrix_eq;
+
+// Sensor pose in the world measured directly (e.g. by motion capture), which
+// depends on the robot pose and the sensor extrinsic
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SensorPoseResidual {
+    meas: SE2,
+}
+
+#[factrs::mark]
+impl Residual2 for SensorPoseResidual {
+    type Differ = ForwardProp<<Self as Residual2>::DimIn>;
+    type V1 = SE2;
+    type V2 = SE2;
+    type DimIn = Const<6>;
+    type DimOut = Const<3>;
+
+    fn residual2<T: Numeric>(&self, body: SE2<T>, extrinsic: SE2<T>) -> VectorX<T> {
+        let meas = self.meas.cast::<T>();
+        body.compose(&extrinsic).ominus(&meas)
+    }
+}
+
+assign_symbols!(X: SE2; E: SE2);
+
+#[test]
+fn shared_extrinsic() {
+    let extrinsic = SE2::new(0.3, 0.5, -0.2);
+    let n = 50;
+
+    let mut graph = Graph::new();
+    let mut values = Values::new();
+    for i in 0..n {
+        let body = SE2::new(0.1 * i as factrs::dtype, i as factrs::dtype, 0.0);
+
+        // Robot poses are well known
+        graph.add_factor(fac![PriorResidual::new(body.clone()), X(i), 1e-3 as std]);
+
+        // Every measurement goes through the same extrinsic
+        let meas = body.compose(&extrinsic);
+        graph.add_factor(fac![SensorPoseResidual { meas }, (X(i), E(0)), 0.1 as std]);
+
+        values.insert(X(i), body);
+    }
+    values.insert(E(0), SE2::identity());
+
+    let mut opt: GaussNewton = GaussNewton::new(graph);
+    let result = opt.optimize(values).expect("Optimization failed");
+
+    let got: &SE2 = result.get(E(0)).expect("Missing extrinsic");
+    assert_matrix_eq!(
+        got.ominus(&extrinsic),
+        VectorX::zeros(3),
+        comp = abs,
+        tol = 1e-4
+    );
+}